@@ -1,4 +1,5 @@
 use proc_macro::TokenStream;
+use proc_macro2::Literal;
 use quote::quote;
 use serde::Deserialize;
 use std::{collections::BTreeMap, fs};
@@ -6,6 +7,7 @@ use syn::{LitStr, parse_macro_input};
 
 #[derive(Deserialize)]
 struct DeserializedJSONEntity {
+    codepoints: Vec<u32>,
     characters: Box<str>,
 }
 
@@ -55,6 +57,12 @@ fn process_file(file_path: &str) -> BTreeMap<[u8; 2], Vec<(Vec<u8>, Vec<u8>)>> {
     prefix_map
 }
 
+/// Expands to a static table of named character references.
+///
+/// The table is a slice of `(prefix, entries)` pairs sorted by the first two bytes of the
+/// entity names, suitable for `binary_search_by_key()`. Each group of entries holds the
+/// remaining bytes of the name and the decoded characters, longest names first. The table
+/// is built entirely at compile time, so it requires no allocation or initialization.
 #[proc_macro]
 pub fn entities_lookup(input: TokenStream) -> TokenStream {
     // Parse the input to get the file path
@@ -63,45 +71,105 @@ pub fn entities_lookup(input: TokenStream) -> TokenStream {
     // Process the file
     let prefix_map = process_file(&file_path);
 
-    // Generate the BTreeMap initialization code
-    let mut prefix_entries = Vec::new();
-
     // For each prefix, generate the entry code
+    let mut prefix_entries = Vec::new();
     for (prefix, suffixes) in prefix_map {
         let prefix_bytes = [prefix[0], prefix[1]];
-        let mut suffix_entries = Vec::new();
-
-        // For each suffix in this prefix group
-        for (suffix, bytes) in suffixes {
-            let suffix_bytes: Vec<_> = suffix.iter().map(|&b| quote! { #b }).collect();
-            let char_bytes: Vec<_> = bytes.iter().map(|&b| quote! { #b }).collect();
-
-            // Create an entry with Box::leak to ensure 'static lifetime
-            suffix_entries.push(quote! {
-                (
-                    Box::leak(Box::new([#(#suffix_bytes),*])) as &'static [u8],
-                    Box::leak(Box::new([#(#char_bytes),*])) as &'static [u8]
-                )
-            });
-        }
+        let suffix_entries = suffixes.iter().map(|(suffix, bytes)| {
+            let suffix = Literal::byte_string(suffix);
+            let bytes = Literal::byte_string(bytes);
+            quote! { (#suffix, #bytes) }
+        });
 
-        // Create the prefix entry with Box::leak for the slice of pairs
         prefix_entries.push(quote! {
-            ([#(#prefix_bytes),*], Box::leak(Box::new([#(#suffix_entries),*])) as &'static [(&'static [u8], &'static [u8])])
+            ([#(#prefix_bytes),*], &[#(#suffix_entries),*])
         });
     }
 
-    // Generate the final TokenStream
     let result = quote! {
-        use lazy_static::lazy_static;
-        use std::collections::BTreeMap;
-
-        lazy_static! {
-            static ref ENTITIES: BTreeMap<[u8; 2], &'static [(&'static [u8], &'static [u8])]> = {
-                let mut map = BTreeMap::new();
-                #(map.insert(#prefix_entries.0, #prefix_entries.1);)*
-                map
-            };
+        {
+            static ENTITIES_TABLE: &[([u8; 2], &[(&[u8], &[u8])])] = &[#(#prefix_entries),*];
+            ENTITIES_TABLE
+        }
+    };
+
+    result.into()
+}
+
+/// Expands to a static table of the preferred named reference for each character.
+///
+/// The table is a slice of `(character, name)` pairs sorted by character, suitable for
+/// `binary_search_by_key()`. Only characters with a name of their own are included. Of
+/// the names ending in a semicolon, the shortest is preferred, then the one with the
+/// fewest uppercase letters, so that U+0026 is `&amp;` rather than `&AMP;`.
+#[proc_macro]
+pub fn named_references_lookup(input: TokenStream) -> TokenStream {
+    let file_path = parse_macro_input!(input as LitStr).value();
+    let json_content = fs::read_to_string(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
+    let entities: BTreeMap<String, DeserializedJSONEntity> =
+        serde_json::from_str(&json_content).expect("Failed to parse JSON");
+
+    let mut preferred_names: BTreeMap<char, String> = BTreeMap::new();
+    for (entity_name, entity_data) in entities {
+        let [code_point] = entity_data.codepoints[..] else {
+            continue;
+        };
+        if !entity_name.ends_with(';') {
+            continue;
+        }
+
+        let character = char::from_u32(code_point).expect("Invalid code point");
+        let preference = |name: &str| {
+            (
+                name.len(),
+                name.bytes().filter(u8::is_ascii_uppercase).count(),
+                name.to_string(),
+            )
+        };
+        let is_preferred = preferred_names
+            .get(&character)
+            .is_none_or(|name| preference(&entity_name) < preference(name));
+        if is_preferred {
+            preferred_names.insert(character, entity_name);
+        }
+    }
+
+    let entries = preferred_names
+        .iter()
+        .map(|(character, name)| quote! { (#character, #name) });
+
+    let result = quote! {
+        {
+            static NAMED_REFERENCES_TABLE: &[(char, &str)] = &[#(#entries),*];
+            NAMED_REFERENCES_TABLE
+        }
+    };
+
+    result.into()
+}
+
+/// Expands to a static table of every entity, as `(name, characters)` pairs.
+///
+/// The names include the leading `&` and the trailing `;`, where there is one. This is
+/// meant for tests, which check the generated lookup tables against the JSON file.
+#[proc_macro]
+pub fn entities_test_cases(input: TokenStream) -> TokenStream {
+    let file_path = parse_macro_input!(input as LitStr).value();
+    let json_content = fs::read_to_string(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
+    let entities: BTreeMap<String, DeserializedJSONEntity> =
+        serde_json::from_str(&json_content).expect("Failed to parse JSON");
+
+    let entries = entities.iter().map(|(name, entity_data)| {
+        let characters = &*entity_data.characters;
+        quote! { (#name, #characters) }
+    });
+
+    let result = quote! {
+        {
+            static ENTITIES_TEST_CASES: &[(&str, &str)] = &[#(#entries),*];
+            ENTITIES_TEST_CASES
         }
     };
 
@@ -1,76 +1,386 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::{borrow::Cow, boxed::Box, vec::Vec};
+use core::{convert::Infallible, ops::Range};
+
 /// U+FFFD REPLACEMENT CHARACTER
 const UNICODE_REPLACEMENT_CHAR: &[u8] = b"\xEF\xBF\xBD";
 
-#[derive(Debug, PartialEq)]
+/// Where in an HTML document some text is found, which determines how it's decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum HtmlContext {
+    /// An attribute value. A named reference without its trailing semicolon is left as-is
+    /// when followed by an alphanumeric character or `=`, e.g. `?a=1&copy=2`. Numeric
+    /// references decode as they do in text, e.g. `&#38x` becomes `&x`.
     Attribute,
+    /// Text between tags, where every character reference is decoded.
     BodyText,
+    /// Text inside SVG or MathML elements, which is decoded like `BodyText`.
     ForeignText,
+    /// The contents of a SCRIPT element, which are never decoded.
     Script,
+    /// The contents of a STYLE element, which are never decoded.
     Style,
 }
 
+impl HtmlContext {
+    /// Whether character references are decoded in this context at all.
+    pub fn decodes_references(&self) -> bool {
+        !matches!(self, Self::Script | Self::Style)
+    }
+}
+
 pub fn decode(ctx: &HtmlContext, input: &[u8]) -> Box<[u8]> {
-    let mut decoded: Vec<u8> = Vec::new();
-    let end = input.len();
-    let mut at = 0;
+    decode_cow(ctx, input).into_owned().into_boxed_slice()
+}
+
+/// Decodes character references in the input, borrowing the input when it contains none.
+///
+/// Most text contains no character references, so this avoids allocating for it.
+pub fn decode_cow<'a>(ctx: &HtmlContext, input: &'a [u8]) -> Cow<'a, [u8]> {
+    if next_character_reference(ctx, input, 0).is_none() {
+        return Cow::Borrowed(input);
+    }
+
+    let mut decoded: Vec<u8> = Vec::with_capacity(input.len());
+    decode_into(ctx, input, &mut decoded);
+    Cow::Owned(decoded)
+}
+
+/// Decodes character references in the input, appending the decoded text to a buffer.
+///
+/// Reusing one buffer for many inputs avoids allocating for each of them.
+///
+/// Example:
+///
+///     # use entities::{ decode_into, HtmlContext };
+///     let mut scratch = Vec::new();
+///     for text in [ b"1 &#60; 2".as_slice(), b"3 &#62; 2" ] {
+///         scratch.clear();
+///         decode_into( &HtmlContext::BodyText, text, &mut scratch );
+///     }
+///     assert_eq!( b"3 > 2", scratch.as_slice() );
+///
+/// @param HtmlContext $ctx    Where the input is found in the document.
+/// @param string      $input  Text to decode.
+/// @param Vec         $output Receives the decoded text after any existing contents.
+pub fn decode_into(ctx: &HtmlContext, input: &[u8], output: &mut Vec<u8>) {
+    let Ok(()) = for_each_decoded_chunk(ctx, input, |chunk| -> Result<(), Infallible> {
+        output.extend_from_slice(chunk);
+        Ok(())
+    });
+}
+
+/// Decodes character references in the input, writing the decoded text into a sink.
+///
+/// @param HtmlContext $ctx    Where the input is found in the document.
+/// @param string      $input  Text to decode.
+/// @param Write       $writer Receives the decoded text.
+#[cfg(feature = "std")]
+pub fn decode_to_writer(
+    ctx: &HtmlContext,
+    input: &[u8],
+    writer: &mut impl std::io::Write,
+) -> std::io::Result<()> {
+    for_each_decoded_chunk(ctx, input, |chunk| writer.write_all(chunk))
+}
+
+/// Decodes character references in the input, passing the decoded text to a callback in
+/// order, one chunk at a time, without collecting it.
+///
+/// Chunks are spans of the input between character references and the decoded characters
+/// of each reference. The callback's first error stops decoding and is returned.
+pub fn for_each_decoded_chunk<E>(
+    ctx: &HtmlContext,
+    input: &[u8],
+    mut write: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
     let mut was_at = 0;
+    while let Some((at, character_reference, token_len)) =
+        next_character_reference(ctx, input, was_at)
+    {
+        if was_at < at {
+            write(&input[was_at..at])?;
+        }
+        write(&character_reference)?;
+        was_at = at + token_len;
+    }
 
-    while at + 3 < end {
-        let next_character_reference_at = if let Some(pos) = memchr::memchr(b'&', &input[at..]) {
-            at + pos
-        } else {
-            break;
-        };
+    if was_at < input.len() {
+        write(&input[was_at..])?;
+    }
+
+    Ok(())
+}
+
+/// Finds the character references in the input without decoding the text around them.
+///
+/// Only references which decode in the given context are found, so this is useful to
+/// highlight, count, or selectively re-encode them. For example, `a &lt; b` contains
+/// one reference, spanning bytes `2..6`, which decodes to `<`.
+///
+/// @param HtmlContext $ctx   Where the input is found in the document.
+/// @param string      $input Text which may contain character references.
+/// @return CharacterReferences Iterator of the byte range of each reference in the input
+///                             and its decoded characters.
+pub fn find_character_references<'a>(
+    ctx: &HtmlContext,
+    input: &'a [u8],
+) -> CharacterReferences<'a> {
+    CharacterReferences {
+        ctx: *ctx,
+        input,
+        at: 0,
+    }
+}
+
+/// Iterator over the character references in some text.
+///
+/// @see find_character_references()
+#[derive(Debug, Clone)]
+pub struct CharacterReferences<'a> {
+    ctx: HtmlContext,
+    input: &'a [u8],
+    at: usize,
+}
 
-        if let Some((character_reference, token_len)) =
+impl Iterator for CharacterReferences<'_> {
+    type Item = (Range<usize>, Box<[u8]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (starts_at, decoded, token_len) =
+            next_character_reference(&self.ctx, self.input, self.at)?;
+        self.at = starts_at + token_len;
+        Some((starts_at..self.at, decoded))
+    }
+}
+
+/// Finds the next character reference which decodes in the given context.
+///
+/// @return Option<(usize, Box<[u8]>, usize)> Where the reference starts, the decoded
+///                                           characters, and the length of the reference.
+fn next_character_reference(
+    ctx: &HtmlContext,
+    input: &[u8],
+    from: usize,
+) -> Option<(usize, Box<[u8]>, usize)> {
+    if !ctx.decodes_references() {
+        return None;
+    }
+
+    let end = input.len();
+    let mut at = from;
+
+    while let Some(pos) = memchr::memchr(b'&', &input[at..]) {
+        let next_character_reference_at = at + pos;
+
+        // Nothing which follows the `&` can contain another `&`, so even when this isn't
+        // a character reference, the search continues from the next byte.
+        at = next_character_reference_at + 1;
+
+        let Some((character_reference, token_len)) =
             decode_html_ref(ctx, input, next_character_reference_at)
-        {
-            // Do ambiguous checking for attributes.
-            if *ctx == HtmlContext::Attribute {
-                let is_ambiguous_entity_terminator =
-                    input[next_character_reference_at + token_len - 1] != b';';
-
-                // Ambiguous entities are not terminated by a semicolon _and_ have trailing
-                // characters that are alphanumeric or "=".
-                if is_ambiguous_entity_terminator
-                    && (end > next_character_reference_at + token_len
-                        && (input[next_character_reference_at + token_len].is_ascii_alphanumeric()
-                            || input[next_character_reference_at + token_len] == b'='))
-                {
-                    // @todo Can't this skip ahead to next_character_reference_at + 1?
-                    at += 1;
-                    continue;
-                }
+        else {
+            continue;
+        };
+
+        // Do ambiguous checking for attributes. Only named references can be ambiguous;
+        // numeric references decode whatever follows them.
+        if *ctx == HtmlContext::Attribute && input[next_character_reference_at + 1] != b'#' {
+            let after_reference_at = next_character_reference_at + token_len;
+            let is_ambiguous_entity_terminator = input[after_reference_at - 1] != b';';
+
+            // Ambiguous entities are not terminated by a semicolon _and_ have trailing
+            // characters that are alphanumeric or "=".
+            if is_ambiguous_entity_terminator
+                && end > after_reference_at
+                && (input[after_reference_at].is_ascii_alphanumeric()
+                    || input[after_reference_at] == b'=')
+            {
+                continue;
             }
+        }
 
-            at = next_character_reference_at;
-            decoded.extend_from_slice(&input[was_at..at]);
-            decoded.extend_from_slice(&character_reference);
-            at += token_len;
-            was_at = at;
-            continue;
+        return Some((next_character_reference_at, character_reference, token_len));
+    }
+
+    None
+}
+
+/// Decodes text which arrives in chunks, e.g. from a network stream.
+///
+/// A chunk may end in the middle of a character reference, so anything which could still
+/// become part of a reference is held back until the next chunk arrives or decoding
+/// finishes. The decoded text is the same as if all the chunks were decoded at once.
+#[derive(Debug)]
+pub struct StreamingDecoder {
+    ctx: HtmlContext,
+    pending: Vec<u8>,
+}
+
+impl StreamingDecoder {
+    pub fn new(ctx: HtmlContext) -> Self {
+        Self {
+            ctx,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Decodes the next chunk of text, passing the decoded text to the callback.
+    ///
+    /// @see for_each_decoded_chunk()
+    pub fn push<E>(
+        &mut self,
+        chunk: &[u8],
+        write: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let complete_at = self.complete_prefix_length(chunk);
+
+        if self.pending.is_empty() {
+            for_each_decoded_chunk(&self.ctx, &chunk[..complete_at], write)?;
+        } else if complete_at > 0 {
+            let mut input = core::mem::take(&mut self.pending);
+            input.extend_from_slice(&chunk[..complete_at]);
+            for_each_decoded_chunk(&self.ctx, &input, write)?;
         }
 
-        // @todo Can't this skip ahead to next_character_reference_at + 1?
-        at += 1;
+        self.pending.extend_from_slice(&chunk[complete_at..]);
+        Ok(())
+    }
+
+    /// Decodes whatever text was held back, once there are no more chunks.
+    pub fn finish<E>(self, write: impl FnMut(&[u8]) -> Result<(), E>) -> Result<(), E> {
+        for_each_decoded_chunk(&self.ctx, &self.pending, write)
     }
 
-    if was_at < end {
-        decoded.extend_from_slice(&input[was_at..]);
+    /// Returns how much of the next chunk can be decoded, along with anything held back,
+    /// without seeing what follows it.
+    ///
+    /// Character references contain only alphanumerics and `#` before an optional `;`,
+    /// so everything before the last `&` is complete unless that `&` is only followed by
+    /// those characters. In attributes, the character after a reference is also needed,
+    /// which is why a reference missing its semicolon is held back too.
+    ///
+    /// Held-back text is always a single `&` followed by such characters, so only the new
+    /// chunk needs scanning: it either continues that reference or ends it.
+    fn complete_prefix_length(&self, chunk: &[u8]) -> usize {
+        if !self.ctx.decodes_references() {
+            return chunk.len();
+        }
+
+        let continues_reference =
+            |text: &[u8]| text.iter().all(|&c| c.is_ascii_alphanumeric() || c == b'#');
+
+        match memchr::memrchr(b'&', chunk) {
+            Some(at) if continues_reference(&chunk[at + 1..]) => at,
+            None if !self.pending.is_empty() && continues_reference(chunk) => 0,
+            _ => chunk.len(),
+        }
     }
+}
 
-    decoded.into_boxed_slice()
+/// Encodes text so that it decodes back to the same text in the given context.
+///
+/// - `&`, `<`, and `>` are always escaped, so the text cannot start a character
+///   reference or a tag.
+/// - `"` is escaped in attributes, which are assumed to be double-quoted.
+/// - U+0000 NULL is replaced with U+FFFD REPLACEMENT CHARACTER, which is what the
+///   parser would turn it into, since it cannot be represented in HTML.
+/// - U+000D CARRIAGE RETURN is escaped as a numeric character reference, since the
+///   parser normalizes a literal carriage return into a newline.
+/// - Other C0 controls besides ASCII whitespace, U+007F DELETE, and noncharacters such
+///   as U+FDD0 or U+FFFE are escaped as numeric character references. These are parse
+///   errors when they appear literally in a document, and the references decode back
+///   to the same code points. C1 controls stay as they are, because numeric references
+///   to them decode as their windows-1252 counterparts instead, e.g. `&#x80;` is `€`.
+///
+/// Byte sequences which aren't valid UTF-8 are passed through unchanged.
+///
+/// The contents of SCRIPT and STYLE elements are never decoded, so nothing in them
+/// can be escaped and they are returned unchanged. It's up to the caller to ensure that
+/// such text contains no closing tag, e.g. `</script>`, which would otherwise end the
+/// element early; this function does not check for it.
+pub fn encode(ctx: &HtmlContext, input: &[u8]) -> Box<[u8]> {
+    if !ctx.decodes_references() {
+        return input.into();
+    }
+
+    let mut encoded: Vec<u8> = Vec::with_capacity(input.len());
+    for chunk in input.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '&' => encoded.extend_from_slice(b"&amp;"),
+                '<' => encoded.extend_from_slice(b"&lt;"),
+                '>' => encoded.extend_from_slice(b"&gt;"),
+                '"' if *ctx == HtmlContext::Attribute => encoded.extend_from_slice(b"&quot;"),
+                '\0' => encoded.extend_from_slice(UNICODE_REPLACEMENT_CHAR),
+                '\r' => encoded.extend_from_slice(b"&#13;"),
+                _ if is_escaped_code_point(c as u32) => {
+                    encoded.extend_from_slice(alloc::format!("&#x{:X};", c as u32).as_bytes());
+                }
+                _ => {
+                    let mut bytes = [0u8; 4];
+                    encoded.extend_from_slice(c.encode_utf8(&mut bytes).as_bytes());
+                }
+            }
+        }
+        encoded.extend_from_slice(chunk.invalid());
+    }
+
+    encoded.into_boxed_slice()
+}
+
+/// Indicates if a code point must be written as a numeric reference by `encode()`.
+///
+/// These are the C0 controls which aren't ASCII whitespace, U+007F DELETE, and the
+/// noncharacters: U+FDD0 to U+FDEF, and the last two code points of every plane.
+fn is_escaped_code_point(code_point: u32) -> bool {
+    matches!(code_point, 0x01..=0x08 | 0x0B | 0x0E..=0x1F | 0x7F | 0xFDD0..=0xFDEF)
+        || (code_point & 0xFFFE) == 0xFFFE
+}
+
+/// Finds the preferred named character reference for a character, if it has one.
+///
+/// Serializers may prefer named references over numeric ones because they're easier to
+/// read. Characters which are only part of a named reference, like the combining long
+/// solidus in `&nvlt;`, don't have one of their own.
+///
+/// Example:
+///
+///     # use entities::encode_named;
+///     assert_eq!( Some( "&amp;" ), encode_named( '&' ) );
+///     assert_eq!( Some( "&nbsp;" ), encode_named( '\u{A0}' ) );
+///     assert_eq!( None, encode_named( 'a' ) );
+///
+/// @param char $character Character to encode.
+/// @return Option<&str> Shortest named reference for the character, including `&` and `;`.
+#[cfg(feature = "named-character-references")]
+pub fn encode_named(character: char) -> Option<&'static str> {
+    let named_references =
+        gen_entities::named_references_lookup!("crates/entities/data/entities.json");
+
+    named_references
+        .binary_search_by_key(&character, |(character, _)| *character)
+        .map(|index| named_references[index].1)
+        .ok()
 }
 
 /// Decodes a reference to an HTML entity.
-/// @todo Ambiguous entitites based on ctx?
+///
+/// Named references are only decoded with the `named-character-references` feature,
+/// which includes the table of entities. Without it, only numeric references decode.
+///
+/// No reference is found in contexts which are never decoded, such as SCRIPT contents.
+/// References in attributes which are ambiguous are still decoded here; it's up to the
+/// caller to check what follows the reference, since that lies outside of the match.
 pub fn decode_html_ref(
     ctx: &HtmlContext,
     input: &[u8],
     offset: usize,
 ) -> Option<(Box<[u8]>, usize)> {
-    if input.len() < offset + 3 {
+    if !ctx.decodes_references() || input.len() < offset + 3 {
         return None;
     }
 
@@ -82,10 +392,29 @@ pub fn decode_html_ref(
         return decode_html5_numeric_character_reference(input, offset);
     }
 
+    #[cfg(feature = "named-character-references")]
+    {
+        decode_html5_named_character_reference(input, offset)
+    }
+
+    #[cfg(not(feature = "named-character-references"))]
+    {
+        None
+    }
+}
+
+#[cfg(feature = "named-character-references")]
+fn decode_html5_named_character_reference(
+    input: &[u8],
+    offset: usize,
+) -> Option<(Box<[u8]>, usize)> {
     let prefix = [input[offset + 1], input[offset + 2]];
-    gen_entities::entities_lookup!("crates/entities/data/entities.json");
+    let entities = gen_entities::entities_lookup!("crates/entities/data/entities.json");
 
-    let candidates = ENTITIES.get(&prefix)?;
+    let candidates = entities
+        .binary_search_by_key(&prefix, |(prefix, _)| *prefix)
+        .map(|index| entities[index].1)
+        .ok()?;
     candidates
         .iter()
         .find_map(|(suffix, decoded_bytes)| -> Option<(Box<[u8]>, usize)> {
@@ -258,64 +587,23 @@ fn decode_html5_numeric_character_reference(
         return Some((UNICODE_REPLACEMENT_CHAR.into(), matched_byte_length));
     }
 
-    /* Should these be disallowed here???
-
-    // A noncharacter is a code point that is in the range U+FDD0 to U+FDEF, inclusive,
-    // or U+FFFE, U+FFFF, U+1FFFE, U+1FFFF, U+2FFFE, U+2FFFF, U+3FFFE, U+3FFFF,
-    // U+4FFFE, U+4FFFF, U+5FFFE, U+5FFFF, U+6FFFE, U+6FFFF, U+7FFFE, U+7FFFF,
-    // U+8FFFE, U+8FFFF, U+9FFFE, U+9FFFF, U+AFFFE, U+AFFFF, U+BFFFE, U+BFFFF,
-    // U+CFFFE, U+CFFFF, U+DFFFE, U+DFFFF, U+EFFFE, U+EFFFF, U+FFFFE, U+FFFFF,
-    // U+10FFFE, or U+10FFFF.
-    if matches!(
-        code_point,
-        0xFDD0
-            ..=0xFDEF
-                | 0xFFFE
-                | 0xFFFF
-                | 0x1FFFE
-                | 0x1FFFF
-                | 0x2FFFE
-                | 0x2FFFF
-                | 0x3FFFE
-                | 0x3FFFF
-                | 0x4FFFE
-                | 0x4FFFF
-                | 0x5FFFE
-                | 0x5FFFF
-                | 0x6FFFE
-                | 0x6FFFF
-                | 0x7FFFE
-                | 0x7FFFF
-                | 0x8FFFE
-                | 0x8FFFF
-                | 0x9FFFE
-                | 0x9FFFF
-                | 0xAFFFE
-                | 0xAFFFF
-                | 0xBFFFE
-                | 0xBFFFF
-                | 0xCFFFE
-                | 0xCFFFF
-                | 0xDFFFE
-                | 0xDFFFF
-                | 0xEFFFE
-                | 0xEFFFF
-                | 0xFFFFE
-                | 0xFFFFF
-                | 0x10FFFE
-                | 0x10FFFF
-    ) {
-        return Some((UNICODE_REPLACEMENT_CHAR.into(), matched_byte_length));
-    }
-
-    // > A control is a C0 control or a code point in the range
-    // > U+007F DELETE to U+009F APPLICATION PROGRAM COMMAND, inclusive.
-    if matches!(code_point, 0x007F..=0x009F) {
-        return Some((UNICODE_REPLACEMENT_CHAR.into(), matched_byte_length));
-    }
-
-    */
-
+    /*
+     * Noncharacters, e.g. U+FDD0 or U+FFFF, and controls other than ASCII whitespace,
+     * including U+000D CARRIAGE RETURN, are parse errors, but the parser keeps their
+     * code points. Only NULL, which is only ever written as zeros and handled above,
+     * surrogates, and values outside of Unicode are replaced. This is the same in every
+     * context which decodes references: the attribute rule for a missing semicolon only
+     * applies to named references.
+     *
+     * > If the number is 0x00, then this is a null-character-reference parse error.
+     * > Set the character reference code to 0xFFFD.
+     * > If the number is a noncharacter, then this is a noncharacter-character-reference
+     * > parse error.
+     * > If the number is 0x0D, or a control that's not ASCII whitespace, then this is a
+     * > control-character-reference parse error.
+     *
+     * @see https://html.spec.whatwg.org/#numeric-character-reference-end-state
+     */
     Some((
         html5_code_point_to_utf8_bytes(code_point),
         matched_byte_length,
@@ -351,6 +639,7 @@ mod tests {
         assert_eq!(token_len, 10);
     }
 
+    #[cfg(feature = "named-character-references")]
     #[test]
     fn test_decode_html() {
         let input = b"&LT";
@@ -359,6 +648,69 @@ mod tests {
         assert_eq!(len, 3);
     }
 
+    /// Every entity in the WHATWG list decodes to its characters.
+    ///
+    /// @see https://html.spec.whatwg.org/entities.json
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_every_named_entity() {
+        let cases = gen_entities::entities_test_cases!("crates/entities/data/entities.json");
+        assert_eq!(cases.len(), 2231);
+
+        for (name, characters) in cases {
+            let has_semicolon = name.ends_with(';');
+            let characters = characters.as_bytes();
+
+            assert_eq!(
+                decode_html_ref(&HtmlContext::BodyText, name.as_bytes(), 0),
+                Some((characters.into(), name.len())),
+                "{name}"
+            );
+
+            // As the entire input.
+            assert_eq!(
+                &*decode(&HtmlContext::BodyText, name.as_bytes()),
+                characters,
+                "{name} alone"
+            );
+
+            // At the end of the input, and followed by more text.
+            let at_end = [b"text ", name.as_bytes()].concat();
+            let followed = [name.as_bytes(), b" text"].concat();
+            for ctx in [HtmlContext::Attribute, HtmlContext::BodyText] {
+                assert_eq!(
+                    &*decode(&ctx, &at_end),
+                    [b"text ", characters].concat(),
+                    "{name} at end in {ctx:?}"
+                );
+                assert_eq!(
+                    &*decode(&ctx, &followed),
+                    [characters, b" text"].concat(),
+                    "{name} followed by text in {ctx:?}"
+                );
+            }
+
+            // Attribute values keep ambiguous references which lack their semicolon.
+            let ambiguous = [name.as_bytes(), b"=1"].concat();
+            assert_eq!(
+                &*decode(&HtmlContext::BodyText, &ambiguous),
+                [characters, b"=1"].concat(),
+                "{name} followed by = in text"
+            );
+            let expected = if has_semicolon {
+                [characters, b"=1"].concat()
+            } else {
+                ambiguous.clone()
+            };
+            assert_eq!(
+                &*decode(&HtmlContext::Attribute, &ambiguous),
+                expected,
+                "{name} followed by = in attribute"
+            );
+        }
+    }
+
+    #[cfg(feature = "named-character-references")]
     #[test]
     fn test_aelig_entity() {
         let (decoded, token_len) = decode_html_ref(&HtmlContext::BodyText, b"&AElig;", 0).unwrap();
@@ -367,6 +719,7 @@ mod tests {
         assert_eq!(token_len, 7);
     }
 
+    #[cfg(feature = "named-character-references")]
     #[test]
     fn test_named_entities() {
         // Common named entities
@@ -464,6 +817,39 @@ mod tests {
         );
     }
 
+    /// Cases from html5lib's tokenizer tests, `numericEntities.test`.
+    #[test]
+    fn test_numeric_noncharacters_and_controls() {
+        for (input, expected) in [
+            ("&#x0000;", "\u{FFFD}"),
+            ("&#0;", "\u{FFFD}"),
+            ("&#x0001;", "\u{0001}"),
+            ("&#x000B;", "\u{000B}"),
+            ("&#x000D;", "\r"),
+            ("&#x007F;", "\u{007F}"),
+            ("&#x0081;", "\u{0081}"),
+            ("&#x008D;", "\u{008D}"),
+            ("&#x009F;", "\u{0178}"),
+            ("&#xFDD0;", "\u{FDD0}"),
+            ("&#xFDEF;", "\u{FDEF}"),
+            ("&#xFFFE;", "\u{FFFE}"),
+            ("&#x1FFFF;", "\u{1FFFF}"),
+            ("&#x10FFFF;", "\u{10FFFF}"),
+            ("&#x110000;", "\u{FFFD}"),
+            ("&#1114112;", "\u{FFFD}"),
+            ("&#xD800;", "\u{FFFD}"),
+            ("&#xDFFF;", "\u{FFFD}"),
+        ] {
+            for ctx in [HtmlContext::Attribute, HtmlContext::BodyText] {
+                assert_eq!(
+                    decode_html_ref(&ctx, input.as_bytes(), 0),
+                    Some((expected.as_bytes().into(), input.len())),
+                    "{input} in {ctx:?}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_cp1252_replacements() {
         // Test CP1252 replacement for code point 0x80 (EURO SIGN)
@@ -509,6 +895,7 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "named-character-references")]
     #[test]
     fn test_entity_with_offset() {
         let input = b"text&amp;more";
@@ -521,6 +908,202 @@ mod tests {
         assert_eq!(decode_html_ref(&HtmlContext::BodyText, input, 10), None);
     }
 
+    #[test]
+    fn test_decode_cow() {
+        for input in [b"".as_slice(), b"plain text", b"a & b", b"&#;", b"&#x;&"] {
+            assert!(matches!(
+                decode_cow(&HtmlContext::BodyText, input),
+                Cow::Borrowed(borrowed) if borrowed == input
+            ));
+        }
+
+        let decoded = decode_cow(&HtmlContext::Attribute, b"a &#x26; b");
+        assert!(matches!(decoded, Cow::Owned(_)));
+        assert_eq!(&*decoded, b"a & b");
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_decode_contexts() {
+        let input = b"a &lt; b &copy=2 &#x26;";
+        assert_eq!(
+            &*decode(&HtmlContext::BodyText, input),
+            "a < b ©=2 &".as_bytes()
+        );
+        assert_eq!(
+            &*decode(&HtmlContext::ForeignText, input),
+            "a < b ©=2 &".as_bytes()
+        );
+        assert_eq!(&*decode(&HtmlContext::Attribute, input), b"a < b &copy=2 &");
+        assert_eq!(&*decode(&HtmlContext::Script, input), input);
+
+        // Numeric references without a semicolon decode even when alphanumerics follow.
+        for ctx in [HtmlContext::Attribute, HtmlContext::BodyText] {
+            assert_eq!(&*decode(&ctx, b"&#38x"), b"&x");
+            assert_eq!(&*decode(&ctx, b"&#x26=1"), b"&=1");
+            assert_eq!(&*decode(&ctx, b"&#106avascript:x"), b"javascript:x");
+        }
+        assert_eq!(&*decode(&HtmlContext::Style, input), input);
+        assert_eq!(decode_html_ref(&HtmlContext::Script, b"&lt;", 0), None);
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_streaming_decoder() {
+        let input = b"Fish &amp; Chips &#x26;&copy=2 &notin; &#128512; &";
+
+        for ctx in [HtmlContext::Attribute, HtmlContext::BodyText] {
+            let expected = decode(&ctx, input);
+
+            for chunk_size in 1..input.len() {
+                let mut decoded = Vec::new();
+                let mut write = |chunk: &[u8]| -> Result<(), Infallible> {
+                    decoded.extend_from_slice(chunk);
+                    Ok(())
+                };
+
+                let mut decoder = StreamingDecoder::new(ctx);
+                for chunk in input.chunks(chunk_size) {
+                    let Ok(()) = decoder.push(chunk, &mut write);
+                }
+                let Ok(()) = decoder.finish(&mut write);
+                assert_eq!(decoded, &*expected, "{ctx:?} in chunks of {chunk_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_decoder_long_reference() {
+        // A reference split across many chunks is held back until it ends.
+        let input = [b"a &#".as_slice(), &[b'0'; 4096], b"65;b &#x4"].concat();
+        let mut decoded = Vec::new();
+        let mut write = |chunk: &[u8]| -> Result<(), Infallible> {
+            decoded.extend_from_slice(chunk);
+            Ok(())
+        };
+
+        let mut decoder = StreamingDecoder::new(HtmlContext::BodyText);
+        for chunk in input.chunks(1) {
+            let Ok(()) = decoder.push(chunk, &mut write);
+        }
+        let Ok(()) = decoder.finish(&mut write);
+        assert_eq!(decoded, b"a Ab \x04");
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_encode_named() {
+        assert_eq!(encode_named('&'), Some("&amp;"));
+        assert_eq!(encode_named('<'), Some("&lt;"));
+        assert_eq!(encode_named('"'), Some("&quot;"));
+        assert_eq!(encode_named('\u{A0}'), Some("&nbsp;"));
+        assert_eq!(encode_named('\u{2190}'), Some("&larr;"));
+        assert_eq!(encode_named('a'), None);
+        assert_eq!(encode_named('\u{1F600}'), None);
+
+        // Every preferred name decodes back to its character.
+        for character in ['&', '\u{A0}', '\u{C6}', '\u{2211}', '\u{1D504}'] {
+            let name = encode_named(character).unwrap();
+            let (decoded, len) = decode_html_ref(&HtmlContext::BodyText, name.as_bytes(), 0)
+                .expect("Named reference should decode");
+            assert_eq!(&*decoded, character.to_string().as_bytes());
+            assert_eq!(len, name.len());
+        }
+    }
+
+    #[test]
+    fn test_find_character_references() {
+        let input = b"&#60;a &#38;amp; &#;&#0 &#x2764;&#xFE0F;";
+        let found: Vec<_> = find_character_references(&HtmlContext::BodyText, input).collect();
+        assert_eq!(
+            found,
+            [
+                (0..5, b"<".as_slice().into()),
+                (7..12, b"&".as_slice().into()),
+                (20..23, "\u{FFFD}".as_bytes().into()),
+                (24..32, "\u{2764}".as_bytes().into()),
+                (32..40, "\u{FE0F}".as_bytes().into()),
+            ]
+        );
+        assert_eq!(
+            find_character_references(&HtmlContext::Style, input).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_decode_at_input_boundaries() {
+        assert_eq!(&*decode(&HtmlContext::BodyText, b"&#9"), b"\t");
+        assert_eq!(&*decode(&HtmlContext::BodyText, b"x&#9"), b"x\t");
+        assert_eq!(&*decode(&HtmlContext::BodyText, b"&#"), b"&#");
+        assert_eq!(&*decode(&HtmlContext::BodyText, b"&&&#9&"), b"&&\t&");
+        assert_eq!(&*decode(&HtmlContext::Attribute, b"&#9;&#9"), b"\t\t");
+    }
+
+    /// Inputs full of `&` which never start a reference are scanned in linear time.
+    #[test]
+    fn test_decode_dense_ampersands() {
+        let input = b"&#&x".repeat(250_000);
+        assert_eq!(&*decode(&HtmlContext::Attribute, &input), input.as_slice());
+        assert_eq!(
+            find_character_references(&HtmlContext::BodyText, &input).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let mut decoded = b"kept ".to_vec();
+        decode_into(&HtmlContext::Attribute, b"&#60;&#62;", &mut decoded);
+        assert_eq!(decoded, b"kept <>");
+
+        let capacity = decoded.capacity();
+        decoded.clear();
+        decode_into(&HtmlContext::BodyText, b"&#62;", &mut decoded);
+        assert_eq!(decoded, b">");
+        assert_eq!(decoded.capacity(), capacity);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_decode_to_writer() {
+        let mut decoded = Vec::new();
+        decode_to_writer(&HtmlContext::BodyText, b"a &#60; b", &mut decoded).unwrap();
+        assert_eq!(decoded, b"a < b");
+    }
+
+    #[test]
+    fn test_encode() {
+        let input = b"<a href=\"?x&y\">'\0'\r\n</a>";
+        assert_eq!(
+            &*encode(&HtmlContext::BodyText, input),
+            b"&lt;a href=\"?x&amp;y\"&gt;'\xEF\xBF\xBD'&#13;\n&lt;/a&gt;"
+        );
+        assert_eq!(
+            &*encode(&HtmlContext::Attribute, input),
+            b"&lt;a href=&quot;?x&amp;y&quot;&gt;'\xEF\xBF\xBD'&#13;\n&lt;/a&gt;"
+        );
+        assert_eq!(&*encode(&HtmlContext::Script, input), input);
+
+        // Controls and noncharacters are escaped; C1 controls and invalid bytes aren't.
+        let input = "\u{1}\t\u{B}\u{7F}\u{80}\u{FDD0}\u{FFFE}\u{FFFF}\u{10FFFF}\u{2764}\u{FFFD}";
+        let expected = "&#x1;\t&#xB;&#x7F;\u{80}&#xFDD0;&#xFFFE;&#xFFFF;&#x10FFFF;\u{2764}\u{FFFD}";
+        for ctx in [HtmlContext::Attribute, HtmlContext::BodyText] {
+            assert_eq!(&*encode(&ctx, input.as_bytes()), expected.as_bytes());
+            assert_eq!(&*decode(&ctx, expected.as_bytes()), input.as_bytes());
+        }
+        assert_eq!(
+            &*encode(&HtmlContext::BodyText, b"a\xFF\xE2\x9C<"),
+            b"a\xFF\xE2\x9C&lt;"
+        );
+
+        #[cfg(feature = "named-character-references")]
+        for ctx in [HtmlContext::Attribute, HtmlContext::BodyText] {
+            let text = b"&amp; &#x26; <!-- \"quoted\" -->\r\n";
+            assert_eq!(&*decode(&ctx, &encode(&ctx, text)), text);
+        }
+    }
+
     #[test]
     fn test_non_entity_input() {
         // No & character
@@ -533,6 +1116,7 @@ mod tests {
         assert_eq!(decode_html_ref(&HtmlContext::BodyText, b"&AE;", 0), None);
     }
 
+    #[cfg(feature = "named-character-references")]
     #[test]
     fn test_php_reference_cases() {
         // Test cases from PHP's decode_html_ref_1.phpt file
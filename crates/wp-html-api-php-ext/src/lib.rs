@@ -11,8 +11,8 @@ use ext_php_rs::{
 };
 use std::ops::Deref;
 use wp_html_api::tag_processor::{
-    AttributeValue, NextTagQuery, NodeName, ParserState, ParsingNamespace, TagClosers,
-    TagProcessor, TokenType,
+    AttributeValue, HtmlTextReplacement, NextTagQuery, NodeName, ParserState, ParsingNamespace,
+    TagClosers, TagProcessor, TokenType,
 };
 use wp_html_api::{doctype::HtmlDoctypeInfo, tag_name::TagName};
 use wp_html_api::{html_processor::HtmlProcessor, tag_processor::CommentType};
@@ -24,7 +24,7 @@ extern "C" fn startup_function(_ty: i32, _module_number: i32) -> i32 {
 #[php_class]
 #[php(change_case = "none")]
 pub struct WP_HTML_Tag_Processor {
-    processor: TagProcessor,
+    processor: TagProcessor<'static>,
 }
 
 #[php_impl]
@@ -562,10 +562,33 @@ impl WP_HTML_Processor {
 
 #[php_class]
 #[php(change_case = "none")]
-struct WP_HTML_Text_Replacement {
-    start: usize,
-    length: usize,
-    text: Binary<u8>,
+pub struct WP_HTML_Text_Replacement {
+    internal: HtmlTextReplacement,
+}
+
+#[php_impl]
+#[php(change_method_case = "none", change_constant_case = "none")]
+impl WP_HTML_Text_Replacement {
+    pub fn __construct(start: usize, length: usize, text: BinarySlice<u8>) -> Self {
+        Self {
+            internal: HtmlTextReplacement::new(start, length, &text),
+        }
+    }
+
+    #[php(getter)]
+    pub fn start(&self) -> usize {
+        self.internal.start
+    }
+
+    #[php(getter)]
+    pub fn length(&self) -> usize {
+        self.internal.length
+    }
+
+    #[php(getter)]
+    pub fn text(&self) -> Binary<u8> {
+        self.internal.text.to_vec().into()
+    }
 }
 
 #[php_class]
@@ -1,6 +1,7 @@
 #![allow(non_camel_case_types, unused_macros)]
 
 extern crate wasm_bindgen;
+#[cfg(feature = "html-processor")]
 use wp_html_api::html_processor::HtmlProcessor;
 use wp_html_api::tag_processor::{AttributeValue, NodeName, TagProcessor, TokenType};
 
@@ -21,7 +22,7 @@ macro_rules! console_log {
 
 #[wasm_bindgen]
 pub struct WP_HTML_Tag_Processor {
-    processor: TagProcessor,
+    processor: TagProcessor<'static>,
 }
 
 #[wasm_bindgen]
@@ -71,11 +72,13 @@ impl WP_HTML_Tag_Processor {
     }
 }
 
+#[cfg(feature = "html-processor")]
 #[wasm_bindgen]
 pub struct WP_HTML_Processor {
     processor: HtmlProcessor,
 }
 
+#[cfg(feature = "html-processor")]
 #[wasm_bindgen]
 impl WP_HTML_Processor {
     pub fn create_full_parser(
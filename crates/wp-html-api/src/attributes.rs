@@ -1,5 +1,79 @@
+pub mod srcset;
+pub mod style;
+
+use alloc::{boxed::Box, sync::Arc};
+use core::hash::{BuildHasher, Hasher};
+
+use hashbrown::HashTable;
+use rustc_hash::FxBuildHasher;
+
 use crate::tag_processor::ParsingNamespace;
 
+/// Most distinct attribute names kept by an interner before it starts over.
+const MAX_INTERNED_ATTRIBUTE_NAMES: usize = 4096;
+
+/// Stores a single copy of each attribute name found in a document.
+///
+/// Real documents repeat the same handful of attribute names many times. Interning
+/// them means each occurrence shares one allocation instead of boxing a new copy,
+/// and names can be compared by pointer. Names are interned by their ASCII
+/// case-insensitive spelling in lower case.
+///
+/// Documents with endless distinct names, e.g. generated `data-` attributes, would
+/// otherwise grow the table without bound, so it's cleared once it's full.
+///
+/// @see TagNameInterner
+#[derive(Default, Clone)]
+pub(crate) struct AttributeNameInterner {
+    names: HashTable<Arc<[u8]>>,
+}
+
+impl AttributeNameInterner {
+    pub(crate) fn intern(&mut self, name: &[u8]) -> Arc<[u8]> {
+        let hash = hash_ignoring_ascii_case(name);
+        if let Some(interned) = self
+            .names
+            .find(hash, |interned| interned.eq_ignore_ascii_case(name))
+        {
+            return interned.clone();
+        }
+
+        if self.names.len() >= MAX_INTERNED_ATTRIBUTE_NAMES {
+            self.names.clear();
+        }
+
+        let interned: Arc<[u8]> = name.to_ascii_lowercase().into();
+        self.names
+            .insert_unique(hash, interned.clone(), |interned| {
+                hash_ignoring_ascii_case(interned)
+            });
+        interned
+    }
+
+    /// Returns how many names are interned.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Forgets the interned names, keeping the allocated table for the next document.
+    pub(crate) fn clear(&mut self) {
+        self.names.clear();
+    }
+}
+
+/// Hashes an attribute name so that names differing only in ASCII case collide.
+///
+/// @param string $name Attribute name, in any case.
+/// @return int Hash of the lower-cased name.
+pub(crate) fn hash_ignoring_ascii_case(name: &[u8]) -> u64 {
+    let mut hasher = FxBuildHasher.build_hasher();
+    for c in name {
+        hasher.write_u8(c.to_ascii_lowercase());
+    }
+    hasher.finish()
+}
+
 pub fn qualified_attribute_name(attribute_name: &[u8], ns: &ParsingNamespace) -> Box<[u8]> {
     let lower_name = attribute_name.to_ascii_lowercase();
 
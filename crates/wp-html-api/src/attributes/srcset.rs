@@ -0,0 +1,497 @@
+//! Parsing and serialization for the `srcset` and `sizes` attributes of IMG and SOURCE.
+//!
+//! These functions operate on decoded attribute values, as returned by `get_attribute()`.
+//! Serialized output is also a plain value and must be encoded when written back into HTML.
+//!
+//! Example:
+//!
+//!     let mut candidates = parse_srcset( b"a.jpg 1x, b.jpg 2x" );
+//!     candidates[1].url = b"https://cdn.example.com/b.jpg".as_slice().into();
+//!     b"a.jpg 1x, https://cdn.example.com/b.jpg 2x" == serialize_srcset( &candidates );
+//!
+//! @see https://html.spec.whatwg.org/#srcset-attributes
+//! @see https://html.spec.whatwg.org/#sizes-attributes
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::fmt::Write;
+
+/// An image candidate from a `srcset` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageCandidate {
+    pub url: Box<[u8]>,
+    pub descriptor: ImageCandidateDescriptor,
+}
+
+/// Describes the size of an image candidate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageCandidateDescriptor {
+    /// No descriptor was given, which is equivalent to a pixel density of `1x`.
+    None,
+
+    /// A width descriptor, e.g. `480w`, with an optional future-compatible height, e.g. `360h`.
+    Width { width: u32, height: Option<u32> },
+
+    /// A pixel density descriptor, e.g. `1.5x`.
+    Density(f64),
+}
+
+/// An entry from a `sizes` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSize {
+    /// Media condition under which the size applies, e.g. `(max-width: 600px)`.
+    ///
+    /// The last entry in a `sizes` attribute usually has no media condition.
+    pub media_condition: Option<Box<[u8]>>,
+
+    /// The source size value, e.g. `480px`, `calc(100vw - 2em)`, or `auto`.
+    pub size: Box<[u8]>,
+}
+
+/// Parses a `srcset` attribute value into a list of image candidates.
+///
+/// Candidates with invalid descriptors are dropped, as they are by browsers.
+///
+/// Example:
+///
+///     [ ( "a.jpg", Width { 480, None } ), ( "b.jpg", Width { 800, None } ) ] == parse_srcset( b"a.jpg 480w, b.jpg 800w" );
+///
+/// @see https://html.spec.whatwg.org/#parse-a-srcset-attribute
+///
+/// @param string $input Decoded value of the `srcset` attribute.
+/// @return array List of valid image candidates.
+pub fn parse_srcset(input: &[u8]) -> Vec<ImageCandidate> {
+    let mut candidates = Vec::new();
+    let end = input.len();
+    let mut at = 0;
+
+    loop {
+        // > Splitting loop: Collect a sequence of code points that are ASCII whitespace or
+        // > U+002C COMMA characters from input given position.
+        at += strspn!(input, b' ' | b'\t' | b'\n' | 0x0c | b'\r' | b',', at);
+        if at >= end {
+            return candidates;
+        }
+
+        let url_length = strcspn!(input, b' ' | b'\t' | b'\n' | 0x0c | b'\r', at);
+        let mut url = &input[at..at + url_length];
+        at += url_length;
+
+        let mut descriptors: Vec<&[u8]> = Vec::new();
+
+        // > If url ends with U+002C (,), then remove all trailing U+002C COMMA characters
+        // > from url. Otherwise, run the descriptor tokenizer.
+        if url.ends_with(b",") {
+            while let Some(trimmed) = url.strip_suffix(b",") {
+                url = trimmed;
+            }
+        } else {
+            at = tokenize_descriptors(input, at, &mut descriptors);
+        }
+
+        if let Some(descriptor) = parse_descriptors(&descriptors) {
+            candidates.push(ImageCandidate {
+                url: url.into(),
+                descriptor,
+            });
+        }
+    }
+}
+
+/// Serializes a list of image candidates as a `srcset` attribute value.
+///
+/// @param array $candidates Image candidates, e.g. as returned by `parse_srcset()`.
+/// @return string Value for the `srcset` attribute, not yet HTML-encoded.
+pub fn serialize_srcset(candidates: &[ImageCandidate]) -> Box<[u8]> {
+    let mut output = Vec::new();
+    let mut descriptor = String::new();
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        if i > 0 {
+            output.extend_from_slice(b", ");
+        }
+        output.extend_from_slice(&candidate.url);
+
+        descriptor.clear();
+        let _ = match candidate.descriptor {
+            ImageCandidateDescriptor::None => Ok(()),
+            ImageCandidateDescriptor::Width {
+                width,
+                height: None,
+            } => write!(descriptor, " {width}w"),
+            ImageCandidateDescriptor::Width {
+                width,
+                height: Some(height),
+            } => write!(descriptor, " {width}w {height}h"),
+            ImageCandidateDescriptor::Density(density) => write!(descriptor, " {density}x"),
+        };
+        output.extend_from_slice(descriptor.as_bytes());
+    }
+
+    output.into()
+}
+
+/// Parses a `sizes` attribute value into its list of source sizes.
+///
+/// This splits the list on commas which aren't nested inside parentheses and separates
+/// each media condition from its size. It does not validate the media conditions or
+/// lengths, which requires a full CSS parser; empty entries are skipped.
+///
+/// Example:
+///
+///     [ ( Some( "(max-width: 600px)" ), "480px" ), ( None, "800px" ) ] == parse_sizes( b"(max-width: 600px) 480px, 800px" );
+///
+/// @see https://html.spec.whatwg.org/#parse-a-sizes-attribute
+///
+/// @param string $input Decoded value of the `sizes` attribute.
+/// @return array List of source sizes.
+pub fn parse_sizes(input: &[u8]) -> Vec<SourceSize> {
+    let mut sizes = Vec::new();
+    let mut depth = 0usize;
+    let mut entry_start = 0;
+
+    for (at, &c) in input.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            b',' if 0 == depth => {
+                sizes.extend(parse_source_size(&input[entry_start..at]));
+                entry_start = at + 1;
+            }
+            _ => {}
+        }
+    }
+    sizes.extend(parse_source_size(&input[entry_start..]));
+
+    sizes
+}
+
+/// Serializes a list of source sizes as a `sizes` attribute value.
+///
+/// @param array $sizes Source sizes, e.g. as returned by `parse_sizes()`.
+/// @return string Value for the `sizes` attribute, not yet HTML-encoded.
+pub fn serialize_sizes(sizes: &[SourceSize]) -> Box<[u8]> {
+    let mut output = Vec::new();
+
+    for (i, source_size) in sizes.iter().enumerate() {
+        if i > 0 {
+            output.extend_from_slice(b", ");
+        }
+        if let Some(media_condition) = &source_size.media_condition {
+            output.extend_from_slice(media_condition);
+            output.push(b' ');
+        }
+        output.extend_from_slice(&source_size.size);
+    }
+
+    output.into()
+}
+
+/// Runs the descriptor tokenizer from the srcset parsing algorithm.
+///
+/// Returns the position in the input after the candidate's descriptors.
+fn tokenize_descriptors<'a>(
+    input: &'a [u8],
+    mut at: usize,
+    descriptors: &mut Vec<&'a [u8]>,
+) -> usize {
+    enum State {
+        InDescriptor,
+        InParens,
+        AfterDescriptor,
+    }
+
+    at += strspn!(input, b' ' | b'\t' | b'\n' | 0x0c | b'\r', at);
+    let mut state = State::InDescriptor;
+    let mut descriptor_start = at;
+
+    loop {
+        let c = input.get(at).copied();
+        match state {
+            State::InDescriptor => match c {
+                Some(b' ' | b'\t' | b'\n' | 0x0c | b'\r') => {
+                    if at > descriptor_start {
+                        descriptors.push(&input[descriptor_start..at]);
+                    }
+                    state = State::AfterDescriptor;
+                }
+                Some(b',') => {
+                    if at > descriptor_start {
+                        descriptors.push(&input[descriptor_start..at]);
+                    }
+                    return at + 1;
+                }
+                Some(b'(') => state = State::InParens,
+                Some(_) => {}
+                None => {
+                    if at > descriptor_start {
+                        descriptors.push(&input[descriptor_start..at]);
+                    }
+                    return at;
+                }
+            },
+
+            State::InParens => match c {
+                Some(b')') => state = State::InDescriptor,
+                Some(_) => {}
+                None => {
+                    descriptors.push(&input[descriptor_start..at]);
+                    return at;
+                }
+            },
+
+            State::AfterDescriptor => match c {
+                Some(b' ' | b'\t' | b'\n' | 0x0c | b'\r') => {}
+                Some(_) => {
+                    // Reconsume the character in the descriptor state.
+                    state = State::InDescriptor;
+                    descriptor_start = at;
+                    continue;
+                }
+                None => return at,
+            },
+        }
+
+        at += 1;
+    }
+}
+
+/// Turns a candidate's descriptors into its size, or `None` if they are invalid.
+fn parse_descriptors(descriptors: &[&[u8]]) -> Option<ImageCandidateDescriptor> {
+    let mut width: Option<u32> = None;
+    let mut density: Option<f64> = None;
+    let mut height: Option<u32> = None;
+
+    for descriptor in descriptors {
+        let (&last, value) = descriptor.split_last()?;
+        match last {
+            b'w' if width.is_none() && density.is_none() => {
+                width = Some(parse_non_negative_integer(value).filter(|&w| w > 0)?);
+            }
+            b'x' if width.is_none() && density.is_none() && height.is_none() => {
+                density = Some(parse_floating_point_number(value).filter(|&d| d >= 0.0)?);
+            }
+            b'h' if height.is_none() && density.is_none() => {
+                height = Some(parse_non_negative_integer(value).filter(|&h| h > 0)?);
+            }
+            _ => return None,
+        }
+    }
+
+    match (width, density, height) {
+        (None, None, None) => Some(ImageCandidateDescriptor::None),
+        (Some(width), None, height) => Some(ImageCandidateDescriptor::Width { width, height }),
+        (None, Some(density), None) => Some(ImageCandidateDescriptor::Density(density)),
+        _ => None,
+    }
+}
+
+/// Parses a valid non-negative integer, which is one or more ASCII digits.
+///
+/// @see https://html.spec.whatwg.org/#valid-non-negative-integer
+fn parse_non_negative_integer(value: &[u8]) -> Option<u32> {
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+
+    core::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// Parses a valid floating-point number.
+///
+/// These are stricter than Rust's float syntax: there is no leading `+`, no `inf` or `NaN`,
+/// and a decimal point must be followed by digits.
+///
+/// @see https://html.spec.whatwg.org/#valid-floating-point-number
+fn parse_floating_point_number(value: &[u8]) -> Option<f64> {
+    let mut at = 0;
+    if value.first() == Some(&b'-') {
+        at += 1;
+    }
+
+    let integer_digits = strspn!(value, b'0'..=b'9', at);
+    at += integer_digits;
+
+    let mut fraction_digits = 0;
+    if value.get(at) == Some(&b'.') {
+        fraction_digits = strspn!(value, b'0'..=b'9', at + 1);
+        if 0 == fraction_digits {
+            return None;
+        }
+        at += 1 + fraction_digits;
+    }
+
+    if 0 == integer_digits && 0 == fraction_digits {
+        return None;
+    }
+
+    if matches!(value.get(at), Some(b'e' | b'E')) {
+        at += 1;
+        if matches!(value.get(at), Some(b'+' | b'-')) {
+            at += 1;
+        }
+        let exponent_digits = strspn!(value, b'0'..=b'9', at);
+        if 0 == exponent_digits {
+            return None;
+        }
+        at += exponent_digits;
+    }
+
+    if at != value.len() {
+        return None;
+    }
+
+    core::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// Splits a single entry of a `sizes` attribute into its media condition and size.
+fn parse_source_size(entry: &[u8]) -> Option<SourceSize> {
+    let entry = entry.trim_ascii();
+    if entry.is_empty() {
+        return None;
+    }
+
+    // The size is the last component value, which may be a function containing whitespace.
+    let mut depth = 0usize;
+    let mut size_start = 0;
+    for (at, &c) in entry.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            b' ' | b'\t' | b'\n' | 0x0c | b'\r' if 0 == depth => size_start = at + 1,
+            _ => {}
+        }
+    }
+
+    let media_condition = entry[..size_start].trim_ascii();
+    Some(SourceSize {
+        media_condition: (!media_condition.is_empty()).then(|| media_condition.into()),
+        size: entry[size_start..].into(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ImageCandidateDescriptor as D;
+
+    fn candidate(url: &str, descriptor: ImageCandidateDescriptor) -> ImageCandidate {
+        ImageCandidate {
+            url: url.as_bytes().into(),
+            descriptor,
+        }
+    }
+
+    #[test]
+    fn test_parse_srcset() {
+        assert_eq!(
+            parse_srcset(b"a.jpg 480w, b.jpg 800w 600h,\n c.jpg"),
+            vec![
+                candidate(
+                    "a.jpg",
+                    D::Width {
+                        width: 480,
+                        height: None
+                    }
+                ),
+                candidate(
+                    "b.jpg",
+                    D::Width {
+                        width: 800,
+                        height: Some(600)
+                    }
+                ),
+                candidate("c.jpg", D::None),
+            ]
+        );
+        assert_eq!(
+            parse_srcset(b"a.jpg, b.jpg 1.5x,c.jpg 2e0x"),
+            vec![
+                candidate("a.jpg", D::None),
+                candidate("b.jpg", D::Density(1.5)),
+                candidate("c.jpg", D::Density(2.0)),
+            ]
+        );
+        assert!(parse_srcset(b"").is_empty());
+        assert!(parse_srcset(b" , ,").is_empty());
+    }
+
+    #[test]
+    fn test_parse_srcset_urls_with_commas() {
+        assert_eq!(
+            parse_srcset(b"data:image/png;base64,iVBO 1x,image,,, 2x"),
+            vec![
+                candidate("data:image/png;base64,iVBO", D::Density(1.0)),
+                candidate("image", D::None),
+                candidate("2x", D::None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_srcset_drops_invalid_candidates() {
+        assert_eq!(
+            parse_srcset(
+                b"a.jpg 0w, b.jpg 1x 2x, c.jpg 100w 1x, d.jpg 1.x, e.jpg +1x, f.jpg 100h, g.jpg 5w"
+            ),
+            vec![candidate(
+                "g.jpg",
+                D::Width {
+                    width: 5,
+                    height: None
+                }
+            )]
+        );
+        assert_eq!(
+            parse_srcset(b"a.jpg foo(1 2) 1x, b.jpg 1x"),
+            vec![candidate("b.jpg", D::Density(1.0))]
+        );
+    }
+
+    #[test]
+    fn test_srcset_round_trip() {
+        let mut candidates = parse_srcset(b"  a.jpg   1x,b.jpg 2x , c.jpg 300w 200h");
+        candidates[1].url = b"https://cdn.example.com/b.jpg".as_slice().into();
+        candidates.push(candidate("d.jpg", D::Density(1.5)));
+
+        assert_eq!(
+            serialize_srcset(&candidates).as_ref(),
+            b"a.jpg 1x, https://cdn.example.com/b.jpg 2x, c.jpg 300w 200h, d.jpg 1.5x"
+        );
+        assert_eq!(parse_srcset(&serialize_srcset(&candidates)), candidates);
+    }
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!(
+            parse_sizes(b"(max-width: 600px) 480px, (min-width: 601px) and (max-width: 900px) calc(100vw - 2em),\n800px, "),
+            vec![
+                SourceSize {
+                    media_condition: Some(b"(max-width: 600px)".as_slice().into()),
+                    size: b"480px".as_slice().into(),
+                },
+                SourceSize {
+                    media_condition: Some(
+                        b"(min-width: 601px) and (max-width: 900px)".as_slice().into()
+                    ),
+                    size: b"calc(100vw - 2em)".as_slice().into(),
+                },
+                SourceSize {
+                    media_condition: None,
+                    size: b"800px".as_slice().into(),
+                },
+            ]
+        );
+        assert!(parse_sizes(b"  ").is_empty());
+    }
+
+    #[test]
+    fn test_sizes_round_trip() {
+        let mut sizes = parse_sizes(b"(max-width:600px)   100vw,auto");
+        sizes[1].size = b"50vw".as_slice().into();
+
+        assert_eq!(
+            serialize_sizes(&sizes).as_ref(),
+            b"(max-width:600px) 100vw, 50vw"
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Parsing and editing for the declarations in a `style` attribute.
+//!
+//! A `style` attribute holds a CSS declaration list, e.g. `color: red; position: fixed`.
+//! This parser splits the list into declarations without interpreting their values, which
+//! is enough to read, remove, or replace individual properties and write the list back.
+//!
+//! These functions operate on decoded attribute values, as returned by `get_attribute()`.
+//! Serialized output is also a plain value and must be escaped when written back into HTML,
+//! which `set_attribute()` does.
+//!
+//! Example:
+//!
+//!     $p->next_tag( 'img' );
+//!     let mut style = Style::parse( $p->get_attribute( 'style' ) );
+//!     style.remove( b"position" );
+//!     style.set( b"aspect-ratio", b"16 / 9" );
+//!     $p->set_attribute( 'style', style.serialize() );
+//!
+//! @see https://drafts.csswg.org/css-style-attr/#syntax
+//! @see https://drafts.csswg.org/css-syntax/#parse-a-list-of-declarations
+
+use alloc::{boxed::Box, vec::Vec};
+
+/// A single declaration from a `style` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleDeclaration {
+    /// Name of the property, lower-cased unless it's a custom property, e.g. `--gap`.
+    pub property: Box<[u8]>,
+
+    /// The declared value, without surrounding whitespace, comments, or `!important`.
+    pub value: Box<[u8]>,
+
+    /// Whether the declaration is marked `!important`.
+    pub important: bool,
+}
+
+/// The list of declarations in a `style` attribute.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    /// Declarations in the order they appear, including any repeated properties.
+    pub declarations: Vec<StyleDeclaration>,
+}
+
+impl Style {
+    /// Parses the declarations in a `style` attribute value.
+    ///
+    /// Declarations without a property name or without a colon are dropped, as they
+    /// would be ignored by browsers. Values are not validated.
+    ///
+    /// Example:
+    ///
+    ///     [ ( "color", "red", false ), ( "margin", "0 auto", true ) ] == Style::parse( b"color: red; margin: 0 auto !important;" );
+    ///
+    /// @param string $text Decoded value of the `style` attribute.
+    /// @return Style Parsed declaration list.
+    pub fn parse(text: &[u8]) -> Self {
+        let declarations = split_declarations(text)
+            .iter()
+            .filter_map(|declaration| {
+                let colon_at = declaration.iter().position(|&c| b':' == c)?;
+                let property = declaration[..colon_at].trim_ascii();
+                if property.is_empty() || property.iter().any(u8::is_ascii_whitespace) {
+                    return None;
+                }
+
+                let (value, important) = split_important(&declaration[colon_at + 1..]);
+                Some(StyleDeclaration {
+                    property: comparable_property(property),
+                    value: value.into(),
+                    important,
+                })
+            })
+            .collect();
+
+        Self { declarations }
+    }
+
+    /// Returns the declaration which determines the value of the given property, if any.
+    ///
+    /// When a property is declared more than once, the last `!important` declaration wins,
+    /// otherwise the last declaration wins.
+    ///
+    /// @param string $property Property name, ASCII case-insensitive unless a custom property.
+    /// @return StyleDeclaration|null Winning declaration, if the property is declared.
+    pub fn get(&self, property: &[u8]) -> Option<&StyleDeclaration> {
+        let property = comparable_property(property);
+        let mut matches = self
+            .declarations
+            .iter()
+            .filter(|declaration| declaration.property == property);
+
+        matches
+            .clone()
+            .rfind(|declaration| declaration.important)
+            .or_else(|| matches.next_back())
+    }
+
+    /// Indicates if the given property is declared.
+    ///
+    /// @param string $property Property name, ASCII case-insensitive unless a custom property.
+    /// @return bool Whether the property is declared at least once.
+    pub fn contains(&self, property: &[u8]) -> bool {
+        self.get(property).is_some()
+    }
+
+    /// Sets the value of a property, replacing any existing declarations for it.
+    ///
+    /// The first existing declaration is updated in place so the order of properties is
+    /// preserved; any repeated declarations are removed. If the property isn't declared,
+    /// the new declaration is appended. A trailing `!important` in the value is honored.
+    ///
+    /// Nothing is changed if the property isn't a plain name, or if the value would spill
+    /// into the declarations around it once serialized: values may not contain a semicolon
+    /// outside of strings and brackets, braces, or an unclosed string, comment, escape,
+    /// or bracket.
+    ///
+    /// Example:
+    ///
+    ///     true  === $style->set( b"background", b"url('a;b.png')" );
+    ///     false === $style->set( b"color", b"red; position: fixed" );
+    ///
+    /// @param string $property Property name, e.g. `aspect-ratio`.
+    /// @param string $value    New value, e.g. `16 / 9`.
+    /// @return bool Whether the property was set.
+    pub fn set(&mut self, property: &[u8], value: &[u8]) -> bool {
+        let property = property.trim_ascii();
+        if !is_property_name(property) || !is_self_contained_value(value) {
+            return false;
+        }
+
+        let property = comparable_property(property);
+        let (value, important) = split_important(value);
+        let declaration = StyleDeclaration {
+            property,
+            value: value.into(),
+            important,
+        };
+
+        match self
+            .declarations
+            .iter()
+            .position(|existing| existing.property == declaration.property)
+        {
+            Some(at) => {
+                let mut i = 0;
+                self.declarations.retain(|existing| {
+                    let keep = i <= at || existing.property != declaration.property;
+                    i += 1;
+                    keep
+                });
+                self.declarations[at] = declaration;
+            }
+            None => self.declarations.push(declaration),
+        }
+
+        true
+    }
+
+    /// Removes all declarations for a property.
+    ///
+    /// @param string $property Property name, ASCII case-insensitive unless a custom property.
+    /// @return bool Whether any declarations were removed.
+    pub fn remove(&mut self, property: &[u8]) -> bool {
+        let property = comparable_property(property);
+        let before = self.declarations.len();
+        self.declarations
+            .retain(|declaration| declaration.property != property);
+        before != self.declarations.len()
+    }
+
+    /// Serializes the declarations for a `style` attribute.
+    ///
+    /// This follows the format browsers use when serializing declarations, e.g.
+    /// `color: red; margin: 0 auto !important;`.
+    ///
+    /// @see https://drafts.csswg.org/cssom/#serialize-a-css-declaration-block
+    ///
+    /// @return string Value for the `style` attribute, not yet HTML-encoded.
+    pub fn serialize(&self) -> Box<[u8]> {
+        let mut output = Vec::new();
+
+        for (i, declaration) in self.declarations.iter().enumerate() {
+            if i > 0 {
+                output.push(b' ');
+            }
+            output.extend_from_slice(&declaration.property);
+            output.extend_from_slice(b": ");
+            output.extend_from_slice(&declaration.value);
+            if declaration.important {
+                output.extend_from_slice(b" !important");
+            }
+            output.push(b';');
+        }
+
+        output.into()
+    }
+}
+
+/// Splits a declaration list on the semicolons which separate declarations.
+///
+/// Semicolons inside strings, parentheses, brackets, and braces don't end a declaration.
+/// Comments are removed, except inside strings.
+fn split_declarations(text: &[u8]) -> Vec<Vec<u8>> {
+    let mut declarations = Vec::new();
+    let mut declaration = Vec::new();
+    let mut closers: Vec<u8> = Vec::new();
+    let mut at = 0;
+
+    while at < text.len() {
+        let c = text[at];
+        match c {
+            b'/' if text.get(at + 1) == Some(&b'*') => {
+                let comment_length = text[at + 2..]
+                    .windows(2)
+                    .position(|window| window == b"*/")
+                    .map_or(text.len() - at, |closer_at| closer_at + 4);
+                at += comment_length;
+                continue;
+            }
+
+            b'"' | b'\'' => {
+                let string_length = strcspn!(text, x if x == c || x == b'\n', at + 1);
+                let string_end = text.len().min(at + string_length + 2);
+                declaration.extend_from_slice(&text[at..string_end]);
+                at = string_end;
+                continue;
+            }
+
+            b'\\' => {
+                let escape_end = text.len().min(at + 2);
+                declaration.extend_from_slice(&text[at..escape_end]);
+                at = escape_end;
+                continue;
+            }
+
+            b'(' => closers.push(b')'),
+            b'[' => closers.push(b']'),
+            b'{' => closers.push(b'}'),
+            b')' | b']' | b'}' if closers.last() == Some(&c) => {
+                closers.pop();
+            }
+
+            b';' if closers.is_empty() => {
+                declarations.push(core::mem::take(&mut declaration));
+                at += 1;
+                continue;
+            }
+
+            _ => {}
+        }
+
+        declaration.push(c);
+        at += 1;
+    }
+    declarations.push(declaration);
+
+    declarations
+}
+
+/// Indicates if a property name can be written without escaping.
+///
+/// This accepts CSS identifier characters, which covers every standard and custom property.
+fn is_property_name(property: &[u8]) -> bool {
+    !property.is_empty()
+        && property
+            .iter()
+            .all(|&c| c.is_ascii_alphanumeric() || b'-' == c || b'_' == c || c >= 0x80)
+}
+
+/// Indicates if a value stays within its own declaration when serialized.
+///
+/// Like `split_declarations()`, semicolons inside strings and brackets are part of the
+/// value. Anything which could end the declaration early or swallow the declarations
+/// after it isn't: a bare semicolon, a brace, or an unclosed string, comment, escape,
+/// or bracket.
+fn is_self_contained_value(value: &[u8]) -> bool {
+    let mut closers: Vec<u8> = Vec::new();
+    let mut at = 0;
+
+    while at < value.len() {
+        let c = value[at];
+        match c {
+            b'/' if value.get(at + 1) == Some(&b'*') => {
+                let Some(closer_at) = value[at + 2..]
+                    .windows(2)
+                    .position(|window| window == b"*/")
+                else {
+                    return false;
+                };
+                at += closer_at + 4;
+                continue;
+            }
+
+            b'"' | b'\'' => {
+                let string_length = strcspn!(value, x if x == c || x == b'\n', at + 1);
+                if value.get(at + 1 + string_length) != Some(&c) {
+                    return false;
+                }
+                at += string_length + 2;
+                continue;
+            }
+
+            b'\\' => {
+                if at + 1 >= value.len() {
+                    return false;
+                }
+                at += 2;
+                continue;
+            }
+
+            b'(' => closers.push(b')'),
+            b'[' => closers.push(b']'),
+            b')' | b']' if closers.last() == Some(&c) => {
+                closers.pop();
+            }
+            b')' | b']' | b'{' | b'}' => return false,
+            b';' if closers.is_empty() => return false,
+
+            _ => {}
+        }
+
+        at += 1;
+    }
+
+    closers.is_empty()
+}
+
+/// Splits a trailing `!important` from a declaration's value.
+fn split_important(value: &[u8]) -> (&[u8], bool) {
+    let value = value.trim_ascii();
+    if value.len() >= 9 && value[value.len() - 9..].eq_ignore_ascii_case(b"important") {
+        let rest = value[..value.len() - 9].trim_ascii_end();
+        if let Some(rest) = rest.strip_suffix(b"!") {
+            return (rest.trim_ascii_end(), true);
+        }
+    }
+
+    (value, false)
+}
+
+/// Returns the form of a property name used for comparison.
+///
+/// Property names are ASCII case-insensitive, except for custom properties.
+fn comparable_property(property: &[u8]) -> Box<[u8]> {
+    if property.starts_with(b"--") {
+        property.into()
+    } else {
+        property.to_ascii_lowercase().into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tag_processor::{AttributeValue, TagProcessor};
+
+    fn declaration(property: &str, value: &str, important: bool) -> StyleDeclaration {
+        StyleDeclaration {
+            property: property.as_bytes().into(),
+            value: value.as_bytes().into(),
+            important,
+        }
+    }
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(
+            Style::parse(b" COLOR : red ;margin:0 auto!IMPORTANT; --Gap: 1em;;").declarations,
+            vec![
+                declaration("color", "red", false),
+                declaration("margin", "0 auto", true),
+                declaration("--Gap", "1em", false),
+            ]
+        );
+        assert!(Style::parse(b"").declarations.is_empty());
+        assert!(
+            Style::parse(b"color; : red; two words: x")
+                .declarations
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parse_style_nested_semicolons() {
+        assert_eq!(
+            Style::parse(
+                b"background: url(\"a;b.png\") /* ; */ no-repeat; content: 'x;y'; --data: [a;b]"
+            )
+            .declarations,
+            vec![
+                declaration("background", "url(\"a;b.png\")  no-repeat", false),
+                declaration("content", "'x;y'", false),
+                declaration("--data", "[a;b]", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_winning_declaration() {
+        let style = Style::parse(b"color: red !important; color: blue; top: 1px; top: 2px");
+        assert_eq!(
+            style.get(b"Color"),
+            Some(&declaration("color", "red", true))
+        );
+        assert_eq!(style.get(b"top"), Some(&declaration("top", "2px", false)));
+        assert_eq!(style.get(b"left"), None);
+        assert!(!style.contains(b"--top"));
+    }
+
+    #[test]
+    fn test_edit_style() {
+        let mut style = Style::parse(b"top: 0; position: fixed; color: red; position: absolute");
+        assert!(style.remove(b"POSITION"));
+        assert!(!style.remove(b"position"));
+
+        style.set(b"color", b"blue !important");
+        style.set(b"aspect-ratio", b"16 / 9");
+
+        assert_eq!(
+            style.serialize().as_ref(),
+            b"top: 0; color: blue !important; aspect-ratio: 16 / 9;"
+        );
+        assert_eq!(Style::parse(&style.serialize()), style);
+    }
+
+    #[test]
+    fn test_set_replaces_repeated_declarations() {
+        let mut style = Style::parse(b"margin: 0; color: red; margin: 1px; margin: 2px");
+        style.set(b"margin", b"auto");
+
+        assert_eq!(
+            style.declarations,
+            vec![
+                declaration("margin", "auto", false),
+                declaration("color", "red", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_rejects_injected_declarations() {
+        let mut style = Style::parse(b"color: red");
+        for value in [
+            b"blue; position: fixed".as_slice(),
+            b"blue } body { color: red",
+            b"url(a.png",
+            b"'blue",
+            b"blue /* ",
+            b"blue\\",
+            b"a)",
+        ] {
+            assert!(!style.set(b"color", value));
+        }
+        assert!(!style.set(b"color: blue; top", b"0"));
+        assert!(!style.set(b"", b"0"));
+        assert_eq!(style.serialize().as_ref(), b"color: red;");
+
+        assert!(style.set(b"background", b"url('a;b.png') /* ; */ no-repeat"));
+        assert!(style.set(b"content", b"\"a;b\" 'c;d'"));
+        assert!(style.set(b"--grid", b"[a;b] calc((1px + 2px) * 3)"));
+        assert_eq!(
+            Style::parse(&style.serialize()).declarations,
+            vec![
+                declaration("color", "red", false),
+                declaration("background", "url('a;b.png')  no-repeat", false),
+                declaration("content", "\"a;b\" 'c;d'", false),
+                declaration("--grid", "[a;b] calc((1px + 2px) * 3)", false),
+            ]
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_update_style_attribute() {
+        let mut processor =
+            TagProcessor::new(b"<img style=\"position:fixed; width: 100&percnt;\" src=a.jpg>");
+        assert!(processor.next_tag(None));
+
+        let AttributeValue::String(value) = processor.get_attribute(b"style").unwrap() else {
+            panic!("Expected a style attribute.");
+        };
+        let mut style = Style::parse(&value);
+        style.remove(b"position");
+        style.set(b"aspect-ratio", b"16 / 9");
+
+        let updated = style.serialize();
+        assert!(processor.set_attribute("style", core::str::from_utf8(&updated).unwrap()));
+        assert_eq!(
+            processor.get_attribute(b"style"),
+            Some(AttributeValue::String(
+                b"width: 100%; aspect-ratio: 16 / 9;".as_slice().into()
+            ))
+        );
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<img style=\"width: 100%; aspect-ratio: 16 / 9;\" src=a.jpg>"
+        );
+    }
+}
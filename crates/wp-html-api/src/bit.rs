@@ -0,0 +1,192 @@
+//! Parsing for WordPress "bits," which are written as funky comments.
+//!
+//! A funky comment is a closing tag whose name starts with an invalid character, e.g.
+//! `</%core/post-title>`. Browsers turn these into comments, which makes them a safe place
+//! to store dynamic placeholders which are rendered on the server.
+//!
+//! Bits start with `%`, followed by the bit name and optional attribute-like arguments:
+//!
+//!     </%core/post-title>
+//!     </%my-plugin/copyright-year format="Y" short>
+//!
+//! Argument values follow the same syntax and decoding rules as HTML attribute values,
+//! except that they cannot contain `>`, which always ends the funky comment.
+//!
+//! @see https://html.spec.whatwg.org/#parse-error-invalid-first-character-of-tag-name
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{decoder, tag_processor::AttributeValue};
+
+/// Indicates that a funky comment is a bit.
+pub const BIT_SIGIL: u8 = b'%';
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Bit {
+    /// Name of the bit as written, e.g. `core/post-title`.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::bytes"))]
+    pub name: Box<[u8]>,
+
+    /// Arguments in the order they appear, with decoded values.
+    ///
+    /// Arguments without a value, e.g. `short`, are `AttributeValue::BooleanTrue`.
+    pub arguments: Vec<(Box<[u8]>, AttributeValue)>,
+}
+
+impl Bit {
+    /// Parses the text of a funky comment, i.e. everything between `</` and `>`.
+    ///
+    /// Returns `None` if the text doesn't start with the bit sigil followed by a name.
+    pub fn parse(text: &[u8]) -> Option<Self> {
+        let text = text.strip_prefix(&[BIT_SIGIL])?;
+
+        let name_length = strcspn!(text, b' ' | b'\t' | b'\n' | 0x0c | b'\r');
+        if 0 == name_length {
+            return None;
+        }
+
+        let mut arguments: Vec<(Box<[u8]>, AttributeValue)> = Vec::new();
+        let mut at = name_length;
+        loop {
+            at += strspn!(text, b' ' | b'\t' | b'\n' | 0x0c | b'\r', at);
+            if at >= text.len() {
+                break;
+            }
+
+            let argument_name_length =
+                strcspn!(text, b' ' | b'\t' | b'\n' | 0x0c | b'\r' | b'=', at + 1) + 1;
+            let argument_name = &text[at..at + argument_name_length];
+            at += argument_name_length;
+            at += strspn!(text, b' ' | b'\t' | b'\n' | 0x0c | b'\r', at);
+
+            if at >= text.len() || b'=' != text[at] {
+                arguments.push((argument_name.into(), AttributeValue::BooleanTrue));
+                continue;
+            }
+
+            at += 1;
+            at += strspn!(text, b' ' | b'\t' | b'\n' | 0x0c | b'\r', at);
+
+            let raw_value = match text.get(at) {
+                Some(&quote) if b'"' == quote || b'\'' == quote => {
+                    let value_length = strcspn!(text, x if x == quote, at + 1);
+                    let raw_value = &text[at + 1..at + 1 + value_length];
+                    // Skip the closing quote, if present.
+                    at = text.len().min(at + value_length + 2);
+                    raw_value
+                }
+                _ => {
+                    let value_length = strcspn!(text, b' ' | b'\t' | b'\n' | 0x0c | b'\r', at);
+                    let raw_value = &text[at..at + value_length];
+                    at += value_length;
+                    raw_value
+                }
+            };
+
+            arguments.push((
+                argument_name.into(),
+                AttributeValue::String(decoder::decode_attribute(raw_value)),
+            ));
+        }
+
+        Some(Self {
+            name: text[..name_length].into(),
+            arguments,
+        })
+    }
+
+    /// Returns the value of the first argument with the given name, if present.
+    pub fn get_argument(&self, name: &[u8]) -> Option<&AttributeValue> {
+        self.arguments
+            .iter()
+            .find(|(argument_name, _)| argument_name.as_ref() == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tag_processor::{TagProcessor, TokenType};
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_parse_bit() {
+        let bit = Bit::parse(b"%core/post-title").unwrap();
+        assert_eq!(bit.name.as_ref(), b"core/post-title");
+        assert!(bit.arguments.is_empty());
+
+        let bit = Bit::parse(b"%my/date format=\"Y &amp; m\" short size=3 empty='' ").unwrap();
+        assert_eq!(bit.name.as_ref(), b"my/date");
+        assert_eq!(
+            bit.arguments,
+            vec![
+                (
+                    b"format".as_slice().into(),
+                    AttributeValue::String(b"Y & m".as_slice().into())
+                ),
+                (b"short".as_slice().into(), AttributeValue::BooleanTrue),
+                (
+                    b"size".as_slice().into(),
+                    AttributeValue::String(b"3".as_slice().into())
+                ),
+                (
+                    b"empty".as_slice().into(),
+                    AttributeValue::String(b"".as_slice().into())
+                ),
+            ]
+        );
+        assert_eq!(
+            bit.get_argument(b"short"),
+            Some(&AttributeValue::BooleanTrue)
+        );
+        assert_eq!(bit.get_argument(b"missing"), None);
+    }
+
+    #[test]
+    fn test_parse_non_bits() {
+        assert_eq!(Bit::parse(b""), None);
+        assert_eq!(Bit::parse(b"%"), None);
+        assert_eq!(Bit::parse(b"% name"), None);
+        assert_eq!(Bit::parse(b"#core/post-title"), None);
+    }
+
+    #[test]
+    fn test_unterminated_quoted_argument() {
+        let bit = Bit::parse(b"%bit a=\"unterminated").unwrap();
+        assert_eq!(
+            bit.get_argument(b"a"),
+            Some(&AttributeValue::String(b"unterminated".as_slice().into()))
+        );
+    }
+
+    #[test]
+    fn test_replace_bits() {
+        let mut processor = TagProcessor::new(
+            b"<p>Hello, </%user/name case=upper>!</p><!-- </%not-a-bit> --></#x>",
+        );
+
+        let mut names = Vec::new();
+        while processor.next_token() {
+            if processor.get_token_type() != Some(&TokenType::FunkyComment) {
+                assert!(processor.get_bit().is_none());
+                continue;
+            }
+
+            match processor.get_bit() {
+                Some(bit) => {
+                    assert!(processor.replace_funky_comment(b"WORLD"));
+                    names.push(bit.name);
+                }
+                None => assert!(processor.replace_funky_comment(b"")),
+            }
+        }
+
+        assert_eq!(names, vec![b"user/name".as_slice().into()]);
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<p>Hello, WORLD!</p><!-- </%not-a-bit> -->"
+        );
+    }
+}
@@ -1,4 +1,7 @@
+use alloc::string::{String, ToString};
+
 #[derive(Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CompatMode {
     /// No-quirks mode document compatability mode.
     ///
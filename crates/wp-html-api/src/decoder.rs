@@ -0,0 +1,255 @@
+//! Context-aware decoding of HTML character references, modeled on WP_HTML_Decoder.
+//!
+//! HTML decodes character references differently depending on where they appear. In
+//! attribute values, a named character reference missing its trailing semicolon is left
+//! as-is when followed by an alphanumeric character or `=`, so that URLs such as
+//! `?a=1&copy=2` keep their query arguments. In text, the same reference is decoded.
+//!
+//! @see https://html.spec.whatwg.org/#named-character-reference-state
+
+use alloc::boxed::Box;
+use entities::HtmlContext;
+
+/// Whether string comparisons consider ASCII case.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CaseSensitivity {
+    #[default]
+    CaseSensitive,
+    AsciiCaseInsensitive,
+}
+
+/// Decodes a span of HTML text found inside an attribute value.
+///
+/// Example:
+///
+///     b"“😄”" == decode_attribute( b"&#x93;&#x1f604;&#x94" );
+///
+/// @param string $text Text containing raw and non-decoded attribute value.
+/// @return string Decoded UTF-8 string.
+pub fn decode_attribute(text: &[u8]) -> Box<[u8]> {
+    entities::decode(&HtmlContext::Attribute, text)
+}
+
+/// Decodes a span of HTML text found between tags, e.g. in a text node.
+///
+/// This does not handle the rules for special elements such as SCRIPT or STYLE,
+/// whose contents are never decoded, and it does not replace NULL bytes.
+///
+/// @param string $text Text document containing span of text to decode.
+/// @return string Decoded UTF-8 string.
+pub fn decode_text(text: &[u8]) -> Box<[u8]> {
+    entities::decode(&HtmlContext::BodyText, text)
+}
+
+/// Attempts to read a character reference at the given location in the text.
+///
+/// Returns the decoded bytes and the byte length of the matched character
+/// reference, or `None` if no character reference appears at the location or
+/// if it would not be decoded in the given context.
+///
+/// Example:
+///
+///     Some( ( b"…", 8 ) ) == read_character_reference( Text, b"&hellip; and more", 0 );
+///     None                == read_character_reference( Attribute, b"&notin", 0 ) // &not + in
+///
+/// @param string $context   `attribute` for decoding attribute values, `data` otherwise.
+/// @param string $text      Text document containing span of text to decode.
+/// @param int    $at        Byte offset into text where the character reference begins.
+pub fn read_character_reference(
+    context: &HtmlContext,
+    text: &[u8],
+    at: usize,
+) -> Option<(Box<[u8]>, usize)> {
+    let (decoded, token_length) = entities::decode_html_ref(context, text, at)?;
+
+    /*
+     * > If the character reference was consumed as part of an attribute, and the last
+     * > character matched is not a U+003B SEMICOLON character (;), and the next input
+     * > character is either a U+003D EQUALS SIGN character (=) or an ASCII alphanumeric,
+     * > then, for historical reasons, flush code points consumed as a character reference
+     * > and switch to the return state.
+     */
+    if HtmlContext::Attribute == *context
+        && b'#' != text[at + 1]
+        && b';' != text[at + token_length - 1]
+        && text
+            .get(at + token_length)
+            .is_some_and(|&c| c.is_ascii_alphanumeric() || b'=' == c)
+    {
+        return None;
+    }
+
+    Some((decoded, token_length))
+}
+
+/// Indicates if an attribute value starts with a given raw string value.
+///
+/// Use this method to determine if an attribute value starts with a given string, regardless
+/// of how it might be encoded in HTML. For instance, `http:` could be represented as `http:`
+/// or as `http&colon;` or as `&#x68;ttp:` or as `h&#116;tp&colon;`, or in many other ways.
+///
+/// Example:
+///
+///     $value = 'http&colon;//wordpress.org/';
+///     true   === WP_HTML_Decoder::attribute_starts_with( $value, 'http:', 'ascii-case-insensitive' );
+///     false  === WP_HTML_Decoder::attribute_starts_with( $value, 'https:', 'ascii-case-insensitive' );
+///
+/// @param string $haystack         String containing the raw non-decoded attribute value.
+/// @param string $search_text      Does the attribute value start with this plain string.
+/// @param string $case_sensitivity Optional. Pass 'ascii-case-insensitive' to ignore ASCII case when matching.
+///                                 Default 'case-sensitive'.
+/// @return bool Whether the attribute value starts with the given string.
+pub fn attribute_starts_with(
+    haystack: &[u8],
+    search_text: &[u8],
+    case_sensitivity: CaseSensitivity,
+) -> bool {
+    let loose_case = CaseSensitivity::AsciiCaseInsensitive == case_sensitivity;
+    let matches = |a: &[u8], b: &[u8]| {
+        if loose_case {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    };
+
+    let mut haystack_at = 0;
+    let mut search_at = 0;
+
+    while search_at < search_text.len() && haystack_at < haystack.len() {
+        let next_chunk = if b'&' == haystack[haystack_at] {
+            read_character_reference(&HtmlContext::Attribute, haystack, haystack_at)
+        } else {
+            None
+        };
+
+        match next_chunk {
+            // Without a character reference, the raw bytes must match.
+            None => {
+                if !matches(
+                    &haystack[haystack_at..haystack_at + 1],
+                    &search_text[search_at..search_at + 1],
+                ) {
+                    return false;
+                }
+                haystack_at += 1;
+                search_at += 1;
+            }
+
+            // A character reference must match what follows in the search text in its entirety.
+            Some((decoded, token_length)) => {
+                let search_end = search_at + decoded.len();
+                if search_end > search_text.len()
+                    || !matches(&search_text[search_at..search_end], &decoded)
+                {
+                    return false;
+                }
+                haystack_at += token_length;
+                search_at = search_end;
+            }
+        }
+    }
+
+    search_at == search_text.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_decode_attribute_and_text() {
+        assert_eq!(
+            decode_attribute(b"&#x93;&#x1f604;&#x94").as_ref(),
+            "“😄”".as_bytes()
+        );
+        assert_eq!(decode_attribute(b"?a=1&copy=2").as_ref(), b"?a=1&copy=2");
+        assert_eq!(decode_text(b"?a=1&copy=2").as_ref(), "?a=1©=2".as_bytes());
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_read_character_reference() {
+        assert_eq!(
+            read_character_reference(&HtmlContext::BodyText, b"&hellip; and more", 0),
+            Some(("…".as_bytes().into(), 8))
+        );
+        assert_eq!(
+            read_character_reference(&HtmlContext::BodyText, b"&notin", 0),
+            Some(("¬".as_bytes().into(), 4))
+        );
+        assert_eq!(
+            read_character_reference(&HtmlContext::Attribute, b"&notin", 0),
+            None
+        );
+        assert_eq!(
+            read_character_reference(&HtmlContext::Attribute, b"&not;in", 0),
+            Some(("¬".as_bytes().into(), 5))
+        );
+        assert_eq!(
+            read_character_reference(&HtmlContext::Attribute, b"&#38x", 0),
+            Some((b"&".as_slice().into(), 4))
+        );
+        assert_eq!(
+            read_character_reference(&HtmlContext::BodyText, b"x&amp;", 0),
+            None
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_attribute_starts_with() {
+        use CaseSensitivity::*;
+
+        let value = b"http&colon;//wordpress.org/";
+        assert!(attribute_starts_with(value, b"http:", CaseSensitive));
+        assert!(attribute_starts_with(value, b"http://", CaseSensitive));
+        assert!(!attribute_starts_with(value, b"https:", CaseSensitive));
+        assert!(!attribute_starts_with(value, b"HTTP:", CaseSensitive));
+        assert!(attribute_starts_with(value, b"HTTP:", AsciiCaseInsensitive));
+        assert!(attribute_starts_with(b"&#x68;ttp:", b"http", CaseSensitive));
+        assert!(attribute_starts_with(
+            b"&#X4A;avaScript:",
+            b"javascript:",
+            AsciiCaseInsensitive
+        ));
+        assert!(attribute_starts_with(b"anything", b"", CaseSensitive));
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_attribute_starts_with_requires_entire_search_text() {
+        use CaseSensitivity::*;
+
+        assert!(!attribute_starts_with(b"htt", b"http", CaseSensitive));
+        assert!(!attribute_starts_with(b"", b"h", CaseSensitive));
+
+        // A character reference only matches when all of its decoded bytes match.
+        assert!(!attribute_starts_with(
+            b"&hellip;",
+            b"\xE2\x80",
+            CaseSensitive
+        ));
+        assert!(!attribute_starts_with(b"&amp;", b"&a", CaseSensitive));
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_attribute_starts_with_ambiguous_references() {
+        use CaseSensitivity::*;
+
+        // `&copy` followed by `=` isn't decoded in attributes.
+        assert!(attribute_starts_with(b"&copy=2", b"&copy=", CaseSensitive));
+        assert!(!attribute_starts_with(
+            b"&copy=2",
+            "©".as_bytes(),
+            CaseSensitive
+        ));
+        assert!(attribute_starts_with(
+            b"&copy 2",
+            "© ".as_bytes(),
+            CaseSensitive
+        ));
+    }
+}
@@ -0,0 +1,705 @@
+//! Reports the differences between the trees two HTML documents parse into.
+//!
+//! Both inputs are parsed with the HTML Processor, so differences in syntax which don't
+//! affect the tree are ignored: attribute quoting and order, character references, implied
+//! tags, and the like. What remains are the differences a browser would see.
+//!
+//! Children of matching elements are aligned with a longest common subsequence of nodes
+//! of the same kind, where elements must also have the same tag name, so an inserted or
+//! removed element appears as a single change rather than as a change to every following
+//! sibling. Matching elements are then compared attribute by attribute, and matching text
+//! and comment nodes by their contents.
+//!
+//! Deeply-nested trees are compared without recursion, so any tree the parser can build
+//! can be compared.
+//!
+//! Example:
+//!
+//!     let differences = diff_fragments( b"<p class=a>One</p><p>Two", b"<p class='b'>One<p>2</p>", "<body>" );
+//!     "p: attribute class changed from \"a\" to \"b\"" == differences[0].to_string();
+//!     "p: text changed from \"Two\" to \"2\"" == differences[1].to_string();
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    html_processor::HtmlProcessor,
+    tag_processor::{AttributeValue, ParserState, ParsingNamespace, TokenType},
+};
+
+/// A qualified attribute name and its decoded value.
+type Attribute = (Box<[u8]>, Box<[u8]>);
+
+/// A node in a parsed tree, as compared by the diff.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Doctype(Box<[u8]>),
+    Element {
+        /// Name of the element, prefixed by its namespace if it's a foreign element,
+        /// e.g. `div` or `svg foreignObject`.
+        name: Box<[u8]>,
+
+        /// Qualified attribute names and their values, sorted by name.
+        attributes: Vec<Attribute>,
+
+        children: Vec<Node>,
+    },
+    Text(Box<[u8]>),
+    Comment(Box<[u8]>),
+}
+
+impl Node {
+    /// Indicates if two nodes are the same kind of node and can be compared in detail.
+    fn is_comparable(&self, other: &Node) -> bool {
+        match (self, other) {
+            (Node::Element { name: a, .. }, Node::Element { name: b, .. }) => a == b,
+            (Node::Doctype(_), Node::Doctype(_))
+            | (Node::Text(_), Node::Text(_))
+            | (Node::Comment(_), Node::Comment(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns a short, single-line description of the node.
+    fn describe(&self) -> Box<[u8]> {
+        match self {
+            Node::Doctype(name) => [b"<!DOCTYPE ", name.as_ref(), b">"].concat().into(),
+            Node::Element { name, .. } => [b"<", name.as_ref(), b">"].concat().into(),
+            Node::Text(text) => [b"\"", text.as_ref(), b"\""].concat().into(),
+            Node::Comment(text) => [b"<!-- ", text.as_ref(), b" -->"].concat().into(),
+        }
+    }
+}
+
+impl Drop for Node {
+    /// Drops the descendants of an element one at a time, since dropping them
+    /// recursively would overflow the stack for deeply-nested trees.
+    fn drop(&mut self) {
+        let Node::Element { children, .. } = self else {
+            return;
+        };
+
+        let mut descendants = core::mem::take(children);
+        while let Some(mut node) = descendants.pop() {
+            if let Node::Element { children, .. } = &mut node {
+                descendants.append(children);
+            }
+        }
+    }
+}
+
+/// A single difference between two trees.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// A node only exists in the new tree.
+    Inserted { node: Box<[u8]> },
+
+    /// A node only exists in the old tree.
+    Removed { node: Box<[u8]> },
+
+    /// An attribute only exists on the element in the new tree.
+    AttributeAdded { name: Box<[u8]>, value: Box<[u8]> },
+
+    /// An attribute only exists on the element in the old tree.
+    AttributeRemoved { name: Box<[u8]>, value: Box<[u8]> },
+
+    /// An attribute has a different value on the element in each tree.
+    AttributeChanged {
+        name: Box<[u8]>,
+        old_value: Box<[u8]>,
+        new_value: Box<[u8]>,
+    },
+
+    /// A text node has different contents in each tree.
+    TextChanged {
+        old_text: Box<[u8]>,
+        new_text: Box<[u8]>,
+    },
+
+    /// A comment has different contents in each tree.
+    CommentChanged {
+        old_text: Box<[u8]>,
+        new_text: Box<[u8]>,
+    },
+
+    /// The DOCTYPE declaration has a different name in each tree.
+    DoctypeChanged {
+        old_name: Box<[u8]>,
+        new_name: Box<[u8]>,
+    },
+}
+
+/// A difference between two trees and where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    /// Names of the elements enclosing the change, from the outermost element.
+    ///
+    /// For attribute changes, this ends with the element holding the attribute. For other
+    /// changes, it ends with the parent of the changed node.
+    pub path: Vec<Box<[u8]>>,
+
+    pub change: Change,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+
+        if self.path.is_empty() {
+            f.write_str("#document")?;
+        }
+        for (i, name) in self.path.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" > ")?;
+            }
+            f.write_str(&text(name))?;
+        }
+
+        match &self.change {
+            Change::Inserted { node } => write!(f, ": inserted {}", text(node)),
+            Change::Removed { node } => write!(f, ": removed {}", text(node)),
+            Change::AttributeAdded { name, value } => {
+                write!(f, ": attribute {} added as {:?}", text(name), text(value))
+            }
+            Change::AttributeRemoved { name, value } => {
+                write!(
+                    f,
+                    ": attribute {} removed, was {:?}",
+                    text(name),
+                    text(value)
+                )
+            }
+            Change::AttributeChanged {
+                name,
+                old_value,
+                new_value,
+            } => write!(
+                f,
+                ": attribute {} changed from {:?} to {:?}",
+                text(name),
+                text(old_value),
+                text(new_value)
+            ),
+            Change::TextChanged { old_text, new_text } => write!(
+                f,
+                ": text changed from {:?} to {:?}",
+                text(old_text),
+                text(new_text)
+            ),
+            Change::CommentChanged { old_text, new_text } => write!(
+                f,
+                ": comment changed from {:?} to {:?}",
+                text(old_text),
+                text(new_text)
+            ),
+            Change::DoctypeChanged { old_name, new_name } => write!(
+                f,
+                ": DOCTYPE changed from {:?} to {:?}",
+                text(old_name),
+                text(new_name)
+            ),
+        }
+    }
+}
+
+/// Compares two full HTML documents.
+///
+/// @param string $old_html Original document.
+/// @param string $new_html Changed document.
+/// @return Difference[]|null Differences in tree order, or `null` if either document
+///                           contains unsupported markup.
+pub fn diff_documents(old_html: &[u8], new_html: &[u8]) -> Option<Vec<Difference>> {
+    let mut old = HtmlProcessor::create_full_parser(old_html, "UTF-8")?;
+    let mut new = HtmlProcessor::create_full_parser(new_html, "UTF-8")?;
+    diff_processors(&mut old, &mut new)
+}
+
+/// Compares two HTML fragments parsed in the same context.
+///
+/// @param string $old_html Original fragment.
+/// @param string $new_html Changed fragment.
+/// @param string $context  Context element for both fragments, e.g. `<body>`.
+/// @return Difference[]|null Differences in tree order, or `null` if either fragment
+///                           contains unsupported markup.
+pub fn diff_fragments(old_html: &[u8], new_html: &[u8], context: &str) -> Option<Vec<Difference>> {
+    let mut old = HtmlProcessor::create_fragment(old_html, context, "UTF-8")?;
+    let mut new = HtmlProcessor::create_fragment(new_html, context, "UTF-8")?;
+    diff_processors(&mut old, &mut new)
+}
+
+/// Compares the HTML in two processors which haven't started processing.
+///
+/// Both processors will be in the completed state afterwards.
+///
+/// @param HtmlProcessor $old Processor for the original HTML.
+/// @param HtmlProcessor $new Processor for the changed HTML.
+/// @return Difference[]|null Differences in tree order, or `null` if either processor had
+///                           already started processing or encountered unsupported markup.
+pub fn diff_processors(
+    old: &mut HtmlProcessor,
+    new: &mut HtmlProcessor,
+) -> Option<Vec<Difference>> {
+    let old_tree = build_tree(old)?;
+    let new_tree = build_tree(new)?;
+
+    Some(diff_trees(&old_tree, &new_tree))
+}
+
+/// Reads the tree from a processor which hasn't started processing.
+fn build_tree(processor: &mut HtmlProcessor) -> Option<Vec<Node>> {
+    if processor.tag_processor.parser_state != ParserState::Ready {
+        return None;
+    }
+
+    // The children of each open element, starting with the document or context element.
+    let mut stack: Vec<Vec<Node>> = vec![Vec::new()];
+
+    while processor.next_token() {
+        let node = match processor.get_token_type() {
+            Some(TokenType::Doctype) => {
+                let doctype = processor.get_doctype_info()?;
+                Node::Doctype(doctype.name.unwrap_or_default())
+            }
+
+            Some(TokenType::Tag) if processor.is_tag_closer() => {
+                let children = stack.pop()?;
+                if let Some(Node::Element { children: slot, .. }) =
+                    stack.last_mut().and_then(|siblings| siblings.last_mut())
+                {
+                    *slot = children;
+                }
+                continue;
+            }
+
+            Some(TokenType::Tag) => {
+                let namespace = processor.get_namespace().clone();
+                let tag_name = processor.get_qualified_tag_name()?;
+                let name = match namespace {
+                    ParsingNamespace::Html => tag_name.to_ascii_lowercase().into(),
+                    _ => {
+                        let prefix: String = (&namespace).into();
+                        [prefix.as_bytes(), b" ", &tag_name].concat().into()
+                    }
+                };
+
+                let mut attributes = Vec::new();
+                for attribute_name in processor
+                    .get_attribute_names_with_prefix(b"")
+                    .unwrap_or_default()
+                {
+                    let value = match processor.get_attribute(&attribute_name) {
+                        Some(AttributeValue::String(value)) => value,
+                        _ => Box::default(),
+                    };
+                    let qualified_name = processor.get_qualified_attribute_name(&attribute_name)?;
+                    attributes.push((qualified_name, value));
+                }
+                attributes.sort();
+
+                let element = Node::Element {
+                    name,
+                    attributes,
+                    children: Vec::new(),
+                };
+                stack.last_mut()?.push(element);
+                if processor.expects_closer(None)? {
+                    stack.push(Vec::new());
+                }
+                continue;
+            }
+
+            Some(TokenType::Text) => {
+                let text = processor.get_modifiable_text();
+                let siblings = stack.last_mut()?;
+
+                // The parser may report one text node as several tokens.
+                if let Some(Node::Text(previous)) = siblings.last_mut() {
+                    *previous = [previous.as_ref(), &text].concat().into();
+                    continue;
+                }
+                Node::Text(text)
+            }
+
+            Some(TokenType::Comment | TokenType::CdataSection | TokenType::FunkyComment) => {
+                Node::Comment(processor.get_modifiable_text())
+            }
+
+            _ => continue,
+        };
+
+        stack.last_mut()?.push(node);
+    }
+
+    if processor.get_last_error().is_some() {
+        return None;
+    }
+
+    // Unclosed elements only remain if the document ended early.
+    while stack.len() > 1 {
+        let children = stack.pop()?;
+        if let Some(Node::Element { children: slot, .. }) =
+            stack.last_mut().and_then(|siblings| siblings.last_mut())
+        {
+            *slot = children;
+        }
+    }
+
+    stack.pop()
+}
+
+/// Work remaining while comparing two trees.
+enum Step<'a> {
+    /// Compare two comparable nodes.
+    Compare(&'a Node, &'a Node),
+
+    /// Align and compare the children of two matching nodes.
+    Children(&'a [Node], &'a [Node]),
+
+    /// Report a node which only exists in the new tree.
+    Inserted(&'a Node),
+
+    /// Report a node which only exists in the old tree.
+    Removed(&'a Node),
+
+    /// Leave the element most recently added to the path.
+    Leave,
+}
+
+/// Compares two lists of sibling nodes, reporting the differences in tree order.
+///
+/// Steps are kept on a stack instead of recursing into each element, so the depth of
+/// the trees doesn't matter.
+fn diff_trees(old: &[Node], new: &[Node]) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    let mut path: Vec<Box<[u8]>> = Vec::new();
+    let mut steps = vec![Step::Children(old, new)];
+
+    while let Some(step) = steps.pop() {
+        let change = match step {
+            Step::Compare(
+                Node::Element {
+                    name,
+                    attributes: old_attributes,
+                    children: old_children,
+                },
+                Node::Element {
+                    attributes: new_attributes,
+                    children: new_children,
+                    ..
+                },
+            ) => {
+                path.push(name.clone());
+                diff_attributes(&path, old_attributes, new_attributes, &mut differences);
+                steps.push(Step::Leave);
+                steps.push(Step::Children(old_children, new_children));
+                continue;
+            }
+
+            Step::Compare(old, new) => match diff_leaves(old, new) {
+                Some(change) => change,
+                None => continue,
+            },
+
+            Step::Children(old, new) => {
+                // Steps run in reverse order from how they're pushed.
+                let start = steps.len();
+                align_children(old, new, &mut steps);
+                steps[start..].reverse();
+                continue;
+            }
+
+            Step::Inserted(node) => Change::Inserted {
+                node: node.describe(),
+            },
+
+            Step::Removed(node) => Change::Removed {
+                node: node.describe(),
+            },
+
+            Step::Leave => {
+                path.pop();
+                continue;
+            }
+        };
+
+        differences.push(Difference {
+            path: path.clone(),
+            change,
+        });
+    }
+
+    differences
+}
+
+/// Aligns the children of two matching nodes, adding a step for each in order.
+///
+/// Matching nodes at the start and end of both lists are aligned without building the
+/// table for the longest common subsequence, which then only covers the changed middle.
+fn align_children<'a>(old: &'a [Node], new: &'a [Node], steps: &mut Vec<Step<'a>>) {
+    let prefix_length = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old.is_comparable(new))
+        .count();
+    let suffix_length = old[prefix_length..]
+        .iter()
+        .rev()
+        .zip(new[prefix_length..].iter().rev())
+        .take_while(|(old, new)| old.is_comparable(new))
+        .count();
+
+    for (old, new) in old.iter().zip(new).take(prefix_length) {
+        steps.push(Step::Compare(old, new));
+    }
+
+    let old_middle = &old[prefix_length..old.len() - suffix_length];
+    let new_middle = &new[prefix_length..new.len() - suffix_length];
+    align_middle(old_middle, new_middle, steps);
+
+    for (old, new) in old[old.len() - suffix_length..]
+        .iter()
+        .zip(&new[new.len() - suffix_length..])
+    {
+        steps.push(Step::Compare(old, new));
+    }
+}
+
+/// Aligns two lists of nodes with a longest common subsequence of comparable nodes.
+fn align_middle<'a>(old: &'a [Node], new: &'a [Node], steps: &mut Vec<Step<'a>>) {
+    if old.is_empty() || new.is_empty() {
+        steps.extend(old.iter().map(Step::Removed));
+        steps.extend(new.iter().map(Step::Inserted));
+        return;
+    }
+
+    // Longest common subsequence of comparable nodes, from the end of each list.
+    let mut lengths = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i].is_comparable(&new[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].is_comparable(&new[j]) {
+            steps.push(Step::Compare(&old[i], &new[j]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            steps.push(Step::Inserted(&new[j]));
+            j += 1;
+        } else {
+            steps.push(Step::Removed(&old[i]));
+            i += 1;
+        }
+    }
+}
+
+/// Compares two comparable nodes which aren't elements.
+///
+/// @return Change|null The change to the node, if any.
+fn diff_leaves(old: &Node, new: &Node) -> Option<Change> {
+    match (old, new) {
+        (Node::Doctype(old_name), Node::Doctype(new_name)) if old_name != new_name => {
+            Some(Change::DoctypeChanged {
+                old_name: old_name.clone(),
+                new_name: new_name.clone(),
+            })
+        }
+
+        (Node::Text(old_text), Node::Text(new_text)) if old_text != new_text => {
+            Some(Change::TextChanged {
+                old_text: old_text.clone(),
+                new_text: new_text.clone(),
+            })
+        }
+
+        (Node::Comment(old_text), Node::Comment(new_text)) if old_text != new_text => {
+            Some(Change::CommentChanged {
+                old_text: old_text.clone(),
+                new_text: new_text.clone(),
+            })
+        }
+
+        _ => None,
+    }
+}
+
+/// Compares the sorted attributes of two matching elements.
+fn diff_attributes(
+    path: &[Box<[u8]>],
+    old: &[Attribute],
+    new: &[Attribute],
+    differences: &mut Vec<Difference>,
+) {
+    let mut old = old.iter().peekable();
+    let mut new = new.iter().peekable();
+
+    loop {
+        let change = match (old.peek(), new.peek()) {
+            (None, None) => return,
+
+            (Some((old_name, old_value)), Some((new_name, new_value))) if old_name == new_name => {
+                old.next();
+                new.next();
+                if old_value == new_value {
+                    continue;
+                }
+                Change::AttributeChanged {
+                    name: old_name.clone(),
+                    old_value: old_value.clone(),
+                    new_value: new_value.clone(),
+                }
+            }
+
+            (Some((old_name, value)), new_attribute)
+                if new_attribute.is_none_or(|(new_name, _)| old_name < new_name) =>
+            {
+                old.next();
+                Change::AttributeRemoved {
+                    name: old_name.clone(),
+                    value: value.clone(),
+                }
+            }
+
+            (_, Some((new_name, value))) => {
+                new.next();
+                Change::AttributeAdded {
+                    name: new_name.clone(),
+                    value: value.clone(),
+                }
+            }
+
+            (Some(_), None) => unreachable!("Remaining old attributes are always removed."),
+        };
+
+        differences.push(Difference {
+            path: path.to_vec(),
+            change,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> Vec<String> {
+        diff_fragments(old.as_bytes(), new.as_bytes(), "<body>")
+            .unwrap()
+            .iter()
+            .map(|difference| difference.to_string())
+            .collect()
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_ignores_syntax_differences() {
+        assert!(
+            diff(
+                "<p class=a id='b'>x &amp; y",
+                "<P ID=\"b\" class=\"a\">x &#38; y</P>"
+            )
+            .is_empty()
+        );
+        assert!(diff("<ul><li>1<li>2</ul>", "<ul><li>1</li><li>2</li></ul>").is_empty());
+    }
+
+    #[test]
+    fn test_attribute_changes() {
+        assert_eq!(
+            diff(
+                "<div id=a class=x><img src=a.jpg alt>",
+                "<div class=y data-new><img alt=Alt src=a.jpg>"
+            ),
+            vec![
+                "div: attribute class changed from \"x\" to \"y\"",
+                "div: attribute data-new added as \"\"",
+                "div: attribute id removed, was \"a\"",
+                "div > img: attribute alt changed from \"\" to \"Alt\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inserted_and_removed_nodes() {
+        assert_eq!(
+            diff(
+                "<ul><li>1</li><li>2</li><li>3</li></ul><!-- a --><p>End",
+                "<ul><li>1</li><li>3</li><li>4</li></ul><!-- b --><hr><p>The end"
+            ),
+            vec![
+                "ul > li: text changed from \"2\" to \"3\"",
+                "ul > li: text changed from \"3\" to \"4\"",
+                "#document: comment changed from \" a \" to \" b \"",
+                "#document: inserted <hr>",
+                "p: text changed from \"End\" to \"The end\"",
+            ]
+        );
+        assert_eq!(diff("<p>", "<p>Hi"), vec!["p: inserted \"Hi\""]);
+        assert_eq!(diff("<b>x</b>y", "y"), vec!["#document: removed <b>"]);
+    }
+
+    #[test]
+    fn test_diff_documents() {
+        let differences = diff_documents(
+            b"<!DOCTYPE html><title>A</title><svg><path d=M0></svg>",
+            b"<!DOCTYPE html><html lang=en><title>A</title><svg><path d=M1></svg>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            differences
+                .iter()
+                .map(|difference| difference.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "html: attribute lang added as \"en\"",
+                "html > body > svg svg > svg path: attribute d changed from \"M0\" to \"M1\"",
+            ]
+        );
+        assert_eq!(
+            differences[0].path,
+            vec![Box::<[u8]>::from(b"html".as_slice())]
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_trees() {
+        // OBJECT elements are scope boundaries, so the parser handles them quickly.
+        let depth = 50_000;
+        let old = ["<object>".repeat(depth), "old".into()].concat();
+        let new = ["<object>".repeat(depth), "new".into()].concat();
+
+        let differences = diff_fragments(old.as_bytes(), new.as_bytes(), "<body>").unwrap();
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].path.len(), depth);
+        assert_eq!(
+            differences[0].change,
+            Change::TextChanged {
+                old_text: b"old".as_slice().into(),
+                new_text: b"new".as_slice().into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_long_sibling_lists() {
+        // Matching nodes around a change don't need a table for the whole list.
+        let items = "<li>x</li>".repeat(10_000);
+        let old = ["<ul>", &items, "<li>a</li>", &items, "</ul>"].concat();
+        let new = ["<ul>", &items, "<li>b</li><hr>", &items, "</ul>"].concat();
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                "ul > li: text changed from \"a\" to \"b\"",
+                "ul: inserted <hr>",
+            ]
+        );
+    }
+}
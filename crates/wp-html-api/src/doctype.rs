@@ -1,3 +1,5 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+
 use crate::compat_mode::CompatMode;
 
 /// HTML API: WP_HTML_Doctype_Info class
@@ -43,6 +45,8 @@ use crate::compat_mode::CompatMode;
 /// @see https://www.iso.org/standard/16387.html
 ///
 /// @see WP_HTML_Processor
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct HtmlDoctypeInfo {
     /// Name of the DOCTYPE: should be "html" for HTML documents.
     ///
@@ -56,6 +60,10 @@ pub struct HtmlDoctypeInfo {
     /// @see https://html.spec.whatwg.org/#tokenization
     ///
     /// @var string|null
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::option_bytes")
+    )]
     pub name: Option<Box<[u8]>>,
 
     /// Public identifier of the DOCTYPE.
@@ -63,7 +71,8 @@ pub struct HtmlDoctypeInfo {
     /// This value should be considered "read only" and not modified.
     ///
     /// The public identifier is optional and should not appear in HTML documents.
-    /// A `null` value indicates that no public identifier was present in the DOCTYPE.
+    /// A `null` value indicates that no public identifier was present in the DOCTYPE,
+    /// which is distinct from an empty identifier, e.g. `PUBLIC ""`.
     ///
     /// Historically the presence of the public identifier indicated that a document
     /// was meant to be shared between computer systems and the value indicated to a
@@ -76,6 +85,10 @@ pub struct HtmlDoctypeInfo {
     /// @see https://html.spec.whatwg.org/#tokenization
     ///
     /// @var string|null
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::option_bytes")
+    )]
     pub public_identifier: Option<Box<[u8]>>,
 
     /// System identifier of the DOCTYPE.
@@ -83,7 +96,8 @@ pub struct HtmlDoctypeInfo {
     /// This value should be considered "read only" and not modified.
     ///
     /// The system identifier is optional and should not appear in HTML documents.
-    /// A `null` value indicates that no system identifier was present in the DOCTYPE.
+    /// A `null` value indicates that no system identifier was present in the DOCTYPE,
+    /// which is distinct from an empty identifier, e.g. `SYSTEM ""`.
     ///
     /// Historically the system identifier specified where a relevant document type
     /// declaration for the given document is stored and may be retrieved.
@@ -102,8 +116,28 @@ pub struct HtmlDoctypeInfo {
     /// @see https://html.spec.whatwg.org/#tokenization
     ///
     /// @var string|null
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::option_bytes")
+    )]
     pub system_identifier: Option<Box<[u8]>>,
 
+    /// Whether the DOCTYPE token's force-quirks flag is set.
+    ///
+    /// This value should be considered "read only" and not modified.
+    ///
+    /// The tokenizer sets the flag for DOCTYPE declarations it can't fully parse, e.g.
+    /// when the name is missing or an identifier isn't closed by its quote. The flag
+    /// puts the document in quirks mode regardless of the name and identifiers.
+    ///
+    ///     <!DOCTYPE html PUBLIC>
+    ///                          ╰─ missing public identifier sets force-quirks.
+    ///
+    /// @see https://html.spec.whatwg.org/#tokenization
+    ///
+    /// @var bool
+    pub force_quirks_flag: bool,
+
     /// Which document compatability mode this DOCTYPE declaration indicates.
     ///
     /// This value should be considered "read only" and not modified.
@@ -152,6 +186,37 @@ impl HtmlDoctypeInfo {
         system_identifier: Option<Box<[u8]>>,
         force_quirks_flag: bool,
     ) -> Self {
+        let indicated_compatability_mode = Self::compatability_mode_for(
+            name.as_deref(),
+            public_identifier.as_deref(),
+            system_identifier.as_deref(),
+            force_quirks_flag,
+        );
+
+        Self {
+            name,
+            public_identifier,
+            system_identifier,
+            force_quirks_flag,
+            indicated_compatability_mode,
+        }
+    }
+
+    /// Determines which document compatability mode a DOCTYPE token indicates.
+    ///
+    /// @see https://html.spec.whatwg.org/#the-initial-insertion-mode
+    ///
+    /// @param string|null $name              Name of the DOCTYPE.
+    /// @param string|null $public_identifier Public identifier of the DOCTYPE.
+    /// @param string|null $system_identifier System identifier of the DOCTYPE.
+    /// @param bool        $force_quirks_flag Whether the force-quirks flag is set for the token.
+    /// @return CompatMode Indicated document compatability mode.
+    fn compatability_mode_for(
+        name: Option<&[u8]>,
+        public_identifier: Option<&[u8]>,
+        system_identifier: Option<&[u8]>,
+        force_quirks_flag: bool,
+    ) -> CompatMode {
         /*
          * > If the DOCTYPE token matches one of the conditions in the following list,
          * > then set the Document to quirks mode:
@@ -161,28 +226,18 @@ impl HtmlDoctypeInfo {
          * > The force-quirks flag is set to on.
          */
         if force_quirks_flag {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::Quirks,
-            };
+            return CompatMode::Quirks;
         }
 
         /*
          * Normative documents will contain the literal `<!DOCTYPE html>` with no
          * public or system identifiers; short-circuit to avoid extra parsing.
          */
-        if name.as_ref().is_some_and(|n| n.as_ref() == b"html")
-            && public_identifier.as_ref().is_none_or(|n| n.is_empty())
-            && system_identifier.as_ref().is_none_or(|n| n.is_empty())
+        if name == Some(b"html")
+            && public_identifier.is_none_or(|n| n.is_empty())
+            && system_identifier.is_none_or(|n| n.is_empty())
         {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::NoQuirks,
-            };
+            return CompatMode::NoQuirks;
         }
 
         /*
@@ -191,13 +246,8 @@ impl HtmlDoctypeInfo {
          * The tokenizer must report the name in lower case even if provided in
          * the document in upper case; thus no conversion is required here.
          */
-        if name.as_ref().is_none_or(|n| n.as_ref() != b"html") {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::Quirks,
-            };
+        if name != Some(b"html") {
+            return CompatMode::Quirks;
         }
 
         /*
@@ -214,11 +264,11 @@ impl HtmlDoctypeInfo {
          */
         let system_identifier_is_missing = system_identifier.is_none();
         let public_identifier_cleaned = match public_identifier {
-            Some(ref s) => s.to_ascii_lowercase(),
+            Some(s) => s.to_ascii_lowercase(),
             None => vec![],
         };
         let system_identifier_cleaned = match system_identifier {
-            Some(ref s) => s.to_ascii_lowercase(),
+            Some(s) => s.to_ascii_lowercase(),
             None => vec![],
         };
 
@@ -229,12 +279,7 @@ impl HtmlDoctypeInfo {
             || *b"-/w3c/dtd html 4.0 transitional/en" == *public_identifier_cleaned
             || *b"html" == *public_identifier_cleaned
         {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::Quirks,
-            };
+            return CompatMode::Quirks;
         }
 
         /*
@@ -243,12 +288,7 @@ impl HtmlDoctypeInfo {
         if *b"http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd"
             == *system_identifier_cleaned
         {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::Quirks,
-            };
+            return CompatMode::Quirks;
         }
 
         /*
@@ -256,12 +296,7 @@ impl HtmlDoctypeInfo {
          * If the public identifier is empty, none of the following conditions will match.
          */
         if public_identifier_cleaned.is_empty() {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::Quirks,
-            };
+            return CompatMode::Quirks;
         }
 
         /*
@@ -342,12 +377,7 @@ impl HtmlDoctypeInfo {
             || public_identifier_cleaned.starts_with(b"-//webtechs//dtd mozilla html 2.0//")
             || public_identifier_cleaned.starts_with(b"-//webtechs//dtd mozilla html//")
         {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::Quirks,
-            };
+            return CompatMode::Quirks;
         }
 
         /*
@@ -357,12 +387,7 @@ impl HtmlDoctypeInfo {
             && (public_identifier_cleaned.starts_with(b"-//w3c//dtd html 4.01 frameset//")
                 || public_identifier_cleaned.starts_with(b"-//w3c//dtd html 4.01 transitional//"))
         {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::Quirks,
-            };
+            return CompatMode::Quirks;
         }
 
         /*
@@ -376,12 +401,7 @@ impl HtmlDoctypeInfo {
         if public_identifier_cleaned.starts_with(b"-//w3c//dtd xhtml 1.0 frameset//")
             || public_identifier_cleaned.starts_with(b"-//w3c//dtd xhtml 1.0 transitional//")
         {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::LimitedQuirks,
-            };
+            return CompatMode::LimitedQuirks;
         }
 
         /*
@@ -391,20 +411,10 @@ impl HtmlDoctypeInfo {
             && (public_identifier_cleaned.starts_with(b"-//w3c//dtd html 4.01 frameset//")
                 || public_identifier_cleaned.starts_with(b"-//w3c//dtd html 4.01 transitional//"))
         {
-            return Self {
-                name,
-                public_identifier,
-                system_identifier,
-                indicated_compatability_mode: CompatMode::LimitedQuirks,
-            };
+            return CompatMode::LimitedQuirks;
         }
 
-        Self {
-            name,
-            public_identifier,
-            system_identifier,
-            indicated_compatability_mode: CompatMode::NoQuirks,
-        }
+        CompatMode::NoQuirks
     }
 
     /// Creates a WP_HTML_Doctype_Info instance by parsing a raw DOCTYPE declaration token.
@@ -768,6 +778,67 @@ impl HtmlDoctypeInfo {
             false,
         ))
     }
+
+    /// Returns a normalized DOCTYPE declaration with the same properties.
+    ///
+    /// Parsing the returned HTML produces the same name, identifiers, and force-quirks
+    /// flag, and therefore the same compatability mode, as this DOCTYPE. Identifiers are
+    /// quoted with double quotes unless they contain one.
+    ///
+    /// Example:
+    ///
+    ///     $doctype = WP_HTML_Doctype_Info::from_doctype_token( "<!doctype HTML\npublic 'x'>" );
+    ///     '<!DOCTYPE html PUBLIC "x">' === $doctype->serialize();
+    ///
+    ///     // The force-quirks flag is preserved by leaving the last identifier unclosed.
+    ///     $doctype = WP_HTML_Doctype_Info::from_doctype_token( "<!DOCTYPE html SYSTEM 'x>" );
+    ///     '<!DOCTYPE html SYSTEM "x>' === $doctype->serialize();
+    ///
+    /// @return string Normalized DOCTYPE declaration.
+    pub fn serialize(&self) -> Box<[u8]> {
+        let quote_for = |identifier: &[u8]| {
+            if identifier.contains(&b'"') {
+                b'\''
+            } else {
+                b'"'
+            }
+        };
+
+        let mut html = b"<!DOCTYPE".to_vec();
+        let Some(name) = self.name.as_ref().filter(|name| !name.is_empty()) else {
+            // A DOCTYPE without a name always sets the force-quirks flag.
+            html.push(b'>');
+            return html.into();
+        };
+        html.push(b' ');
+        html.extend_from_slice(name);
+
+        if self.public_identifier.is_some() {
+            html.extend_from_slice(b" PUBLIC");
+        } else if self.system_identifier.is_some() {
+            html.extend_from_slice(b" SYSTEM");
+        } else if self.force_quirks_flag {
+            // A keyword without an identifier sets the force-quirks flag.
+            html.extend_from_slice(b" PUBLIC");
+        }
+
+        let identifiers = [&self.public_identifier, &self.system_identifier];
+        let mut identifiers = identifiers.into_iter().flatten().peekable();
+        while let Some(identifier) = identifiers.next() {
+            let quote = quote_for(identifier);
+            html.push(b' ');
+            html.push(quote);
+            html.extend_from_slice(identifier);
+
+            // Leaving the last identifier unclosed sets the force-quirks flag.
+            if !self.force_quirks_flag || identifiers.peek().is_some() {
+                html.push(quote);
+            }
+        }
+
+        html.push(b'>');
+        html.into()
+    }
 }
 
 enum Proceed {
@@ -821,6 +892,15 @@ mod test {
                     "Failed to parse the expected DOCTYPE system identifier for {:?}",
                     String::from_utf8_lossy(a.as_bytes())
                 );
+
+                let serialized = doctype.serialize();
+                assert_eq!(
+                    HtmlDoctypeInfo::from_doctype_token(&serialized).as_ref(),
+                    Some(&doctype),
+                    "Failed to round-trip {:?} through {:?}",
+                    String::from_utf8_lossy(a.as_bytes()),
+                    String::from_utf8_lossy(&serialized)
+                );
             }
         )*
         }
@@ -853,6 +933,45 @@ mod test {
         special_limited_quirks_mode_if_system_set:             ( r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Frameset//" "">"#,                                         CompatMode::LimitedQuirks, Some("html"),                                                            Some("-//W3C//DTD HTML 4.01 Frameset//"), Some("") ),
     }
 
+    #[test]
+    fn test_force_quirks_flag_and_serialize() {
+        let cases: [(&[u8], bool, &[u8]); 8] = [
+            (b"<!DOCTYPE>", true, b"<!DOCTYPE>"),
+            (b"<!doctype HTML>", false, b"<!DOCTYPE html>"),
+            (b"<!DOCTYPE html PUBLIC>", true, b"<!DOCTYPE html PUBLIC>"),
+            (b"<!DOCTYPE html FOOBAR>", true, b"<!DOCTYPE html PUBLIC>"),
+            (
+                b"<!DOCTYPE html\npublic 'x\"y'\t''>",
+                false,
+                b"<!DOCTYPE html PUBLIC 'x\"y' \"\">",
+            ),
+            (
+                b"<!DOCTYPE html SYSTEM 'xyz>",
+                true,
+                b"<!DOCTYPE html SYSTEM \"xyz>",
+            ),
+            (
+                b"<!DOCTYPE html PUBLIC ''x''>",
+                true,
+                b"<!DOCTYPE html PUBLIC \">",
+            ),
+            (
+                b"<!DOCTYPE html PUBLIC 'a' 'b' trailing>",
+                false,
+                b"<!DOCTYPE html PUBLIC \"a\" \"b\">",
+            ),
+        ];
+
+        for (html, force_quirks_flag, serialized) in cases {
+            let doctype = HtmlDoctypeInfo::from_doctype_token(html).unwrap();
+            assert_eq!(doctype.force_quirks_flag, force_quirks_flag);
+            assert_eq!(
+                String::from_utf8_lossy(&doctype.serialize()),
+                String::from_utf8_lossy(serialized)
+            );
+        }
+    }
+
     #[test]
     fn test_invalid_inputs() {
         let test_cases = vec![
@@ -0,0 +1,400 @@
+//! Re-indents HTML for reading, e.g. in snapshot tests or developer tools.
+//!
+//! The formatter walks the document with the HTML Processor and writes every node on its
+//! own line, indented by its depth in the tree. Since it follows the parsed tree rather than
+//! the input text, implied elements are written out and the markup is normalized the same
+//! way as by `HtmlProcessor::serialize()`.
+//!
+//! Whitespace between nodes is not preserved, and runs of whitespace inside text are
+//! collapsed, so formatted HTML may render differently than its input. The contents of PRE,
+//! LISTING, SCRIPT, STYLE, TEXTAREA, and other elements whose whitespace is significant or
+//! which hold raw text are left untouched.
+//!
+//! Example:
+//!
+//!     $processor = WP_HTML_Processor::create_fragment( '<ul><li>One<li>Two</ul>' );
+//!     echo format( $processor, FormatOptions::default() );
+//!     // <ul>
+//!     //   <li>
+//!     //     One
+//!     //   </li>
+//!     //   <li>
+//!     //     Two
+//!     //   </li>
+//!     // </ul>
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{
+    html_processor::HtmlProcessor,
+    tag_name::TagName,
+    tag_processor::{ParserState, TokenType},
+};
+
+/// When to write each attribute of a tag on its own line.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WrapAttributes {
+    /// Attributes always stay on the same line as the tag name.
+    Never,
+
+    /// Attributes are wrapped when the tag would be longer than the line width.
+    #[default]
+    WhenLong,
+
+    /// Attributes are wrapped whenever a tag has more than one of them.
+    Always,
+}
+
+/// Settings for the formatter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    /// Text written once for each level of nesting, e.g. two spaces or a tab.
+    pub indent: Box<[u8]>,
+
+    /// Preferred maximum length of a line, in characters, including indentation.
+    ///
+    /// Longer text is wrapped between words. Words, comments, and the contents of
+    /// elements whose whitespace is significant are never broken. A value of 0
+    /// disables wrapping.
+    pub line_width: usize,
+
+    /// When to write each attribute of a tag on its own line.
+    pub wrap_attributes: WrapAttributes,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent: b"  ".as_slice().into(),
+            line_width: 100,
+            wrap_attributes: WrapAttributes::default(),
+        }
+    }
+}
+
+/// Formats the HTML in a processor which hasn't started processing.
+///
+/// The processor will be in the completed state afterwards.
+///
+/// @param HtmlProcessor $processor Processor for the HTML, immediately after creating it.
+/// @param FormatOptions $options   Settings for the formatter.
+/// @return string|null Formatted HTML, or `null` if the processor had already started
+///                     processing or if it encountered unsupported markup.
+pub fn format(processor: &mut HtmlProcessor, options: &FormatOptions) -> Option<Box<[u8]>> {
+    if processor.tag_processor.parser_state != ParserState::Ready {
+        return None;
+    }
+
+    // A fragment parser starts inside its context element, which isn't part of the output.
+    let base_depth = processor.get_current_depth();
+    let mut output = Vec::new();
+
+    // Depth of the PRE or LISTING element whose contents are being copied verbatim, if any.
+    let mut verbatim_depth: Option<usize> = None;
+
+    // Whether the last line is an opening tag whose element may be closed on the same line.
+    let mut after_opener = false;
+
+    // Every token is visited, even if the processor skips raw text contents.
+    while processor.next_visitable_token() {
+        let depth = processor.get_current_depth();
+        let is_closer = processor.is_tag_closer();
+
+        if let Some(verbatim_at) = verbatim_depth {
+            output.extend_from_slice(&processor.serialize_token());
+            if depth < verbatim_at {
+                verbatim_depth = None;
+            }
+            continue;
+        }
+
+        let level = if is_closer {
+            depth.saturating_sub(base_depth)
+        } else {
+            depth.saturating_sub(base_depth + 1)
+        };
+
+        if is_closer && after_opener {
+            output.extend_from_slice(&processor.serialize_token());
+            after_opener = false;
+            continue;
+        }
+        let was_after_opener = after_opener;
+        after_opener = false;
+
+        match processor.get_token_type() {
+            Some(TokenType::Text) => {
+                let text = processor.serialize_token();
+                let words: Vec<&[u8]> = text
+                    .split(u8::is_ascii_whitespace)
+                    .filter(|word| !word.is_empty())
+                    .collect();
+
+                // Inter-element whitespace disappears, leaving empty elements on one line.
+                after_opener = was_after_opener && words.is_empty();
+                write_words(&mut output, &words, level, options);
+            }
+
+            Some(TokenType::Tag) if !is_closer => {
+                let mut tag = processor.serialize_token();
+                let attribute_count = processor
+                    .get_attribute_names_with_prefix(b"")
+                    .map_or(0, |names| names.len());
+                let wrap = match options.wrap_attributes {
+                    WrapAttributes::Never => false,
+                    WrapAttributes::Always => attribute_count > 1,
+                    WrapAttributes::WhenLong => {
+                        // Attribute values are escaped, so the first `>` ends the opening tag.
+                        let opener_length =
+                            tag.iter().position(|&c| b'>' == c).map_or(0, |at| at + 1);
+                        attribute_count > 0
+                            && options.line_width > 0
+                            && level * width(&options.indent) + width(&tag[..opener_length])
+                                > options.line_width
+                    }
+                };
+
+                if wrap {
+                    let mut separator = Vec::from(b"\n".as_slice());
+                    for _ in 0..=level {
+                        separator.extend_from_slice(&options.indent);
+                    }
+                    tag = processor.serialize_token_with(&separator, |_, _| true);
+                }
+
+                start_line(&mut output, level, options);
+                output.extend_from_slice(&tag);
+
+                let expects_closer = processor.expects_closer(None).unwrap_or(false);
+                after_opener = expects_closer;
+                if expects_closer
+                    && matches!(processor.get_tag(), Some(TagName::PRE | TagName::LISTING))
+                {
+                    verbatim_depth = Some(depth);
+                }
+            }
+
+            _ => {
+                let token = processor.serialize_token();
+                if !token.is_empty() {
+                    start_line(&mut output, level, options);
+                    output.extend_from_slice(&token);
+                }
+            }
+        }
+    }
+
+    if processor.get_last_error().is_some() {
+        return None;
+    }
+
+    if !output.is_empty() {
+        output.push(b'\n');
+    }
+
+    Some(output.into())
+}
+
+/// Starts a new line at the given level of indentation.
+fn start_line(output: &mut Vec<u8>, level: usize, options: &FormatOptions) {
+    if !output.is_empty() {
+        output.push(b'\n');
+    }
+
+    for _ in 0..level {
+        output.extend_from_slice(&options.indent);
+    }
+}
+
+/// Writes words as lines of text, wrapping them at the line width.
+fn write_words(output: &mut Vec<u8>, words: &[&[u8]], level: usize, options: &FormatOptions) {
+    let indent_width = level * width(&options.indent);
+    let mut line_width = 0;
+
+    for word in words {
+        let word_width = width(word);
+        if 0 == line_width
+            || (options.line_width > 0
+                && indent_width + line_width + 1 + word_width > options.line_width)
+        {
+            start_line(output, level, options);
+            line_width = 0;
+        } else {
+            output.push(b' ');
+            line_width += 1;
+        }
+
+        output.extend_from_slice(word);
+        line_width += word_width;
+    }
+}
+
+/// Returns the number of characters in UTF-8 text.
+///
+/// Every byte except for continuation bytes starts a new character.
+fn width(text: &[u8]) -> usize {
+    text.iter().filter(|&&c| (c & 0xC0) != 0x80).count()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format_fragment(html: &[u8], options: &FormatOptions) -> String {
+        let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+        String::from_utf8(format(&mut processor, options).unwrap().into_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_format_fragment() {
+        assert_eq!(
+            format_fragment(
+                b"<ul><li>One<li>Two <b>bold</b></ul>\n\n<p> </p><img src=a.jpg><!-- note -->",
+                &FormatOptions::default()
+            ),
+            "<ul>
+  <li>
+    One
+  </li>
+  <li>
+    Two
+    <b>
+      bold
+    </b>
+  </li>
+</ul>
+<p></p>
+<img src=\"a.jpg\">
+<!-- note -->
+"
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_format_document() {
+        let mut processor = HtmlProcessor::create_full_parser(
+            b"<!DOCTYPE html><title>A &amp; B</title><table><td>cell</table>",
+            "UTF-8",
+        )
+        .unwrap();
+        let options = FormatOptions {
+            indent: b"\t".as_slice().into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            String::from_utf8(format(&mut processor, &options).unwrap().into_vec()).unwrap(),
+            "<!DOCTYPE html>
+<html>
+\t<head>
+\t\t<title>A &amp; B</title>
+\t</head>
+\t<body>
+\t\t<table>
+\t\t\t<tbody>
+\t\t\t\t<tr>
+\t\t\t\t\t<td>
+\t\t\t\t\t\tcell
+\t\t\t\t\t</td>
+\t\t\t\t</tr>
+\t\t\t</tbody>
+\t\t</table>
+\t</body>
+</html>
+"
+        );
+        assert_eq!(format(&mut processor, &options), None);
+    }
+
+    #[test]
+    fn test_preserves_whitespace_sensitive_contents() {
+        assert_eq!(
+            format_fragment(
+                b"<div><pre>\n\n  a <b> b </b>\n</pre><textarea>\n\n x  y</textarea><script>if (a  < b) {}</script></div>",
+                &FormatOptions::default()
+            ),
+            "<div>
+  <pre>
+
+  a <b> b </b>
+</pre>
+  <textarea>
+
+ x  y</textarea>
+  <script>if (a  < b) {}</script>
+</div>
+"
+        );
+
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<style>p {}</style><template>x</template>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+        processor.set_skip_raw_text_contents(true);
+        assert_eq!(
+            String::from_utf8(
+                format(&mut processor, &FormatOptions::default())
+                    .unwrap()
+                    .into_vec()
+            )
+            .unwrap(),
+            "<style>p {}</style>
+<template>
+  x
+</template>
+"
+        );
+    }
+
+    #[test]
+    fn test_wraps_text_and_attributes() {
+        let options = FormatOptions {
+            line_width: 20,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_fragment(
+                b"<p>The quick brown fox jumps over the lazy dog.</p><img src=a.jpg alt='A long description'><br class=x>",
+                &options
+            ),
+            "<p>
+  The quick brown
+  fox jumps over the
+  lazy dog.
+</p>
+<img
+  src=\"a.jpg\"
+  alt=\"A long description\">
+<br class=\"x\">
+"
+        );
+
+        let options = FormatOptions {
+            wrap_attributes: WrapAttributes::Always,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_fragment(b"<a href=/ class=x>Home</a><br class=x>", &options),
+            "<a
+  href=\"/\"
+  class=\"x\">
+  Home
+</a>
+<br class=\"x\">
+"
+        );
+
+        let options = FormatOptions {
+            line_width: 10,
+            wrap_attributes: WrapAttributes::Never,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_fragment(b"<img src=a.jpg alt='A long description'>", &options),
+            "<img src=\"a.jpg\" alt=\"A long description\">\n"
+        );
+    }
+}
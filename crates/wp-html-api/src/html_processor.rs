@@ -1,6 +1,7 @@
 #![allow(dead_code, unused_variables, non_camel_case_types)]
 
 mod active_formatting_elements;
+pub mod budget;
 pub mod errors;
 mod html_stack_event;
 mod html_token;
@@ -8,19 +9,24 @@ mod insertion_mode;
 mod processor_state;
 mod stack_of_open_elements;
 
-use std::collections::VecDeque;
+use alloc::{boxed::Box, collections::VecDeque, format, string::String, sync::Arc, vec, vec::Vec};
 
 use crate::{
     attributes::qualified_attribute_name,
     compat_mode::CompatMode,
+    decoder::CaseSensitivity,
     doctype::HtmlDoctypeInfo,
+    str_fns::esc_attr,
     tag_name::TagName,
     tag_processor::{
-        AttributeValue, BookmarkName, ClassList, CommentType, HtmlSpan, NodeName, ParserState,
-        ParsingNamespace, TagProcessor, TextNodeClassification, TokenType,
+        AttributeValue, Attributes, BookmarkName, ClassList, CommentType, HtmlSpan, MAX_SEEK_OPS,
+        NodeName, ParserState, ParsingNamespace, TagProcessor, TagProcessorSnapshot,
+        TextNodeClassification, TokenSnapshot, TokenType, TokenizerCheckpoint,
     },
+    utf8::Utf8Policy,
 };
 use active_formatting_elements::*;
+use budget::{BudgetTracker, ParsingBudget};
 use errors::{HtmlProcessorError, UnsupportedException};
 use html_stack_event::*;
 use html_token::*;
@@ -36,11 +42,86 @@ enum NodeToProcess {
 
 #[derive(Default)]
 pub struct TagQuery<'a> {
-    tag_name: Option<TagName>,
-    tag_closers: VisitClosers,
-    match_offset: Option<usize>,
-    class_name: Option<&'a str>,
-    breadcrumbs: Option<Vec<&'a str>>,
+    pub tag_name: Option<TagName>,
+    pub tag_closers: VisitClosers,
+    pub match_offset: Option<usize>,
+    pub class_name: Option<&'a str>,
+    pub breadcrumbs: Option<Vec<&'a str>>,
+}
+
+/// An element on the path from the root of the document to the currently-matched node.
+///
+/// @see WP_HTML_Processor::get_open_elements()
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OpenElement {
+    /// Name of the node, as it appears in the breadcrumbs.
+    pub node_name: NodeName,
+
+    /// Namespace of the element; HTML, SVG, or MathML.
+    pub namespace: ParsingNamespace,
+
+    /// Whether the element was implied by the HTML rather than found in it, e.g. a BODY
+    /// element that was never opened, or a formatting element that was reconstructed.
+    pub is_virtual: bool,
+}
+
+/// A node visited by the HTML Processor, as returned by `try_next_token()`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NodeEvent {
+    /// Name of the node, e.g. a tag name, or a token type such as `#text`.
+    pub node_name: NodeName,
+
+    /// Whether this is the closing of an element rather than a node.
+    pub is_closer: bool,
+
+    /// Whether the node was implied by the HTML rather than found in it.
+    pub is_virtual: bool,
+}
+
+/// Refers to a node visited by the HTML Processor so that it can be revisited.
+///
+/// Handles are created by `current_node_handle()`. Each holds a bookmark which follows
+/// its node through updates to the document. Like other bookmarks, they should be
+/// released with `release_node_handle()` once they're no longer needed.
+///
+/// @see WP_HTML_Processor::current_node_handle()
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeHandle {
+    bookmark_name: u32,
+    token: HTMLToken,
+    is_virtual: bool,
+}
+
+impl NodeHandle {
+    /// Returns the name of the node the handle refers to, e.g. `P` or `#text`.
+    pub fn node_name(&self) -> &NodeName {
+        &self.token.node_name
+    }
+
+    /// Returns the namespace of the node the handle refers to.
+    pub fn namespace(&self) -> &ParsingNamespace {
+        &self.token.namespace
+    }
+
+    /// Indicates if the node was implied by the HTML rather than found in it.
+    pub fn is_virtual(&self) -> bool {
+        self.is_virtual
+    }
+}
+
+/// An entry in the list of active formatting elements.
+///
+/// @see WP_HTML_Processor::get_active_formatting_elements()
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ActiveFormattingEntry {
+    /// A formatting element, e.g. B or EM, which may be reconstructed if it's closed implicitly.
+    Element(NodeName),
+
+    /// A marker, which stops formatting from leaking into elements such as TD or TEMPLATE.
+    Marker,
 }
 
 #[derive(Default, PartialEq)]
@@ -50,7 +131,22 @@ pub enum VisitClosers {
     Skip,
 }
 
-#[derive(PartialEq)]
+/// Which revision of the HTML parsing rules for SELECT elements the processor follows.
+///
+/// @see https://html.spec.whatwg.org/multipage/form-elements.html#the-select-element
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SelectParsing {
+    /// SELECT elements are parsed in their own insertion modes, which only allow
+    /// OPTION, OPTGROUP, and HR elements and text inside them; other tags are ignored.
+    #[default]
+    Legacy,
+
+    /// Customizable SELECT elements are parsed like other elements, so they may contain
+    /// other content, e.g. a BUTTON, or images and formatting inside OPTION elements.
+    Customizable,
+}
+
+#[derive(Clone, PartialEq)]
 enum EncodingConfidence {
     Tentative,
     Certain,
@@ -58,19 +154,86 @@ enum EncodingConfidence {
 }
 
 pub struct HtmlProcessor {
-    pub tag_processor: TagProcessor,
+    pub tag_processor: TagProcessor<'static>,
     state: ProcessorState,
     last_error: Option<HtmlProcessorError>,
     unsupported_exception: Option<String>,
     element_queue: VecDeque<HTMLStackEvent>,
     current_element: Option<HTMLStackEvent>,
     breadcrumbs: Vec<NodeName>,
+
+    /// Elements on the path to the current node, parallel to the breadcrumbs.
+    open_elements: Vec<OpenElement>,
+
+    /// Tokens for the elements on the path to the current node, parallel to the breadcrumbs.
+    /// Their bookmarks are held until the closing of the element is visited, so that the
+    /// processor can return to any of them.
+    open_element_tokens: Vec<HTMLToken>,
+
     bookmark_counter: u32,
 
+    /// Maximum number of elements allowed on the stack of open elements, if limited.
+    max_depth: Option<usize>,
+
+    /// Which rules to follow when parsing SELECT elements.
+    select_parsing: SelectParsing,
+
+    /// Whether `next_token()` skips text inside SCRIPT, STYLE, and TEMPLATE elements.
+    skip_raw_text_contents: bool,
+
+    /// Limits on the work performed for the document.
+    budget: BudgetTracker,
+
     /// Context node if created as a fragment parser.
     context_node: Option<HTMLToken>,
+
+    /// Parser state at the start of a fragment, restored when seeking backwards.
+    fragment_start_state: Option<Box<ProcessorState>>,
+
+    /// How many tokens to read between reparse checkpoints, if they're enabled.
+    checkpoint_every: Option<usize>,
+    tokens_since_checkpoint: usize,
+
+    /// Parser state recorded along the document, in document order, from which
+    /// seeking backwards reparses instead of from the start of the document.
+    reparse_checkpoints: Vec<ReparseCheckpoint>,
 }
 
+/// State of an HTML Processor, recorded by `snapshot()` to roll back to with `restore()`.
+///
+/// @see HtmlProcessor::snapshot()
+pub struct HtmlProcessorSnapshot {
+    tag_processor: TagProcessorSnapshot<'static>,
+    state: ProcessorState,
+    last_error: Option<HtmlProcessorError>,
+    unsupported_exception: Option<String>,
+    element_queue: VecDeque<HTMLStackEvent>,
+    current_element: Option<HTMLStackEvent>,
+    breadcrumbs: Vec<NodeName>,
+    open_elements: Vec<OpenElement>,
+    open_element_tokens: Vec<HTMLToken>,
+}
+
+/// Parser state between two tokens, recorded so that reparsing can start there.
+#[derive(Clone)]
+struct ReparseCheckpoint {
+    tokenizer: TokenizerCheckpoint,
+    state: ProcessorState,
+    breadcrumbs: Vec<NodeName>,
+    open_elements: Vec<OpenElement>,
+    open_element_tokens: Vec<HTMLToken>,
+
+    /// Bookmarks for the tokens referred to by the recorded state.
+    bookmarks: Vec<(u32, HtmlSpan)>,
+}
+
+// Processors are `Send` so that documents can be processed on other threads.
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<TagProcessor<'static>>();
+    assert_send::<HtmlProcessor>();
+};
+
 impl HtmlProcessor {
     /// Creates an HTML processor in the fragment parsing mode.
     ///
@@ -170,8 +333,18 @@ impl HtmlProcessor {
             unsupported_exception: None,
             current_element: None,
             breadcrumbs: Vec::new(),
+            open_elements: Vec::new(),
+            open_element_tokens: Vec::new(),
             bookmark_counter: 0,
+            max_depth: None,
+            select_parsing: SelectParsing::default(),
+            skip_raw_text_contents: false,
+            budget: BudgetTracker::default(),
             context_node: None,
+            fragment_start_state: None,
+            checkpoint_every: None,
+            tokens_since_checkpoint: 0,
+            reparse_checkpoints: Vec::new(),
         }
     }
 
@@ -218,11 +391,9 @@ impl HtmlProcessor {
             return None;
         }
 
-        let (tag_name, namespace) = {
-            let ce = &self.current_element.as_ref().unwrap().token;
-            let tag = ce.node_name.tag()?;
-            (tag.clone(), ce.namespace.clone())
-        };
+        let mut context_node = self.current_element.as_ref()?.token.clone();
+        let tag_name = context_node.node_name.tag()?.clone();
+        let namespace = context_node.namespace.clone();
 
         if namespace == ParsingNamespace::Html && Self::is_void(&tag_name) {
             // @todo _doing_it_wrong( __METHOD__, __( 'The context element cannot be a void element, found "%s".' ), tag_name );
@@ -254,6 +425,7 @@ impl HtmlProcessor {
         let mut fragment_processor = Self::new(html);
 
         fragment_processor.tag_processor.compat_mode = self.tag_processor.compat_mode.clone();
+        fragment_processor.select_parsing = self.select_parsing;
 
         // @todo Create "fake" bookmarks for non-existent but implied nodes.
         fragment_processor
@@ -269,19 +441,15 @@ impl HtmlProcessor {
         };
 
         fragment_processor.bookmark_counter += 1;
-        fragment_processor.push(root_node);
+        fragment_processor.push(root_node.clone());
 
         fragment_processor
             .tag_processor
             .internal_bookmarks
             .insert(fragment_processor.bookmark_counter, HtmlSpan::new(0, 0));
-        fragment_processor.context_node =
-            Some(self.current_element.as_ref().unwrap().token.clone());
-        fragment_processor
-            .context_node
-            .as_mut()
-            .unwrap()
-            .bookmark_name = Some(fragment_processor.bookmark_counter);
+        context_node.bookmark_name = Some(fragment_processor.bookmark_counter);
+        let is_integration_node = context_node.integration_node_type.is_some();
+        fragment_processor.context_node = Some(context_node.clone());
         fragment_processor.bookmark_counter += 1;
 
         if tag_name == TagName::TEMPLATE {
@@ -291,8 +459,23 @@ impl HtmlProcessor {
                 .push(InsertionMode::IN_TEMPLATE);
         }
 
-        fragment_processor.breadcrumbs =
-            vec![NodeName::Tag(TagName::HTML), NodeName::Tag(tag_name)];
+        fragment_processor.breadcrumbs = vec![
+            NodeName::Tag(TagName::HTML),
+            NodeName::Tag(tag_name.clone()),
+        ];
+        fragment_processor.open_elements = vec![
+            OpenElement {
+                node_name: NodeName::Tag(TagName::HTML),
+                namespace: ParsingNamespace::Html,
+                is_virtual: true,
+            },
+            OpenElement {
+                node_name: NodeName::Tag(tag_name),
+                namespace: namespace.clone(),
+                is_virtual: true,
+            },
+        ];
+        fragment_processor.open_element_tokens = vec![root_node, context_node];
 
         fragment_processor.reset_insertion_mode_appropriately();
 
@@ -304,13 +487,9 @@ impl HtmlProcessor {
          */
         for element in self.state.stack_of_open_elements.walk_up() {
             if element.node_name == NodeName::Tag(TagName::FORM) {
-                fragment_processor.state.form_element = Some(element.clone());
-                fragment_processor
-                    .state
-                    .form_element
-                    .as_mut()
-                    .unwrap()
-                    .bookmark_name = None;
+                let mut form_element = element.clone();
+                form_element.bookmark_name = None;
+                fragment_processor.state.form_element = Some(form_element);
                 break;
             }
         }
@@ -322,20 +501,15 @@ impl HtmlProcessor {
          * This is important so that any push/pop from the stack of open
          * elements does not change the parsing namespace.
          */
-        fragment_processor.tag_processor.change_parsing_namespace(
-            if self
-                .current_element
-                .as_ref()
-                .unwrap()
-                .token
-                .integration_node_type
-                .is_some()
-            {
+        fragment_processor
+            .tag_processor
+            .change_parsing_namespace(if is_integration_node {
                 ParsingNamespace::Html
             } else {
                 namespace
-            },
-        );
+            });
+
+        fragment_processor.fragment_start_state = Some(Box::new(fragment_processor.state.clone()));
 
         Some(fragment_processor)
     }
@@ -346,10 +520,23 @@ impl HtmlProcessor {
     ///
     /// @param string $message Explains support is missing in order to parse the current node.
     fn bail(&mut self, error: UnsupportedException) -> bool {
+        trace_event!(DEBUG, ?error, "bail");
         self.last_error = Some(HtmlProcessorError::UnsupportedException(error));
         false
     }
 
+    /// Stops processing when the parser's internal state is inconsistent.
+    ///
+    /// This is never expected to happen. It stands in for a panic so that
+    /// hostile input can at worst stop the processor.
+    ///
+    /// @return bool Always false.
+    fn fail_with_unexpected_state(&mut self) -> bool {
+        trace_event!(ERROR, "unexpected state");
+        self.last_error = Some(HtmlProcessorError::UnexpectedState);
+        false
+    }
+
     /// Returns the last error, if any.
     ///
     /// Various situations lead to parsing failure but this class will
@@ -373,6 +560,212 @@ impl HtmlProcessor {
         self.last_error.as_ref()
     }
 
+    /// Limits how deeply elements may be nested in the document.
+    ///
+    /// Deeply-nested HTML, e.g. many thousands of unclosed DIV elements, grows
+    /// the stack of open elements and the memory needed to track it. When parsing
+    /// untrusted input, set a maximum depth to bound this. If an element would be
+    /// nested more deeply, the processor stops and reports `ExceededMaxDepth` from
+    /// `get_last_error()`, the same way it reports running out of bookmarks.
+    ///
+    /// The depth counts every open element, including implied elements such as
+    /// HTML and BODY, and the context element in a fragment parser.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_full_parser( str_repeat( '<div>', 100000 ) );
+    ///     $processor->set_max_depth( Some( 512 ) );
+    ///     while ( $processor->next_token() ) {
+    ///         continue;
+    ///     }
+    ///     Some( &ExceededMaxDepth ) === $processor->get_last_error();
+    ///
+    /// @param int|null $max_depth Maximum number of open elements, or `null` for no limit.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    /// Returns the maximum depth of nested elements, if limited.
+    ///
+    /// @see self::set_max_depth()
+    ///
+    /// @return int|null Maximum number of open elements, or `null` for no limit.
+    pub fn get_max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// Chooses which rules to follow when parsing SELECT elements.
+    ///
+    /// The HTML specification changed how SELECT elements are parsed to support
+    /// customizable SELECT, which allows other content inside SELECT and OPTION
+    /// elements. The legacy rules are the default; choose the customizable rules
+    /// to match the output of current browsers.
+    ///
+    /// The rules may only be changed before processing starts.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_fragment( '<select><button>Pick</button><option><img src=a.png>A' );
+    ///     $processor->set_select_parsing( SelectParsing::Customizable );
+    ///     echo $processor->serialize();
+    ///     // <select><button>Pick</button><option><img src="a.png">A</option></select>
+    ///
+    /// @param SelectParsing $select_parsing Which SELECT parsing rules to follow.
+    /// @return bool Whether the rules were changed, which fails if processing has started.
+    pub fn set_select_parsing(&mut self, select_parsing: SelectParsing) -> bool {
+        if self.tag_processor.parser_state != ParserState::Ready {
+            return false;
+        }
+
+        self.select_parsing = select_parsing;
+        true
+    }
+
+    /// Returns which rules the processor follows when parsing SELECT elements.
+    ///
+    /// @see self::set_select_parsing()
+    ///
+    /// @return SelectParsing Which SELECT parsing rules are followed.
+    pub fn get_select_parsing(&self) -> SelectParsing {
+        self.select_parsing
+    }
+
+    /// Skips text which isn't content of the document, i.e. inside SCRIPT, STYLE,
+    /// and TEMPLATE elements, when finding the next token.
+    ///
+    /// Content-extraction code usually ignores these elements, but would otherwise need
+    /// to check the breadcrumbs of every text node to do so. HTML SCRIPT and STYLE elements
+    /// hold their contents as modifiable text rather than as text nodes, which is still
+    /// available from `get_modifiable_text()` on the tag; inside SVG and MathML, their
+    /// contents are text nodes, which are skipped. Text anywhere inside a TEMPLATE element
+    /// is skipped, but its elements are still visited.
+    ///
+    /// Serialization, seeking, formatting, and sanitizing aren't affected.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_fragment( '<p>One</p><template>Two</template><svg><style>.a{}</style></svg>' );
+    ///     $processor->set_skip_raw_text_contents( true );
+    ///     while ( $processor->next_text() ) {
+    ///         echo $processor->get_modifiable_text();
+    ///     }
+    ///     // One
+    ///
+    /// @param bool $skip Whether to skip the text.
+    pub fn set_skip_raw_text_contents(&mut self, skip: bool) {
+        self.skip_raw_text_contents = skip;
+    }
+
+    /// Sets limits on the work performed while processing the document.
+    ///
+    /// Work already performed is not counted against the new budget.
+    ///
+    /// @see ParsingBudget
+    ///
+    /// @param ParsingBudget $budget Limits to apply from this point on.
+    pub fn set_budget(&mut self, budget: ParsingBudget) {
+        self.budget = BudgetTracker::new(budget);
+    }
+
+    /// Enables reparsing from checkpoints when seeking backwards.
+    ///
+    /// Seeking to an earlier location normally rewinds to the start of the document and
+    /// reparses everything up to that location. With checkpoints enabled, the processor
+    /// records its state every so many tokens and reparses from the closest checkpoint
+    /// before the location instead.
+    ///
+    /// Applying lexical updates only discards the checkpoints after the earliest modified
+    /// span, so pipelines which alternate between modifying the document and seeking back
+    /// to earlier bookmarks don't reparse the whole document each time.
+    ///
+    /// Each checkpoint holds a copy of the parser state, which grows with the depth of the
+    /// document, so more frequent checkpoints trade memory for less reparsing.
+    ///
+    /// Example:
+    ///
+    ///     $processor->set_reparse_checkpoints( Some( 1_000 ) );
+    ///
+    /// @param int|null $every_tokens How many tokens to read between checkpoints,
+    ///                               or `null` to disable them. A value of 0 is treated as 1.
+    pub fn set_reparse_checkpoints(&mut self, every_tokens: Option<usize>) {
+        self.checkpoint_every = every_tokens.map(|every| every.max(1));
+        self.tokens_since_checkpoint = 0;
+        if self.checkpoint_every.is_none() {
+            self.reparse_checkpoints.clear();
+        }
+    }
+
+    /// Starts processing a new document, reusing the memory allocated for the last one.
+    ///
+    /// The new document is parsed the same way as the last one: a full parser parses
+    /// it as a full document, and a fragment parser parses it as a fragment in the same
+    /// context. Settings such as the maximum depth, the budget, and the UTF-8 policy are
+    /// kept, while the work counted against the budget starts over. Internal buffers,
+    /// e.g. the stack of open elements, the event queue, and the bookmarks, keep their
+    /// capacity, which avoids reallocating them when processing many small documents.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_fragment( '' );
+    ///     foreach ( $posts as $post ) {
+    ///         $processor->reset_with( $post );
+    ///         echo $processor->serialize();
+    ///     }
+    ///
+    /// @see WP_HTML_Tag_Processor::reset_with()
+    ///
+    /// @param string $html HTML to process.
+    /// @return bool Whether the document is acceptable under the UTF-8 policy.
+    pub fn reset_with(&mut self, html: &[u8]) -> bool {
+        // The presence of a context node indicates that this is a fragment parser.
+        let initial_depth = if self.context_node.is_some() { 2 } else { 0 };
+        let compat_mode = self.tag_processor.compat_mode.clone();
+
+        self.tag_processor.reset_with(html);
+        self.rewind();
+
+        // The root and context nodes are implied at the start of a fragment.
+        for token in &self.open_element_tokens[..initial_depth.min(self.open_element_tokens.len())]
+        {
+            if let Some(bookmark_name) = token.bookmark_name {
+                self.tag_processor
+                    .internal_bookmarks
+                    .insert(bookmark_name, HtmlSpan::new(0, 0));
+            }
+        }
+        if self.context_node.is_some() {
+            self.tag_processor.compat_mode = compat_mode;
+        }
+
+        // Bookmark names keep counting up, so node handles from the last document
+        // can't refer to nodes in the new one.
+        self.reparse_checkpoints.clear();
+        self.tokens_since_checkpoint = 0;
+        self.budget.reset();
+
+        let policy = self.tag_processor.get_utf8_policy();
+        self.set_utf8_policy(policy)
+    }
+
+    /// Sets how byte sequences which aren't valid UTF-8 are handled.
+    ///
+    /// If the policy rejects the document, the processor stops with an `InvalidUtf8` error.
+    ///
+    /// @see WP_HTML_Tag_Processor::set_utf8_policy()
+    ///
+    /// @param Utf8Policy $policy How to handle invalid UTF-8.
+    /// @return bool Whether the document is acceptable under the policy.
+    pub fn set_utf8_policy(&mut self, policy: Utf8Policy) -> bool {
+        if self.tag_processor.set_utf8_policy(policy) {
+            return true;
+        }
+
+        if self.last_error.is_none() {
+            self.last_error = Some(HtmlProcessorError::InvalidUtf8);
+        }
+        false
+    }
+
     /// Returns context for why the parser aborted due to unsupported HTML, if it did.
     ///
     /// This is meant for debugging purposes, not for production use.
@@ -402,6 +795,54 @@ impl HtmlProcessor {
         self.tag_processor.get_doctype_info()
     }
 
+    /// Finds the next text node.
+    ///
+    /// @see WP_HTML_Tag_Processor::next_text()
+    ///
+    /// @return bool Whether a text node was found.
+    pub fn next_text(&mut self) -> bool {
+        self.next_token_of_type(|token_type| {
+            matches!(token_type, TokenType::Text | TokenType::CdataSection)
+        })
+    }
+
+    /// Finds the next comment, including "funky comments" such as `</%invalid>`.
+    ///
+    /// @see WP_HTML_Tag_Processor::next_comment()
+    ///
+    /// @return bool Whether a comment was found.
+    pub fn next_comment(&mut self) -> bool {
+        self.next_token_of_type(|token_type| {
+            matches!(token_type, TokenType::Comment | TokenType::FunkyComment)
+        })
+    }
+
+    /// Finds the next DOCTYPE declaration.
+    ///
+    /// DOCTYPE declarations are only found at the start of a full document;
+    /// anywhere else they are ignored by the parser.
+    ///
+    /// @see WP_HTML_Tag_Processor::next_doctype()
+    ///
+    /// @return bool Whether a DOCTYPE declaration was found.
+    pub fn next_doctype(&mut self) -> bool {
+        self.next_token_of_type(|token_type| *token_type == TokenType::Doctype)
+    }
+
+    /// Advances to the next token whose type matches.
+    ///
+    /// @param callable $matches Whether to stop on a token of the given type.
+    /// @return bool Whether a matching token was found.
+    fn next_token_of_type(&mut self, matches: impl Fn(&TokenType) -> bool) -> bool {
+        while self.next_token() {
+            if self.get_token_type().is_some_and(&matches) {
+                return true;
+            }
+        }
+
+        false
+    }
+
     /// Finds the next tag matching the query.
     ///
     /// @todo Support matching the class name and tag name.
@@ -423,7 +864,7 @@ impl HtmlProcessor {
     /// @return bool Whether a tag was matched.
     pub fn next_tag(&mut self, query: Option<TagQuery>) -> bool {
         // Handle null/None query case
-        if query.is_none() {
+        let Some(query) = query else {
             while self.next_token() {
                 if self.get_token_type() != Some(&TokenType::Tag) {
                     continue;
@@ -434,9 +875,8 @@ impl HtmlProcessor {
                 }
             }
             return false;
-        }
+        };
 
-        let query = query.unwrap();
         let visit_closers = query.tag_closers == VisitClosers::Visit;
 
         if query.breadcrumbs.is_none() {
@@ -446,7 +886,7 @@ impl HtmlProcessor {
                 }
 
                 if let Some(tag_name) = &query.tag_name {
-                    if self.get_tag().unwrap() != *tag_name {
+                    if self.get_tag().as_ref() != Some(tag_name) {
                         continue;
                     }
                 }
@@ -466,7 +906,7 @@ impl HtmlProcessor {
         }
 
         let breadcrumbs = &query.breadcrumbs;
-        let mut match_offset = query.match_offset.unwrap_or(0);
+        let mut match_offset = query.match_offset.unwrap_or(1);
 
         while match_offset > 0 && self.next_token() {
             if self.get_token_type() != Some(&TokenType::Tag) || self.is_tag_closer() {
@@ -480,10 +920,9 @@ impl HtmlProcessor {
             }
 
             if self.matches_breadcrumbs(breadcrumbs.as_ref()) {
-                if match_offset < 1 {
+                match_offset -= 1;
+                if 0 == match_offset {
                     return true;
-                } else {
-                    match_offset -= 1;
                 }
             }
         }
@@ -499,7 +938,177 @@ impl HtmlProcessor {
     ///
     /// @return bool Whether a token was parsed.
     pub fn next_token(&mut self) -> bool {
-        self.next_visitable_token()
+        while self.next_visitable_token() {
+            if !self.skip_raw_text_contents || !self.is_in_raw_text_contents() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Indicates if the matched token is text inside a SCRIPT, STYLE, or TEMPLATE element.
+    ///
+    /// @see self::set_skip_raw_text_contents()
+    ///
+    /// @return bool Whether the token is text which isn't content of the document.
+    fn is_in_raw_text_contents(&self) -> bool {
+        if self.get_token_type() != Some(&TokenType::Text) {
+            return false;
+        }
+
+        // The text node itself is the last of the open elements.
+        let Some((_, ancestors)) = self.open_elements.split_last() else {
+            return false;
+        };
+
+        let is_template = |element: &OpenElement| {
+            element.namespace == ParsingNamespace::Html
+                && element.node_name == NodeName::Tag(TagName::TEMPLATE)
+        };
+
+        ancestors.last().is_some_and(|parent| {
+            matches!(
+                parent.node_name,
+                NodeName::Tag(TagName::SCRIPT | TagName::STYLE)
+            )
+        }) || ancestors.iter().any(is_template)
+    }
+
+    /// Finds the next token in the HTML document, reporting why processing stopped.
+    ///
+    /// This is the same as `next_token()`, except that instead of returning `false` and
+    /// leaving it to the caller to check `get_last_error()`, it returns the error.
+    ///
+    /// Example:
+    ///
+    ///     while let Some( event ) = $processor->try_next_token()? {
+    ///         // …
+    ///     }
+    ///
+    /// @see self::next_token()
+    ///
+    /// @return Result<NodeEvent|null, HtmlProcessorError> The visited node, `null` at the end
+    ///                                                    of the document, or the error which
+    ///                                                    stopped processing.
+    pub fn try_next_token(&mut self) -> Result<Option<NodeEvent>, HtmlProcessorError> {
+        let found = self.next_token();
+        self.to_node_event(found)
+    }
+
+    /// Finds the next tag matching the query, reporting why processing stopped.
+    ///
+    /// @see self::next_tag()
+    ///
+    /// @param TagQuery|null $query Which tag to find, or `null` for any tag.
+    /// @return Result<NodeEvent|null, HtmlProcessorError> The matched tag, `null` if there's no
+    ///                                                    match, or the error which stopped
+    ///                                                    processing.
+    pub fn try_next_tag(
+        &mut self,
+        query: Option<TagQuery>,
+    ) -> Result<Option<NodeEvent>, HtmlProcessorError> {
+        let found = self.next_tag(query);
+        self.to_node_event(found)
+    }
+
+    /// Describes the outcome of advancing the processor.
+    ///
+    /// @param bool $found Whether the processor matched a node.
+    /// @return Result<NodeEvent|null, HtmlProcessorError> The matched node, if found, otherwise
+    ///                                                    the last error, if any.
+    fn to_node_event(&self, found: bool) -> Result<Option<NodeEvent>, HtmlProcessorError> {
+        if let Some(error) = self.last_error {
+            return Err(error);
+        }
+
+        if !found {
+            return Ok(None);
+        }
+
+        let node_name = self
+            .get_token_name()
+            .ok_or(HtmlProcessorError::UnexpectedState)?;
+        Ok(Some(NodeEvent {
+            node_name,
+            is_closer: self.is_tag_closer(),
+            is_virtual: self.is_virtual(),
+        }))
+    }
+
+    /// Advances past the contents of the currently-matched element.
+    ///
+    /// After skipping, the processor is matched on the closing of the element, whether
+    /// or not a closing tag appears in the HTML. Elements without contents, such as void
+    /// elements, are skipped without moving. The HTML inside the element must still be
+    /// parsed, but nothing inside it is visited.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_fragment( '<ul><li>One<li>Two</ul><p>After' );
+    ///     $processor->next_tag( 'UL' );
+    ///     $processor->skip_current_subtree();
+    ///     'UL' === $processor->get_tag() && $processor->is_tag_closer();
+    ///
+    /// @return bool Whether the element was skipped, or `false` if not matched on an
+    ///              opening tag or if the document ended before the element closed.
+    pub fn skip_current_subtree(&mut self) -> bool {
+        if self.get_token_type() != Some(&TokenType::Tag) || self.is_tag_closer() {
+            return false;
+        }
+
+        if !self.expects_closer(None).unwrap_or(false) {
+            return true;
+        }
+
+        let depth = self.get_current_depth();
+        while self.next_token() {
+            if self.get_current_depth() < depth {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Advances to the next sibling element of the currently-matched element.
+    ///
+    /// Everything inside the current element is skipped. Siblings may be virtual,
+    /// e.g. when a formatting element is reconstructed.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_fragment( '<ul><li>One <b>1</b><li>Two</ul><p>After' );
+    ///     $processor->next_tag( 'LI' );
+    ///     $processor->next_sibling_tag();
+    ///     'LI' === $processor->get_tag();
+    ///
+    ///     // There are no more siblings, so this stops on the closing UL.
+    ///     false === $processor->next_sibling_tag();
+    ///
+    /// @return bool Whether a sibling element was found, or `false` if not matched on an
+    ///              opening tag or if the parent element closed before finding one.
+    pub fn next_sibling_tag(&mut self) -> bool {
+        let depth = self.get_current_depth();
+        if !self.skip_current_subtree() {
+            return false;
+        }
+
+        while self.next_token() {
+            let current_depth = self.get_current_depth();
+            if current_depth < depth - 1 {
+                return false;
+            }
+
+            if current_depth == depth
+                && self.get_token_type() == Some(&TokenType::Tag)
+                && !self.is_tag_closer()
+            {
+                return true;
+            }
+        }
+
+        false
     }
 
     /// Ensures internal accounting is maintained for HTML semantic rules while
@@ -515,7 +1124,7 @@ impl HtmlProcessor {
     /// calls the {@see WP_HTML_Tag_Processor::base_class_next_token()} method.
     ///
     /// @return bool
-    fn next_visitable_token(&mut self) -> bool {
+    pub(crate) fn next_visitable_token(&mut self) -> bool {
         self.current_element = None;
 
         if self.last_error.is_some() {
@@ -532,8 +1141,11 @@ impl HtmlProcessor {
          *       until there are events or until there are no more
          *       tokens works in the meantime and isn't obviously wrong.
          */
-        if self.element_queue.is_empty() && self.step(NodeToProcess::ProcessNextNode) {
-            return self.next_visitable_token();
+        if self.element_queue.is_empty() {
+            self.record_reparse_checkpoint();
+            if self.step(NodeToProcess::ProcessNextNode) {
+                return self.next_visitable_token();
+            }
         }
 
         // Process the next event on the queue
@@ -563,15 +1175,29 @@ impl HtmlProcessor {
         // Adjust the breadcrumbs for this event
         if is_pop {
             self.breadcrumbs.pop();
+            self.open_elements.pop();
+            if let Some(bookmark_name) = self
+                .open_element_tokens
+                .pop()
+                .and_then(|token| token.bookmark_name)
+            {
+                let _ = self.tag_processor.internal_bookmarks.remove(&bookmark_name);
+            }
         } else {
             self.breadcrumbs
                 .push(current_element.token.node_name.clone());
+            self.open_elements.push(OpenElement {
+                node_name: current_element.token.node_name.clone(),
+                namespace: current_element.token.namespace.clone(),
+                is_virtual: current_element.provenance == StackProvenance::Virtual,
+            });
+            self.open_element_tokens.push(current_element.token.clone());
         }
 
         // Avoid sending close events for elements which don't expect a closing
         if is_pop
             && !self
-                .expects_closer(Some(&current_element.token))
+                .token_expects_closer(Some(&current_element.token))
                 .unwrap_or(false)
         {
             return self.next_visitable_token();
@@ -593,18 +1219,12 @@ impl HtmlProcessor {
     ///
     /// @return bool Whether the current tag is a tag closer.
     pub fn is_tag_closer(&self) -> bool {
-        if self.is_virtual() {
-            self.current_element
-                .as_ref()
-                .expect("Must have current element if is virtual")
-                .operation
-                == StackOperation::Pop
-                && self
-                    .get_token_type()
-                    .map(|t| t == &TokenType::Tag)
-                    .unwrap_or(false)
-        } else {
-            self.tag_processor.is_tag_closer()
+        match self.current_element.as_ref() {
+            Some(current_element) if self.is_virtual() => {
+                current_element.operation == StackOperation::Pop
+                    && self.get_token_type() == Some(&TokenType::Tag)
+            }
+            _ => self.tag_processor.is_tag_closer(),
         }
     }
 
@@ -612,7 +1232,7 @@ impl HtmlProcessor {
     /// while processing HTML, rather than a token found in the HTML text itself.
     ///
     /// @return bool Whether the current token is virtual.
-    fn is_virtual(&self) -> bool {
+    pub(crate) fn is_virtual(&self) -> bool {
         self.current_element
             .as_ref()
             .is_some_and(|current_element| current_element.provenance == StackProvenance::Virtual)
@@ -640,8 +1260,40 @@ impl HtmlProcessor {
     ///                              May also contain the wildcard `*` which matches a single element, e.g. `array( 'SECTION', '*' )`.
     /// @return bool Whether the currently-matched tag is found at the given nested structure.
     pub fn matches_breadcrumbs(&self, breadcrumbs: Option<&Vec<&str>>) -> bool {
-        todo!()
-    }
+        let matches = |node_name: &NodeName, crumb: &str| match node_name {
+            NodeName::Tag(tag_name) => tag_name.as_bytes().eq_ignore_ascii_case(crumb.as_bytes()),
+            NodeName::Token(_) => false,
+        };
+
+        // Everything matches when there are zero constraints.
+        let Some((breadcrumbs, last_crumb)) =
+            breadcrumbs.and_then(|breadcrumbs| Some((breadcrumbs, breadcrumbs.last()?)))
+        else {
+            return true;
+        };
+
+        // Start at the last crumb.
+        if "*" != *last_crumb
+            && !self
+                .get_tag()
+                .is_some_and(|tag_name| matches(&NodeName::Tag(tag_name), last_crumb))
+        {
+            return false;
+        }
+
+        let mut crumbs = breadcrumbs.iter().rev();
+        for node_name in self.breadcrumbs.iter().rev() {
+            let Some(crumb) = crumbs.next() else {
+                return true;
+            };
+
+            if "*" != *crumb && !matches(node_name, crumb) {
+                return false;
+            }
+        }
+
+        crumbs.next().is_none()
+    }
 
     /// Indicates if the currently-matched node expects a closing
     /// token, or if it will self-close on the next step.
@@ -653,11 +1305,22 @@ impl HtmlProcessor {
     /// foreign content will also act just like a void tag, immediately
     /// closing as soon as the processor advances to the next token.
     ///
-    /// @param WP_HTML_Token|null $node Optional. Node to examine, if provided.
-    ///                                 Default is to examine current node.
+    /// @param NodeHandle|null $node Optional. Node to examine, if provided.
+    ///                              Default is to examine current node.
     /// @return bool|null Whether to expect a closer for the currently-matched node,
     ///                   or `null` if not matched on any token.
-    pub fn expects_closer(&self, node: Option<&HTMLToken>) -> Option<bool> {
+    pub fn expects_closer(&self, node: Option<&NodeHandle>) -> Option<bool> {
+        self.token_expects_closer(node.map(|handle| &handle.token))
+    }
+
+    /// Indicates if a node on the stack of open elements expects a closing token.
+    ///
+    /// @see self::expects_closer()
+    ///
+    /// @param WP_HTML_Token|null $node Optional. Node to examine, if provided.
+    ///                                 Default is to examine current node.
+    /// @return bool|null Whether to expect a closer for the node, or `null` if not matched on any token.
+    fn token_expects_closer(&self, node: Option<&HTMLToken>) -> Option<bool> {
         let (node_name, namespace, has_self_closing_flag) = if let Some(token) = node {
             (
                 &token.node_name,
@@ -684,7 +1347,8 @@ impl HtmlProcessor {
                 | TokenType::FunkyComment,
             ) => false,
 
-            NodeName::Token(TokenType::Tag) => unreachable!("#tag NodeName should never exist"),
+            // Tags are always named by their tag name, so this never describes a token.
+            NodeName::Token(TokenType::Tag) => false,
 
             // Self-closing elements in foreign content.
             NodeName::Tag(_) if *namespace != ParsingNamespace::Html => !has_self_closing_flag,
@@ -738,7 +1402,7 @@ impl HtmlProcessor {
              * on the stack is a void element, it must be closed.
              */
             if let Some(top_node) = self.state.stack_of_open_elements.current_node() {
-                if !self.expects_closer(Some(top_node)).unwrap_or(false) {
+                if !self.token_expects_closer(Some(top_node)).unwrap_or(false) {
                     self.pop();
                 }
             }
@@ -759,7 +1423,15 @@ impl HtmlProcessor {
             return false;
         }
 
-        let token_name = self.get_token_name().unwrap();
+        if node_to_process == NodeToProcess::ProcessNextNode && !self.budget.count_token() {
+            self.last_error = Some(HtmlProcessorError::ExceededBudget);
+            return false;
+        }
+
+        let Some(token_name) = self.get_token_name() else {
+            self.last_error = Some(HtmlProcessorError::UnexpectedState);
+            return false;
+        };
         if node_to_process != NodeToProcess::ReprocessCurrentNode {
             if let Ok(bookmark) = self.bookmark_token() {
                 self.state.current_token = Some(HTMLToken::new(
@@ -774,7 +1446,10 @@ impl HtmlProcessor {
         }
 
         let parse_in_current_insertion_mode = self.state.stack_of_open_elements.count() == 0 || {
-            let adjusted_current_node = self.get_adjusted_current_node().unwrap();
+            let Some(adjusted_current_node) = self.get_adjusted_current_node() else {
+                self.last_error = Some(HtmlProcessorError::UnexpectedState);
+                return false;
+            };
             let is_closer = self.is_tag_closer();
             let is_start_tag =
                 self.tag_processor.parser_state == ParserState::MatchedTag && !is_closer;
@@ -796,27 +1471,44 @@ impl HtmlProcessor {
                     && (is_start_tag || token_name == TokenType::Text.into()))
         };
 
-        let op = match self.get_token_type() {
-            Some(TokenType::Tag) if self.is_tag_closer() => Op::TagPop(self.get_tag().unwrap()),
-            Some(TokenType::Tag) => Op::TagPush(self.get_tag().unwrap()),
-            Some(
-                token @ (TokenType::CdataSection
-                | TokenType::Comment
-                | TokenType::Doctype
-                | TokenType::FunkyComment
-                | TokenType::PresumptuousTag
-                | TokenType::Text),
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "step",
+            token = ?token_name,
+            insertion_mode = ?self.state.insertion_mode,
+        )
+        .entered();
+
+        let op = match (self.get_token_type(), self.get_tag()) {
+            (Some(TokenType::Tag), Some(tag)) if self.is_tag_closer() => Op::TagPop(tag),
+            (Some(TokenType::Tag), Some(tag)) => Op::TagPush(tag),
+            (
+                Some(
+                    token @ (TokenType::CdataSection
+                    | TokenType::Comment
+                    | TokenType::Doctype
+                    | TokenType::FunkyComment
+                    | TokenType::PresumptuousTag
+                    | TokenType::Text),
+                ),
+                _,
             ) => Op::Token(token.clone()),
-            None => unreachable!("Op should never be made when no token is available."),
+            // An op is never made without a token, and every tag token has a tag name.
+            (Some(TokenType::Tag), None) | (None, _) => {
+                self.last_error = Some(HtmlProcessorError::UnexpectedState);
+                return false;
+            }
         };
 
-        if parse_in_current_insertion_mode {
+        let stepped = if parse_in_current_insertion_mode {
             self.step_in_current_insertion_mode(&op)
         } else {
             self.step_in_foreign_content(&op)
-        }
+        };
 
+        // Errors, such as exceeding the maximum depth, may occur while processing the token.
         // @todo use Results
+        stepped && self.last_error.is_none()
     }
 
     fn step_in_current_insertion_mode(&mut self, op: &Op) -> bool {
@@ -862,6 +1554,60 @@ impl HtmlProcessor {
         self.breadcrumbs.as_slice()
     }
 
+    /// Returns the elements on the path to the currently-matched node, if matched.
+    ///
+    /// This is a richer form of the breadcrumbs, starting at the outermost element and
+    /// descending toward the matched node. Each element reports its namespace and whether
+    /// it was implied by the HTML rather than found in it.
+    ///
+    /// Like the breadcrumbs, this reflects the currently-matched node, which may differ from
+    /// the parser's internal stack of open elements while it's processing ahead.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_full_parser( '<p><svg><rect/></svg>' );
+    ///     $processor->next_tag( 'RECT' );
+    ///     foreach ( $processor->get_open_elements() as $element ) {
+    ///         // HTML (virtual), BODY (virtual), P, SVG (svg), RECT (svg)
+    ///     }
+    ///
+    /// @return OpenElement[] Elements from the root of the document to the matched node.
+    pub fn get_open_elements(&self) -> core::slice::Iter<'_, OpenElement> {
+        self.open_elements.iter()
+    }
+
+    /// Returns the parser's list of active formatting elements, for debugging.
+    ///
+    /// The list starts with the entry added first. Formatting elements in this list
+    /// are reconstructed when they're implicitly closed before their content ends,
+    /// and they're rearranged by the adoption agency algorithm for misnested tags.
+    /// Inspecting the list helps when explaining why the parser produced a tree.
+    ///
+    /// Unlike the breadcrumbs, this reflects the parser's internal state, which may be
+    /// ahead of the currently-matched node.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_full_parser( '<b><i><table><td>x' );
+    ///     $processor->next_tag( 'TD' );
+    ///     $processor->get_active_formatting_elements() === array( 'B', 'I', 'marker' );
+    ///
+    /// @see https://html.spec.whatwg.org/#list-of-active-formatting-elements
+    ///
+    /// @return ActiveFormattingEntry[] Entries in the list of active formatting elements.
+    pub fn get_active_formatting_elements(&self) -> Vec<ActiveFormattingEntry> {
+        self.state
+            .active_formatting_elements
+            .walk_down()
+            .map(|entry| match entry {
+                ActiveFormattingElement::Token(token) => {
+                    ActiveFormattingEntry::Element(token.node_name.clone())
+                }
+                ActiveFormattingElement::Marker => ActiveFormattingEntry::Marker,
+            })
+            .collect()
+    }
+
     /// Returns the nesting depth of the current location in the document.
     ///
     /// Example:
@@ -883,8 +1629,8 @@ impl HtmlProcessor {
     ///     3 === $processor->get_current_depth();
     ///
     /// @return int Nesting-depth of current location in the document.
-    pub fn get_current_depth() -> usize {
-        todo!()
+    pub fn get_current_depth(&self) -> usize {
+        self.breadcrumbs.len()
     }
 
     /// Normalizes an HTML fragment by serializing it.
@@ -922,8 +1668,10 @@ impl HtmlProcessor {
     ///
     /// @return string|null Normalized output, or `null` if unable to normalize.
     pub fn normalize(html: &[u8]) -> Result<String, ()> {
-        let processor = Self::create_fragment(html, "<body>", "UTF-8")
-            .expect("Fragment creation fails when not UTF-8. Statically set here.");
+        // Fragment creation only fails for unsupported contexts and encodings.
+        let Some(mut processor) = Self::create_fragment(html, "<body>", "UTF-8") else {
+            return Err(());
+        };
         processor.serialize()
     }
 
@@ -962,8 +1710,309 @@ impl HtmlProcessor {
     ///
     /// @return string|null Normalized HTML markup represented by processor,
     ///                     or `null` if unable to generate serialization.
-    pub fn serialize(&self) -> Result<String, ()> {
-        todo!()
+    pub fn serialize(&mut self) -> Result<String, ()> {
+        let mut html = Vec::new();
+        self.serialize_each_token(|token| {
+            html.extend_from_slice(token);
+            Ok::<_, ()>(())
+        })
+        .map_err(|_| ())?;
+
+        Ok(String::from_utf8_lossy(&html).into_owned())
+    }
+
+    /// Writes the normalized HTML into a writer as it's parsed, token by token.
+    ///
+    /// The output is the same as from `serialize()`, but without first building the whole
+    /// document in memory. The same restrictions apply: the processor must not have started
+    /// scanning. Since output is written as parsing proceeds, an error may leave incomplete
+    /// HTML in the writer.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_full_parser( $post_content );
+    ///     $processor->write_serialized( $file )?;
+    ///
+    /// @see self::serialize()
+    ///
+    /// @param Write $writer Where to write the normalized HTML.
+    /// @return Result Any error from the writer, or an `InvalidData` error if the processor
+    ///                had already started scanning or if it stopped on a parsing error.
+    #[cfg(feature = "std")]
+    pub fn write_serialized(&mut self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        self.serialize_each_token(|token| writer.write_all(String::from_utf8_lossy(token).as_bytes()))
+            .map_err(|error| {
+                error.unwrap_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Cannot serialize an HTML Processor which has started scanning or stopped on a parsing error.",
+                    )
+                })
+            })
+    }
+
+    /// Writes the normalized HTML into a string writer, e.g. a `String` or a `Formatter`.
+    ///
+    /// @see self::write_serialized()
+    ///
+    /// @param Write $writer Where to write the normalized HTML.
+    /// @return Result `fmt::Error` if the writer failed, if the processor had already
+    ///                started scanning, or if it stopped on a parsing error.
+    pub fn write_serialized_fmt(
+        &mut self,
+        writer: &mut impl core::fmt::Write,
+    ) -> core::fmt::Result {
+        self.serialize_each_token(|token| writer.write_str(&String::from_utf8_lossy(token)))
+            .map_err(|_| core::fmt::Error)
+    }
+
+    /// Passes the serialization of each token, in order, to a function.
+    ///
+    /// @param callable $write Called with each serialized token; stops on the first error.
+    /// @return Result `Err(Some)` with the first error from the function, or `Err(None)` if
+    ///                the processor had already started scanning or stopped on a parsing error.
+    fn serialize_each_token<E>(
+        &mut self,
+        mut write: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), Option<E>> {
+        if self.tag_processor.parser_state != ParserState::Ready {
+            // @todo wp_trigger_error( __METHOD__, 'An HTML Processor which has already started processing cannot serialize its contents. Serialize immediately after creating the instance.', E_USER_WARNING );
+            return Err(None);
+        }
+
+        while self.next_visitable_token() {
+            write(&self.serialize_token()).map_err(Some)?;
+        }
+
+        if self.last_error.is_some() {
+            // @todo wp_trigger_error( __METHOD__, "Cannot serialize HTML Processor with parsing error: {$this->get_last_error()}.", E_USER_WARNING );
+            return Err(None);
+        }
+
+        Ok(())
+    }
+
+    /// Returns a representation of the document tree in the format used by the html5lib tests.
+    ///
+    /// Each node appears on its own line, indented by two spaces for each level of depth.
+    /// Elements are followed by their attributes, sorted by name, and the contents of a
+    /// TEMPLATE element are nested under a `content` line. Elements implied by the HTML
+    /// appear as if they were in it, and adjacent text nodes are merged.
+    ///
+    /// Like serialization, this must start with a processor which hasn't started scanning,
+    /// and will leave it in the completed state.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_full_parser( '<p class=note>Hi<!-- x -->' );
+    ///     echo $processor->debug_tree();
+    ///     // <html>
+    ///     //   <head>
+    ///     //   <body>
+    ///     //     <p>
+    ///     //       class="note"
+    ///     //       "Hi"
+    ///     //       <!--  x  -->
+    ///
+    /// @see https://github.com/html5lib/html5lib-tests/tree/master/tree-construction
+    ///
+    /// @return string|null Tree representation of the document, or `null` if the processor
+    ///                     had already started scanning or if it stopped on an error.
+    pub fn debug_tree(&mut self) -> Option<Box<[u8]>> {
+        const INDENT: &[u8] = b"  ";
+
+        /// The last of the elements always found in a full document which has been visited.
+        enum Reached {
+            None,
+            Html,
+            Head,
+            BodyOrFrameset,
+        }
+
+        if self.tag_processor.parser_state != ParserState::Ready {
+            return None;
+        }
+
+        let mut output = Vec::new();
+        let mut text_node = Vec::new();
+        let mut indent_level = 0;
+        let mut reached = Reached::None;
+
+        while self.next_visitable_token() {
+            let token_type = self.get_token_type()?;
+
+            if !matches!(token_type, TokenType::Text | TokenType::CdataSection)
+                && !text_node.is_empty()
+            {
+                output.append(&mut text_node);
+                output.extend_from_slice(b"\"\n");
+            }
+
+            match token_type {
+                TokenType::Doctype => {
+                    let doctype = self.get_doctype_info()?;
+                    output.extend_from_slice(b"<!DOCTYPE ");
+                    output.extend_from_slice(doctype.name.as_deref().unwrap_or_default());
+                    if doctype.public_identifier.is_some() || doctype.system_identifier.is_some() {
+                        output.extend_from_slice(b" \"");
+                        output.extend_from_slice(
+                            doctype.public_identifier.as_deref().unwrap_or_default(),
+                        );
+                        output.extend_from_slice(b"\" \"");
+                        output.extend_from_slice(
+                            doctype.system_identifier.as_deref().unwrap_or_default(),
+                        );
+                        output.push(b'"');
+                    }
+                    output.extend_from_slice(b">\n");
+                }
+
+                TokenType::Tag => {
+                    let tag_name = self.get_tag()?;
+                    let is_template = *self.get_namespace() == ParsingNamespace::Html
+                        && tag_name == TagName::TEMPLATE;
+
+                    if self.is_tag_closer() {
+                        indent_level -= if is_template { 2 } else { 1 };
+                        continue;
+                    }
+
+                    match tag_name {
+                        TagName::HTML => reached = Reached::Html,
+                        TagName::HEAD => reached = Reached::Head,
+                        TagName::BODY | TagName::FRAMESET => reached = Reached::BodyOrFrameset,
+                        _ => {}
+                    }
+
+                    let tag_indent = indent_level;
+                    if self.expects_closer(None)? {
+                        indent_level += 1;
+                    }
+
+                    output.extend(INDENT.repeat(tag_indent));
+                    output.push(b'<');
+                    match self.get_namespace() {
+                        ParsingNamespace::Html => output.extend_from_slice(
+                            &tag_name
+                                .qualified_name(&ParsingNamespace::Html)
+                                .to_ascii_lowercase(),
+                        ),
+                        namespace => {
+                            output.extend_from_slice(String::from(namespace).as_bytes());
+                            output.push(b' ');
+                            output.extend_from_slice(&self.get_qualified_tag_name()?);
+                        }
+                    }
+                    output.extend_from_slice(b">\n");
+
+                    let mut attributes = Vec::new();
+                    for name in self
+                        .get_attribute_names_with_prefix(b"")
+                        .unwrap_or_default()
+                    {
+                        let display_name = self.get_qualified_attribute_name(&name)?;
+                        let value = match self.get_attribute(&name)? {
+                            // Attributes with no value appear with an empty value.
+                            AttributeValue::BooleanTrue | AttributeValue::BooleanFalse => {
+                                Box::default()
+                            }
+                            AttributeValue::String(value) => value,
+                        };
+                        attributes.push((display_name, value));
+                    }
+
+                    /*
+                     * Sorts attributes to match html5lib sort order.
+                     *
+                     *  - First come normal HTML attributes.
+                     *  - Then come adjusted foreign attributes; these have spaces in their names.
+                     *  - Finally come non-adjusted foreign attributes; these have a colon in their names.
+                     *
+                     * Example:
+                     *
+                     *       From: <math xlink:author definitionurl xlink:title xlink:show>
+                     *     Sorted: 'definitionURL', 'xlink show', 'xlink title', 'xlink:author'
+                     */
+                    attributes.sort_by(|(a, _), (b, _)| {
+                        (a.contains(&b':'), a.contains(&b' '), a).cmp(&(
+                            b.contains(&b':'),
+                            b.contains(&b' '),
+                            b,
+                        ))
+                    });
+
+                    for (display_name, value) in attributes {
+                        output.extend(INDENT.repeat(tag_indent + 1));
+                        output.extend_from_slice(&display_name);
+                        output.extend_from_slice(b"=\"");
+                        output.extend_from_slice(&value);
+                        output.extend_from_slice(b"\"\n");
+                    }
+
+                    let modifiable_text = self.get_modifiable_text();
+                    if !modifiable_text.is_empty() {
+                        output.extend(INDENT.repeat(tag_indent + 1));
+                        output.push(b'"');
+                        output.extend_from_slice(&modifiable_text);
+                        output.extend_from_slice(b"\"\n");
+                    }
+
+                    if is_template {
+                        output.extend(INDENT.repeat(indent_level));
+                        output.extend_from_slice(b"content\n");
+                        indent_level += 1;
+                    }
+                }
+
+                TokenType::Text | TokenType::CdataSection => {
+                    let text = self.get_modifiable_text();
+                    if text.is_empty() {
+                        continue;
+                    }
+                    if text_node.is_empty() {
+                        text_node.extend(INDENT.repeat(indent_level));
+                        text_node.push(b'"');
+                    }
+                    text_node.extend_from_slice(&text);
+                }
+
+                TokenType::Comment | TokenType::FunkyComment => {
+                    let text = if *token_type == TokenType::Comment {
+                        self.get_full_comment_text()?
+                    } else {
+                        self.get_modifiable_text()
+                    };
+                    output.extend(INDENT.repeat(indent_level));
+                    output.extend_from_slice(b"<!-- ");
+                    output.extend_from_slice(&text);
+                    output.extend_from_slice(b" -->\n");
+                }
+
+                // `</>` is ignored in HTML.
+                TokenType::PresumptuousTag => {}
+            }
+        }
+
+        if self.last_error.is_some() {
+            return None;
+        }
+
+        if !text_node.is_empty() {
+            output.append(&mut text_node);
+            output.extend_from_slice(b"\"\n");
+        }
+
+        // A full document always contains these elements, even if parsing stopped before them.
+        if self.context_node.is_none() {
+            match reached {
+                Reached::None => output.extend_from_slice(b"<html>\n  <head>\n  <body>\n"),
+                Reached::Html => output.extend_from_slice(b"  <head>\n  <body>\n"),
+                Reached::Head => output.extend_from_slice(b"  <body>\n"),
+                Reached::BodyOrFrameset => {}
+            }
+        }
+
+        Some(output.into())
     }
 
     /// Serializes the currently-matched token.
@@ -975,10 +2024,150 @@ impl HtmlProcessor {
     /// @see static::serialize()
     ///
     /// @return string Serialization of token, or empty string if no serialization exists.
+    pub(crate) fn serialize_token(&self) -> Vec<u8> {
+        self.serialize_token_with(b" ", |_, _| true)
+    }
+
+    /// Serializes the currently-matched token, separating each attribute from what
+    /// precedes it with the given whitespace and writing only the chosen attributes.
     ///
-    /// @todo What do wo with this _protected_ function?
-    fn serialize_token() -> String {
-        todo!()
+    /// @see static::serialize_token()
+    ///
+    /// @param string   $attribute_separator Whitespace to write before each attribute.
+    /// @param callable $keep_attribute      Given the lower-cased name and value of each
+    ///                                      attribute, returns whether to write it.
+    /// @return string Serialization of token, or empty string if no serialization exists.
+    pub(crate) fn serialize_token_with(
+        &self,
+        attribute_separator: &[u8],
+        keep_attribute: impl Fn(&[u8], &AttributeValue) -> bool,
+    ) -> Vec<u8> {
+        let mut html = Vec::new();
+
+        match self.get_token_type() {
+            Some(TokenType::Doctype) => {
+                let Some(doctype) = self.get_doctype_info() else {
+                    return html;
+                };
+
+                html.extend_from_slice(&doctype.serialize());
+                return html;
+            }
+
+            Some(TokenType::Text) => {
+                let text = self.get_modifiable_text();
+                if text.starts_with(b"\n") && self.tag_processor.ignores_leading_newline() {
+                    html.push(b'\n');
+                }
+                html.extend_from_slice(&esc_attr(&text));
+                return html;
+            }
+
+            // Unlike the `<>` which is interpreted as plaintext, this is ignored entirely.
+            Some(TokenType::PresumptuousTag) | None => return html,
+
+            Some(TokenType::FunkyComment | TokenType::Comment) => {
+                html.extend_from_slice(b"<!--");
+                html.extend_from_slice(&self.get_full_comment_text().unwrap_or_default());
+                html.extend_from_slice(b"-->");
+                return html;
+            }
+
+            Some(TokenType::CdataSection) => {
+                html.extend_from_slice(b"<![CDATA[");
+                html.extend_from_slice(&self.get_modifiable_text());
+                html.extend_from_slice(b"]]>");
+                return html;
+            }
+
+            Some(TokenType::Tag) => {}
+        }
+
+        let Some(tag_name) = self.get_tag() else {
+            return html;
+        };
+        let in_html = *self.get_namespace() == ParsingNamespace::Html;
+        let qualified_name = replace_null_bytes(&if in_html {
+            tag_name
+                .qualified_name(&ParsingNamespace::Html)
+                .to_ascii_lowercase()
+        } else {
+            tag_name.qualified_name(self.get_namespace()).into_vec()
+        });
+
+        if self.is_tag_closer() {
+            html.extend_from_slice(b"</");
+            html.extend_from_slice(&qualified_name);
+            html.push(b'>');
+            return html;
+        }
+
+        html.push(b'<');
+        html.extend_from_slice(&qualified_name);
+        let mut attribute_names = self
+            .get_attribute_names_with_prefix(b"")
+            .unwrap_or_default();
+        // Only the first of any duplicated attributes applies.
+        let mut seen = hashbrown::HashSet::new();
+        attribute_names.retain(|name| seen.insert(name.clone()));
+        for attribute_name in attribute_names {
+            // Attributes which have been removed have a value of `false`.
+            let value = match self.get_attribute(&attribute_name) {
+                None | Some(AttributeValue::BooleanFalse) => continue,
+                Some(value) => value,
+            };
+            if !keep_attribute(&attribute_name, &value) {
+                continue;
+            }
+
+            html.extend_from_slice(attribute_separator);
+            let qualified_attribute_name = self
+                .get_qualified_attribute_name(&attribute_name)
+                .unwrap_or_else(|| attribute_name.as_ref().into());
+            html.extend_from_slice(&replace_null_bytes(&qualified_attribute_name));
+
+            if let AttributeValue::String(value) = value {
+                html.extend_from_slice(b"=\"");
+                html.extend_from_slice(&replace_null_bytes(&esc_attr(&value)));
+                html.push(b'"');
+            }
+        }
+
+        if !in_html && self.has_self_closing_flag() {
+            html.extend_from_slice(b" /");
+        }
+
+        html.push(b'>');
+
+        // Flush out self-contained elements.
+        if in_html {
+            let text = match tag_name {
+                TagName::IFRAME | TagName::NOEMBED | TagName::NOFRAMES => Some(Vec::new()),
+                TagName::SCRIPT | TagName::STYLE | TagName::XMP => {
+                    Some(self.get_modifiable_text().into_vec())
+                }
+                TagName::TITLE => Some(esc_attr(&self.get_modifiable_text())),
+                TagName::TEXTAREA => {
+                    let text = self.get_modifiable_text();
+                    let mut escaped = Vec::with_capacity(text.len() + 1);
+                    if text.starts_with(b"\n") {
+                        escaped.push(b'\n');
+                    }
+                    escaped.extend_from_slice(&esc_attr(&text));
+                    Some(escaped)
+                }
+                _ => None,
+            };
+
+            if let Some(text) = text {
+                html.extend_from_slice(&text);
+                html.extend_from_slice(b"</");
+                html.extend_from_slice(&qualified_name);
+                html.push(b'>');
+            }
+        }
+
+        html
     }
 
     /// Parses next element in the 'initial' insertion mode.
@@ -1014,7 +2203,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1031,8 +2220,8 @@ impl HtmlProcessor {
                 /*
                  * > Then, switch the insertion mode to "before html".
                  */
-                self.state.insertion_mode = InsertionMode::BEFORE_HTML;
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.set_insertion_mode(InsertionMode::BEFORE_HTML);
+                self.insert_current_html_element();
                 true
             }
             /*
@@ -1040,7 +2229,7 @@ impl HtmlProcessor {
              */
             _ => {
                 self.tag_processor.compat_mode = CompatMode::Quirks;
-                self.state.insertion_mode = InsertionMode::BEFORE_HTML;
+                self.set_insertion_mode(InsertionMode::BEFORE_HTML);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -1070,7 +2259,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1092,8 +2281,8 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "html"
              */
             Op::TagPush(TagName::HTML) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::BEFORE_HEAD;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::BEFORE_HEAD);
                 true
             }
 
@@ -1122,7 +2311,7 @@ impl HtmlProcessor {
              */
             _ => {
                 self.insert_virtual_node(TagName::HTML, None);
-                self.state.insertion_mode = InsertionMode::BEFORE_HEAD;
+                self.set_insertion_mode(InsertionMode::BEFORE_HEAD);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -1161,8 +2350,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                let token: HTMLToken = self.state.current_token.clone().unwrap();
-                self.insert_html_element(token);
+                self.insert_current_html_element();
                 true
             }
 
@@ -1180,10 +2368,12 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "head"
              */
             Op::TagPush(TagName::HEAD) => {
-                let token: HTMLToken = self.state.current_token.clone().unwrap();
+                let Some(token) = self.cloned_current_token() else {
+                    return false;
+                };
                 self.insert_html_element(token.clone());
                 self.state.head_element = Some(token);
-                self.state.insertion_mode = InsertionMode::IN_HEAD;
+                self.set_insertion_mode(InsertionMode::IN_HEAD);
                 true
             }
 
@@ -1210,7 +2400,7 @@ impl HtmlProcessor {
              */
             _ => {
                 self.insert_virtual_node(TagName::HEAD, None);
-                self.state.insertion_mode = InsertionMode::IN_HEAD;
+                self.set_insertion_mode(InsertionMode::IN_HEAD);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -1241,8 +2431,7 @@ impl HtmlProcessor {
                     == TextNodeClassification::Whitespace =>
             {
                 // Insert the character.
-                let token: HTMLToken = self.state.current_token.clone().unwrap();
-                self.insert_html_element(token.clone());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1252,7 +2441,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1272,7 +2461,7 @@ impl HtmlProcessor {
              * > A start tag whose tag name is one of: "base", "basefont", "bgsound", "link"
              */
             Op::TagPush(TagName::BASE | TagName::BASEFONT | TagName::BGSOUND | TagName::LINK) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1280,7 +2469,7 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "meta"
              */
             Op::TagPush(TagName::META) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
 
                 /*
                  * > If the active speculative HTML parser is null, then:
@@ -1320,7 +2509,7 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "title"
              */
             Op::TagPush(TagName::TITLE) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1331,7 +2520,7 @@ impl HtmlProcessor {
              * The scripting flag is never enabled in this parser.
              */
             Op::TagPush(TagName::NOFRAMES | TagName::STYLE) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1339,8 +2528,8 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "noscript", if the scripting flag is disabled
              */
             Op::TagPush(TagName::NOSCRIPT) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::IN_HEAD_NOSCRIPT;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::IN_HEAD_NOSCRIPT);
                 true
             }
 
@@ -1350,7 +2539,7 @@ impl HtmlProcessor {
              * @todo Could the adjusted insertion location be anything other than the current location?
              */
             Op::TagPush(TagName::SCRIPT) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1359,7 +2548,7 @@ impl HtmlProcessor {
              */
             Op::TagPop(TagName::HEAD) => {
                 self.pop();
-                self.state.insertion_mode = InsertionMode::AFTER_HEAD;
+                self.set_insertion_mode(InsertionMode::AFTER_HEAD);
                 true
             }
 
@@ -1380,12 +2569,12 @@ impl HtmlProcessor {
                 self.state.active_formatting_elements.insert_marker();
                 self.state.frameset_ok = false;
 
-                self.state.insertion_mode = InsertionMode::IN_TEMPLATE;
+                self.set_insertion_mode(InsertionMode::IN_TEMPLATE);
                 self.state
                     .stack_of_template_insertion_modes
                     .push(InsertionMode::IN_TEMPLATE);
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1431,7 +2620,7 @@ impl HtmlProcessor {
              */
             _ => {
                 self.pop();
-                self.state.insertion_mode = InsertionMode::AFTER_HEAD;
+                self.set_insertion_mode(InsertionMode::AFTER_HEAD);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -1482,7 +2671,7 @@ impl HtmlProcessor {
              */
             Op::TagPop(TagName::NOSCRIPT) => {
                 self.pop();
-                self.state.insertion_mode = InsertionMode::IN_HEAD;
+                self.set_insertion_mode(InsertionMode::IN_HEAD);
                 true
             }
 
@@ -1526,7 +2715,7 @@ impl HtmlProcessor {
              */
             _ => {
                 self.pop();
-                self.state.insertion_mode = InsertionMode::IN_HEAD;
+                self.set_insertion_mode(InsertionMode::IN_HEAD);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -1555,7 +2744,7 @@ impl HtmlProcessor {
                     == TextNodeClassification::Whitespace =>
             {
                 // Insert the character.
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1565,7 +2754,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1586,9 +2775,9 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "body"
              */
             Op::TagPush(TagName::BODY) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.state.frameset_ok = false;
-                self.state.insertion_mode = InsertionMode::IN_BODY;
+                self.set_insertion_mode(InsertionMode::IN_BODY);
                 true
             }
 
@@ -1596,8 +2785,8 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "frameset"
              */
             Op::TagPush(TagName::FRAMESET) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::IN_FRAMESET;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::IN_FRAMESET);
                 true
             }
 
@@ -1659,7 +2848,7 @@ impl HtmlProcessor {
              */
             _ => {
                 self.insert_virtual_node(TagName::BODY, None);
-                self.state.insertion_mode = InsertionMode::IN_BODY;
+                self.set_insertion_mode(InsertionMode::IN_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -1713,7 +2902,7 @@ impl HtmlProcessor {
                     self.state.frameset_ok = false;
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1723,7 +2912,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1858,7 +3047,7 @@ impl HtmlProcessor {
                      * There is nothing to do for this parse error, so don't check for it.
                      */
 
-                    self.state.insertion_mode = InsertionMode::AFTER_BODY;
+                    self.set_insertion_mode(InsertionMode::AFTER_BODY);
                     /*
                      * The BODY element is not removed from the stack of open elements.
                      * Only internal state has changed, this does not qualify as a "step"
@@ -1892,7 +3081,7 @@ impl HtmlProcessor {
                      * There is nothing to do for this parse error, so don't check for it.
                      */
 
-                    self.state.insertion_mode = InsertionMode::AFTER_BODY;
+                    self.set_insertion_mode(InsertionMode::AFTER_BODY);
                     self.step(NodeToProcess::ReprocessCurrentNode)
                 }
             }
@@ -1934,7 +3123,7 @@ impl HtmlProcessor {
                     self.close_a_p_element();
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1967,7 +3156,7 @@ impl HtmlProcessor {
                     }
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -1987,7 +3176,7 @@ impl HtmlProcessor {
                  * This is handled in `get_modifiable_text()`.
                  */
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.state.frameset_ok = false;
                 true
             }
@@ -2009,7 +3198,7 @@ impl HtmlProcessor {
                         self.close_a_p_element();
                     }
 
-                    self.insert_html_element(self.state.current_token.clone().unwrap());
+                    self.insert_current_html_element();
                     if !stack_contains_template {
                         self.state.form_element = self.state.current_token.clone();
                     }
@@ -2031,69 +3220,62 @@ impl HtmlProcessor {
                  * The logic for LI and DT/DD is the same except for one point: LI elements _only_
                  * close other LI elements, but a DT or DD element closes _any_ open DT or DD element.
                  */
-                loop {
-                    match node {
-                        Some(
-                            some_node @ HTMLToken {
-                                node_name: NodeName::Tag(current_tag_name),
-                                ..
-                            },
-                        ) => {
-                            let current_tag_name = current_tag_name.clone();
-                            let match_tag_name_test = if is_li {
-                                current_tag_name == TagName::LI
-                            } else {
-                                matches!(current_tag_name, TagName::DD | TagName::DT)
-                            };
-                            if match_tag_name_test {
-                                self.generate_implied_end_tags(Some(&current_tag_name));
-                                if !self
-                                    .state
-                                    .stack_of_open_elements
-                                    .current_node_is(&NodeName::Tag(current_tag_name.clone()))
-                                {
-                                    // @todo Indicate a parse error once it's possible. This error does not impact the logic here.
-                                }
-
-                                self.pop_until(&current_tag_name);
-                                break;
-                            }
+                // Only tags are expected here; anything else ends the search.
+                while let Some(
+                    some_node @ HTMLToken {
+                        node_name: NodeName::Tag(current_tag_name),
+                        ..
+                    },
+                ) = node
+                {
+                    let current_tag_name = current_tag_name.clone();
+                    let match_tag_name_test = if is_li {
+                        current_tag_name == TagName::LI
+                    } else {
+                        matches!(current_tag_name, TagName::DD | TagName::DT)
+                    };
+                    if match_tag_name_test {
+                        self.generate_implied_end_tags(Some(&current_tag_name));
+                        if !self
+                            .state
+                            .stack_of_open_elements
+                            .current_node_is(&NodeName::Tag(current_tag_name.clone()))
+                        {
+                            // @todo Indicate a parse error once it's possible. This error does not impact the logic here.
+                        }
 
-                            /*
-                             * > If node is in the special category, but is not an address, div,
-                             * > or p element, then jump to the step labeled done below.
-                             */
-                            if !matches!(
-                                current_tag_name,
-                                TagName::ADDRESS | TagName::DIV | TagName::P
-                            ) && Self::is_special(&current_tag_name)
-                            {
-                                break;
-                            }
+                        self.pop_until(&current_tag_name);
+                        break;
+                    }
 
-                            /*
-                             * > Otherwise, set node to the previous entry in the stack of open elements
-                             * > and return to the step labeled loop.
-                             */
-                            node = self
-                                .state
-                                .stack_of_open_elements
-                                .walk_up()
-                                .skip_while(|&stack_node| stack_node != some_node)
-                                .nth(1);
-                            continue;
-                        }
-                        None => break,
-                        _ => {
-                            unreachable!("Should not have token nodes here")
-                        }
+                    /*
+                     * > If node is in the special category, but is not an address, div,
+                     * > or p element, then jump to the step labeled done below.
+                     */
+                    if !matches!(
+                        current_tag_name,
+                        TagName::ADDRESS | TagName::DIV | TagName::P
+                    ) && Self::is_special(&current_tag_name)
+                    {
+                        break;
                     }
+
+                    /*
+                     * > Otherwise, set node to the previous entry in the stack of open elements
+                     * > and return to the step labeled loop.
+                     */
+                    node = self
+                        .state
+                        .stack_of_open_elements
+                        .walk_up()
+                        .skip_while(|&stack_node| stack_node != some_node)
+                        .nth(1);
                 }
                 if self.state.stack_of_open_elements.has_p_in_button_scope() {
                     self.close_a_p_element();
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2128,7 +3310,7 @@ impl HtmlProcessor {
                 }
 
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.state.frameset_ok = false;
 
                 true
@@ -2139,6 +3321,8 @@ impl HtmlProcessor {
              * > "button", "center", "details", "dialog", "dir", "div", "dl", "fieldset",
              * > "figcaption", "figure", "footer", "header", "hgroup", "listing", "main",
              * > "menu", "nav", "ol", "pre", "search", "section", "summary", "ul"
+             *
+             * With customizable SELECT, "select" is also in this list.
              */
             Op::TagPop(
                 tag_name @ (TagName::ADDRESS
@@ -2167,8 +3351,11 @@ impl HtmlProcessor {
                 | TagName::SEARCH
                 | TagName::SECTION
                 | TagName::SUMMARY
+                | TagName::SELECT
                 | TagName::UL),
-            ) => {
+            ) if *tag_name != TagName::SELECT
+                || self.select_parsing == SelectParsing::Customizable =>
+            {
                 if !self
                     .state
                     .stack_of_open_elements
@@ -2208,20 +3395,17 @@ impl HtmlProcessor {
                      * @todo It's necessary to check if the form token itself is in scope, not
                      *       simply whether any FORM is in scope.
                      */
-                    if node.is_none()
-                        || !self
-                            .state
+                    let Some(node) = node.filter(|_| {
+                        self.state
                             .stack_of_open_elements
                             .has_element_in_scope(&TagName::FORM)
-                    {
+                    }) else {
                         // Parse error: ignore the token.
                         return self.step(NodeToProcess::ProcessNextNode);
-                    }
-
-                    let node = node.unwrap();
+                    };
 
                     self.generate_implied_end_tags(None);
-                    if node != *self.state.stack_of_open_elements.current_node().unwrap() {
+                    if self.state.stack_of_open_elements.current_node() != Some(&node) {
                         // @todo Indicate a parse error once it's possible. This error does not impact the logic here.
                         return self
                             .bail(UnsupportedException::CannotCloseFormWithOtherElementsOpen);
@@ -2264,7 +3448,7 @@ impl HtmlProcessor {
              */
             Op::TagPop(TagName::P) => {
                 if !self.state.stack_of_open_elements.has_p_in_button_scope() {
-                    self.insert_html_element(self.state.current_token.clone().unwrap());
+                    self.insert_current_html_element();
                 }
 
                 self.close_a_p_element();
@@ -2385,10 +3569,11 @@ impl HtmlProcessor {
                 }
 
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state
-                    .active_formatting_elements
-                    .push(self.state.current_token.clone().unwrap());
+                let Some(token) = self.cloned_current_token() else {
+                    return false;
+                };
+                self.insert_html_element(token.clone());
+                self.state.active_formatting_elements.push(token);
                 true
             }
 
@@ -2411,10 +3596,11 @@ impl HtmlProcessor {
                 | TagName::U,
             ) => {
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state
-                    .active_formatting_elements
-                    .push(self.state.current_token.clone().unwrap());
+                let Some(token) = self.cloned_current_token() else {
+                    return false;
+                };
+                self.insert_html_element(token.clone());
+                self.state.active_formatting_elements.push(token);
                 true
             }
 
@@ -2434,11 +3620,12 @@ impl HtmlProcessor {
                     self.reconstruct_active_formatting_elements();
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state
-                    .active_formatting_elements
-                    .push(self.state.current_token.clone().unwrap());
-                true
+                let Some(token) = self.cloned_current_token() else {
+                    return false;
+                };
+                self.insert_html_element(token.clone());
+                self.state.active_formatting_elements.push(token);
+                true
             }
 
             /*
@@ -2470,7 +3657,7 @@ impl HtmlProcessor {
              */
             Op::TagPush(TagName::APPLET | TagName::MARQUEE | TagName::OBJECT) => {
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.state.active_formatting_elements.insert_marker();
                 self.state.frameset_ok = false;
                 true
@@ -2497,7 +3684,7 @@ impl HtmlProcessor {
                         // This is a parse error.
                     }
 
-                    self.pop_until(&self.get_tag().unwrap());
+                    self.pop_until(tag_name);
                     self.state
                         .active_formatting_elements
                         .clear_up_to_last_marker();
@@ -2519,9 +3706,9 @@ impl HtmlProcessor {
                     self.close_a_p_element();
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.state.frameset_ok = false;
-                self.state.insertion_mode = InsertionMode::IN_TABLE;
+                self.set_insertion_mode(InsertionMode::IN_TABLE);
                 true
             }
 
@@ -2544,7 +3731,7 @@ impl HtmlProcessor {
                 | TagName::WBR,
             ) => {
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.state.frameset_ok = false;
                 true
             }
@@ -2553,8 +3740,32 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "input"
              */
             Op::TagPush(TagName::INPUT) => {
+                if self.select_parsing == SelectParsing::Customizable {
+                    /*
+                     * > If the parser was created as part of the HTML fragment parsing algorithm
+                     * > (fragment case) and the context element passed to that algorithm is a
+                     * > select element: Parse error. Ignore the token.
+                     */
+                    if self.is_select_fragment() {
+                        return self.step(NodeToProcess::ProcessNextNode);
+                    }
+
+                    /*
+                     * > If the stack of open elements has a select element in scope: Parse error.
+                     * > Pop elements from the stack of open elements until a select element
+                     * > has been popped from the stack.
+                     */
+                    if self
+                        .state
+                        .stack_of_open_elements
+                        .has_element_in_scope(&TagName::SELECT)
+                    {
+                        self.pop_until(&TagName::SELECT);
+                    }
+                }
+
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
 
                 /*
                  * > If the token does not have an attribute with the name "type", or if it does,
@@ -2581,7 +3792,7 @@ impl HtmlProcessor {
              * > A start tag whose tag name is one of: "param", "source", "track"
              */
             Op::TagPush(TagName::PARAM | TagName::SOURCE | TagName::TRACK) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2592,7 +3803,22 @@ impl HtmlProcessor {
                 if self.state.stack_of_open_elements.has_p_in_button_scope() {
                     self.close_a_p_element();
                 }
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+
+                /*
+                 * > If the stack of open elements has a select element in scope, then generate
+                 * > implied end tags. If the stack of open elements has an option element in
+                 * > scope or has an optgroup element in scope, then this is a parse error.
+                 */
+                if self.select_parsing == SelectParsing::Customizable
+                    && self
+                        .state
+                        .stack_of_open_elements
+                        .has_element_in_scope(&TagName::SELECT)
+                {
+                    self.generate_implied_end_tags(None);
+                }
+
+                self.insert_current_html_element();
                 self.state.frameset_ok = false;
                 true
             }
@@ -2609,7 +3835,7 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "textarea"
              */
             Op::TagPush(TagName::TEXTAREA) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
 
                 /*
                  * > If the next token is a U+000A LINE FEED (LF) character token, then ignore
@@ -2645,7 +3871,7 @@ impl HtmlProcessor {
                  *
                  * As a self-contained node, this behavior is handled in the Tag Processor.
                  */
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2660,7 +3886,7 @@ impl HtmlProcessor {
                  *
                  * As a self-contained node, this behavior is handled in the Tag Processor.
                  */
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2671,7 +3897,7 @@ impl HtmlProcessor {
              * The scripting flag is never enabled in this parser.
              */
             Op::TagPush(TagName::NOEMBED) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2679,8 +3905,44 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "select"
              */
             Op::TagPush(TagName::SELECT) => {
+                if self.select_parsing == SelectParsing::Customizable {
+                    /*
+                     * > If the parser was created as part of the HTML fragment parsing algorithm
+                     * > (fragment case) and the context element passed to that algorithm is a
+                     * > select element: Parse error. Ignore the token.
+                     */
+                    if self.is_select_fragment() {
+                        return self.step(NodeToProcess::ProcessNextNode);
+                    }
+
+                    /*
+                     * > Otherwise, if the stack of open elements has a select element in scope:
+                     * > Parse error. Pop elements from the stack of open elements until a select
+                     * > element has been popped from the stack.
+                     *
+                     * The token itself is ignored; it only closes the open SELECT.
+                     */
+                    if self
+                        .state
+                        .stack_of_open_elements
+                        .has_element_in_scope(&TagName::SELECT)
+                    {
+                        self.pop_until(&TagName::SELECT);
+                        return true;
+                    }
+
+                    /*
+                     * > Otherwise: Reconstruct the active formatting elements, if any. Insert an
+                     * > HTML element for the token. Set the frameset-ok flag to "not ok".
+                     */
+                    self.reconstruct_active_formatting_elements();
+                    self.insert_current_html_element();
+                    self.state.frameset_ok = false;
+                    return true;
+                }
+
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.state.frameset_ok = false;
 
                 match self.state.insertion_mode {
@@ -2693,19 +3955,42 @@ impl HtmlProcessor {
                     | InsertionMode::IN_TABLE_BODY
                     | InsertionMode::IN_ROW
                     | InsertionMode::IN_CELL => {
-                        self.state.insertion_mode = InsertionMode::IN_SELECT_IN_TABLE;
+                        self.set_insertion_mode(InsertionMode::IN_SELECT_IN_TABLE);
                     }
 
                     /*
                      * > Otherwise, switch the insertion mode to "in select".
                      */
                     _ => {
-                        self.state.insertion_mode = InsertionMode::IN_SELECT;
+                        self.set_insertion_mode(InsertionMode::IN_SELECT);
                     }
                 }
                 true
             }
 
+            /*
+             * > A start tag whose tag name is "option"
+             * > A start tag whose tag name is "optgroup"
+             *
+             * > If the stack of open elements has a select element in scope, then generate
+             * > implied end tags, except for optgroup elements when the token is an option.
+             * > It's a parse error if this leaves an option (or optgroup) element in scope.
+             */
+            Op::TagPush(tag_name @ (TagName::OPTGROUP | TagName::OPTION))
+                if self.select_parsing == SelectParsing::Customizable
+                    && self
+                        .state
+                        .stack_of_open_elements
+                        .has_element_in_scope(&TagName::SELECT) =>
+            {
+                self.generate_implied_end_tags(
+                    (*tag_name == TagName::OPTION).then_some(&TagName::OPTGROUP),
+                );
+                self.reconstruct_active_formatting_elements();
+                self.insert_current_html_element();
+                true
+            }
+
             /*
              * > A start tag whose tag name is one of: "optgroup", "option"
              */
@@ -2718,7 +4003,7 @@ impl HtmlProcessor {
                     self.pop();
                 }
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2742,7 +4027,7 @@ impl HtmlProcessor {
                     }
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2757,22 +4042,19 @@ impl HtmlProcessor {
                 {
                     self.generate_implied_end_tags(Some(&TagName::RTC));
 
-                    let current_node_name = self
+                    if self
                         .state
                         .stack_of_open_elements
                         .current_node()
-                        .unwrap()
-                        .node_name
-                        .clone();
-                    if matches!(
-                        current_node_name,
-                        NodeName::Tag(TagName::RUBY | TagName::RTC)
-                    ) {
+                        .is_some_and(|node| {
+                            matches!(node.node_name, NodeName::Tag(TagName::RUBY | TagName::RTC))
+                        })
+                    {
                         // @todo Indicate a parse error once it's possible.
                     }
                 }
 
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2788,9 +4070,10 @@ impl HtmlProcessor {
                  *
                  * These ought to be handled in the attribute methods.
                  */
-                let token = self.state.current_token.as_mut().unwrap();
+                let Some(mut token) = self.cloned_current_token() else {
+                    return false;
+                };
                 token.namespace = ParsingNamespace::MathML;
-                let token = token.clone();
                 let has_self_closing_flag = token.has_self_closing_flag;
                 self.insert_html_element(token);
                 if has_self_closing_flag {
@@ -2811,9 +4094,10 @@ impl HtmlProcessor {
                  *
                  * These ought to be handled in the attribute methods.
                  */
-                let token = self.state.current_token.as_mut().unwrap();
+                let Some(mut token) = self.cloned_current_token() else {
+                    return false;
+                };
                 token.namespace = ParsingNamespace::Svg;
-                let token = token.clone();
                 let has_self_closing_flag = token.has_self_closing_flag;
                 self.insert_html_element(token);
                 if has_self_closing_flag {
@@ -2847,7 +4131,7 @@ impl HtmlProcessor {
              */
             Op::TagPush(_) => {
                 self.reconstruct_active_formatting_elements();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -2911,9 +4195,9 @@ impl HtmlProcessor {
                 }
             }
 
-            Op::Token(TokenType::CdataSection) => unreachable!("CDATA does not exist in HTML5."),
-            Op::Token(TokenType::Tag) => {
-                unreachable!("#tag token operations are handled by Op::TagPush and Op::TagPop.")
+            // CDATA does not exist in HTML5 and #tag tokens are handled by Op::TagPush and Op::TagPop.
+            Op::Token(TokenType::CdataSection | TokenType::Tag) => {
+                self.fail_with_unexpected_state()
             }
         }
     }
@@ -2930,19 +4214,12 @@ impl HtmlProcessor {
     ///
     /// @return bool Whether an element was found.
     fn step_in_table(&mut self, op: &Op) -> bool {
-        let HTMLToken {
-            node_name: current_node_tag_name,
+        let Some(HTMLToken {
+            node_name: NodeName::Tag(current_node_tag_name),
             ..
-        } = self
-            .state
-            .stack_of_open_elements
-            .current_node()
-            .expect("Step in table expects a current node.");
-        let current_node_tag_name = match current_node_tag_name {
-            NodeName::Tag(tag_name) => tag_name,
-            NodeName::Token(_) => {
-                unreachable!("Step in table should never have a non-tag current node.")
-            }
+        }) = self.state.stack_of_open_elements.current_node()
+        else {
+            return self.fail_with_unexpected_state();
         };
 
         match op {
@@ -2989,7 +4266,7 @@ impl HtmlProcessor {
                      * @see https://html.spec.whatwg.org/#parsing-main-intabletext
                      */
                     TextNodeClassification::Whitespace => {
-                        self.insert_html_element(self.state.current_token.clone().unwrap());
+                        self.insert_current_html_element();
                         true
                     }
 
@@ -3006,7 +4283,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -3023,8 +4300,8 @@ impl HtmlProcessor {
             Op::TagPush(TagName::CAPTION) => {
                 self.clear_to_table_context();
                 self.state.active_formatting_elements.insert_marker();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::IN_CAPTION;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::IN_CAPTION);
                 true
             }
 
@@ -3033,8 +4310,8 @@ impl HtmlProcessor {
              */
             Op::TagPush(TagName::COLGROUP) => {
                 self.clear_to_table_context();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::IN_COLUMN_GROUP;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::IN_COLUMN_GROUP);
                 true
             }
 
@@ -3049,7 +4326,7 @@ impl HtmlProcessor {
                  * > then switch the insertion mode to "in column group".
                  */
                 self.insert_virtual_node(TagName::COLGROUP, None);
-                self.state.insertion_mode = InsertionMode::IN_COLUMN_GROUP;
+                self.set_insertion_mode(InsertionMode::IN_COLUMN_GROUP);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -3058,8 +4335,8 @@ impl HtmlProcessor {
              */
             Op::TagPush(TagName::TBODY | TagName::TFOOT | TagName::THEAD) => {
                 self.clear_to_table_context();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::IN_TABLE_BODY;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::IN_TABLE_BODY);
                 true
             }
 
@@ -3074,7 +4351,7 @@ impl HtmlProcessor {
                  * > then switch the insertion mode to "in table body".
                  */
                 self.insert_virtual_node(TagName::TBODY, None);
-                self.state.insertion_mode = InsertionMode::IN_TABLE_BODY;
+                self.set_insertion_mode(InsertionMode::IN_TABLE_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -3161,7 +4438,7 @@ impl HtmlProcessor {
                     }) =>
             {
                 // @todo Indicate a parse error once it's possible.
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -3180,8 +4457,11 @@ impl HtmlProcessor {
                     self.step(NodeToProcess::ProcessNextNode)
                 } else {
                     // This FORM is special because it immediately closes and cannot have other children.
-                    self.insert_html_element(self.state.current_token.clone().unwrap());
-                    self.state.form_element = Some(self.state.current_token.clone().unwrap());
+                    let Some(token) = self.cloned_current_token() else {
+                        return false;
+                    };
+                    self.insert_html_element(token.clone());
+                    self.state.form_element = Some(token);
                     self.pop();
                     true
                 }
@@ -3209,8 +4489,10 @@ impl HtmlProcessor {
     /// @see WP_HTML_Processor::step
     ///
     /// @return bool Whether an element was found.
-    fn step_in_table_text(&mut self, op: &Op) -> bool {
-        todo!()
+    fn step_in_table_text(&mut self, _op: &Op) -> bool {
+        // Text in tables is handled in place by the "in table" insertion mode,
+        // which never switches to this one.
+        self.fail_with_unexpected_state()
     }
 
     /// Parses next element in the 'in caption' insertion mode.
@@ -3269,7 +4551,7 @@ impl HtmlProcessor {
                 self.state
                     .active_formatting_elements
                     .clear_up_to_last_marker();
-                self.state.insertion_mode = InsertionMode::IN_TABLE;
+                self.set_insertion_mode(InsertionMode::IN_TABLE);
 
                 // If this is not a CAPTION end tag, the token should be reprocessed.
                 if op != &Op::TagPop(TagName::CAPTION) {
@@ -3327,7 +4609,7 @@ impl HtmlProcessor {
                 if self.tag_processor.text_node_classification
                     == TextNodeClassification::Whitespace =>
             {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -3337,7 +4619,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -3359,7 +4641,7 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "col"
              */
             Op::TagPush(TagName::COL) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.pop();
                 true
             }
@@ -3377,7 +4659,7 @@ impl HtmlProcessor {
                     self.step(NodeToProcess::ProcessNextNode)
                 } else {
                     self.pop();
-                    self.state.insertion_mode = InsertionMode::IN_TABLE;
+                    self.set_insertion_mode(InsertionMode::IN_TABLE);
                     true
                 }
             }
@@ -3409,7 +4691,7 @@ impl HtmlProcessor {
                     self.step(NodeToProcess::ProcessNextNode)
                 } else {
                     self.pop();
-                    self.state.insertion_mode = InsertionMode::IN_TABLE;
+                    self.set_insertion_mode(InsertionMode::IN_TABLE);
                     self.step(NodeToProcess::ReprocessCurrentNode)
                 }
             }
@@ -3434,8 +4716,8 @@ impl HtmlProcessor {
              */
             Op::TagPush(TagName::TR) => {
                 self.clear_to_table_body_context();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::IN_ROW;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::IN_ROW);
                 true
             }
 
@@ -3446,7 +4728,7 @@ impl HtmlProcessor {
                 // @todo Indicate a parse error once it's possible.
                 self.clear_to_table_body_context();
                 self.insert_virtual_node(TagName::TR, None);
-                self.state.insertion_mode = InsertionMode::IN_ROW;
+                self.set_insertion_mode(InsertionMode::IN_ROW);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -3464,7 +4746,7 @@ impl HtmlProcessor {
                 } else {
                     self.clear_to_table_body_context();
                     self.pop();
-                    self.state.insertion_mode = InsertionMode::IN_TABLE;
+                    self.set_insertion_mode(InsertionMode::IN_TABLE);
                     true
                 }
             }
@@ -3500,7 +4782,7 @@ impl HtmlProcessor {
                 } else {
                     self.clear_to_table_body_context();
                     self.pop();
-                    self.state.insertion_mode = InsertionMode::IN_TABLE;
+                    self.set_insertion_mode(InsertionMode::IN_TABLE);
                     self.step(NodeToProcess::ReprocessCurrentNode)
                 }
             }
@@ -3548,8 +4830,8 @@ impl HtmlProcessor {
              */
             Op::TagPush(TagName::TH | TagName::TD) => {
                 self.clear_to_table_row_context();
-                self.insert_html_element(self.state.current_token.clone().unwrap());
-                self.state.insertion_mode = InsertionMode::IN_CELL;
+                self.insert_current_html_element();
+                self.set_insertion_mode(InsertionMode::IN_CELL);
                 self.state.active_formatting_elements.insert_marker();
                 true
             }
@@ -3569,7 +4851,7 @@ impl HtmlProcessor {
 
                 self.clear_to_table_row_context();
                 self.pop();
-                self.state.insertion_mode = InsertionMode::IN_TABLE_BODY;
+                self.set_insertion_mode(InsertionMode::IN_TABLE_BODY);
                 true
             }
 
@@ -3598,7 +4880,7 @@ impl HtmlProcessor {
 
                 self.clear_to_table_row_context();
                 self.pop();
-                self.state.insertion_mode = InsertionMode::IN_TABLE_BODY;
+                self.set_insertion_mode(InsertionMode::IN_TABLE_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -3626,7 +4908,7 @@ impl HtmlProcessor {
 
                 self.clear_to_table_row_context();
                 self.pop();
-                self.state.insertion_mode = InsertionMode::IN_TABLE_BODY;
+                self.set_insertion_mode(InsertionMode::IN_TABLE_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -3700,7 +4982,7 @@ impl HtmlProcessor {
                 self.state
                     .active_formatting_elements
                     .clear_up_to_last_marker();
-                self.state.insertion_mode = InsertionMode::IN_ROW;
+                self.set_insertion_mode(InsertionMode::IN_ROW);
                 true
             }
 
@@ -3800,7 +5082,7 @@ impl HtmlProcessor {
              * > Any other character token
              */
             Op::Token(TokenType::Text) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -3810,7 +5092,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -3838,7 +5120,7 @@ impl HtmlProcessor {
                 {
                     self.pop();
                 }
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -3864,7 +5146,7 @@ impl HtmlProcessor {
                 {
                     self.pop();
                 }
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -4100,7 +5382,7 @@ impl HtmlProcessor {
                 self.state
                     .stack_of_template_insertion_modes
                     .push(InsertionMode::IN_TABLE);
-                self.state.insertion_mode = InsertionMode::IN_TABLE;
+                self.set_insertion_mode(InsertionMode::IN_TABLE);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -4112,7 +5394,7 @@ impl HtmlProcessor {
                 self.state
                     .stack_of_template_insertion_modes
                     .push(InsertionMode::IN_COLUMN_GROUP);
-                self.state.insertion_mode = InsertionMode::IN_COLUMN_GROUP;
+                self.set_insertion_mode(InsertionMode::IN_COLUMN_GROUP);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -4124,7 +5406,7 @@ impl HtmlProcessor {
                 self.state
                     .stack_of_template_insertion_modes
                     .push(InsertionMode::IN_TABLE_BODY);
-                self.state.insertion_mode = InsertionMode::IN_TABLE_BODY;
+                self.set_insertion_mode(InsertionMode::IN_TABLE_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -4136,7 +5418,7 @@ impl HtmlProcessor {
                 self.state
                     .stack_of_template_insertion_modes
                     .push(InsertionMode::IN_ROW);
-                self.state.insertion_mode = InsertionMode::IN_ROW;
+                self.set_insertion_mode(InsertionMode::IN_ROW);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -4148,7 +5430,7 @@ impl HtmlProcessor {
                 self.state
                     .stack_of_template_insertion_modes
                     .push(InsertionMode::IN_BODY);
-                self.state.insertion_mode = InsertionMode::IN_BODY;
+                self.set_insertion_mode(InsertionMode::IN_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
 
@@ -4158,13 +5440,10 @@ impl HtmlProcessor {
              */
             Op::TagPop(_) => self.step(NodeToProcess::ProcessNextNode),
 
-            Op::Token(TokenType::CdataSection) => {
-                unreachable!("CDATA sections cannot appear in HTML content.")
-            }
-            Op::Token(TokenType::Tag) => {
-                unreachable!(
-                    "TAG tokens are represented as Op::TagPush or Op::TagPop, never Op::Token."
-                )
+            // CDATA sections cannot appear in HTML content and TAG tokens are
+            // represented as Op::TagPush or Op::TagPop, never Op::Token.
+            Op::Token(TokenType::CdataSection | TokenType::Tag) => {
+                self.fail_with_unexpected_state()
             }
         }
     }
@@ -4227,7 +5506,7 @@ impl HtmlProcessor {
                 if self.context_node.is_some() {
                     self.step(NodeToProcess::ProcessNextNode)
                 } else {
-                    self.state.insertion_mode = InsertionMode::AFTER_AFTER_BODY;
+                    self.set_insertion_mode(InsertionMode::AFTER_AFTER_BODY);
                     /*
                      * The HTML element is not removed from the stack of open elements.
                      * Only internal state has changed, this does not qualify as a "step"
@@ -4244,7 +5523,7 @@ impl HtmlProcessor {
                  * > Anything else
                  *   > Parse error. Switch the insertion mode to "in body" and reprocess the token.
                  */
-                self.state.insertion_mode = InsertionMode::IN_BODY;
+                self.set_insertion_mode(InsertionMode::IN_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -4288,7 +5567,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -4309,7 +5588,7 @@ impl HtmlProcessor {
              * > A start tag whose tag name is "frameset"
              */
             Op::TagPush(TagName::FRAMESET) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -4345,7 +5624,7 @@ impl HtmlProcessor {
                         .stack_of_open_elements
                         .current_node_is(&NodeName::Tag(TagName::FRAMESET))
                 {
-                    self.state.insertion_mode = InsertionMode::AFTER_FRAMESET;
+                    self.set_insertion_mode(InsertionMode::AFTER_FRAMESET);
                 }
 
                 true
@@ -4360,7 +5639,7 @@ impl HtmlProcessor {
              * > Acknowledge the token's self-closing flag, if it is set.
              */
             Op::TagPush(TagName::FRAME) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 self.pop();
                 true
             }
@@ -4418,7 +5697,7 @@ impl HtmlProcessor {
             Op::Token(
                 TokenType::Comment | TokenType::FunkyComment | TokenType::PresumptuousTag,
             ) => {
-                self.insert_html_element(self.state.current_token.clone().unwrap());
+                self.insert_current_html_element();
                 true
             }
 
@@ -4439,7 +5718,7 @@ impl HtmlProcessor {
              * > An end tag whose tag name is "html"
              */
             Op::TagPop(TagName::HTML) => {
-                self.state.insertion_mode = InsertionMode::AFTER_AFTER_FRAMESET;
+                self.set_insertion_mode(InsertionMode::AFTER_AFTER_FRAMESET);
                 /*
                  * The HTML element is not removed from the stack of open elements.
                  * Only internal state has changed, this does not qualify as a "step"
@@ -4509,7 +5788,7 @@ impl HtmlProcessor {
              * > Parse error. Switch the insertion mode to "in body" and reprocess the token.
              */
             _ => {
-                self.state.insertion_mode = InsertionMode::IN_BODY;
+                self.set_insertion_mode(InsertionMode::IN_BODY);
                 self.step(NodeToProcess::ReprocessCurrentNode)
             }
         }
@@ -4629,13 +5908,15 @@ impl HtmlProcessor {
                  * NULL bytes and whitespace do not change the frameset-ok flag.
                  */
 
-                let current_token_span = self
+                let Some(current_token_span) = self
                     .state
                     .current_token
                     .as_ref()
                     .and_then(|token| token.bookmark_name)
                     .and_then(|mark| self.tag_processor.internal_bookmarks.get(&mark))
-                    .unwrap();
+                else {
+                    return self.fail_with_unexpected_state();
+                };
 
                 let cdata_content_start = current_token_span.start + 9;
                 let cdata_content_length = current_token_span.length - 12;
@@ -4765,12 +6046,14 @@ impl HtmlProcessor {
              * > Any other start tag
              */
             (Op::TagPush(_), _) => {
-                let has_self_closing_flag = self
+                let Some(has_self_closing_flag) = self
                     .state
                     .current_token
                     .as_ref()
-                    .unwrap()
-                    .has_self_closing_flag;
+                    .map(|token| token.has_self_closing_flag)
+                else {
+                    return self.fail_with_unexpected_state();
+                };
                 self.insert_foreign_element_from_current_token(false);
 
                 /*
@@ -4797,8 +6080,11 @@ impl HtmlProcessor {
             }
 
             (Op::TagPop(TagName::SCRIPT), _)
-                if self.state.current_token.as_ref().unwrap().namespace
-                    == ParsingNamespace::Svg =>
+                if self
+                    .state
+                    .current_token
+                    .as_ref()
+                    .is_some_and(|token| token.namespace == ParsingNamespace::Svg) =>
             {
                 self.pop();
                 true
@@ -4864,14 +6150,11 @@ impl HtmlProcessor {
                         first_iteration = false;
                     }
 
-                    let node_tag_name = node.node_name.tag().expect("Must have a tag node.");
-                    if node
-                        == self
-                            .state
-                            .stack_of_open_elements
-                            .at(1)
-                            .expect("There must be a node on the stack.")
-                    {
+                    // Only tags are expected on the stack here.
+                    let Some(node_tag_name) = node.node_name.tag() else {
+                        break;
+                    };
+                    if self.state.stack_of_open_elements.at(1) == Some(node) {
                         continuation = Continuation::ProcessNextToken;
                         break;
                     }
@@ -4883,9 +6166,7 @@ impl HtmlProcessor {
                 }
 
                 match continuation {
-                    Continuation::Unknown => {
-                        unreachable!("Unknown foreign content end tag continuation.")
-                    }
+                    Continuation::Unknown => self.fail_with_unexpected_state(),
                     Continuation::PopUntilTagName => {
                         // See ::pop_until
                         while let Some(token) = self.pop() {
@@ -4896,7 +6177,7 @@ impl HtmlProcessor {
                                 }
                             }
                         }
-                        unreachable!("Must have returned before reaching this point.");
+                        self.fail_with_unexpected_state()
                     }
                     Continuation::ProcessNextToken => self.step(NodeToProcess::ProcessNextNode),
                     Continuation::StepInCurrentInsertionMode => {
@@ -4905,7 +6186,8 @@ impl HtmlProcessor {
                 }
             }
 
-            (Op::Token(TokenType::Tag), _) => unreachable!("Tag token ops are never constructed"),
+            // Tag token ops are never constructed.
+            (Op::Token(TokenType::Tag), _) => self.fail_with_unexpected_state(),
         }
     }
 
@@ -4980,17 +6262,33 @@ impl HtmlProcessor {
         }
 
         if self.is_virtual() {
-            let node_name = &self.current_element.as_ref().unwrap().token.node_name;
+            let node_name = &self.current_element.as_ref()?.token.node_name;
             debug_assert!(matches!(node_name, NodeName::Tag(_)));
             return match node_name {
                 NodeName::Tag(tag_name) => Some(tag_name.clone()),
-                _ => unreachable!(),
+                NodeName::Token(_) => None,
             };
         }
 
         self.tag_processor.get_tag()
     }
 
+    /// Returns the name of the matched tag as it's spelled in the input HTML.
+    ///
+    /// Virtual tags, which the parser creates without a corresponding tag in
+    /// the input HTML, have no spelling.
+    ///
+    /// @see WP_HTML_Tag_Processor::get_tag_raw()
+    ///
+    /// @return string|null Name of currently matched tag in input HTML, or `null` if none found.
+    pub fn get_tag_raw(&self) -> Option<&[u8]> {
+        if self.last_error.is_some() || self.is_virtual() {
+            return None;
+        }
+
+        self.tag_processor.get_tag_raw()
+    }
+
     /// Returns the adjusted tag name for a given token, taking into
     /// account the current parsing context, whether HTML, SVG, or MathML.
     ///
@@ -5054,14 +6352,7 @@ impl HtmlProcessor {
     /// @return string|null Name of the matched token.
     pub fn get_token_name(&self) -> Option<NodeName> {
         if self.is_virtual() {
-            Some(
-                self.current_element
-                    .as_ref()
-                    .unwrap()
-                    .token
-                    .node_name
-                    .clone(),
-            )
+            Some(self.current_element.as_ref()?.token.node_name.clone())
         } else {
             self.tag_processor.get_token_name()
         }
@@ -5093,12 +6384,10 @@ impl HtmlProcessor {
              * @todo It would be ideal not to repeat this here, but it's not clearly
              *       better to allow passing a token name to `get_token_type()`.
              */
-            Some(
-                match &self.current_element.as_ref().unwrap().token.node_name {
-                    NodeName::Tag(_) => &TokenType::Tag,
-                    NodeName::Token(token_type) => token_type,
-                },
-            )
+            Some(match &self.current_element.as_ref()?.token.node_name {
+                NodeName::Tag(_) => &TokenType::Tag,
+                NodeName::Token(token_type) => token_type,
+            })
         } else {
             self.tag_processor.get_token_type()
         }
@@ -5127,6 +6416,57 @@ impl HtmlProcessor {
         }
     }
 
+    /// Indicates if the value of an attribute on the matched tag starts with the given text,
+    /// decoding only as much of the raw value as needed.
+    ///
+    /// @see WP_HTML_Tag_Processor::attribute_starts_with()
+    ///
+    /// @param string $name             Attribute name, ASCII case-insensitive.
+    /// @param string $search_text      Does the attribute value start with this plain string.
+    /// @param string $case_sensitivity Whether to ignore ASCII case when matching.
+    /// @return bool|null Whether the attribute value starts with the given text,
+    ///                   or `null` if not matched on a tag or on a virtual node.
+    pub fn attribute_starts_with(
+        &self,
+        name: &[u8],
+        search_text: &[u8],
+        case_sensitivity: CaseSensitivity,
+    ) -> Option<bool> {
+        if self.is_virtual() {
+            None
+        } else {
+            self.tag_processor
+                .attribute_starts_with(name, search_text, case_sensitivity)
+        }
+    }
+
+    /// Returns an iterator over the attributes of the matched tag, without allocating.
+    ///
+    /// @see WP_HTML_Tag_Processor::attributes()
+    ///
+    /// @return Attributes Iterator over the attributes, which visits none for a virtual node.
+    pub fn attributes(&self) -> Attributes<'_> {
+        if self.is_virtual() {
+            Attributes::default()
+        } else {
+            self.tag_processor.attributes()
+        }
+    }
+
+    /// Returns the name of an attribute on the matched tag as it's spelled in the input HTML.
+    ///
+    /// @see WP_HTML_Tag_Processor::get_attribute_name_raw()
+    ///
+    /// @param string $name Name of attribute whose spelling is requested.
+    /// @return string|null Attribute name as found in the input HTML, or `null` if not present.
+    pub fn get_attribute_name_raw(&self, name: &[u8]) -> Option<&[u8]> {
+        if self.is_virtual() {
+            None
+        } else {
+            self.tag_processor.get_attribute_name_raw(name)
+        }
+    }
+
     /// Updates or creates a new attribute on the currently matched tag with the passed value.
     ///
     /// For boolean attributes special handling is provided:
@@ -5188,6 +6528,25 @@ impl HtmlProcessor {
         }
     }
 
+    /// Gets lowercase names of all attributes matching a given prefix in the current tag,
+    /// sharing one copy of each name across the document.
+    ///
+    /// @see WP_HTML_Tag_Processor::get_interned_attribute_names_with_prefix()
+    ///
+    /// @param string $prefix Prefix of requested attribute names.
+    /// @return array|null List of attribute names, or `null` when no tag opener is matched.
+    pub fn get_interned_attribute_names_with_prefix(
+        &mut self,
+        prefix: &[u8],
+    ) -> Option<Vec<Arc<[u8]>>> {
+        if self.is_virtual() {
+            None
+        } else {
+            self.tag_processor
+                .get_interned_attribute_names_with_prefix(prefix)
+        }
+    }
+
     /// Adds a new class name to the currently matched tag.
     ///
     /// @param string $class_name The class name to add.
@@ -5272,6 +6631,73 @@ impl HtmlProcessor {
         }
     }
 
+    /// Returns the raw HTML of the matched token, exactly as it appears in the input.
+    ///
+    /// Nodes implied by the HTML, e.g. a TBODY element or the closing of an unclosed
+    /// P element, don't appear in the input and have no raw HTML.
+    ///
+    /// @see WP_HTML_Tag_Processor::get_token_html()
+    ///
+    /// @return string|null Raw HTML of the matched token, or `null` if it's not in the input.
+    pub fn get_token_html(&self) -> Option<&[u8]> {
+        if self.is_virtual() {
+            None
+        } else {
+            self.tag_processor.get_token_html()
+        }
+    }
+
+    /// Returns a copy of the matched token, including its attributes and modifiable text.
+    ///
+    /// Virtual tokens, which the parser implied, have no attributes and no span.
+    ///
+    /// @see WP_HTML_Tag_Processor::get_token_snapshot()
+    ///
+    /// @return TokenSnapshot|null Copy of the matched token, or `null` if not on a token.
+    pub fn get_token_snapshot(&self) -> Option<TokenSnapshot> {
+        let span = if self.is_virtual() {
+            None
+        } else {
+            self.tag_processor.get_token_span()
+        };
+
+        Some(TokenSnapshot::new(
+            self.get_token_type()?.clone(),
+            self.get_token_name()?,
+            self.is_tag_closer(),
+            self.attributes(),
+            span,
+            self.get_modifiable_text(),
+        ))
+    }
+
+    /// Returns what kind of characters the matched text node contains.
+    ///
+    /// Text nodes are subdivided as they're parsed, so runs of whitespace, e.g. between
+    /// elements, and of NULL bytes are reported separately from the text which follows.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_fragment( "<ul>\n\t<li>One</li>\n</ul>" );
+    ///     while ( $processor->next_token() ) {
+    ///         if ( Some( &TextNodeClassification::Whitespace ) === $processor->get_text_node_classification() ) {
+    ///             continue;
+    ///         }
+    ///         …
+    ///     }
+    ///
+    /// @see WP_HTML_Tag_Processor::get_text_node_classification()
+    ///
+    /// @return TextNodeClassification|null Classification of the text node, or `null` if
+    ///                                     not on a text node.
+    pub fn get_text_node_classification(&self) -> Option<&TextNodeClassification> {
+        if self.is_virtual() {
+            None
+        } else {
+            self.tag_processor.get_text_node_classification()
+        }
+    }
+
     /// Indicates what kind of comment produced the comment node.
     ///
     /// Because there are different kinds of HTML syntax which produce
@@ -5321,14 +6747,75 @@ impl HtmlProcessor {
     /// @param string $bookmark_name Name of the bookmark to remove.
     /// @return bool Whether the bookmark already existed before removal.
     pub fn release_bookmark(&mut self, bookmark_name: &str) -> bool {
-        todo!()
+        let bookmark_name = format!("_{}", bookmark_name);
+        self.tag_processor.release_bookmark(bookmark_name.as_str())
+    }
+
+    /// Records the state of the processor so that it can be rolled back later.
+    ///
+    /// This allows speculative parsing: try parsing ahead, possibly modifying the
+    /// document along the way, and restore the snapshot if the attempt fails, without
+    /// reparsing the document to return to where the attempt started.
+    ///
+    /// Limits such as the parsing budget aren't part of the snapshot, and neither is
+    /// the work counted against the budget: restoring a snapshot doesn't refund it.
+    ///
+    /// Example:
+    ///
+    ///     $snapshot = $processor->snapshot();
+    ///     if ( ! try_to_parse_a_widget( $processor ) ) {
+    ///         $processor->restore( $snapshot );
+    ///     }
+    ///
+    /// @see WP_HTML_Tag_Processor::snapshot()
+    ///
+    /// @return HtmlProcessorSnapshot State to pass to `restore()`.
+    pub fn snapshot(&self) -> HtmlProcessorSnapshot {
+        HtmlProcessorSnapshot {
+            tag_processor: self.tag_processor.snapshot(),
+            state: self.state.clone(),
+            last_error: self.last_error,
+            unsupported_exception: self.unsupported_exception.clone(),
+            element_queue: self.element_queue.clone(),
+            current_element: self.current_element.clone(),
+            breadcrumbs: self.breadcrumbs.clone(),
+            open_elements: self.open_elements.clone(),
+            open_element_tokens: self.open_element_tokens.clone(),
+        }
+    }
+
+    /// Rolls the processor back to a snapshot.
+    ///
+    /// Node handles created after the snapshot no longer refer to any node.
+    /// Reparse checkpoints are discarded, as the document may differ.
+    ///
+    /// @see self::snapshot()
+    ///
+    /// @param HtmlProcessorSnapshot $snapshot State recorded by `snapshot()`.
+    pub fn restore(&mut self, snapshot: HtmlProcessorSnapshot) {
+        // The HTML Processor never streams its input, so its Tag Processor can always roll back.
+        let restored = self.tag_processor.restore(snapshot.tag_processor);
+        debug_assert!(restored);
+
+        self.state = snapshot.state;
+        self.last_error = snapshot.last_error;
+        self.unsupported_exception = snapshot.unsupported_exception;
+        self.element_queue = snapshot.element_queue;
+        self.current_element = snapshot.current_element;
+        self.breadcrumbs = snapshot.breadcrumbs;
+        self.open_elements = snapshot.open_elements;
+        self.open_element_tokens = snapshot.open_element_tokens;
+        self.reparse_checkpoints.clear();
+        self.tokens_since_checkpoint = 0;
     }
 
     /// Moves the internal cursor in the HTML Processor to a given bookmark's location.
     ///
     /// Be careful! Seeking backwards to a previous location resets the parser to the
     /// start of the document and reparses the entire contents up until it finds the
-    /// sought-after bookmarked location.
+    /// sought-after bookmarked location, unless reparse checkpoints are enabled.
+    ///
+    /// @see self::set_reparse_checkpoints()
     ///
     /// In order to prevent accidental infinite loops, there's a
     /// maximum limit on the number of times seek() can be called.
@@ -5338,72 +6825,478 @@ impl HtmlProcessor {
     /// @param string $bookmark_name Jump to the place in the document identified by this bookmark name.
     /// @return bool Whether the internal cursor was successfully moved to the bookmark's location.
     pub fn seek(&mut self, bookmark_name: &str) -> bool {
-        todo!()
+        let bookmark_name = format!("_{}", bookmark_name);
+        self.seek_to_bookmark(BookmarkName::String(bookmark_name.into()), None)
     }
 
-    /// Sets a bookmark in the HTML document.
-    ///
-    /// Bookmarks represent specific places or tokens in the HTML
-    /// document, such as a tag opener or closer. When applying
-    /// edits to a document, such as setting an attribute, the
-    /// text offsets of that token may shift; the bookmark is
-    /// kept updated with those shifts and remains stable unless
-    /// the entire span of text in which the token sits is removed.
+    /// Returns a handle to the currently-matched node, which can be used to return to it.
     ///
-    /// Release bookmarks when they are no longer needed.
+    /// Handles can refer to any node the processor visits, including virtual nodes and
+    /// text nodes, but not to the closing of an element. Each handle holds a bookmark;
+    /// release it with `release_node_handle()` when it's no longer needed.
     ///
     /// Example:
     ///
-    ///     <main><h2>Surprising fact you may not know!</h2></main>
-    ///           ^  ^
-    ///            \-|-- this `H2` opener bookmark tracks the token
+    ///     $processor = WP_HTML_Processor::create_full_parser( '<ul><li>One<li>Two</ul>' );
+    ///     $processor->next_tag( 'UL' );
+    ///     $list = $processor->current_node_handle();
+    ///     while ( $processor->next_tag( 'LI' ) ) {
+    ///         // ...
+    ///     }
+    ///     $processor->seek_to( $list );
+    ///     'UL' === $processor->get_tag();
+    ///
+    /// @return NodeHandle|null Handle to the current node, or `null` if not on an opening
+    ///                         token or if unable to allocate a bookmark for it.
+    pub fn current_node_handle(&mut self) -> Option<NodeHandle> {
+        let current_element = self.current_element.as_ref()?;
+        if current_element.operation != StackOperation::Push {
+            return None;
+        }
+
+        let token = current_element.token.clone();
+        let is_virtual = current_element.provenance == StackProvenance::Virtual;
+        let span = self.current_token_span()?;
+        let span = HtmlSpan::new(span.start, if is_virtual { 0 } else { span.length });
+
+        let bookmark_name = self.bookmark_token().ok()?;
+        self.tag_processor
+            .internal_bookmarks
+            .insert(bookmark_name, span);
+
+        Some(NodeHandle {
+            bookmark_name,
+            token,
+            is_virtual,
+        })
+    }
+
+    /// Moves the internal cursor to the node referred to by a handle.
     ///
-    ///     <main class="clickbait"><h2>Surprising fact you may no…
-    ///                             ^  ^
-    ///                              \-|-- it shifts with edits
+    /// This has the same performance characteristics as seeking to a bookmark.
     ///
-    /// Bookmarks provide the ability to seek to a previously-scanned
-    /// place in the HTML document. This avoids the need to re-scan
-    /// the entire document.
+    /// @see self::seek()
     ///
-    /// Example:
+    /// @param NodeHandle $handle Refers to a previously-visited node.
+    /// @return bool Whether the internal cursor was successfully moved to the node.
+    pub fn seek_to(&mut self, handle: &NodeHandle) -> bool {
+        self.seek_to_bookmark(BookmarkName::Internal(handle.bookmark_name), Some(handle))
+    }
+
+    /// Releases the bookmark held by a node handle.
     ///
-    ///     <ul><li>One</li><li>Two</li><li>Three</li></ul>
-    ///                                 ^^^^
-    ///                                 want to note this last item
+    /// @param NodeHandle $handle Handle which is no longer needed.
+    /// @return bool Whether the handle's bookmark existed before removal.
+    pub fn release_node_handle(&mut self, handle: NodeHandle) -> bool {
+        self.tag_processor
+            .release_bookmark(BookmarkName::Internal(handle.bookmark_name))
+    }
+
+    /// Moves the internal cursor to the opening of the parent element of the current node.
     ///
-    ///     $p = new WP_HTML_Tag_Processor( $html );
-    ///     $in_list = false;
-    ///     while ( $p->next_tag( array( 'tag_closers' => $in_list ? 'visit' : 'skip' ) ) ) {
-    ///         if ( 'UL' === $p->get_tag() ) {
-    ///             if ( $p->is_tag_closer() ) {
-    ///                 $in_list = false;
-    ///                 $p->set_bookmark( 'resume' );
-    ///                 if ( $p->seek( 'last-li' ) ) {
-    ///                     $p->add_class( 'last-li' );
-    ///                 }
-    ///                 $p->seek( 'resume' );
-    ///                 $p->release_bookmark( 'last-li' );
-    ///                 $p->release_bookmark( 'resume' );
-    ///             } else {
-    ///                 $in_list = true;
-    ///             }
-    ///         }
+    /// When matched on the closing of an element, this moves to the opening of the element
+    /// containing it. Since the parent opened before the current node, this always rewinds
+    /// and reparses the document; see `seek()` for the performance implications.
     ///
-    ///         if ( 'LI' === $p->get_tag() ) {
-    ///             $p->set_bookmark( 'last-li' );
-    ///         }
-    ///     }
+    /// Example:
     ///
-    /// Bookmarks intentionally hide the internal string offsets
-    /// to which they refer. They are maintained internally as
-    /// updates are applied to the HTML document and therefore
-    /// retain their "position" - the location to which they
-    /// originally pointed. The inability to use bookmarks with
-    /// functions like `substr` is therefore intentional to guard
-    /// against accidentally breaking the HTML.
+    ///     $processor = WP_HTML_Processor::create_fragment( '<figure><img src="a.jpg"><figcaption>A</figcaption></figure>' );
+    ///     $processor->next_tag( 'IMG' );
+    ///     $processor->goto_parent();
+    ///     'FIGURE' === $processor->get_tag();
     ///
-    /// Because bookmarks allocate memory and require processing
+    /// @return bool Whether the internal cursor moved to the parent element. Returns `false`
+    ///              if there is no parent, or if the parent is the context element of a fragment.
+    pub fn goto_parent(&mut self) -> bool {
+        let Some(current_element) = self.current_element.as_ref() else {
+            return false;
+        };
+
+        let parent_at = match current_element.operation {
+            StackOperation::Push => self.open_elements.len().checked_sub(2),
+            StackOperation::Pop => self.open_elements.len().checked_sub(1),
+        };
+
+        parent_at.is_some_and(|at| self.seek_to_open_element(at))
+    }
+
+    /// Moves the internal cursor to the opening of the closest element with the given tag
+    /// name, starting with the current element and continuing through its ancestors.
+    ///
+    /// This is the HTML Processor's analog of `Element.closest()` in the DOM. When matched
+    /// on the closing of an element, the search starts with the element containing it.
+    /// Moving to an ancestor rewinds and reparses the document; see `seek()` for the
+    /// performance implications.
+    ///
+    /// Example:
+    ///
+    ///     $processor = WP_HTML_Processor::create_fragment( '<a href="/"><span><img></span></a>' );
+    ///     $processor->next_tag( 'IMG' );
+    ///     $image = $processor->current_node_handle();
+    ///     if ( $processor->closest( 'A' ) ) {
+    ///         $processor->set_attribute( 'class', 'has-image' );
+    ///         // Return to the image to continue scanning from there.
+    ///         $processor->seek_to( $image );
+    ///     }
+    ///
+    /// @param string $tag_name Name of the element to find.
+    /// @return bool Whether the internal cursor moved to a matching element. Returns `false` if
+    ///              there is no match, or if the match is the context element of a fragment.
+    pub fn closest(&mut self, tag_name: &TagName) -> bool {
+        let Some(current_element) = self.current_element.as_ref() else {
+            return false;
+        };
+
+        let tag_name = NodeName::Tag(tag_name.clone());
+        let Some(at) = self
+            .open_elements
+            .iter()
+            .rposition(|element| element.node_name == tag_name)
+        else {
+            return false;
+        };
+
+        let is_current_element =
+            current_element.operation == StackOperation::Push && at + 1 == self.open_elements.len();
+        is_current_element || self.seek_to_open_element(at)
+    }
+
+    /// Moves the internal cursor to the opening of an element on the path to the current node.
+    ///
+    /// @param int $at Index of the element in the list of open elements.
+    /// @return bool Whether the internal cursor moved to the element.
+    fn seek_to_open_element(&mut self, at: usize) -> bool {
+        // The HTML and context elements of a fragment were never visited.
+        if self.context_node.is_some() && at < 2 {
+            return false;
+        }
+
+        let (Some(element), Some(token)) =
+            (self.open_elements.get(at), self.open_element_tokens.get(at))
+        else {
+            return false;
+        };
+
+        let Some(bookmark_name) = token.bookmark_name else {
+            return false;
+        };
+
+        let handle = NodeHandle {
+            bookmark_name,
+            token: token.clone(),
+            is_virtual: element.is_virtual,
+        };
+        self.seek_to(&handle)
+    }
+
+    /// Moves the internal cursor to a bookmarked location, reparsing as needed.
+    ///
+    /// @param BookmarkName    $bookmark_name Bookmark in the underlying Tag Processor.
+    /// @param NodeHandle|null $handle        Handle if seeking to a specific node, otherwise
+    ///                                       seeks to the first non-virtual node at the bookmark.
+    /// @return bool Whether the internal cursor was successfully moved to the bookmark's location.
+    fn seek_to_bookmark(
+        &mut self,
+        bookmark_name: BookmarkName,
+        handle: Option<&NodeHandle>,
+    ) -> bool {
+        self.tag_processor.seek_count += 1;
+        if self.tag_processor.seek_count > MAX_SEEK_OPS {
+            // @todo _doing_it_wrong( __METHOD__, __( 'Too many calls to seek() - this can lead to performance issues.' ), '6.2.0' );
+            return false;
+        }
+
+        // Flush any pending updates to the document before beginning.
+        self.tag_processor.apply_lexical_updates();
+
+        let bookmark = match &bookmark_name {
+            BookmarkName::Internal(name) => self.tag_processor.internal_bookmarks.get(name),
+            BookmarkName::String(name) => self.tag_processor.bookmarks.get(name.as_ref()),
+        };
+        let Some(bookmark) = bookmark.map(|span| HtmlSpan::new(span.start, span.length)) else {
+            return false;
+        };
+
+        /*
+         * If seeking backwards, it's possible that the sought-after bookmark exists within an element
+         * which has been closed before the current cursor; in other words, it has already been removed
+         * from the stack of open elements. This means that it's insufficient to simply pop off elements
+         * from the stack of open elements which appear after the bookmarked location and then jump to
+         * that location, as the elements which were open before won't be re-opened.
+         *
+         * In order to maintain consistency, the HTML Processor rewinds to the start of the document
+         * and reparses everything until it finds the sought-after bookmark.
+         */
+        if bookmark.start < self.tag_processor.bytes_already_parsed {
+            self.rewind_before(bookmark.start);
+        }
+
+        // Reparse the document until reaching the same location as the original bookmark.
+        while self.next_visitable_token() {
+            if self.is_at_bookmark(&bookmark, handle) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Records a reparse checkpoint at the cursor, if checkpoints are enabled and one is due.
+    ///
+    /// Checkpoints are only recorded after tags, which end at a known byte, so that the
+    /// parser state doesn't depend on anything in the document after the checkpoint.
+    fn record_reparse_checkpoint(&mut self) {
+        let Some(every) = self.checkpoint_every else {
+            return;
+        };
+
+        self.discard_stale_checkpoints();
+        self.tokens_since_checkpoint += 1;
+        let at = self.tag_processor.bytes_already_parsed;
+        if self.tokens_since_checkpoint < every
+            || self.last_error.is_some()
+            || self.tag_processor.parser_state != ParserState::MatchedTag
+            || self
+                .reparse_checkpoints
+                .last()
+                .is_some_and(|checkpoint| checkpoint.tokenizer.at >= at)
+        {
+            return;
+        }
+
+        let referenced_tokens = self
+            .state
+            .stack_of_open_elements
+            .walk_down()
+            .chain(&self.open_element_tokens)
+            .chain(&self.state.current_token)
+            .chain(&self.state.head_element)
+            .chain(&self.state.form_element);
+        let bookmarks = referenced_tokens
+            .filter_map(|token| token.bookmark_name)
+            .filter_map(|name| {
+                let span = self.tag_processor.internal_bookmarks.get(&name)?;
+                Some((name, span.clone()))
+            })
+            .collect();
+
+        self.tokens_since_checkpoint = 0;
+        self.reparse_checkpoints.push(ReparseCheckpoint {
+            tokenizer: self.tag_processor.checkpoint(),
+            state: self.state.clone(),
+            breadcrumbs: self.breadcrumbs.clone(),
+            open_elements: self.open_elements.clone(),
+            open_element_tokens: self.open_element_tokens.clone(),
+            bookmarks,
+        });
+    }
+
+    /// Discards the reparse checkpoints after the earliest span changed by lexical updates.
+    fn discard_stale_checkpoints(&mut self) {
+        if let Some(modified_from) = self.tag_processor.modified_from.take() {
+            let still_valid = self
+                .reparse_checkpoints
+                .partition_point(|checkpoint| checkpoint.tokenizer.at <= modified_from);
+            self.reparse_checkpoints.truncate(still_valid);
+        }
+    }
+
+    /// Resets the processor to the closest reparse checkpoint at or before a location in
+    /// the document, or to the start of the document if there is none.
+    ///
+    /// Pending lexical updates must be applied beforehand.
+    ///
+    /// @param int $at Byte offset in the document from which to reparse.
+    fn rewind_before(&mut self, at: usize) {
+        self.discard_stale_checkpoints();
+        let closest = self
+            .reparse_checkpoints
+            .partition_point(|checkpoint| checkpoint.tokenizer.at <= at);
+        let Some(checkpoint) = closest
+            .checked_sub(1)
+            .and_then(|index| self.reparse_checkpoints.get(index))
+        else {
+            self.rewind();
+            return;
+        };
+
+        let checkpoint = checkpoint.clone();
+        self.release_open_element_bookmarks(0);
+        for (name, span) in checkpoint.bookmarks {
+            self.tag_processor.internal_bookmarks.insert(name, span);
+        }
+
+        self.state = checkpoint.state;
+        self.breadcrumbs = checkpoint.breadcrumbs;
+        self.open_elements = checkpoint.open_elements;
+        self.open_element_tokens = checkpoint.open_element_tokens;
+        self.current_element = None;
+        self.last_error = None;
+        self.unsupported_exception = None;
+        self.tokens_since_checkpoint = 0;
+        self.tag_processor.resume_from(&checkpoint.tokenizer);
+    }
+
+    /// Releases the bookmarks for open and queued elements, which are reopened as the
+    /// document is reparsed.
+    ///
+    /// @param int $initial_depth How many of the outermost open elements to keep.
+    fn release_open_element_bookmarks(&mut self, initial_depth: usize) {
+        let open_tokens = self
+            .open_element_tokens
+            .drain(initial_depth.min(self.open_element_tokens.len())..);
+        let queued_tokens = self.element_queue.drain(..).map(|event| event.token);
+        for token in open_tokens.chain(queued_tokens) {
+            if let Some(bookmark_name) = token.bookmark_name {
+                let _ = self.tag_processor.internal_bookmarks.remove(&bookmark_name);
+            }
+        }
+    }
+
+    /// Resets the processor to the start of the document, as if nothing had been parsed.
+    fn rewind(&mut self) {
+        // The presence of a context node indicates that this is a fragment parser.
+        let initial_depth = if self.context_node.is_some() { 2 } else { 0 };
+
+        // Release the bookmarks for elements which will be reopened as the document is reparsed.
+        self.release_open_element_bookmarks(initial_depth);
+
+        self.state = match self.fragment_start_state.as_ref() {
+            Some(fragment_start_state) => fragment_start_state.as_ref().clone(),
+            None => {
+                let mut state = ProcessorState::new();
+                state.encoding = self.state.encoding.clone();
+                state.encoding_confidence = self.state.encoding_confidence.clone();
+                state
+            }
+        };
+
+        self.current_element = None;
+        self.last_error = None;
+        self.unsupported_exception = None;
+
+        self.breadcrumbs.truncate(initial_depth);
+        self.open_elements.truncate(initial_depth);
+
+        self.tag_processor.bytes_already_parsed = 0;
+        self.tag_processor.parser_state = ParserState::Ready;
+        match self.context_node.as_ref() {
+            Some(context_node) => {
+                let namespace = if context_node.integration_node_type.is_some() {
+                    ParsingNamespace::Html
+                } else {
+                    context_node.namespace.clone()
+                };
+                self.tag_processor.change_parsing_namespace(namespace);
+            }
+            None => {
+                self.tag_processor.compat_mode = CompatMode::default();
+                self.tag_processor
+                    .change_parsing_namespace(ParsingNamespace::Html);
+            }
+        }
+    }
+
+    /// Returns the location in the document of the token which produced the current events.
+    fn current_token_span(&self) -> Option<&HtmlSpan> {
+        self.state
+            .current_token
+            .as_ref()
+            .and_then(|token| token.bookmark_name)
+            .and_then(|name| self.tag_processor.internal_bookmarks.get(&name))
+    }
+
+    /// Indicates if the currently-matched node is the one found at a bookmark.
+    ///
+    /// @param HtmlSpan        $bookmark Location of the sought-after node.
+    /// @param NodeHandle|null $handle   Handle if seeking to a specific node.
+    /// @return bool Whether the processor is matched on the bookmarked node.
+    fn is_at_bookmark(&self, bookmark: &HtmlSpan, handle: Option<&NodeHandle>) -> bool {
+        let (Some(current_element), Some(span)) =
+            (self.current_element.as_ref(), self.current_token_span())
+        else {
+            return false;
+        };
+
+        let is_virtual = current_element.provenance == StackProvenance::Virtual;
+        let length = if is_virtual { 0 } else { span.length };
+        if span.start != bookmark.start || length != bookmark.length {
+            return false;
+        }
+
+        match handle {
+            Some(handle) => {
+                current_element.operation == StackOperation::Push
+                    && is_virtual == handle.is_virtual
+                    && current_element.token.node_name == handle.token.node_name
+            }
+            None => !is_virtual,
+        }
+    }
+
+    /// Sets a bookmark in the HTML document.
+    ///
+    /// Bookmarks represent specific places or tokens in the HTML
+    /// document, such as a tag opener or closer. When applying
+    /// edits to a document, such as setting an attribute, the
+    /// text offsets of that token may shift; the bookmark is
+    /// kept updated with those shifts and remains stable unless
+    /// the entire span of text in which the token sits is removed.
+    ///
+    /// Release bookmarks when they are no longer needed.
+    ///
+    /// Example:
+    ///
+    ///     <main><h2>Surprising fact you may not know!</h2></main>
+    ///           ^  ^
+    ///            \-|-- this `H2` opener bookmark tracks the token
+    ///
+    ///     <main class="clickbait"><h2>Surprising fact you may no…
+    ///                             ^  ^
+    ///                              \-|-- it shifts with edits
+    ///
+    /// Bookmarks provide the ability to seek to a previously-scanned
+    /// place in the HTML document. This avoids the need to re-scan
+    /// the entire document.
+    ///
+    /// Example:
+    ///
+    ///     <ul><li>One</li><li>Two</li><li>Three</li></ul>
+    ///                                 ^^^^
+    ///                                 want to note this last item
+    ///
+    ///     $p = new WP_HTML_Tag_Processor( $html );
+    ///     $in_list = false;
+    ///     while ( $p->next_tag( array( 'tag_closers' => $in_list ? 'visit' : 'skip' ) ) ) {
+    ///         if ( 'UL' === $p->get_tag() ) {
+    ///             if ( $p->is_tag_closer() ) {
+    ///                 $in_list = false;
+    ///                 $p->set_bookmark( 'resume' );
+    ///                 if ( $p->seek( 'last-li' ) ) {
+    ///                     $p->add_class( 'last-li' );
+    ///                 }
+    ///                 $p->seek( 'resume' );
+    ///                 $p->release_bookmark( 'last-li' );
+    ///                 $p->release_bookmark( 'resume' );
+    ///             } else {
+    ///                 $in_list = true;
+    ///             }
+    ///         }
+    ///
+    ///         if ( 'LI' === $p->get_tag() ) {
+    ///             $p->set_bookmark( 'last-li' );
+    ///         }
+    ///     }
+    ///
+    /// Bookmarks intentionally hide the internal string offsets
+    /// to which they refer. They are maintained internally as
+    /// updates are applied to the HTML document and therefore
+    /// retain their "position" - the location to which they
+    /// originally pointed. The inability to use bookmarks with
+    /// functions like `substr` is therefore intentional to guard
+    /// against accidentally breaking the HTML.
+    ///
+    /// Because bookmarks allocate memory and require processing
     /// for every applied update, they are limited and require
     /// a name. They should not be created with programmatically-made
     /// names, such as "li_{$index}" with some loop. As a general
@@ -5452,6 +7345,16 @@ impl HtmlProcessor {
         self.pop_until(&TagName::P);
     }
 
+    /// Indicates if this is a fragment parser whose context element is a SELECT element.
+    ///
+    /// @return bool Whether the context element is a SELECT element.
+    fn is_select_fragment(&self) -> bool {
+        self.context_node.as_ref().is_some_and(|context_node| {
+            context_node.namespace == ParsingNamespace::Html
+                && context_node.node_name == NodeName::Tag(TagName::SELECT)
+        })
+    }
+
     /// Closes elements that have implied end tags.
     ///
     /// > while the current node is a dd element, a dt element, an li element,
@@ -5565,15 +7468,9 @@ impl HtmlProcessor {
          * > If there are no entries in the list of active formatting elements, then there is nothing
          * > to reconstruct; stop this algorithm.
          */
-        if self.state.active_formatting_elements.count() == 0 {
+        let Some(last_entry) = self.state.active_formatting_elements.current_node() else {
             return false;
-        }
-
-        let last_entry = self
-            .state
-            .active_formatting_elements
-            .current_node()
-            .unwrap();
+        };
 
         let last_entry = match last_entry {
             ActiveFormattingElement::Token(token) => token,
@@ -5595,20 +7492,45 @@ impl HtmlProcessor {
             return false;
         }
 
+        trace_event!(
+            TRACE,
+            node = ?last_entry.node_name,
+            "reconstruct active formatting elements"
+        );
         self.bail(UnsupportedException::ActiveFormattingElementsWhenAdvancingAndRewindingIsRequired)
     }
 
+    /// Switches the insertion mode used for following tokens.
+    ///
+    /// @param InsertionMode $insertion_mode New insertion mode.
+    fn set_insertion_mode(&mut self, insertion_mode: InsertionMode) {
+        trace_event!(
+            TRACE,
+            from = ?self.state.insertion_mode,
+            to = ?insertion_mode,
+            "insertion mode"
+        );
+        self.state.insertion_mode = insertion_mode;
+    }
+
     /// Runs the reset the insertion mode appropriately algorithm.
     ///
     /// @see https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
     fn reset_insertion_mode_appropriately(&mut self) {
-        // Set the first node.
-        let first_node = self
-            .state
-            .stack_of_open_elements
-            .walk_down()
-            .next()
-            .expect("Cannot reset insertion mode with an empty stack of open elements");
+        let insertion_mode = self.appropriate_insertion_mode();
+        self.set_insertion_mode(insertion_mode);
+    }
+
+    /// Determines the insertion mode for the reset the insertion mode appropriately algorithm.
+    ///
+    /// @see self::reset_insertion_mode_appropriately()
+    ///
+    /// @return InsertionMode Insertion mode to switch to.
+    fn appropriate_insertion_mode(&self) -> InsertionMode {
+        // Set the first node. With nothing on the stack there is nothing to reset from.
+        let Some(first_node) = self.state.stack_of_open_elements.walk_down().next() else {
+            return InsertionMode::IN_BODY;
+        };
 
         /*
          * > 1. Let _last_ be false.
@@ -5656,7 +7578,7 @@ impl HtmlProcessor {
                  * >   7. Jump back to the step labeled _loop_.
                  * >   8. _Done_: Switch the insertion mode to "in select" and return.
                  */
-                TagName::SELECT => {
+                TagName::SELECT if self.select_parsing == SelectParsing::Legacy => {
                     if !last {
                         for ancestor in self
                             .state
@@ -5694,15 +7616,13 @@ impl HtmlProcessor {
                                  * >    "in select in table" and return.
                                  */
                                 TagName::TABLE => {
-                                    self.state.insertion_mode = InsertionMode::IN_SELECT_IN_TABLE;
-                                    return;
+                                    return InsertionMode::IN_SELECT_IN_TABLE;
                                 }
                                 _ => {}
                             }
                         }
                     }
-                    self.state.insertion_mode = InsertionMode::IN_SELECT;
-                    return;
+                    return InsertionMode::IN_SELECT;
                 }
 
                 /*
@@ -5710,8 +7630,7 @@ impl HtmlProcessor {
                  * >    insertion mode to "in cell" and return.
                  */
                 TagName::TD | TagName::TH if !last => {
-                    self.state.insertion_mode = InsertionMode::IN_CELL;
-                    return;
+                    return InsertionMode::IN_CELL;
                 }
 
                 /*
@@ -5719,40 +7638,35 @@ impl HtmlProcessor {
                  * >    and return.
                  */
                 TagName::TR => {
-                    self.state.insertion_mode = InsertionMode::IN_ROW;
-                    return;
+                    return InsertionMode::IN_ROW;
                 }
 
                 /*
                  * > 7. If _node_ is a `tbody`, `thead`, or `tfoot` element, then switch the insertion mode to "in table body" and return.
                  */
                 TagName::TBODY | TagName::THEAD | TagName::TFOOT => {
-                    self.state.insertion_mode = InsertionMode::IN_TABLE_BODY;
-                    return;
+                    return InsertionMode::IN_TABLE_BODY;
                 }
 
                 /*
                  * > 8. If _node_ is a `caption` element, then switch the insertion mode to "in caption" and return.
                  */
                 TagName::CAPTION => {
-                    self.state.insertion_mode = InsertionMode::IN_CAPTION;
-                    return;
+                    return InsertionMode::IN_CAPTION;
                 }
 
                 /*
                  * > 9. If _node_ is a `colgroup` element, then switch the insertion mode to "in column group" and return.
                  */
                 TagName::COLGROUP => {
-                    self.state.insertion_mode = InsertionMode::IN_COLUMN_GROUP;
-                    return;
+                    return InsertionMode::IN_COLUMN_GROUP;
                 }
 
                 /*
                  * > 10. If _node_ is a `table` element, then switch the insertion mode to "in table" and return.
                  */
                 TagName::TABLE => {
-                    self.state.insertion_mode = InsertionMode::IN_TABLE;
-                    return;
+                    return InsertionMode::IN_TABLE;
                 }
 
                 /*
@@ -5760,8 +7674,13 @@ impl HtmlProcessor {
                  * >     current template insertion mode and return.
                  */
                 TagName::TEMPLATE => {
-                    self.state.insertion_mode = self.state.stack_of_template_insertion_modes.last().expect("There must be a template insertion mode to reset the insertion mode appropriately.").clone();
-                    return;
+                    // There is always a template insertion mode while a TEMPLATE element is open.
+                    return self
+                        .state
+                        .stack_of_template_insertion_modes
+                        .last()
+                        .cloned()
+                        .unwrap_or(InsertionMode::IN_BODY);
                 }
 
                 /*
@@ -5769,8 +7688,7 @@ impl HtmlProcessor {
                  * >     insertion mode to "in head" and return.
                  */
                 TagName::HEAD if !last => {
-                    self.state.insertion_mode = InsertionMode::IN_HEAD;
-                    return;
+                    return InsertionMode::IN_HEAD;
                 }
 
                 /*
@@ -5778,8 +7696,7 @@ impl HtmlProcessor {
                  * >     and return.
                  */
                 TagName::BODY => {
-                    self.state.insertion_mode = InsertionMode::IN_BODY;
-                    return;
+                    return InsertionMode::IN_BODY;
                 }
 
                 /*
@@ -5787,8 +7704,7 @@ impl HtmlProcessor {
                  * >     "in frameset" and return. (fragment case)
                  */
                 TagName::FRAMESET => {
-                    self.state.insertion_mode = InsertionMode::IN_FRAMESET;
-                    return;
+                    return InsertionMode::IN_FRAMESET;
                 }
 
                 /*
@@ -5799,11 +7715,10 @@ impl HtmlProcessor {
                  * >        mode to "after head" and return.
                  */
                 TagName::HTML => {
-                    self.state.insertion_mode = match self.state.head_element {
+                    return match self.state.head_element {
                         None => InsertionMode::BEFORE_HEAD,
                         Some(_) => InsertionMode::AFTER_HEAD,
                     };
-                    return;
                 }
 
                 /*
@@ -5813,7 +7728,7 @@ impl HtmlProcessor {
                  * This is only reachable if `$last` is true, as per the fragment parsing case.
                  */
                 _ if last => {
-                    self.state.insertion_mode = InsertionMode::IN_BODY;
+                    return InsertionMode::IN_BODY;
                 }
 
                 _ => {}
@@ -5826,7 +7741,7 @@ impl HtmlProcessor {
          *
          * This is only reachable if `$last` is true, as per the fragment parsing case.
          */
-        self.state.insertion_mode = InsertionMode::IN_BODY;
+        InsertionMode::IN_BODY
     }
 
     /// Runs the adoption agency algorithm.
@@ -5836,7 +7751,10 @@ impl HtmlProcessor {
     /// @see https://html.spec.whatwg.org/#adoption-agency-algorithm
     fn run_adoption_agency_algorithm(&mut self) {
         let mut budget: u16 = 1_000;
-        let subject = &self.get_tag().unwrap();
+        let Some(subject) = &self.get_tag() else {
+            self.fail_with_unexpected_state();
+            return;
+        };
         let current_node = self.state.stack_of_open_elements.current_node();
 
         // > If the current node is an HTML element whose tag name is subject
@@ -6001,7 +7919,7 @@ impl HtmlProcessor {
         self.state
             .active_formatting_elements
             .clear_up_to_last_marker();
-        self.state.insertion_mode = InsertionMode::IN_ROW;
+        self.set_insertion_mode(InsertionMode::IN_ROW);
     }
 
     /// Inserts an HTML element on the stack of open elements.
@@ -6013,6 +7931,30 @@ impl HtmlProcessor {
         self.push(token);
     }
 
+    /// Inserts an HTML element for the current token.
+    ///
+    /// Steps only process tokens once they have been made the current token, so this
+    /// fails with `UnexpectedState` only if the processor's state is inconsistent.
+    fn insert_current_html_element(&mut self) {
+        if let Some(token) = self.cloned_current_token() {
+            self.insert_html_element(token);
+        }
+    }
+
+    /// Returns a copy of the current token.
+    ///
+    /// Steps only process tokens once they have been made the current token, so this
+    /// fails with `UnexpectedState` only if the processor's state is inconsistent.
+    ///
+    /// @return HTMLToken|null The current token, if any.
+    fn cloned_current_token(&mut self) -> Option<HTMLToken> {
+        let token = self.state.current_token.clone();
+        if token.is_none() {
+            self.fail_with_unexpected_state();
+        }
+        token
+    }
+
     /// Inserts a foreign element on to the stack of open elements.
     ///
     /// @see https://html.spec.whatwg.org/#insert-a-foreign-element
@@ -6053,7 +7995,7 @@ impl HtmlProcessor {
              */
         }
 
-        self.insert_html_element(self.state.current_token.as_ref().unwrap().clone());
+        self.insert_current_html_element();
     }
 
     /// Inserts a virtual element on the stack of open elements.
@@ -6069,10 +8011,19 @@ impl HtmlProcessor {
             .as_ref()
             .and_then(|token| token.bookmark_name)
             .and_then(|mark| self.tag_processor.internal_bookmarks.get(&mark))
-            .map(|span| span.start)
-            .unwrap();
+            .map(|span| span.start);
+        let Some(current_token_start) = current_token_start else {
+            self.fail_with_unexpected_state();
+            return;
+        };
 
-        let name = self.bookmark_token().unwrap();
+        let name = match self.bookmark_token() {
+            Ok(name) => name,
+            Err(error) => {
+                self.last_error = Some(error);
+                return;
+            }
+        };
         self.tag_processor
             .internal_bookmarks
             .insert(name, HtmlSpan::new(current_token_start, 0));
@@ -6318,7 +8269,24 @@ impl HtmlProcessor {
     }
 
     fn push(&mut self, token: HTMLToken) {
+        if self
+            .max_depth
+            .is_some_and(|max_depth| self.state.stack_of_open_elements.count() >= max_depth)
+        {
+            if self.last_error.is_none() {
+                self.last_error = Some(HtmlProcessorError::ExceededMaxDepth);
+            }
+            return;
+        }
+
         self.state.stack_of_open_elements._push(token.clone());
+        trace_event!(
+            TRACE,
+            node = ?token.node_name,
+            namespace = ?token.namespace,
+            depth = self.state.stack_of_open_elements.count(),
+            "push"
+        );
 
         let is_virtual = self.state.current_token.is_none() || self.is_tag_closer();
         let same_node = self
@@ -6331,6 +8299,12 @@ impl HtmlProcessor {
         } else {
             StackProvenance::Real
         };
+        if provenance == StackProvenance::Virtual
+            && !self.budget.count_virtual_element()
+            && self.last_error.is_none()
+        {
+            self.last_error = Some(HtmlProcessorError::ExceededBudget);
+        }
         self.element_queue.push_back(HTMLStackEvent {
             token: token.clone(),
             operation: StackOperation::Push,
@@ -6352,10 +8326,15 @@ impl HtmlProcessor {
     }
 
     fn after_pop(&mut self, token: &HTMLToken) {
-        if let Some(bookmark_name) = token.bookmark_name.as_ref() {
-            let _ = self.tag_processor.internal_bookmarks.remove(bookmark_name);
-        }
+        trace_event!(
+            TRACE,
+            node = ?token.node_name,
+            namespace = ?token.namespace,
+            depth = self.state.stack_of_open_elements.count(),
+            "pop"
+        );
 
+        // The token's bookmark is released once its closing is visited.
         let is_virtual = self.state.current_token.is_none() || !self.is_tag_closer();
         let same_node = self
             .state
@@ -6553,3 +8532,999 @@ enum Op {
     TagPop(TagName),
     Token(TokenType),
 }
+
+/// Replaces NULL bytes, which can't be serialized, with U+FFFD.
+fn replace_null_bytes(text: &[u8]) -> Vec<u8> {
+    let mut replaced = Vec::with_capacity(text.len());
+    for &c in text {
+        match c {
+            b'\0' => replaced.extend_from_slice("\u{FFFD}".as_bytes()),
+            _ => replaced.push(c),
+        }
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_max_depth() {
+        let html = "<div>".repeat(100);
+        let mut processor = HtmlProcessor::create_full_parser(html.as_bytes(), "UTF-8").unwrap();
+        processor.set_max_depth(Some(10));
+
+        let mut deepest = 0;
+        while processor.next_token() {
+            deepest = deepest.max(processor.get_current_depth());
+        }
+
+        assert_eq!(
+            processor.get_last_error(),
+            Some(&HtmlProcessorError::ExceededMaxDepth)
+        );
+        assert!(deepest <= 10);
+        assert!(!processor.next_token());
+    }
+
+    #[test]
+    fn test_budget_max_tokens() {
+        let mut processor =
+            HtmlProcessor::create_full_parser(b"<p>One</p><p>Two</p>", "UTF-8").unwrap();
+        processor.set_budget(ParsingBudget {
+            max_tokens: Some(3),
+            ..Default::default()
+        });
+
+        let mut tokens = Vec::new();
+        while processor.next_token() {
+            tokens.push(processor.get_token_name().unwrap());
+        }
+
+        assert_eq!(
+            processor.get_last_error(),
+            Some(&HtmlProcessorError::ExceededBudget)
+        );
+        // The closing P is the third token; the second paragraph is never reached.
+        assert_eq!(
+            tokens,
+            vec![
+                NodeName::Tag(TagName::HTML),
+                NodeName::Tag(TagName::HEAD),
+                NodeName::Tag(TagName::HEAD),
+                NodeName::Tag(TagName::BODY),
+                NodeName::Tag(TagName::P),
+                NodeName::Token(TokenType::Text),
+                NodeName::Tag(TagName::P),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_budget_max_virtual_elements() {
+        // Every table implies a TBODY and a TR, so the virtual elements grow with the input.
+        let html = "<table><td>x</table>".repeat(100);
+        let mut processor = HtmlProcessor::create_full_parser(html.as_bytes(), "UTF-8").unwrap();
+        processor.set_budget(ParsingBudget {
+            max_virtual_elements: Some(10),
+            ..Default::default()
+        });
+
+        while processor.next_token() {}
+
+        assert_eq!(
+            processor.get_last_error(),
+            Some(&HtmlProcessorError::ExceededBudget)
+        );
+    }
+
+    #[test]
+    fn test_budget_checkpoint() {
+        let html = "<br>".repeat(100);
+        let mut processor = HtmlProcessor::create_full_parser(html.as_bytes(), "UTF-8").unwrap();
+
+        let checkpoints = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = checkpoints.clone();
+        processor.set_budget(ParsingBudget {
+            checkpoint_every: 10,
+            checkpoint: Some(Box::new(move |tokens| {
+                seen.lock().unwrap().push(tokens);
+                tokens < 30
+            })),
+            ..Default::default()
+        });
+
+        while processor.next_token() {}
+
+        assert_eq!(checkpoints.lock().unwrap().as_slice(), &[10, 20, 30]);
+        assert_eq!(
+            processor.get_last_error(),
+            Some(&HtmlProcessorError::ExceededBudget)
+        );
+    }
+
+    #[test]
+    fn test_reject_invalid_utf8() {
+        let mut processor = HtmlProcessor::create_full_parser(b"<p>\xC3\x28</p>", "UTF-8").unwrap();
+        assert!(!processor.set_utf8_policy(Utf8Policy::Reject));
+        assert!(!processor.next_token());
+        assert_eq!(
+            processor.get_last_error(),
+            Some(&HtmlProcessorError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn test_open_elements() {
+        let mut processor =
+            HtmlProcessor::create_full_parser(b"<p><svg><rect/></svg>", "UTF-8").unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::Arbitrary(b"RECT".as_slice().into())),
+            ..Default::default()
+        })));
+
+        let open_elements: Vec<_> = processor
+            .get_open_elements()
+            .map(|element| {
+                (
+                    element.node_name.clone(),
+                    element.namespace.clone(),
+                    element.is_virtual,
+                )
+            })
+            .collect();
+        assert_eq!(
+            open_elements,
+            vec![
+                (NodeName::Tag(TagName::HTML), ParsingNamespace::Html, true),
+                (NodeName::Tag(TagName::BODY), ParsingNamespace::Html, true),
+                (NodeName::Tag(TagName::P), ParsingNamespace::Html, false),
+                (NodeName::Tag(TagName::SVG), ParsingNamespace::Svg, false),
+                (
+                    NodeName::Tag(TagName::Arbitrary(b"RECT".as_slice().into())),
+                    ParsingNamespace::Svg,
+                    false
+                ),
+            ]
+        );
+        assert_eq!(
+            processor.get_open_elements().len(),
+            processor.get_breadcrumbs().len()
+        );
+    }
+
+    #[test]
+    fn test_active_formatting_elements() {
+        let mut processor =
+            HtmlProcessor::create_full_parser(b"<b><i><table><td>x</td></table></i>", "UTF-8")
+                .unwrap();
+        assert!(processor.get_active_formatting_elements().is_empty());
+
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::TD),
+            ..Default::default()
+        })));
+        assert_eq!(
+            processor.get_active_formatting_elements(),
+            vec![
+                ActiveFormattingEntry::Element(NodeName::Tag(TagName::B)),
+                ActiveFormattingEntry::Element(NodeName::Tag(TagName::I)),
+                ActiveFormattingEntry::Marker,
+            ]
+        );
+
+        while processor.next_token() {}
+        assert_eq!(
+            processor.get_active_formatting_elements(),
+            vec![ActiveFormattingEntry::Element(NodeName::Tag(TagName::B))]
+        );
+    }
+
+    #[test]
+    fn test_create_fragment() {
+        let mut processor = HtmlProcessor::create_fragment(b"<p>Hi", "<body>", "UTF-8").unwrap();
+        assert_eq!(processor.get_current_depth(), 2);
+        assert!(processor.next_tag(None));
+        assert_eq!(
+            processor.get_breadcrumbs(),
+            &[
+                NodeName::Tag(TagName::HTML),
+                NodeName::Tag(TagName::BODY),
+                NodeName::Tag(TagName::P),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<div><p>One<p>Two</div><em>Three", "<body>", "UTF-8")
+                .unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::P),
+            ..Default::default()
+        })));
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::P),
+            ..Default::default()
+        })));
+        assert!(processor.set_bookmark("second").is_ok());
+        assert!(processor.set_attribute("id", "second"));
+
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::EM),
+            ..Default::default()
+        })));
+        assert!(processor.seek("second"));
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+        assert_eq!(
+            processor.get_attribute(b"id"),
+            Some(AttributeValue::String(b"second".as_slice().into()))
+        );
+        assert_eq!(
+            processor.get_breadcrumbs(),
+            &[
+                NodeName::Tag(TagName::HTML),
+                NodeName::Tag(TagName::BODY),
+                NodeName::Tag(TagName::DIV),
+                NodeName::Tag(TagName::P),
+            ]
+        );
+
+        assert!(processor.release_bookmark("second"));
+        assert!(!processor.seek("second"));
+    }
+
+    #[test]
+    fn test_try_next_token() {
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<p>One</p><plaintext>", "<body>", "UTF-8").unwrap();
+        assert_eq!(
+            processor.try_next_token(),
+            Ok(Some(NodeEvent {
+                node_name: NodeName::Tag(TagName::P),
+                is_closer: false,
+                is_virtual: false,
+            }))
+        );
+        assert_eq!(
+            processor.try_next_token(),
+            Ok(Some(NodeEvent {
+                node_name: TokenType::Text.into(),
+                is_closer: false,
+                is_virtual: false,
+            }))
+        );
+        assert!(matches!(
+            processor.try_next_tag(None),
+            Err(HtmlProcessorError::UnsupportedException(
+                UnsupportedException::CannotProcessPlaintextElements
+            ))
+        ));
+
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<p>One</p>", "<body>", "UTF-8").unwrap();
+        while processor.try_next_token().unwrap().is_some() {}
+        assert_eq!(processor.try_next_token(), Ok(None));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<ul><li>One<li>Two</ul><p>After", "<body>", "UTF-8")
+                .unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::LI),
+            ..Default::default()
+        })));
+        let snapshot = processor.snapshot();
+
+        // Speculatively parse ahead, modifying the document along the way.
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::LI),
+            ..Default::default()
+        })));
+        assert!(processor.set_attribute("id", "two"));
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::P),
+            ..Default::default()
+        })));
+
+        processor.restore(snapshot);
+        assert_eq!(processor.get_tag(), Some(TagName::LI));
+        assert_eq!(
+            processor.get_breadcrumbs(),
+            &[
+                NodeName::Tag(TagName::HTML),
+                NodeName::Tag(TagName::BODY),
+                NodeName::Tag(TagName::UL),
+                NodeName::Tag(TagName::LI),
+            ]
+        );
+
+        // Parsing continues as it would have from the snapshot.
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::LI),
+            ..Default::default()
+        })));
+        assert_eq!(
+            processor.get_attribute(b"id"),
+            Some(AttributeValue::BooleanFalse)
+        );
+        assert_eq!(
+            processor.get_breadcrumbs(),
+            &[
+                NodeName::Tag(TagName::HTML),
+                NodeName::Tag(TagName::BODY),
+                NodeName::Tag(TagName::UL),
+                NodeName::Tag(TagName::LI),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reparse_checkpoints() {
+        let html = format!("<ul>{}</ul><p>After", "<li><b>Item</b>".repeat(100));
+        let mut processor = HtmlProcessor::create_full_parser(html.as_bytes(), "UTF-8").unwrap();
+        processor.set_reparse_checkpoints(Some(10));
+
+        let mut items = 0;
+        while processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::LI),
+            ..Default::default()
+        })) {
+            items += 1;
+            if 90 == items {
+                assert!(processor.set_bookmark("item-90").is_ok());
+            }
+            if 95 == items {
+                assert!(processor.set_attribute("id", "item-95"));
+            }
+        }
+        assert_eq!(items, 100);
+
+        // Reparsing starts close to the bookmark, despite the update after it.
+        processor.set_budget(ParsingBudget {
+            max_tokens: Some(20),
+            ..Default::default()
+        });
+        assert!(processor.seek("item-90"));
+        assert_eq!(processor.get_tag(), Some(TagName::LI));
+        assert_eq!(
+            processor.get_breadcrumbs(),
+            &[
+                NodeName::Tag(TagName::HTML),
+                NodeName::Tag(TagName::BODY),
+                NodeName::Tag(TagName::UL),
+                NodeName::Tag(TagName::LI),
+            ]
+        );
+
+        processor.set_budget(ParsingBudget::default());
+        let mut items = 90;
+        while processor.next_tag(None) {
+            if processor.get_tag() == Some(TagName::LI) {
+                items += 1;
+            }
+            if 95 == items {
+                break;
+            }
+        }
+        assert_eq!(
+            processor.get_attribute(b"id"),
+            Some(AttributeValue::String(b"item-95".as_slice().into()))
+        );
+
+        // Without checkpoints, the whole document is reparsed and exceeds the budget.
+        processor.set_reparse_checkpoints(None);
+        processor.set_budget(ParsingBudget {
+            max_tokens: Some(20),
+            ..Default::default()
+        });
+        assert!(!processor.seek("item-90"));
+        assert_eq!(
+            processor.get_last_error(),
+            Some(&HtmlProcessorError::ExceededBudget)
+        );
+    }
+
+    #[test]
+    fn test_node_handles() {
+        let mut processor =
+            HtmlProcessor::create_full_parser(b"<ul><li>One<li>Two</ul>", "UTF-8").unwrap();
+
+        // BODY is implied by the UL.
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::BODY),
+            ..Default::default()
+        })));
+        let body = processor.current_node_handle().unwrap();
+        assert!(body.is_virtual());
+
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::LI),
+            ..Default::default()
+        })));
+        let first_item = processor.current_node_handle().unwrap();
+        assert_eq!(first_item.node_name(), &NodeName::Tag(TagName::LI));
+        assert!(!first_item.is_virtual());
+        assert_eq!(processor.expects_closer(Some(&first_item)), Some(true));
+
+        while processor.next_token() {}
+
+        assert!(processor.seek_to(&first_item));
+        assert_eq!(processor.get_tag(), Some(TagName::LI));
+        assert!(processor.next_token());
+        assert_eq!(&*processor.get_modifiable_text(), b"One");
+
+        assert!(processor.seek_to(&body));
+        assert_eq!(processor.get_tag(), Some(TagName::BODY));
+        assert_eq!(processor.get_current_depth(), 2);
+
+        assert!(processor.release_node_handle(first_item.clone()));
+        assert!(!processor.seek_to(&first_item));
+    }
+
+    #[test]
+    fn test_skip_current_subtree() {
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<ul><li>One <b>bold</b><li>Two</ul><img><p>After",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+        assert!(!processor.skip_current_subtree());
+
+        assert!(processor.next_tag(None));
+        assert!(processor.skip_current_subtree());
+        assert_eq!(processor.get_tag(), Some(TagName::UL));
+        assert!(processor.is_tag_closer());
+        assert!(!processor.skip_current_subtree());
+
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_tag(), Some(TagName::IMG));
+        assert!(processor.skip_current_subtree());
+        assert_eq!(processor.get_tag(), Some(TagName::IMG));
+
+        assert!(processor.next_token());
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+        assert!(processor.skip_current_subtree());
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+        assert!(processor.is_tag_closer());
+        assert!(!processor.next_token());
+    }
+
+    #[test]
+    fn test_next_sibling_tag() {
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<ul><li>One <b>bold</b><li>Two</ul><img><p>After</p>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+
+        let mut siblings = Vec::new();
+        assert!(processor.next_tag(None));
+        siblings.push(processor.get_tag().unwrap());
+        while processor.next_sibling_tag() {
+            siblings.push(processor.get_tag().unwrap());
+        }
+        assert_eq!(siblings, vec![TagName::UL, TagName::IMG, TagName::P]);
+
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<ul><li>One <b>bold</b><li>Two</ul>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::LI),
+            ..Default::default()
+        })));
+        assert!(processor.next_sibling_tag());
+        assert_eq!(processor.get_tag(), Some(TagName::LI));
+        assert!(!processor.next_sibling_tag());
+        assert_eq!(processor.get_tag(), Some(TagName::UL));
+        assert!(processor.is_tag_closer());
+    }
+
+    #[test]
+    fn test_goto_parent() {
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<figure><img src=a.jpg><figcaption>A <em>caption</em></figcaption></figure>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::EM),
+            ..Default::default()
+        })));
+
+        assert!(processor.goto_parent());
+        assert_eq!(processor.get_tag(), Some(TagName::FIGCAPTION));
+        assert!(!processor.is_tag_closer());
+
+        assert!(processor.goto_parent());
+        assert_eq!(processor.get_tag(), Some(TagName::FIGURE));
+        assert!(processor.set_attribute("class", "wp-block-image"));
+
+        // The context element can't be visited.
+        assert!(!processor.goto_parent());
+
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<ul><li>One</ul><p>Two</p>", "<body>", "UTF-8")
+                .unwrap();
+        while processor.next_token() {
+            if processor.get_tag() == Some(TagName::LI) && processor.is_tag_closer() {
+                break;
+            }
+        }
+        assert!(processor.goto_parent());
+        assert_eq!(processor.get_tag(), Some(TagName::UL));
+        assert!(!processor.is_tag_closer());
+    }
+
+    #[test]
+    fn test_goto_virtual_parent() {
+        let mut processor = HtmlProcessor::create_full_parser(b"<p>Hello", "UTF-8").unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::P),
+            ..Default::default()
+        })));
+        assert!(processor.goto_parent());
+        assert_eq!(processor.get_tag(), Some(TagName::BODY));
+        assert!(processor.is_virtual());
+
+        assert!(processor.goto_parent());
+        assert_eq!(processor.get_tag(), Some(TagName::HTML));
+        assert!(!processor.goto_parent());
+    }
+
+    #[test]
+    fn test_closest() {
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<a href=/><span><img src=a.jpg></span></a><div><img src=b.jpg></div>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+
+        let mut linked = Vec::new();
+        while processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::IMG),
+            ..Default::default()
+        })) {
+            let Some(AttributeValue::String(src)) = processor.get_attribute(b"src") else {
+                panic!("Expected a src attribute.");
+            };
+            let image = processor.current_node_handle().unwrap();
+            if processor.closest(&TagName::A) {
+                assert_eq!(processor.get_tag(), Some(TagName::A));
+                assert!(processor.set_attribute("class", "has-image"));
+                linked.push(src);
+                assert!(processor.seek_to(&image));
+            }
+            processor.release_node_handle(image);
+        }
+        assert_eq!(linked, vec![b"a.jpg".as_slice().into()]);
+        assert_eq!(
+            processor.tag_processor.get_updated_html().as_ref(),
+            b"<a class=\"has-image\" href=/><span><img src=a.jpg></span></a><div><img src=b.jpg></div>"
+        );
+
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<div><p>x</p></div>", "<body>", "UTF-8").unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::P),
+            ..Default::default()
+        })));
+        assert!(processor.closest(&TagName::P));
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+        assert!(processor.closest(&TagName::DIV));
+        assert_eq!(processor.get_tag(), Some(TagName::DIV));
+        assert!(!processor.closest(&TagName::BODY));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            HtmlProcessor::normalize(
+                b"<a href=#anchor v=5 href=\"/\" enabled>One</a another v=5><!--"
+            ),
+            Ok("<a href=\"#anchor\" v=\"5\" enabled>One</a>".into())
+        );
+        assert_eq!(
+            HtmlProcessor::normalize(b"<div></p>fun<table><td>cell</div>"),
+            Ok("<div><p></p>fun<table><tbody><tr><td>cell</td></tr></tbody></table></div>".into())
+        );
+        assert_eq!(
+            HtmlProcessor::normalize(b"<![CDATA[invalid comment]]> syntax < <> \"oddities\""),
+            Ok("<!--[CDATA[invalid comment]]--> syntax &lt; &lt;&gt; &quot;oddities&quot;".into())
+        );
+        assert_eq!(
+            HtmlProcessor::normalize(b"<svg viewbox='0 0 1 1'><path d=x /></svg><pre>\n\nx</pre><textarea>\n\ny</textarea>"),
+            Ok("<svg viewBox=\"0 0 1 1\"><path d=\"x\" /></svg><pre>\n\nx</pre><textarea>\n\ny</textarea>".into())
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_serialize_document() {
+        let mut processor = HtmlProcessor::create_full_parser(
+            b"<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'x\"y'><title>A&amp;B</title>hi",
+            "UTF-8",
+        )
+        .unwrap();
+        assert_eq!(
+            processor.serialize(),
+            Ok("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'x\"y'><html><head><title>A&amp;B</title></head><body>hi</body></html>".into())
+        );
+
+        // Serialization must start before processing.
+        assert_eq!(processor.serialize(), Err(()));
+    }
+
+    #[test]
+    fn test_reset_with() {
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<ul><li>One<li>Two", "<body>", "UTF-8").unwrap();
+        processor.set_max_depth(Some(10));
+        assert!(processor.next_tag(None));
+        let ul = processor.current_node_handle().unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::LI),
+            ..Default::default()
+        })));
+        assert!(processor.set_bookmark("li").is_ok());
+
+        assert!(processor.reset_with(b"<p>Three<table><td>Four"));
+        assert!(!processor.has_bookmark("li"));
+        assert_eq!(processor.get_max_depth(), Some(10));
+        assert_eq!(
+            processor.serialize(),
+            Ok("<p>Three</p><table><tbody><tr><td>Four</td></tr></tbody></table>".into())
+        );
+
+        // Node handles from an earlier document don't refer to nodes in the new one.
+        assert!(processor.reset_with(b"<ul><li>Six<li>Seven"));
+        let mut handles = Vec::new();
+        while processor.next_token() {
+            handles.extend(processor.current_node_handle());
+        }
+        assert!(!handles.contains(&ul));
+        assert!(!processor.seek_to(&ul));
+
+        // The processor may be reused after reaching the end of the document.
+        assert!(processor.reset_with(b"<b>Five"));
+        assert!(processor.next_tag(None));
+        assert_eq!(
+            processor.get_breadcrumbs(),
+            vec![
+                NodeName::Tag(TagName::HTML),
+                NodeName::Tag(TagName::BODY),
+                NodeName::Tag(TagName::B)
+            ]
+        );
+        assert!(processor.set_bookmark("b").is_ok());
+        assert!(processor.next_token());
+        assert!(processor.seek("b"));
+
+        let mut processor =
+            HtmlProcessor::create_full_parser(b"<title>A</title>", "UTF-8").unwrap();
+        while processor.next_token() {}
+        assert!(processor.reset_with(b"<!DOCTYPE html><p>B"));
+        assert_eq!(
+            processor.serialize(),
+            Ok("<!DOCTYPE html><html><head></head><body><p>B</p></body></html>".into())
+        );
+    }
+
+    #[test]
+    fn test_next_text_comment_and_doctype() {
+        let html = b"<!DOCTYPE html><!DOCTYPE html><p>One <!-- a --><table>\n<td>two</table>";
+
+        let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8").unwrap();
+        let mut texts = Vec::new();
+        while processor.next_text() {
+            texts.push(processor.get_modifiable_text());
+        }
+        assert_eq!(
+            texts,
+            vec![
+                b"One ".as_slice().into(),
+                b"\n".as_slice().into(),
+                b"two".as_slice().into()
+            ]
+        );
+
+        let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8").unwrap();
+        assert!(processor.next_doctype());
+        assert!(!processor.next_doctype());
+
+        let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8").unwrap();
+        assert!(processor.next_comment());
+        assert_eq!(processor.get_modifiable_text().as_ref(), b" a ");
+        assert!(!processor.next_comment());
+    }
+
+    #[test]
+    fn test_skip_raw_text_contents() {
+        let html = b"<p>One</p><template>Two<b>Three</b></template><svg><style>.a{}</style><script>x()</script><text>Four</text></svg><script>y()</script>";
+
+        let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+        processor.set_skip_raw_text_contents(true);
+        let mut texts = Vec::new();
+        while processor.next_text() {
+            texts.push(processor.get_modifiable_text());
+        }
+        assert_eq!(
+            texts,
+            vec![b"One".as_slice().into(), b"Four".as_slice().into()]
+        );
+
+        // Elements inside the TEMPLATE are still visited.
+        let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+        processor.set_skip_raw_text_contents(true);
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::B),
+            ..Default::default()
+        })));
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::SCRIPT),
+            ..Default::default()
+        })));
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some(TagName::SCRIPT),
+            ..Default::default()
+        })));
+        assert_eq!(processor.get_modifiable_text().as_ref(), b"y()");
+
+        // Serialization keeps all of the text.
+        let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+        processor.set_skip_raw_text_contents(true);
+        assert!(processor.serialize().unwrap().contains("Three"));
+    }
+
+    #[test]
+    fn test_get_text_node_classification() {
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<ul>\n\t<li> One</li>\n</ul>", "<body>", "UTF-8")
+                .unwrap();
+
+        let mut texts = Vec::new();
+        while processor.next_token() {
+            if let Some(classification) = processor.get_text_node_classification() {
+                texts.push((classification.clone(), processor.get_modifiable_text()));
+            }
+        }
+        assert_eq!(
+            texts,
+            vec![
+                (
+                    TextNodeClassification::Whitespace,
+                    b"\n\t".as_slice().into()
+                ),
+                (TextNodeClassification::Whitespace, b" ".as_slice().into()),
+                (TextNodeClassification::Generic, b"One".as_slice().into()),
+                (TextNodeClassification::Whitespace, b"\n".as_slice().into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_token_html() {
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<p class=a>One<table><td>x</TD></table>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+
+        let mut tokens = Vec::new();
+        while processor.next_token() {
+            tokens.push(processor.get_token_html().map(<[u8]>::to_vec));
+        }
+        assert_eq!(
+            tokens,
+            vec![
+                Some(b"<p class=a>".to_vec()),
+                Some(b"One".to_vec()),
+                None,
+                Some(b"<table>".to_vec()),
+                None,
+                None,
+                Some(b"<td>".to_vec()),
+                Some(b"x".to_vec()),
+                Some(b"</TD>".to_vec()),
+                None,
+                None,
+                Some(b"</table>".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_customizable_select() {
+        let normalize = |html: &[u8], select_parsing| {
+            let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+            assert!(processor.set_select_parsing(select_parsing));
+            processor.serialize()
+        };
+
+        let html = b"<select><button>Pick</button><option><img src=a>A<option>B<hr><optgroup><option>C</select>";
+        assert_eq!(
+            normalize(html, SelectParsing::Legacy),
+            Ok("<select>Pick<option>A</option><option>B</option><hr><optgroup><option>C</option></optgroup></select>".into())
+        );
+        assert_eq!(
+            normalize(html, SelectParsing::Customizable),
+            Ok("<select><button>Pick</button><option><img src=\"a\">A</option><option>B</option><hr><optgroup><option>C</option></optgroup></select>".into())
+        );
+
+        // A nested SELECT or INPUT closes the open SELECT.
+        assert_eq!(
+            normalize(
+                b"<select><div>a<select>b<select>c<input>",
+                SelectParsing::Customizable
+            ),
+            Ok("<select><div>a</div></select>b<select>c</select><input>".into())
+        );
+        assert_eq!(
+            normalize(
+                b"<div><select><span>a</select>b",
+                SelectParsing::Customizable
+            ),
+            Ok("<div><select><span>a</span></select>b</div>".into())
+        );
+
+        let mut processor = HtmlProcessor::create_fragment(b"<select>", "<body>", "UTF-8").unwrap();
+        assert_eq!(processor.get_select_parsing(), SelectParsing::Legacy);
+        assert!(processor.next_token());
+        assert!(!processor.set_select_parsing(SelectParsing::Customizable));
+        assert_eq!(processor.get_select_parsing(), SelectParsing::Legacy);
+    }
+
+    #[test]
+    fn test_write_serialized() {
+        let html = b"<div></p>fun<table><td>cell</div><!--";
+        let expected = HtmlProcessor::create_fragment(html, "<body>", "UTF-8")
+            .unwrap()
+            .serialize()
+            .unwrap();
+
+        let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+        let mut written = String::new();
+        assert_eq!(processor.write_serialized_fmt(&mut written), Ok(()));
+        assert_eq!(written, expected);
+        assert!(processor.write_serialized_fmt(&mut String::new()).is_err());
+
+        #[cfg(feature = "std")]
+        {
+            let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+            let mut written = Vec::new();
+            processor.write_serialized(&mut written).unwrap();
+            assert_eq!(written, expected.as_bytes());
+            assert_eq!(
+                processor.write_serialized(&mut written).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidData
+            );
+        }
+    }
+
+    #[test]
+    fn test_debug_tree() {
+        let mut processor = HtmlProcessor::create_full_parser(
+            b"<!DOCTYPE html><p class=note id=a>Hi there<!-- c --><template><b></template>\
+            <svg viewbox='0 0 1 1' xlink:href=#a><foreignObject>",
+            "UTF-8",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(processor.debug_tree().unwrap().into_vec()).unwrap(),
+            "<!DOCTYPE html>
+<html>
+  <head>
+  <body>
+    <p>
+      class=\"note\"
+      id=\"a\"
+      \"Hi there\"
+      <!--  c  -->
+      <template>
+        content
+          <b>
+      <svg svg>
+        viewBox=\"0 0 1 1\"
+        xlink href=\"#a\"
+        <svg foreignObject>
+"
+        );
+
+        // The tree must be built before processing.
+        assert_eq!(processor.debug_tree(), None);
+
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<li>One<li>Two", "<body>", "UTF-8").unwrap();
+        assert_eq!(
+            processor.debug_tree().as_deref(),
+            Some(b"<li>\n  \"One\"\n<li>\n  \"Two\"\n".as_slice())
+        );
+    }
+
+    #[test]
+    fn test_max_depth_not_exceeded() {
+        let html = "<div><p>Shallow</p></div>";
+        let mut processor = HtmlProcessor::create_full_parser(html.as_bytes(), "UTF-8").unwrap();
+        processor.set_max_depth(Some(5));
+        assert_eq!(processor.get_max_depth(), Some(5));
+
+        while processor.next_token() {}
+
+        assert_eq!(processor.get_last_error(), None);
+    }
+
+    #[test]
+    fn test_hostile_input_does_not_panic() {
+        let inputs: [&[u8]; 8] = [
+            b"<table><td><svg><desc><td></table></svg>",
+            b"<form><table><form></table></form></form>",
+            b"<math><mi></p></math></mi><svg><script/></svg>",
+            b"<ruby><rtc><rp><rt></ruby><select><option><svg>",
+            b"<template><col><tr></template></template><frameset>",
+            b"<a><b><nobr><nobr><applet></b></marquee></a>",
+            b"</html></body><![CDATA[x]]><table>\0x</table>",
+            b"<svg><![CDATA[<table>]]><foreignObject><table></svg><td>",
+        ];
+
+        for html in inputs {
+            for context in ["<body>", "<table>", "<svg>", "<template>", "<select>"] {
+                if let Some(mut processor) = HtmlProcessor::create_fragment(html, context, "UTF-8")
+                {
+                    while processor.next_token() {}
+                }
+            }
+            let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8").unwrap();
+            while processor.next_token() {}
+        }
+    }
+
+    #[quickcheck]
+    fn test_arbitrary_tag_soup_does_not_panic(pieces: Vec<u8>) -> bool {
+        const PIECES: [&str; 24] = [
+            "<table>",
+            "</table>",
+            "<tr>",
+            "<td>",
+            "</td>",
+            "<form>",
+            "</form>",
+            "<svg>",
+            "</svg>",
+            "<math>",
+            "<mi>",
+            "</p>",
+            "<p>",
+            "<b>",
+            "</b>",
+            "<a>",
+            "<template>",
+            "</template>",
+            "<select>",
+            "<![CDATA[x]]>",
+            "<!--c-->",
+            "text",
+            " ",
+            "\0",
+        ];
+        let html: String = pieces
+            .iter()
+            .map(|piece| PIECES[*piece as usize % PIECES.len()])
+            .collect();
+
+        let mut processor = HtmlProcessor::create_full_parser(html.as_bytes(), "UTF-8").unwrap();
+        while processor.next_token() {}
+        true
+    }
+}
@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use super::html_token::HTMLToken;
 
 /// Core class used by the HTML processor during HTML parsing
@@ -20,6 +22,7 @@ use super::html_token::HTMLToken;
 ///
 /// @see https://html.spec.whatwg.org/#list-of-active-formatting-elements
 /// @see WP_HTML_Processor
+#[derive(Clone)]
 pub(super) struct ActiveFormattingElements {
     /// Holds the stack of active formatting element references.
     stack: Vec<ActiveFormattingElement>,
@@ -107,6 +110,25 @@ impl ActiveFormattingElements {
         self.stack.iter().rev()
     }
 
+    /// Steps through the stack of active formatting elements, starting with the
+    /// top element (added first) and walking downwards to the one added last.
+    ///
+    /// This generator function is designed to be used inside a "foreach" loop.
+    ///
+    /// Example:
+    ///
+    ///     $html = '<em><strong><a>We are here';
+    ///     foreach ( $stack->walk_down() as $node ) {
+    ///         echo "{$node->node_name} -> ";
+    ///     }
+    ///     > EM -> STRONG -> A ->
+    ///
+    /// To start with the most-recently added element and walk towards the top,
+    /// see WP_HTML_Active_Formatting_Elements::walk_up().
+    pub fn walk_down(&self) -> impl Iterator<Item = &ActiveFormattingElement> {
+        self.stack.iter()
+    }
+
     /// Removes a node from the stack of active formatting elements.
     ///
     /// @param WP_HTML_Token $token Remove this node from the stack, if it's there already.
@@ -136,7 +158,7 @@ impl ActiveFormattingElements {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(super) enum ActiveFormattingElement {
     Token(HTMLToken),
     Marker,
@@ -0,0 +1,95 @@
+use alloc::boxed::Box;
+
+/// Limits on the work the HTML Processor performs for a document.
+///
+/// Some inputs are pathologically expensive to parse: a long document of tiny tokens,
+/// or markup which makes the parser create many virtual elements, e.g. reconstructing
+/// a deep list of active formatting elements for every paragraph. When processing
+/// untrusted HTML, a budget bounds this work. When any limit is exceeded, the processor
+/// stops and reports `ExceededBudget` from `get_last_error()`.
+///
+/// All limits are disabled by default.
+///
+/// Example:
+///
+///     let started = Instant::now();
+///     $processor->set_budget( ParsingBudget {
+///         max_tokens: Some( 100_000 ),
+///         checkpoint_every: 1_000,
+///         checkpoint: Some( Box::new( move |_tokens| started.elapsed() < Duration::from_secs( 1 ) ) ),
+///         ..Default::default()
+///     } );
+#[derive(Default)]
+pub struct ParsingBudget {
+    /// Maximum number of tokens to read from the input HTML.
+    pub max_tokens: Option<usize>,
+
+    /// Maximum number of virtual elements to create, i.e. elements which are implied
+    /// by the HTML rather than found in it. This includes HTML, HEAD, and BODY elements
+    /// which are created when missing, and reconstructed formatting elements.
+    pub max_virtual_elements: Option<usize>,
+
+    /// How many tokens to read between calls to the checkpoint callback.
+    ///
+    /// A value of 0 is treated as 1.
+    pub checkpoint_every: usize,
+
+    /// Called periodically with the number of tokens read so far. Return `false` to
+    /// stop processing, e.g. when a deadline has passed.
+    ///
+    /// The callback must be `Send` so that processors can move between threads.
+    pub checkpoint: Option<Box<dyn FnMut(usize) -> bool + Send>>,
+}
+
+/// Tracks the work performed against a `ParsingBudget`.
+#[derive(Default)]
+pub(super) struct BudgetTracker {
+    budget: ParsingBudget,
+    tokens: usize,
+    virtual_elements: usize,
+}
+
+impl BudgetTracker {
+    pub fn new(budget: ParsingBudget) -> Self {
+        Self {
+            budget,
+            tokens: 0,
+            virtual_elements: 0,
+        }
+    }
+
+    /// Forgets the work performed so far, keeping the limits.
+    pub fn reset(&mut self) {
+        self.tokens = 0;
+        self.virtual_elements = 0;
+    }
+
+    /// Records that a token was read from the input HTML.
+    ///
+    /// @return bool Whether processing may continue.
+    pub fn count_token(&mut self) -> bool {
+        self.tokens += 1;
+
+        if self.budget.max_tokens.is_some_and(|max| self.tokens > max) {
+            return false;
+        }
+
+        let tokens = self.tokens;
+        let every = self.budget.checkpoint_every.max(1);
+        match &mut self.budget.checkpoint {
+            Some(checkpoint) if tokens.is_multiple_of(every) => checkpoint(tokens),
+            _ => true,
+        }
+    }
+
+    /// Records that a virtual element was created.
+    ///
+    /// @return bool Whether processing may continue.
+    pub fn count_virtual_element(&mut self) -> bool {
+        self.virtual_elements += 1;
+
+        self.budget
+            .max_virtual_elements
+            .is_none_or(|max| self.virtual_elements <= max)
+    }
+}
@@ -1,15 +1,20 @@
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum HtmlProcessorError {
     ExceededMaxBookmarks,
+    ExceededMaxDepth,
+    ExceededBudget,
+    InvalidUtf8,
+    UnexpectedState,
     UnsupportedException(UnsupportedException),
 }
-impl std::error::Error for HtmlProcessorError {
+impl core::error::Error for HtmlProcessorError {
     fn description(&self) -> &str {
         "description() is deprecated; use Display"
     }
 }
-impl std::fmt::Display for HtmlProcessorError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for HtmlProcessorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str(self.into())
     }
 }
@@ -17,6 +22,10 @@ impl From<HtmlProcessorError> for &str {
     fn from(val: HtmlProcessorError) -> Self {
         match val {
             HtmlProcessorError::ExceededMaxBookmarks => "exceeded-max-bookmarks",
+            HtmlProcessorError::ExceededMaxDepth => "exceeded-max-depth",
+            HtmlProcessorError::ExceededBudget => "exceeded-budget",
+            HtmlProcessorError::InvalidUtf8 => "invalid-utf8",
+            HtmlProcessorError::UnexpectedState => "unexpected-state",
             HtmlProcessorError::UnsupportedException(_) => "unsupported",
         }
     }
@@ -27,7 +36,8 @@ impl From<&HtmlProcessorError> for &str {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum UnsupportedException {
     MetaTagCharsetDetermineEncoding,
     MetaTagHttpEquivDetermineEncoding,
@@ -46,8 +56,8 @@ pub enum UnsupportedException {
     NonWhitespaceCharsAfterAfterFrameset,
     CannotCloseFormWithOtherElementsOpen,
 }
-impl std::fmt::Display for UnsupportedException {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for UnsupportedException {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str(self.into())
     }
 }
@@ -1,18 +1,18 @@
 use crate::html_processor::HTMLToken;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(super) struct HTMLStackEvent {
     pub operation: StackOperation,
     pub token: HTMLToken,
     pub provenance: StackProvenance,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(super) enum StackOperation {
     Push,
     Pop,
 }
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(super) enum StackProvenance {
     Real,
     Virtual,
@@ -1,6 +1,7 @@
 use crate::tag_processor::{NodeName, ParsingNamespace};
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct HTMLToken {
     pub(crate) is_root_node: bool,
     pub(crate) is_context_node: bool,
@@ -85,6 +86,7 @@ impl Default for HTMLToken {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum IntegrationNodeType {
     HTML,
     MathML,
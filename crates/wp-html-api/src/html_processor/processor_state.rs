@@ -1,8 +1,11 @@
+use alloc::{boxed::Box, vec::Vec};
+
 use super::{
     ActiveFormattingElements, EncodingConfidence, InsertionMode, StackOfOpenElements,
     html_token::HTMLToken,
 };
 
+#[derive(Clone)]
 pub(super) struct ProcessorState {
     pub(super) active_formatting_elements: ActiveFormattingElements,
     pub(super) current_token: Option<HTMLToken>,
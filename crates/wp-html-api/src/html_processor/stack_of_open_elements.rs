@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use crate::{
     html_processor::HTMLToken,
     tag_name::TagName,
@@ -41,6 +43,7 @@ const ELEMENT_IN_SCOPE_TERMINATION_LIST: [(&TagName, &ParsingNamespace); 18] = [
 ///
 /// @see https://html.spec.whatwg.org/#stack-of-open-elements
 /// @see WP_HTML_Processor
+#[derive(Clone)]
 pub(super) struct StackOfOpenElements {
     /// Holds the stack of open element references.
     pub stack: Vec<HTMLToken>,
@@ -1,14 +1,43 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 #[cfg(test)]
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
 
 #[macro_use]
 pub(crate) mod macros;
-pub(crate) mod attributes;
+
+#[cfg(feature = "serde")]
+pub(crate) mod serialize;
 pub(crate) mod str_fns;
 
+pub mod attributes;
+pub mod bit;
 pub mod compat_mode;
+pub mod decoder;
+#[cfg(feature = "html-processor")]
+pub mod diff;
 pub mod doctype;
+#[cfg(feature = "html-processor")]
+pub mod format;
+#[cfg(feature = "html-processor")]
 pub mod html_processor;
+pub mod links;
+pub mod metadata;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod query;
+#[cfg(feature = "html-processor")]
+pub mod sanitizer;
+#[cfg(feature = "html-processor")]
+pub mod stats;
+#[cfg(feature = "html-processor")]
+pub mod structured_data;
 pub mod tag_name;
 pub mod tag_processor;
+#[cfg(feature = "html-processor")]
+pub mod text;
+pub mod utf8;
+pub mod xml_processor;
@@ -0,0 +1,505 @@
+//! Finding and rewriting the URLs in a document, e.g. for crawlers or CDN-rewriting proxies.
+//!
+//! Links are found in the attributes of HTML tags which hold URLs: `a[href]`, `img[src]`
+//! and `img[srcset]`, `script[src]`, `form[action]`, the URL in a `<meta http-equiv=refresh>`,
+//! and others. The document is scanned with the Tag Processor, so links are reported in
+//! document order and never found inside comments, SCRIPT, STYLE, or other raw text.
+//!
+//! Every link carries the span of the raw attribute value it came from in the input HTML.
+//! Rewriting replaces only the attributes whose links change; the rest of the document is
+//! copied byte-for-byte.
+//!
+//! Example:
+//!
+//!     let html = rewrite_links( b"<img src=/a.jpg srcset='/a.jpg 1x, /b.jpg 2x'>", |link| {
+//!         link.url.starts_with( b"/" ).then( || [ b"https://cdn.example.com", link.url.as_ref() ].concat().into() )
+//!     } );
+//!     // <img src="https://cdn.example.com/a.jpg" srcset="https://cdn.example.com/a.jpg 1x, https://cdn.example.com/b.jpg 2x">
+//!
+//! @see https://html.spec.whatwg.org/#attributes-3
+
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use crate::{
+    attributes::srcset::{parse_srcset, serialize_srcset},
+    str_fns::esc_attr,
+    tag_name::TagName,
+    tag_processor::{AttributeValue, HtmlSpan, TagProcessor},
+};
+
+/// What a link is used for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    /// A link to another document, e.g. `a[href]` or `link[href]`.
+    Hyperlink,
+
+    /// A resource loaded into the document, e.g. `img[src]`, `script[src]`, or `video[poster]`.
+    Embedded,
+
+    /// Where a form is submitted, e.g. `form[action]` or `button[formaction]`.
+    FormAction,
+
+    /// The source of a quotation or an edit, e.g. `blockquote[cite]` or `del[cite]`.
+    Citation,
+
+    /// The base URL of the document, from `base[href]`.
+    Base,
+
+    /// The redirect target of a `<meta http-equiv="refresh">` element.
+    Refresh,
+}
+
+/// A URL found in the attributes of a tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// What the link is used for.
+    pub kind: LinkKind,
+
+    /// Name of the tag holding the link.
+    pub tag: TagName,
+
+    /// Lower-case name of the attribute holding the link, e.g. `href`.
+    pub attribute: &'static [u8],
+
+    /// The decoded URL, without surrounding whitespace.
+    ///
+    /// This may be only part of the attribute value, e.g. one candidate from a `srcset`.
+    pub url: Box<[u8]>,
+
+    /// Byte offset in the input HTML where the raw attribute value starts, after any quote.
+    pub value_starts_at: usize,
+
+    /// Byte length of the raw attribute value in the input HTML, without quotes.
+    pub value_length: usize,
+}
+
+/// Which part of a decoded attribute value holds a link.
+enum UrlPart {
+    /// The entire value, ignoring surrounding whitespace.
+    Value,
+
+    /// The image candidate at the given index in a `srcset` value.
+    Candidate(usize),
+
+    /// The given byte range of the value.
+    Range(usize, usize),
+}
+
+/// An attribute holding one or more links.
+struct UrlAttribute {
+    /// Span of the entire attribute in the input HTML.
+    span: HtmlSpan,
+
+    /// Decoded attribute value.
+    value: Box<[u8]>,
+
+    links: Vec<(Link, UrlPart)>,
+}
+
+impl UrlAttribute {
+    /// Returns the updated attribute value after replacing some of its links.
+    ///
+    /// Rewriting a `srcset` serializes its image candidates anew, which normalizes
+    /// whitespace and drops any candidates which browsers would ignore.
+    ///
+    /// @param array $replacements Replacement URL for each link, or `null` to keep it.
+    /// @return string|null Decoded attribute value, or `null` if no links were replaced.
+    fn rewrite(&self, replacements: &[Option<Box<[u8]>>]) -> Option<Box<[u8]>> {
+        if replacements.iter().all(Option::is_none) {
+            return None;
+        }
+
+        let mut candidates = None;
+        let mut value = self.value.clone();
+        for ((_, part), replacement) in self.links.iter().zip(replacements) {
+            let Some(replacement) = replacement else {
+                continue;
+            };
+
+            match part {
+                UrlPart::Value => value = replacement.clone(),
+                UrlPart::Candidate(index) => {
+                    candidates.get_or_insert_with(|| parse_srcset(&self.value))[*index].url =
+                        replacement.clone();
+                }
+                UrlPart::Range(start, end) => {
+                    value = [&self.value[..*start], replacement, &self.value[*end..]]
+                        .concat()
+                        .into();
+                }
+            }
+        }
+
+        Some(match candidates {
+            Some(candidates) => serialize_srcset(&candidates),
+            None => value,
+        })
+    }
+}
+
+/// Finds every link in an HTML document.
+///
+/// Attributes which are present but empty, or which hold only whitespace, contain no link.
+///
+/// Example:
+///
+///     let links = extract_links( b"<a href='/about'>About</a><img src=&quot;>" );
+///     ( Hyperlink, A, "href", "/about", 9, 6 ) == links[0];
+///     ( Embedded, IMG, "src", "\"", 35, 6 ) == links[1];
+///
+/// @param string $html Input HTML.
+/// @return Link[] Links in document order.
+pub fn extract_links(html: &[u8]) -> Vec<Link> {
+    let mut processor = TagProcessor::new(html);
+    let mut links = Vec::new();
+
+    while processor.next_tag(None) {
+        for attribute in url_attributes(&processor) {
+            links.extend(attribute.links.into_iter().map(|(link, _)| link));
+        }
+    }
+
+    links
+}
+
+/// Replaces links in an HTML document.
+///
+/// The callback is called for every link in document order, as `extract_links()` would
+/// return them. It returns the decoded replacement URL, or `None` to leave the link alone.
+/// Rewritten attributes are written as `name="value"` and the rest of the document is
+/// left untouched.
+///
+/// @param string   $html    Input HTML.
+/// @param callable $rewrite Returns the replacement URL for a link, if it should change.
+/// @return string Updated HTML.
+pub fn rewrite_links(
+    html: &[u8],
+    mut rewrite: impl FnMut(&Link) -> Option<Box<[u8]>>,
+) -> Box<[u8]> {
+    let mut processor = TagProcessor::new(html);
+    let mut output = Vec::with_capacity(html.len());
+    let mut copied_until = 0;
+
+    while processor.next_tag(None) {
+        for attribute in url_attributes(&processor) {
+            let replacements: Vec<_> = attribute
+                .links
+                .iter()
+                .map(|(link, _)| rewrite(link))
+                .collect();
+            let Some(value) = attribute.rewrite(&replacements) else {
+                continue;
+            };
+
+            // The attribute name is kept as written; it matched its lower-case form.
+            let name_length = attribute.links[0].0.attribute.len();
+            let start = attribute.span.start;
+            output.extend_from_slice(&html[copied_until..start]);
+            output.extend_from_slice(&html[start..start + name_length]);
+            output.extend_from_slice(b"=\"");
+            output.extend_from_slice(&esc_attr(&value));
+            output.push(b'"');
+            copied_until = start + attribute.span.length;
+        }
+    }
+
+    output.extend_from_slice(&html[copied_until..]);
+    output.into()
+}
+
+/// Returns the names of the attributes which may hold links on a given tag.
+///
+/// @param string $tag_name Lower-case tag name.
+/// @return array Attribute names and the kind of link each of them holds.
+fn link_attributes(tag_name: &[u8]) -> &'static [(&'static [u8], LinkKind)] {
+    match tag_name {
+        b"a" | b"area" => &[(b"href", LinkKind::Hyperlink)],
+        b"link" => &[
+            (b"href", LinkKind::Hyperlink),
+            (b"imagesrcset", LinkKind::Embedded),
+        ],
+        b"base" => &[(b"href", LinkKind::Base)],
+        b"img" | b"source" => &[
+            (b"src", LinkKind::Embedded),
+            (b"srcset", LinkKind::Embedded),
+        ],
+        b"audio" | b"embed" | b"frame" | b"iframe" | b"script" | b"track" => {
+            &[(b"src", LinkKind::Embedded)]
+        }
+        b"video" => &[
+            (b"src", LinkKind::Embedded),
+            (b"poster", LinkKind::Embedded),
+        ],
+        b"object" => &[(b"data", LinkKind::Embedded)],
+        b"input" => &[
+            (b"src", LinkKind::Embedded),
+            (b"formaction", LinkKind::FormAction),
+        ],
+        b"button" => &[(b"formaction", LinkKind::FormAction)],
+        b"form" => &[(b"action", LinkKind::FormAction)],
+        b"blockquote" | b"del" | b"ins" | b"q" => &[(b"cite", LinkKind::Citation)],
+        b"meta" => &[(b"content", LinkKind::Refresh)],
+        _ => &[],
+    }
+}
+
+/// Finds the attributes holding links on the matched tag, in the order they appear.
+fn url_attributes(processor: &TagProcessor) -> Vec<UrlAttribute> {
+    let mut attributes = Vec::new();
+    let (Some(tag), Some(tag_name)) = (processor.get_tag(), processor.get_tag_raw()) else {
+        return attributes;
+    };
+    if processor.is_tag_closer() {
+        return attributes;
+    }
+
+    for &(name, kind) in link_attributes(&tag_name.to_ascii_lowercase()) {
+        let Some(AttributeValue::String(value)) = processor.get_attribute(name) else {
+            continue;
+        };
+        let Some((span, value_span)) = processor.get_attribute_spans(name) else {
+            continue;
+        };
+
+        let link = |url: &[u8]| Link {
+            kind,
+            tag: tag.clone(),
+            attribute: name,
+            url: url.into(),
+            value_starts_at: value_span.start,
+            value_length: value_span.length,
+        };
+
+        let links: Vec<(Link, UrlPart)> = match (name, kind) {
+            (b"srcset" | b"imagesrcset", _) => parse_srcset(&value)
+                .iter()
+                .enumerate()
+                .map(|(index, candidate)| (link(&candidate.url), UrlPart::Candidate(index)))
+                .collect(),
+
+            (_, LinkKind::Refresh) => {
+                let is_refresh = matches!(
+                    processor.get_attribute(b"http-equiv"),
+                    Some(AttributeValue::String(http_equiv))
+                        if http_equiv.trim_ascii().eq_ignore_ascii_case(b"refresh")
+                );
+                match refresh_url(&value) {
+                    Some((start, end)) if is_refresh => {
+                        vec![(link(&value[start..end]), UrlPart::Range(start, end))]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+
+            _ => {
+                let url = value.trim_ascii();
+                if url.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![(link(url), UrlPart::Value)]
+                }
+            }
+        };
+
+        if !links.is_empty() {
+            attributes.push(UrlAttribute { span, value, links });
+        }
+    }
+
+    attributes.sort_by_key(|attribute| attribute.span.start);
+    attributes
+}
+
+/// Finds the URL in the `content` attribute of a `<meta http-equiv="refresh">` element.
+///
+/// Example:
+///
+///     Some( ( 10, 20 ) ) == refresh_url( b"5; URL = '/next.html'" );
+///     None == refresh_url( b"5" );
+///
+/// @see https://html.spec.whatwg.org/#shared-declarative-refresh-steps
+///
+/// @param string $content Decoded value of the `content` attribute.
+/// @return array|null Byte range of the URL in the content, if it holds one.
+fn refresh_url(content: &[u8]) -> Option<(usize, usize)> {
+    let end = content.len();
+    let skip_whitespace =
+        |at: usize| at + strspn!(content, b' ' | b'\t' | b'\n' | 0x0c | b'\r', at);
+
+    // The delay is a non-negative number, possibly with a fractional part which is ignored.
+    let mut at = skip_whitespace(0);
+    let digits = strspn!(content, b'0'..=b'9', at);
+    if 0 == digits && content.get(at) != Some(&b'.') {
+        return None;
+    }
+    at += strspn!(content, b'0'..=b'9' | b'.', at);
+
+    if at < end {
+        if !matches!(
+            content[at],
+            b';' | b',' | b' ' | b'\t' | b'\n' | 0x0c | b'\r'
+        ) {
+            return None;
+        }
+        at = skip_whitespace(at);
+        if matches!(content.get(at), Some(b';' | b',')) {
+            at = skip_whitespace(at + 1);
+        }
+    }
+
+    // An optional `url=` prefix.
+    if content.len() >= at + 3 && content[at..at + 3].eq_ignore_ascii_case(b"url") {
+        let equals_at = skip_whitespace(at + 3);
+        if content.get(equals_at) == Some(&b'=') {
+            at = skip_whitespace(equals_at + 1);
+        }
+    }
+
+    let mut url_end = end;
+    if let Some(&quote @ (b'"' | b'\'')) = content.get(at) {
+        at += 1;
+        url_end = content[at..]
+            .iter()
+            .position(|&c| c == quote)
+            .map_or(end, |quote_at| at + quote_at);
+    }
+
+    let url_length = content[at..url_end].trim_ascii_end().len();
+    (url_length > 0).then_some((at, at + url_length))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn link(kind: LinkKind, tag: TagName, attribute: &'static [u8], url: &[u8]) -> Link {
+        Link {
+            kind,
+            tag,
+            attribute,
+            url: url.into(),
+            value_starts_at: 0,
+            value_length: 0,
+        }
+    }
+
+    fn urls(html: &[u8]) -> Vec<Link> {
+        extract_links(html)
+            .into_iter()
+            .map(|found| Link {
+                value_starts_at: 0,
+                value_length: 0,
+                ..found
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let html = b"<!-- <a href=/comment> --><base href=/root/><a href=' /about '>About</a>\
+            <script src=app.js>document.write('<a href=/script>')</script>\
+            <img src=a.jpg srcset='a.jpg 1x, b.jpg 2x'><a href>Empty</a>\
+            <form action=/submit><button formaction=/other>Go</button></form>\
+            <blockquote cite=/source></blockquote><video poster=p.jpg><source src=v.mp4></video>";
+
+        assert_eq!(
+            urls(html),
+            vec![
+                link(LinkKind::Base, TagName::BASE, b"href", b"/root/"),
+                link(LinkKind::Hyperlink, TagName::A, b"href", b"/about"),
+                link(LinkKind::Embedded, TagName::SCRIPT, b"src", b"app.js"),
+                link(LinkKind::Embedded, TagName::IMG, b"src", b"a.jpg"),
+                link(LinkKind::Embedded, TagName::IMG, b"srcset", b"a.jpg"),
+                link(LinkKind::Embedded, TagName::IMG, b"srcset", b"b.jpg"),
+                link(LinkKind::FormAction, TagName::FORM, b"action", b"/submit"),
+                link(
+                    LinkKind::FormAction,
+                    TagName::BUTTON,
+                    b"formaction",
+                    b"/other"
+                ),
+                link(LinkKind::Citation, TagName::BLOCKQUOTE, b"cite", b"/source"),
+                link(
+                    LinkKind::Embedded,
+                    TagName::Arbitrary(b"VIDEO".as_slice().into()),
+                    b"poster",
+                    b"p.jpg"
+                ),
+                link(LinkKind::Embedded, TagName::SOURCE, b"src", b"v.mp4"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_link_spans() {
+        let html = b"<a HREF=\"/a?x=1&amp;y=2\">A</a><img src=b.jpg>";
+        let links = extract_links(html);
+
+        assert_eq!(links[0].url.as_ref(), b"/a?x=1&y=2");
+        assert_eq!(
+            &html[links[0].value_starts_at..links[0].value_starts_at + links[0].value_length],
+            b"/a?x=1&amp;y=2"
+        );
+        assert_eq!(
+            &html[links[1].value_starts_at..links[1].value_starts_at + links[1].value_length],
+            b"b.jpg"
+        );
+    }
+
+    #[test]
+    fn test_refresh_url() {
+        assert_eq!(refresh_url(b"5; URL = '/next.html'"), Some((10, 20)));
+        assert_eq!(refresh_url(b"0;url=/a b "), Some((6, 10)));
+        assert_eq!(refresh_url(b" 1.5 , /next"), Some((7, 12)));
+        assert_eq!(refresh_url(b"0 urgent"), Some((2, 8)));
+        assert_eq!(refresh_url(b"5"), None);
+        assert_eq!(refresh_url(b"5;"), None);
+        assert_eq!(refresh_url(b"soon; url=/next"), None);
+        assert_eq!(refresh_url(b"5x; url=/next"), None);
+
+        assert_eq!(
+            urls(b"<meta http-equiv=Refresh content='0; url=/moved'><meta name=x content=/no>"),
+            vec![link(
+                LinkKind::Refresh,
+                TagName::META,
+                b"content",
+                b"/moved"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let html = b"<a class=x HREF='/a' id=y>A</a><img srcset='/a.jpg 1x, http://x/b.jpg 2x' src=/a.jpg>\
+            <meta http-equiv=refresh content=\"0; url=/moved\"><script src=/app.js></script><p title=/a>";
+
+        let rewritten = rewrite_links(html, |link| {
+            link.url
+                .starts_with(b"/")
+                .then(|| [b"https://cdn.test".as_slice(), &link.url].concat().into())
+        });
+
+        assert_eq!(
+            String::from_utf8(rewritten.into_vec()).unwrap(),
+            "<a class=x HREF=\"https://cdn.test/a\" id=y>A</a>\
+            <img srcset=\"https://cdn.test/a.jpg 1x, http://x/b.jpg 2x\" src=\"https://cdn.test/a.jpg\">\
+            <meta http-equiv=refresh content=\"0; url=https://cdn.test/moved\">\
+            <script src=\"https://cdn.test/app.js\"></script><p title=/a>"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_escapes_urls() {
+        let html = b"<a href=/a>A</a><a href=/b>B</a><a href=/c";
+        let rewritten = rewrite_links(html, |link| {
+            (link.url.as_ref() == b"/b").then(|| b"/q?a=\"1\"&b=2".as_slice().into())
+        });
+
+        assert_eq!(
+            rewritten.as_ref(),
+            b"<a href=/a>A</a><a href=\"/q?a=&quot;1&quot;&amp;b=2\">B</a><a href=/c"
+        );
+        assert_eq!(rewrite_links(html, |_| None).as_ref(), html);
+    }
+}
@@ -44,6 +44,17 @@ macro_rules! strcspn {
     };
 }
 
+/// Emits a `tracing` event at the given level when the `tracing` feature is enabled.
+///
+/// Without the feature, the event and its fields are never evaluated.
+#[cfg_attr(not(feature = "html-processor"), allow(unused_macros))]
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::$level, $($arg)+);
+    };
+}
+
 #[cfg(test)]
 mod test {
     mod strspn {
@@ -0,0 +1,295 @@
+//! Extracts the metadata describing a document, e.g. its title, description, and icons.
+//!
+//! The document is scanned once with the Tag Processor. Metadata is usually found in the
+//! HEAD element, but since many pages misplace it, the entire document is scanned and
+//! metadata elements are recognized wherever they appear.
+//!
+//! Values are decoded but otherwise reported as written. URLs aren't resolved against the
+//! base URL; the base URL itself is reported so callers can resolve them.
+//!
+//! Example:
+//!
+//!     let metadata = extract_metadata( b"<html lang=en><title>Home</title><meta name=description content='A site'>" );
+//!     "Home" == metadata.title;
+//!     "A site" == metadata.description;
+//!     "en" == metadata.lang;
+//!
+//! @see https://html.spec.whatwg.org/#the-title-element
+//! @see https://ogp.me/
+//! @see https://developer.x.com/en/docs/x-for-websites/cards/overview/markup
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{
+    tag_name::TagName,
+    tag_processor::{AttributeValue, NextTagQuery, TagClosers, TagProcessor},
+};
+
+/// A `<meta>` property, e.g. from Open Graph or Twitter card markup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaProperty {
+    /// Name of the property as written, e.g. `og:title`.
+    pub property: Box<[u8]>,
+
+    /// Value of the property from the `content` attribute.
+    pub content: Box<[u8]>,
+}
+
+/// An icon for the document from a `<link rel="icon">` or similar element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Icon {
+    /// The link relation, e.g. `icon` or `apple-touch-icon`.
+    pub rel: Box<[u8]>,
+
+    /// URL of the icon.
+    pub href: Box<[u8]>,
+
+    /// Sizes the icon is available in, e.g. `32x32 64x64` or `any`, if given.
+    pub sizes: Option<Box<[u8]>>,
+
+    /// MIME type of the icon, e.g. `image/png`, if given.
+    pub mime_type: Option<Box<[u8]>>,
+}
+
+/// Metadata describing a document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentMetadata {
+    /// The document title, with whitespace stripped and collapsed as `document.title` does.
+    pub title: Option<Box<[u8]>>,
+
+    /// The description from `<meta name="description">`.
+    pub description: Option<Box<[u8]>>,
+
+    /// The canonical URL from `<link rel="canonical">`.
+    pub canonical: Option<Box<[u8]>>,
+
+    /// The base URL from `<base href>`.
+    pub base: Option<Box<[u8]>>,
+
+    /// The document language from `<html lang>`.
+    pub lang: Option<Box<[u8]>>,
+
+    /// Open Graph properties, e.g. `og:title`, in document order.
+    pub open_graph: Vec<MetaProperty>,
+
+    /// Twitter card properties, e.g. `twitter:card`, in document order.
+    pub twitter: Vec<MetaProperty>,
+
+    /// Icons for the document, in document order.
+    pub icons: Vec<Icon>,
+}
+
+/// Extracts the metadata from an HTML document.
+///
+/// When an element which may only appear once appears more than once, e.g. TITLE or
+/// `<meta name="description">`, the first one wins, as it does for browsers. Elements
+/// with missing or empty values are ignored.
+///
+/// @param string $html Input HTML.
+/// @return DocumentMetadata Metadata found in the document.
+pub fn extract_metadata(html: &[u8]) -> DocumentMetadata {
+    let mut processor = TagProcessor::new(html);
+    let mut metadata = DocumentMetadata::default();
+
+    // TITLE elements inside SVG title a graphic, not the document.
+    let mut svg_depth = 0_usize;
+
+    while processor.next_tag(Some(NextTagQuery {
+        tag_closers: TagClosers::Visit,
+        ..Default::default()
+    })) {
+        let Some(tag) = processor.get_tag() else {
+            continue;
+        };
+
+        if processor.is_tag_closer() {
+            if tag == TagName::SVG {
+                svg_depth = svg_depth.saturating_sub(1);
+            }
+            continue;
+        }
+
+        match tag {
+            TagName::SVG if !processor.has_self_closing_flag() => svg_depth += 1,
+
+            TagName::HTML => {
+                set_once(&mut metadata.lang, string_attribute(&processor, b"lang"));
+            }
+
+            TagName::TITLE if 0 == svg_depth && metadata.title.is_none() => {
+                let text = processor.get_modifiable_text();
+                let words: Vec<&[u8]> = text
+                    .split(u8::is_ascii_whitespace)
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                if !words.is_empty() {
+                    metadata.title = Some(words.join(b" ".as_slice()).into());
+                }
+            }
+
+            TagName::BASE => {
+                set_once(&mut metadata.base, url_attribute(&processor, b"href"));
+            }
+
+            TagName::LINK => {
+                let (Some(rel), Some(href)) = (
+                    string_attribute(&processor, b"rel"),
+                    url_attribute(&processor, b"href"),
+                ) else {
+                    continue;
+                };
+
+                for rel in rel
+                    .split(u8::is_ascii_whitespace)
+                    .map(<[u8]>::to_ascii_lowercase)
+                {
+                    match rel.as_slice() {
+                        b"canonical" => set_once(&mut metadata.canonical, Some(href.clone())),
+                        b"icon"
+                        | b"apple-touch-icon"
+                        | b"apple-touch-icon-precomposed"
+                        | b"mask-icon" => metadata.icons.push(Icon {
+                            rel: rel.into(),
+                            href: href.clone(),
+                            sizes: string_attribute(&processor, b"sizes"),
+                            mime_type: string_attribute(&processor, b"type"),
+                        }),
+                        _ => {}
+                    }
+                }
+            }
+
+            TagName::META => {
+                let Some(content) = string_attribute(&processor, b"content") else {
+                    continue;
+                };
+
+                // Open Graph uses `property` but `name` is common in the wild, and vice versa
+                // for Twitter cards, so both are accepted for each.
+                let Some(name) = string_attribute(&processor, b"property")
+                    .or_else(|| string_attribute(&processor, b"name"))
+                else {
+                    continue;
+                };
+
+                let comparable_name = name.to_ascii_lowercase();
+                let property = MetaProperty {
+                    property: name,
+                    content,
+                };
+                if comparable_name.starts_with(b"og:") {
+                    metadata.open_graph.push(property);
+                } else if comparable_name.starts_with(b"twitter:") {
+                    metadata.twitter.push(property);
+                } else if comparable_name == b"description" {
+                    set_once(&mut metadata.description, Some(property.content));
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+/// Sets a value unless it was already set.
+fn set_once(field: &mut Option<Box<[u8]>>, value: Option<Box<[u8]>>) {
+    if field.is_none() {
+        *field = value;
+    }
+}
+
+/// Returns the value of an attribute on the matched tag, unless it's missing or empty.
+fn string_attribute(processor: &TagProcessor, name: &[u8]) -> Option<Box<[u8]>> {
+    match processor.get_attribute(name) {
+        Some(AttributeValue::String(value)) if !value.trim_ascii().is_empty() => Some(value),
+        _ => None,
+    }
+}
+
+/// Returns the value of a URL attribute on the matched tag, without surrounding whitespace.
+fn url_attribute(processor: &TagProcessor, name: &[u8]) -> Option<Box<[u8]>> {
+    string_attribute(processor, name).map(|url| url.trim_ascii().into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bytes(value: &str) -> Option<Box<[u8]>> {
+        Some(value.as_bytes().into())
+    }
+
+    fn property(property: &str, content: &str) -> MetaProperty {
+        MetaProperty {
+            property: property.as_bytes().into(),
+            content: content.as_bytes().into(),
+        }
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_extract_metadata() {
+        let metadata = extract_metadata(
+            b"<!DOCTYPE html><html lang=en-US><head>\
+            <title>\n  Hello &amp;\n  welcome  </title><title>Second</title>\
+            <base href=' https://example.com/ '>\
+            <meta name=Description content='About &lt;us&gt;'><meta name=description content=Later>\
+            <link rel='canonical' href=/home>\
+            <meta property=og:title content=Hello><meta name=og:image content=/og.png>\
+            <meta name=twitter:card content=summary><meta property=og:empty content=''>\
+            <link rel='shortcut icon' href=/favicon.ico>\
+            <link rel=apple-touch-icon sizes=180x180 type=image/png href=/apple.png>\
+            </head></html>",
+        );
+
+        assert_eq!(metadata.title, bytes("Hello & welcome"));
+        assert_eq!(metadata.description, bytes("About <us>"));
+        assert_eq!(metadata.canonical, bytes("/home"));
+        assert_eq!(metadata.base, bytes("https://example.com/"));
+        assert_eq!(metadata.lang, bytes("en-US"));
+        assert_eq!(
+            metadata.open_graph,
+            vec![
+                property("og:title", "Hello"),
+                property("og:image", "/og.png")
+            ]
+        );
+        assert_eq!(metadata.twitter, vec![property("twitter:card", "summary")]);
+        assert_eq!(
+            metadata.icons,
+            vec![
+                Icon {
+                    rel: b"icon".as_slice().into(),
+                    href: b"/favicon.ico".as_slice().into(),
+                    sizes: None,
+                    mime_type: None,
+                },
+                Icon {
+                    rel: b"apple-touch-icon".as_slice().into(),
+                    href: b"/apple.png".as_slice().into(),
+                    sizes: bytes("180x180"),
+                    mime_type: bytes("image/png"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_metadata() {
+        assert_eq!(extract_metadata(b""), DocumentMetadata::default());
+        assert_eq!(
+            extract_metadata(b"<title> </title><link rel=canonical><meta name=description>"),
+            DocumentMetadata::default()
+        );
+    }
+
+    #[test]
+    fn test_ignores_svg_titles() {
+        let metadata = extract_metadata(
+            b"<svg><title>Graphic</title></svg><svg/><title>Page</title><svg><title>Icon</title></svg>",
+        );
+        assert_eq!(metadata.title, bytes("Page"));
+    }
+}
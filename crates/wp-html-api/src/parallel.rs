@@ -0,0 +1,107 @@
+//! Processing batches of independent HTML fragments across threads, e.g. every post in a site.
+//!
+//! Each fragment is processed by its own processor on the [rayon] thread pool, with the
+//! results collected in the order of the fragments. The Tag Processor borrows the fragment
+//! rather than copying it, so only the HTML Processor copies its input.
+//!
+//! Example:
+//!
+//!     let image_counts = map_with_tag_processor( &post_contents, |mut processor| {
+//!         let mut images = 0;
+//!         while processor.next_tag( None ) {
+//!             if processor.get_tag() == Some( TagName::IMG ) {
+//!                 images += 1;
+//!             }
+//!         }
+//!         images
+//!     } );
+//!
+//! [rayon]: https://docs.rs/rayon
+
+use alloc::vec::Vec;
+
+use rayon::prelude::*;
+
+#[cfg(feature = "html-processor")]
+use crate::html_processor::HtmlProcessor;
+use crate::tag_processor::TagProcessor;
+
+/// Runs a closure on a Tag Processor for each fragment, in parallel.
+///
+/// @param array    $fragments HTML fragments to process.
+/// @param callable $process   Called with a processor for each fragment.
+/// @return array Results of the closure, in the order of the fragments.
+pub fn map_with_tag_processor<Fragment, Output>(
+    fragments: &[Fragment],
+    process: impl Fn(TagProcessor<'_>) -> Output + Sync,
+) -> Vec<Output>
+where
+    Fragment: AsRef<[u8]> + Sync,
+    Output: Send,
+{
+    fragments
+        .par_iter()
+        .map(|fragment| process(TagProcessor::new_borrowed(fragment.as_ref())))
+        .collect()
+}
+
+/// Runs a closure on an HTML Processor for each fragment, in parallel.
+///
+/// Fragments are parsed in the default `<body>` context.
+///
+/// @see HtmlProcessor::create_fragment()
+///
+/// @param array    $fragments HTML fragments to process.
+/// @param callable $process   Called with a processor for each fragment.
+/// @return array Results of the closure, in the order of the fragments, or `None` where
+///               a processor couldn't be created for the fragment.
+#[cfg(feature = "html-processor")]
+pub fn map_with_html_processor<Fragment, Output>(
+    fragments: &[Fragment],
+    process: impl Fn(HtmlProcessor) -> Output + Sync,
+) -> Vec<Option<Output>>
+where
+    Fragment: AsRef<[u8]> + Sync,
+    Output: Send,
+{
+    fragments
+        .par_iter()
+        .map(|fragment| {
+            HtmlProcessor::create_fragment(fragment.as_ref(), "<body>", "UTF-8").map(&process)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tag_name::TagName;
+    use alloc::format;
+
+    #[test]
+    fn test_map_fragments() {
+        let fragments: Vec<_> = (0..100)
+            .map(|i| format!("<p>{i}</p>{}", "<img>".repeat(i)))
+            .collect();
+
+        let counts = map_with_tag_processor(&fragments, |mut processor| {
+            let mut images = 0;
+            while processor.next_tag(None) {
+                if processor.get_tag() == Some(TagName::IMG) {
+                    images += 1;
+                }
+            }
+            images
+        });
+        assert_eq!(counts, (0..100).collect::<Vec<_>>());
+
+        #[cfg(feature = "html-processor")]
+        {
+            let depths = map_with_html_processor(&fragments, |mut processor| {
+                assert!(processor.next_tag(None));
+                processor.get_current_depth()
+            });
+            assert!(depths.iter().all(|depth| *depth == Some(3)));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Building queries for `next_tag()` without spelling out every field of the query structs.
+//!
+//! A `Query` converts into the query for either processor, so the same query can find
+//! tags with the Tag Processor or the HTML Processor. Only the HTML Processor knows the
+//! structure of the document, so a query with breadcrumbs can't be converted for the
+//! Tag Processor.
+//!
+//! Example:
+//!
+//!     let query = Query::tag( "IMG" ).class( "hero" ).visit_closers().nth( 3 );
+//!     tag_processor.next_tag( Some( query.clone().try_into()? ) );
+//!     html_processor.next_tag( Some( query.breadcrumbs( vec![ "FIGURE", "IMG" ] ).into() ) );
+
+use alloc::{string::ToString, vec::Vec};
+
+#[cfg(feature = "html-processor")]
+use crate::html_processor::{TagQuery, VisitClosers};
+use crate::{
+    tag_name::TagName,
+    tag_processor::{NextTagQuery, ParsingNamespace, TagClosers},
+};
+
+/// Which tag to find with `next_tag()`.
+///
+/// By default, a query matches the first opening tag of any name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query<'a> {
+    tag_name: Option<TagName>,
+    class_name: Option<&'a str>,
+    visit_closers: bool,
+    match_offset: usize,
+    breadcrumbs: Option<Vec<&'a str>>,
+}
+
+impl Default for Query<'_> {
+    fn default() -> Self {
+        Self {
+            tag_name: None,
+            class_name: None,
+            visit_closers: false,
+            match_offset: 1,
+            breadcrumbs: None,
+        }
+    }
+}
+
+impl<'a> Query<'a> {
+    /// Creates a query which matches tags of any name.
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// Creates a query which matches tags with the given name.
+    ///
+    /// @param string $tag_name HTML tag name, ASCII case-insensitive, e.g. "img".
+    pub fn tag(tag_name: &str) -> Self {
+        Self {
+            tag_name: Some((tag_name.as_bytes(), &ParsingNamespace::Html).into()),
+            ..Self::default()
+        }
+    }
+
+    /// Only matches tags containing this whole class name.
+    ///
+    /// @param string $class_name Class name which must be present on the tag.
+    pub fn class(mut self, class_name: &'a str) -> Self {
+        self.class_name = Some(class_name);
+        self
+    }
+
+    /// Also matches tag closers, e.g. `</div>`, which are skipped by default.
+    pub fn visit_closers(mut self) -> Self {
+        self.visit_closers = true;
+        self
+    }
+
+    /// Finds the Nth tag matching all search criteria: 1 for "first", 3 for "third," etc.
+    ///
+    /// Counting starts at 1, so this panics when given 0.
+    ///
+    /// @param int $match_offset Which matching tag to find.
+    pub fn nth(mut self, match_offset: usize) -> Self {
+        assert!(match_offset > 0, "Tags are counted from 1, not 0.");
+        self.match_offset = match_offset;
+        self
+    }
+
+    /// Only matches tags found at this DOM sub-path, e.g. `vec!["FIGURE", "IMG"]`.
+    ///
+    /// The path may contain the wildcard `*` which matches a single element. Only the
+    /// HTML Processor knows the structure of the document, so the query can then no
+    /// longer be converted for the Tag Processor.
+    ///
+    /// @param string[] $breadcrumbs DOM sub-path at which the tag is found.
+    pub fn breadcrumbs(mut self, breadcrumbs: Vec<&'a str>) -> Self {
+        self.breadcrumbs = Some(breadcrumbs);
+        self
+    }
+}
+
+/// Converts a query for the Tag Processor, failing if it has breadcrumbs.
+impl TryFrom<Query<'_>> for NextTagQuery {
+    type Error = ();
+
+    fn try_from(query: Query<'_>) -> Result<Self, ()> {
+        if query.breadcrumbs.is_some() {
+            return Err(());
+        }
+
+        Ok(Self {
+            tag_name: query.tag_name,
+            match_offset: query.match_offset,
+            class_name: query.class_name.map(ToString::to_string),
+            tag_closers: if query.visit_closers {
+                TagClosers::Visit
+            } else {
+                TagClosers::Skip
+            },
+        })
+    }
+}
+
+#[cfg(feature = "html-processor")]
+impl<'a> From<Query<'a>> for TagQuery<'a> {
+    fn from(query: Query<'a>) -> Self {
+        Self {
+            tag_name: query.tag_name,
+            tag_closers: if query.visit_closers {
+                VisitClosers::Visit
+            } else {
+                VisitClosers::Skip
+            },
+            match_offset: Some(query.match_offset),
+            class_name: query.class_name,
+            breadcrumbs: query.breadcrumbs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tag_processor::TagProcessor;
+    use alloc::vec;
+
+    #[test]
+    fn test_query_builder() {
+        let html = b"<figure><img class=hero id=1></figure><img class=hero id=2><img id=3><figure><img class='x hero' id=4>";
+        let query = Query::tag("img").class("hero").nth(2);
+
+        let mut processor = TagProcessor::new(html);
+        assert!(processor.next_tag(Some(query.clone().try_into().unwrap())));
+        assert_eq!(
+            processor.get_attribute(b"id").unwrap().as_bytes(),
+            Some(b"2".as_slice())
+        );
+
+        let mut processor = TagProcessor::new(html);
+        assert!(processor.next_tag(Some(
+            Query::tag("FIGURE").visit_closers().try_into().unwrap()
+        )));
+        assert!(processor.next_tag(Some(
+            Query::any().visit_closers().nth(2).try_into().unwrap()
+        )));
+        assert!(processor.is_tag_closer());
+
+        // The Tag Processor can't follow breadcrumbs.
+        assert_eq!(
+            NextTagQuery::try_from(Query::tag("img").breadcrumbs(vec!["FIGURE", "IMG"])),
+            Err(())
+        );
+
+        #[cfg(feature = "html-processor")]
+        {
+            use crate::html_processor::HtmlProcessor;
+
+            let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8").unwrap();
+            assert!(
+                processor.next_tag(Some(query.nth(1).breadcrumbs(vec!["FIGURE", "IMG"]).into()))
+            );
+            assert!(processor.next_tag(Some(
+                Query::tag("img").breadcrumbs(vec!["FIGURE", "IMG"]).into()
+            )));
+            assert_eq!(
+                processor.get_attribute(b"id").unwrap().as_bytes(),
+                Some(b"4".as_slice())
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_nth_counts_from_one() {
+        let _ = Query::any().nth(0);
+    }
+}
@@ -0,0 +1,548 @@
+//! Allowlist-based HTML sanitization, in the spirit of WordPress's `wp_kses()`.
+//!
+//! A policy lists which tags may appear, which attributes each of them may carry, and which
+//! URL protocols may appear in attributes holding URLs. The sanitizer parses the input with
+//! the HTML Processor and writes a normalized serialization of it, leaving out everything the
+//! policy doesn't allow:
+//!
+//!  - Disallowed tags are removed, but their contents are kept. Elements whose contents
+//!    aren't markup, e.g. SCRIPT, STYLE, and TEXTAREA, are removed along with their contents.
+//!  - Disallowed attributes are removed, as are URL attributes with disallowed protocols.
+//!  - SVG and MathML elements are always removed, since their parsing rules differ from HTML.
+//!  - Text is always escaped.
+//!
+//! Because the output is serialized from the parsed tree, it contains no unclosed tags or
+//! malformed syntax which a browser might interpret differently than the sanitizer did.
+//!
+//! Example:
+//!
+//!     let policy = SanitizerPolicy::default();
+//!     "<p>Hello <a href=\"/\">world</a>alert(1)</p>" == policy.sanitize_fragment( b"<p onclick=x>Hello <a href='/' style='x'>world</a><script>alert(1)</script><blink>alert(1)" );
+//!
+//! @see https://developer.wordpress.org/reference/functions/wp_kses/
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+
+use crate::{
+    attributes::srcset::parse_srcset,
+    html_processor::HtmlProcessor,
+    str_fns::esc_attr,
+    tag_processor::{AttributeValue, ParserState, ParsingNamespace, TokenType},
+};
+
+/// Attributes whose values are URLs, which are checked against the allowed protocols.
+///
+/// @see wp_kses_uri_attributes()
+const URL_ATTRIBUTES: [&[u8]; 17] = [
+    b"action",
+    b"archive",
+    b"background",
+    b"cite",
+    b"classid",
+    b"codebase",
+    b"data",
+    b"formaction",
+    b"href",
+    b"icon",
+    b"longdesc",
+    b"manifest",
+    b"ping",
+    b"poster",
+    b"profile",
+    b"src",
+    b"usemap",
+];
+
+/// What to do with tags the policy doesn't allow.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DisallowedTags {
+    /// Remove the tags, keeping the contents of their elements.
+    #[default]
+    Strip,
+
+    /// Write the tags as escaped text, so they appear as written in the rendered page.
+    Escape,
+}
+
+/// Describes which HTML is allowed to pass through the sanitizer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SanitizerPolicy {
+    /// Allowed tags, by lower-cased name, and the attributes allowed on each.
+    ///
+    /// Attribute names are lower-cased; a name ending in `*` allows every attribute
+    /// starting with what precedes it, e.g. `data-*`.
+    pub allowed_tags: BTreeMap<Box<[u8]>, Vec<Box<[u8]>>>,
+
+    /// Attributes allowed on every allowed tag, in the same form as for `allowed_tags`.
+    pub global_attributes: Vec<Box<[u8]>>,
+
+    /// Lower-cased protocols allowed in URL attributes, e.g. `https`.
+    ///
+    /// Relative URLs, which have no protocol, are always allowed.
+    pub allowed_protocols: Vec<Box<[u8]>>,
+
+    /// What to do with tags which aren't allowed.
+    pub disallowed_tags: DisallowedTags,
+
+    /// Whether to keep HTML comments.
+    pub allow_comments: bool,
+}
+
+impl Default for SanitizerPolicy {
+    /// Creates a policy similar to what WordPress allows in post content.
+    ///
+    /// Unlike WordPress, `style` attributes aren't allowed by default, since their
+    /// contents aren't inspected.
+    ///
+    /// @see wp_kses_allowed_html()
+    /// @see wp_allowed_protocols()
+    fn default() -> Self {
+        let mut policy = Self::empty();
+
+        policy.allow_tag(
+            "a",
+            &[
+                "href",
+                "download",
+                "hreflang",
+                "name",
+                "referrerpolicy",
+                "rel",
+                "target",
+            ],
+        );
+        policy.allow_tag(
+            "audio",
+            &["autoplay", "controls", "loop", "muted", "preload", "src"],
+        );
+        policy.allow_tag("blockquote", &["cite"]);
+        policy.allow_tag("button", &["disabled", "name", "type", "value"]);
+        policy.allow_tag("col", &["align", "span", "valign", "width"]);
+        policy.allow_tag("colgroup", &["align", "span", "valign", "width"]);
+        policy.allow_tag("del", &["cite", "datetime"]);
+        policy.allow_tag("details", &["open"]);
+        policy.allow_tag(
+            "img",
+            &[
+                "alt", "decoding", "height", "loading", "sizes", "src", "srcset", "width",
+            ],
+        );
+        policy.allow_tag("ins", &["cite", "datetime"]);
+        policy.allow_tag("li", &["value"]);
+        policy.allow_tag("ol", &["reversed", "start", "type"]);
+        policy.allow_tag("q", &["cite"]);
+        policy.allow_tag("source", &["media", "sizes", "src", "srcset", "type"]);
+        policy.allow_tag(
+            "table",
+            &["align", "border", "cellpadding", "cellspacing", "width"],
+        );
+        policy.allow_tag(
+            "td",
+            &["align", "colspan", "headers", "rowspan", "valign", "width"],
+        );
+        policy.allow_tag(
+            "th",
+            &[
+                "abbr", "align", "colspan", "headers", "rowspan", "scope", "valign", "width",
+            ],
+        );
+        policy.allow_tag("time", &["datetime"]);
+        policy.allow_tag(
+            "video",
+            &[
+                "autoplay",
+                "controls",
+                "height",
+                "loop",
+                "muted",
+                "playsinline",
+                "poster",
+                "preload",
+                "src",
+                "width",
+            ],
+        );
+
+        for tag in [
+            "abbr",
+            "address",
+            "article",
+            "aside",
+            "b",
+            "bdo",
+            "big",
+            "br",
+            "caption",
+            "cite",
+            "code",
+            "dd",
+            "dfn",
+            "div",
+            "dl",
+            "dt",
+            "em",
+            "figcaption",
+            "figure",
+            "footer",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "header",
+            "hgroup",
+            "hr",
+            "i",
+            "kbd",
+            "main",
+            "mark",
+            "nav",
+            "p",
+            "pre",
+            "s",
+            "samp",
+            "section",
+            "small",
+            "span",
+            "strike",
+            "strong",
+            "sub",
+            "summary",
+            "sup",
+            "tbody",
+            "tfoot",
+            "thead",
+            "tr",
+            "u",
+            "ul",
+            "var",
+        ] {
+            policy.allow_tag(tag, &[]);
+        }
+
+        policy.global_attributes = [
+            "aria-*", "class", "data-*", "dir", "hidden", "id", "lang", "role", "title", "xml:lang",
+        ]
+        .map(|name| name.as_bytes().into())
+        .into();
+
+        policy.allowed_protocols = [
+            "http", "https", "ftp", "ftps", "mailto", "news", "irc", "irc6", "ircs", "gopher",
+            "nntp", "feed", "telnet", "mms", "rtsp", "sms", "svn", "tel", "fax", "xmpp", "webcal",
+            "urn",
+        ]
+        .map(|protocol| protocol.as_bytes().into())
+        .into();
+
+        policy
+    }
+}
+
+impl SanitizerPolicy {
+    /// Creates a policy which allows no tags, attributes, or URL protocols, leaving only text.
+    pub fn empty() -> Self {
+        Self {
+            allowed_tags: BTreeMap::new(),
+            global_attributes: Vec::new(),
+            allowed_protocols: Vec::new(),
+            disallowed_tags: DisallowedTags::default(),
+            allow_comments: false,
+        }
+    }
+
+    /// Allows a tag with the given attributes, in addition to any already allowed for it.
+    ///
+    /// Example:
+    ///
+    ///     $policy->allow_tag( 'iframe', array( 'src', 'width', 'height' ) );
+    ///
+    /// @param string   $tag_name   Name of the tag, e.g. `img`.
+    /// @param string[] $attributes Names of attributes allowed on the tag, e.g. `src` or `data-*`.
+    pub fn allow_tag(&mut self, tag_name: &str, attributes: &[&str]) {
+        let allowed = self
+            .allowed_tags
+            .entry(tag_name.as_bytes().to_ascii_lowercase().into())
+            .or_default();
+
+        for attribute in attributes {
+            allowed.push(attribute.as_bytes().to_ascii_lowercase().into());
+        }
+    }
+
+    /// Sanitizes an HTML fragment found in the BODY element.
+    ///
+    /// @param string $html Untrusted HTML.
+    /// @return string|null Sanitized HTML, or `null` if the HTML couldn't be parsed.
+    pub fn sanitize_fragment(&self, html: &[u8]) -> Option<Box<[u8]>> {
+        let mut processor = HtmlProcessor::create_fragment(html, "<body>", "UTF-8")?;
+        self.sanitize(&mut processor)
+    }
+
+    /// Sanitizes the HTML in a processor which hasn't started processing.
+    ///
+    /// When the HTML Processor can't parse the input, e.g. because it contains
+    /// unsupported markup, there is no output: an incomplete parse can't be
+    /// sanitized safely.
+    ///
+    /// @param HtmlProcessor $processor Processor for untrusted HTML, immediately after creating it.
+    /// @return string|null Sanitized HTML, or `null` if the HTML couldn't be parsed.
+    pub fn sanitize(&self, processor: &mut HtmlProcessor) -> Option<Box<[u8]>> {
+        if processor.tag_processor.parser_state != ParserState::Ready {
+            return None;
+        }
+
+        let mut output = Vec::new();
+
+        // Every token is visited, even if the processor skips raw text contents.
+        while processor.next_visitable_token() {
+            match processor.get_token_type() {
+                Some(TokenType::Tag) => {
+                    let allowed_attributes = (*processor.get_namespace() == ParsingNamespace::Html)
+                        .then(|| processor.get_tag())
+                        .flatten()
+                        .and_then(|tag_name| {
+                            let tag_name: Box<[u8]> = (&tag_name).into();
+                            self.allowed_tags
+                                .get(tag_name.to_ascii_lowercase().as_slice())
+                        });
+
+                    match allowed_attributes {
+                        Some(allowed_attributes) => {
+                            let token = processor.serialize_token_with(b" ", |name, value| {
+                                self.allows_attribute(allowed_attributes, name, value)
+                            });
+                            output.extend_from_slice(&token);
+                        }
+
+                        None if self.disallowed_tags == DisallowedTags::Escape
+                            && !processor.is_virtual() =>
+                        {
+                            output.extend_from_slice(&esc_attr(&processor.serialize_token()));
+                        }
+
+                        None => {}
+                    }
+                }
+
+                // CDATA sections only exist in foreign content, which is removed.
+                Some(TokenType::CdataSection) => {
+                    output.extend_from_slice(&esc_attr(&processor.get_modifiable_text()));
+                }
+
+                Some(TokenType::Comment | TokenType::FunkyComment) if self.allow_comments => {
+                    output.extend_from_slice(&processor.serialize_token());
+                }
+
+                Some(TokenType::Text) => {
+                    output.extend_from_slice(&processor.serialize_token());
+                }
+
+                _ => {}
+            }
+        }
+
+        if processor.get_last_error().is_some() {
+            return None;
+        }
+
+        Some(output.into())
+    }
+
+    /// Indicates if an attribute may be written on an allowed tag.
+    ///
+    /// @param string[] $allowed_attributes Attributes allowed on the tag.
+    /// @param string   $name               Lower-cased name of the attribute.
+    /// @param mixed    $value              Decoded value of the attribute.
+    /// @return bool Whether the attribute may be written.
+    fn allows_attribute(
+        &self,
+        allowed_attributes: &[Box<[u8]>],
+        name: &[u8],
+        value: &AttributeValue,
+    ) -> bool {
+        let matches = |allowed: &[u8]| match allowed.strip_suffix(b"*") {
+            Some(prefix) => name.starts_with(prefix),
+            None => allowed == name,
+        };
+
+        if !allowed_attributes
+            .iter()
+            .chain(&self.global_attributes)
+            .any(|allowed| matches(allowed))
+        {
+            return false;
+        }
+
+        let AttributeValue::String(value) = value else {
+            return true;
+        };
+
+        if b"srcset" == name {
+            return parse_srcset(value)
+                .iter()
+                .all(|candidate| self.allows_url(&candidate.url));
+        }
+
+        !URL_ATTRIBUTES.contains(&name) || self.allows_url(value)
+    }
+
+    /// Indicates if a URL is relative or has an allowed protocol.
+    ///
+    /// Browsers ignore leading spaces and control characters in URLs, as well as tabs and
+    /// newlines anywhere inside them, so these are removed before finding the protocol.
+    ///
+    /// @see wp_kses_bad_protocol()
+    ///
+    /// @param string $url Decoded URL.
+    /// @return bool Whether the URL is allowed.
+    pub fn allows_url(&self, url: &[u8]) -> bool {
+        let url: Vec<u8> = url
+            .iter()
+            .skip_while(|&&c| c <= b' ')
+            .copied()
+            .filter(|c| !matches!(c, b'\t' | b'\n' | b'\r'))
+            .collect();
+
+        let Some(colon_at) = url.iter().position(|&c| b':' == c) else {
+            return true;
+        };
+
+        // A colon after the start of a path, query, or fragment doesn't end a protocol.
+        if url[..colon_at]
+            .iter()
+            .any(|c| matches!(c, b'/' | b'?' | b'#'))
+        {
+            return true;
+        }
+
+        let protocol = url[..colon_at].to_ascii_lowercase();
+        self.allowed_protocols
+            .iter()
+            .any(|allowed| allowed.as_ref() == protocol.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sanitize(policy: &SanitizerPolicy, html: &str) -> String {
+        String::from_utf8(
+            policy
+                .sanitize_fragment(html.as_bytes())
+                .unwrap()
+                .into_vec(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_strips_disallowed_markup() {
+        let policy = SanitizerPolicy::default();
+        assert_eq!(
+            sanitize(
+                &policy,
+                "<p onclick=x class=intro>Hello <a href='/' style='color:red' data-id=1>world</a><script>alert(1)</script><blink>!</blink><!-- note -->"
+            ),
+            "<p class=\"intro\">Hello <a href=\"/\" data-id=\"1\">world</a>!</p>"
+        );
+        assert_eq!(
+            sanitize(
+                &policy,
+                "<svg><g><text>Label</text><p>Breakout</svg><math><mi>x</mi></math>"
+            ),
+            "Label<p>Breakoutx</p>"
+        );
+        assert_eq!(
+            sanitize(&policy, "<table><td>cell</table><textarea>gone</textarea>"),
+            "<table><tbody><tr><td>cell</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn test_url_protocols() {
+        let policy = SanitizerPolicy::default();
+        assert_eq!(
+            sanitize(
+                &policy,
+                "<a href='java\tscript:alert(1)'>1</a><a href=' JAVASCRIPT:alert(1)'>2</a><a href='&#106;avascript:alert(1)'>3</a>"
+            ),
+            "<a>1</a><a>2</a><a>3</a>"
+        );
+        assert_eq!(
+            sanitize(
+                &policy,
+                "<a href='HTTPS://example.com/'>1</a><a href='/path:with:colons'>2</a><a href='?q=a:b'>3</a><a href='mailto:me@example.com'>4</a>"
+            ),
+            "<a href=\"HTTPS://example.com/\">1</a><a href=\"/path:with:colons\">2</a><a href=\"?q=a:b\">3</a><a href=\"mailto:me@example.com\">4</a>"
+        );
+        assert_eq!(
+            sanitize(
+                &policy,
+                "<img src=a.jpg srcset='a.jpg 1x, javascript:alert(1) 2x'><img srcset='b.jpg 1x, https://example.com/b.jpg 2x'>"
+            ),
+            "<img src=\"a.jpg\"><img srcset=\"b.jpg 1x, https://example.com/b.jpg 2x\">"
+        );
+
+        assert!(!policy.allows_url(b"data:text/html,<script>"));
+        assert!(policy.allows_url(b"relative/path"));
+    }
+
+    #[test]
+    fn test_escape_disallowed_tags() {
+        let mut policy = SanitizerPolicy {
+            disallowed_tags: DisallowedTags::Escape,
+            allow_comments: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            sanitize(
+                &policy,
+                "<p>Hi<script>alert(1)</script><!--x--><?php echo 1; ?></p>"
+            ),
+            "<p>Hi&lt;script&gt;alert(1)&lt;/script&gt;<!--x--><!--?php echo 1; ?--></p>"
+        );
+
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<p>Hi<template>there</template></p>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+        processor.set_skip_raw_text_contents(true);
+        assert_eq!(
+            policy.sanitize(&mut processor).as_deref(),
+            Some(b"<p>Hi&lt;template&gt;there&lt;/template&gt;</p>".as_slice())
+        );
+
+        // Tags implied by the parser are never escaped.
+        policy.allowed_tags.remove(b"tbody".as_slice());
+        assert_eq!(
+            sanitize(&policy, "<table><tr><td>x</table><blink>"),
+            "<table><tr><td>x</td></tr></table>&lt;blink&gt;"
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_custom_policy() {
+        let mut policy = SanitizerPolicy::empty();
+        assert_eq!(
+            sanitize(&policy, "<b>bold</b> &amp; <i>more</i>"),
+            "bold &amp; more"
+        );
+
+        policy.allow_tag("B", &["Title"]);
+        policy.allow_tag("iframe", &["src"]);
+        policy.allowed_protocols.push(b"https".as_slice().into());
+        assert_eq!(
+            sanitize(
+                &policy,
+                "<b title=x id=y>bold</b><iframe src='https://example.com/'>ignored</iframe><iframe src='http://example.com/'></iframe>"
+            ),
+            "<b title=\"x\">bold</b><iframe src=\"https://example.com/\"></iframe><iframe></iframe>"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Helpers for the optional `serde` feature.
+//!
+//! Raw HTML is bytes, but serialized token streams are mostly read by people and compared
+//! against the PHP implementation, which deals in strings. Byte fields are serialized as
+//! strings when they hold valid UTF-8 and fall back to raw bytes otherwise.
+
+use alloc::{boxed::Box, string::String};
+use serde::{Serializer, ser::SerializeMap};
+
+use crate::tag_processor::AttributeValue;
+
+pub(crate) fn bytes<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    match core::str::from_utf8(value) {
+        Ok(value) => serializer.serialize_str(value),
+        Err(_) => serializer.serialize_bytes(value),
+    }
+}
+
+pub(crate) fn option_bytes<S: Serializer>(
+    value: &Option<alloc::boxed::Box<[u8]>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => bytes(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes attributes as a map from name to value.
+///
+/// Map keys must be strings, so names which aren't valid UTF-8 are converted lossily.
+pub(crate) fn attributes<S: Serializer>(
+    value: &[(Box<[u8]>, AttributeValue)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(value.len()))?;
+    for (name, value) in value {
+        map.serialize_entry(&String::from_utf8_lossy(name), value)?;
+    }
+    map.end()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        doctype::HtmlDoctypeInfo,
+        tag_name::TagName,
+        tag_processor::{AttributeValue, NodeName, TagProcessor, TokenType},
+    };
+
+    #[test]
+    fn test_serialize_token_names() {
+        let mut processor = TagProcessor::new(b"<div>text<custom-el>");
+        let mut names = Vec::new();
+        while processor.next_token() {
+            names.push(processor.get_token_name().unwrap());
+        }
+
+        assert_eq!(
+            serde_json::to_string(&names).unwrap(),
+            r##"["DIV","#text","CUSTOM-EL"]"##
+        );
+        assert_eq!(
+            serde_json::to_string(&NodeName::Token(TokenType::Comment)).unwrap(),
+            r##""#comment""##
+        );
+        assert_eq!(serde_json::to_string(&TagName::SVG).unwrap(), r#""SVG""#);
+    }
+
+    #[test]
+    fn test_serialize_attribute_values() {
+        let mut processor = TagProcessor::new(b"<input type=\"t&amp;xt\" disabled>");
+        assert!(processor.next_tag(None));
+
+        assert_eq!(
+            serde_json::to_string(&processor.get_attribute(b"type")).unwrap(),
+            r#""t&xt""#
+        );
+        assert_eq!(
+            serde_json::to_string(&processor.get_attribute(b"disabled")).unwrap(),
+            "true"
+        );
+        assert_eq!(
+            serde_json::to_string(&AttributeValue::String(b"\xFF".as_slice().into())).unwrap(),
+            "[255]"
+        );
+    }
+
+    #[test]
+    fn test_serialize_token_snapshots() {
+        let mut processor =
+            TagProcessor::new(b"<img SRC=\"a&amp;b.png\" hidden>caf\xC3\xA9<!-- c --></P>");
+        let mut tokens = Vec::new();
+        while processor.next_token() {
+            tokens.push(processor.get_token_snapshot().unwrap());
+        }
+
+        assert_eq!(
+            serde_json::to_value(&tokens).unwrap(),
+            serde_json::json!([
+                {
+                    "token_type": "#tag",
+                    "name": "IMG",
+                    "is_closer": false,
+                    "attributes": { "src": "a&b.png", "hidden": true },
+                    "span": { "start": 0, "end": 30 },
+                    "modifiable_text": "",
+                },
+                {
+                    "token_type": "#text",
+                    "name": "#text",
+                    "is_closer": false,
+                    "attributes": {},
+                    "span": { "start": 30, "end": 35 },
+                    "modifiable_text": "café",
+                },
+                {
+                    "token_type": "#comment",
+                    "name": "#comment",
+                    "is_closer": false,
+                    "attributes": {},
+                    "span": { "start": 35, "end": 45 },
+                    "modifiable_text": " c ",
+                },
+                {
+                    "token_type": "#tag",
+                    "name": "P",
+                    "is_closer": true,
+                    "attributes": {},
+                    "span": { "start": 45, "end": 49 },
+                    "modifiable_text": "",
+                },
+            ])
+        );
+        assert_eq!(processor.get_token_snapshot(), None);
+
+        #[cfg(feature = "html-processor")]
+        {
+            use crate::html_processor::HtmlProcessor;
+
+            let mut processor =
+                HtmlProcessor::create_fragment(b"<p id=a>x</table>", "<body>", "UTF-8").unwrap();
+            let mut tokens = Vec::new();
+            while processor.next_token() {
+                let token = processor.get_token_snapshot().unwrap();
+                tokens.push((token.name, token.is_closer, token.span));
+            }
+            assert_eq!(
+                serde_json::to_string(&tokens).unwrap(),
+                r##"[["P",false,{"start":0,"end":8}],["#text",false,{"start":8,"end":9}],["P",true,null]]"##
+            );
+        }
+    }
+
+    #[test]
+    fn test_serialize_doctype() {
+        let doctype = HtmlDoctypeInfo::from_doctype_token(
+            b"<!DOCTYPE html PUBLIC '-//W3C//DTD HTML 4.01//EN' 'http://www.w3.org/TR/html4/strict.dtd'>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&doctype).unwrap(),
+            r#"{"name":"html","public_identifier":"-//W3C//DTD HTML 4.01//EN","system_identifier":"http://www.w3.org/TR/html4/strict.dtd","force_quirks_flag":false,"indicated_compatability_mode":"NoQuirks"}"#
+        );
+    }
+}
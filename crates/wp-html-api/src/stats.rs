@@ -0,0 +1,222 @@
+//! Summarizes the structure of a document, e.g. for profiling or auditing collections of HTML.
+//!
+//! Example:
+//!
+//!     let stats = DocumentStats::collect( b"<p class=a>One<p class=b>Two<!-- note -->" );
+//!     2 == stats.tag_counts["p"];
+//!     2 == stats.attribute_counts["class"];
+//!     1 == stats.comments;
+
+use alloc::{boxed::Box, collections::BTreeMap};
+
+use crate::{
+    html_processor::{HtmlProcessor, errors::HtmlProcessorError},
+    tag_processor::{CommentType, ParserState, ParsingNamespace, TokenType},
+};
+
+/// Counts of what a document contains.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentStats {
+    /// Number of opening tags for each element, by lower-case name for HTML elements and
+    /// by qualified name for foreign elements, e.g. `div` or `foreignObject`.
+    ///
+    /// Only tags found in the HTML are counted, not elements the parser implied.
+    pub tag_counts: BTreeMap<Box<[u8]>, usize>,
+
+    /// Number of times each attribute name appears on an opening tag, by lower-case name.
+    pub attribute_counts: BTreeMap<Box<[u8]>, usize>,
+
+    /// Depth of the most deeply-nested node, where elements at the top level have a
+    /// depth of 1 in a fragment and the HTML element has a depth of 1 in a full document.
+    pub max_depth: usize,
+
+    /// Number of comments, including those created from invalid markup.
+    pub comments: usize,
+
+    /// Number of bytes of the input HTML in text nodes.
+    ///
+    /// Text inside elements holding raw text, e.g. SCRIPT or TEXTAREA, counts as markup.
+    pub text_bytes: usize,
+
+    /// Number of bytes of the input HTML outside of text nodes.
+    pub markup_bytes: usize,
+
+    /// Number of elements the parser created without finding a tag for them in the HTML,
+    /// e.g. a missing HEAD or TBODY.
+    pub implied_elements: usize,
+
+    /// Number of comments created from invalid markup, e.g. `<?php ... ?>`, `<!-->`, or `</3>`.
+    pub bogus_comments: usize,
+
+    /// Number of attributes which repeat the name of an earlier attribute on the same tag.
+    /// Browsers ignore all but the first.
+    pub duplicate_attributes: usize,
+
+    /// Why collecting stopped early, if it did. Counts then only cover the HTML before the
+    /// point where the parser stopped.
+    pub last_error: Option<HtmlProcessorError>,
+}
+
+impl DocumentStats {
+    /// Collects stats for a full HTML document.
+    ///
+    /// @param string $html Input HTML.
+    /// @return DocumentStats Stats for the document.
+    pub fn collect(html: &[u8]) -> Self {
+        HtmlProcessor::create_full_parser(html, "UTF-8")
+            .and_then(|mut processor| Self::collect_from_processor(&mut processor))
+            .unwrap_or_default()
+    }
+
+    /// Collects stats for the HTML in a processor which hasn't started processing.
+    ///
+    /// The processor will be in the completed state afterwards.
+    ///
+    /// @param HtmlProcessor $processor Processor for the HTML, immediately after creating it.
+    /// @return DocumentStats|null Stats for the HTML, or `null` if the processor had already
+    ///                            started processing.
+    pub fn collect_from_processor(processor: &mut HtmlProcessor) -> Option<Self> {
+        if processor.tag_processor.parser_state != ParserState::Ready {
+            return None;
+        }
+
+        // A fragment parser starts inside its context element.
+        let base_depth = processor.get_current_depth();
+        let mut stats = Self::default();
+
+        while processor.next_token() {
+            stats.max_depth = stats
+                .max_depth
+                .max(processor.get_current_depth().saturating_sub(base_depth));
+
+            if processor.is_virtual() {
+                if !processor.is_tag_closer() {
+                    stats.implied_elements += 1;
+                }
+                continue;
+            }
+
+            match processor.get_token_type() {
+                Some(TokenType::Text) => {
+                    stats.text_bytes += processor
+                        .tag_processor
+                        .get_token_span()
+                        .map_or(0, |span| span.length);
+                }
+
+                Some(TokenType::Tag) if !processor.is_tag_closer() => {
+                    let name = match processor.get_namespace() {
+                        ParsingNamespace::Html => processor
+                            .get_tag_raw()
+                            .map(|name| name.to_ascii_lowercase().into()),
+                        _ => processor.get_qualified_tag_name(),
+                    };
+                    if let Some(name) = name {
+                        *stats.tag_counts.entry(name).or_default() += 1;
+                    }
+
+                    let names = processor
+                        .get_attribute_names_with_prefix(b"")
+                        .unwrap_or_default();
+                    for (i, name) in names.iter().enumerate() {
+                        if names[..i].contains(name) {
+                            stats.duplicate_attributes += 1;
+                        }
+                        *stats
+                            .attribute_counts
+                            .entry(name.as_ref().into())
+                            .or_default() += 1;
+                    }
+                }
+
+                Some(TokenType::Comment) => {
+                    stats.comments += 1;
+                    if processor.get_comment_type() != Some(&CommentType::HtmlComment) {
+                        stats.bogus_comments += 1;
+                    }
+                }
+
+                Some(TokenType::FunkyComment) => {
+                    stats.comments += 1;
+                    stats.bogus_comments += 1;
+                }
+
+                _ => {}
+            }
+        }
+
+        stats.last_error = processor.get_last_error().copied();
+        stats.markup_bytes = processor
+            .tag_processor
+            .bytes_already_parsed
+            .saturating_sub(stats.text_bytes);
+
+        Some(stats)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counts(map: &BTreeMap<Box<[u8]>, usize>) -> Vec<(&str, usize)> {
+        map.iter()
+            .map(|(name, count)| (core::str::from_utf8(name).unwrap(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn test_collect_stats() {
+        let html =
+            b"<!DOCTYPE html><title>T</title><div id=a ID=b class=x>Hello<p>One<p class=y>Two\
+            <svg viewBox='0 0 1 1'><foreignObject><b>Deep</b></foreignObject></svg>\
+            <!-- note --><?php echo 1; ?></div>";
+        let stats = DocumentStats::collect(html);
+
+        assert_eq!(
+            counts(&stats.tag_counts),
+            vec![
+                ("b", 1),
+                ("div", 1),
+                ("foreignObject", 1),
+                ("p", 2),
+                ("svg", 1),
+                ("title", 1),
+            ]
+        );
+        assert_eq!(
+            counts(&stats.attribute_counts),
+            vec![("class", 2), ("id", 2), ("viewbox", 1)]
+        );
+        assert_eq!(stats.duplicate_attributes, 1);
+        assert_eq!(stats.comments, 2);
+        assert_eq!(stats.bogus_comments, 1);
+
+        // HTML, HEAD, and BODY are implied.
+        assert_eq!(stats.implied_elements, 3);
+
+        // HTML > BODY > DIV > P > SVG > FOREIGNOBJECT > B > #text
+        assert_eq!(stats.max_depth, 8);
+
+        assert_eq!(stats.text_bytes, b"HelloOneTwoDeep".len());
+        assert_eq!(stats.markup_bytes, html.len() - stats.text_bytes);
+        assert_eq!(stats.last_error, None);
+    }
+
+    #[test]
+    fn test_collect_fragment_stats() {
+        let mut processor =
+            HtmlProcessor::create_fragment(b"<table><tr><td>x</table>", "<body>", "UTF-8").unwrap();
+        let stats = DocumentStats::collect_from_processor(&mut processor).unwrap();
+
+        assert_eq!(
+            counts(&stats.tag_counts),
+            vec![("table", 1), ("td", 1), ("tr", 1)]
+        );
+
+        // TABLE > TBODY > TR > TD > #text, where TBODY is implied.
+        assert_eq!(stats.max_depth, 5);
+        assert_eq!(stats.implied_elements, 1);
+        assert_eq!(DocumentStats::collect_from_processor(&mut processor), None);
+    }
+}
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use memchr::memchr;
 
 /// substr — Return part of a string
@@ -76,6 +77,26 @@ pub fn stripos(s: &[u8], pattern: &[u8], offset: usize) -> Option<usize> {
     None
 }
 
+/// esc_attr — Escaping for HTML attributes
+/// See https://developer.wordpress.org/reference/functions/esc_attr/
+///
+/// Unlike the WordPress function, this treats its input as plain text and escapes every `&`,
+/// including those which already start a character reference.
+pub fn esc_attr(s: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(s.len());
+    for &c in s {
+        match c {
+            b'&' => escaped.extend_from_slice(b"&amp;"),
+            b'<' => escaped.extend_from_slice(b"&lt;"),
+            b'>' => escaped.extend_from_slice(b"&gt;"),
+            b'"' => escaped.extend_from_slice(b"&quot;"),
+            b'\'' => escaped.extend_from_slice(b"&#039;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -147,4 +168,13 @@ mod test {
         assert_eq!(strpos(s, b"t", 5), None);
         assert_eq!(stripos(s, b"t", 5), None);
     }
+
+    #[test]
+    fn test_esc_attr() {
+        assert_eq!(esc_attr(b"plain text"), b"plain text");
+        assert_eq!(
+            esc_attr(b"<a href=\"x\">Tom's &amp;</a>"),
+            b"&lt;a href=&quot;x&quot;&gt;Tom&#039;s &amp;amp;&lt;/a&gt;"
+        );
+    }
 }
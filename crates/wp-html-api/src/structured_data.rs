@@ -0,0 +1,441 @@
+//! Extracts structured data from a document: JSON-LD payloads and microdata items.
+//!
+//! JSON-LD is found in `<script type="application/ld+json">` elements. Its payloads are
+//! returned as raw text for a JSON parser of the caller's choosing.
+//!
+//! Microdata is spread across the tree: an element with `itemscope` creates an item, and
+//! descendants with `itemprop` add properties to the nearest enclosing item. The HTML
+//! Processor tracks which elements are open, so properties are assigned to the items they
+//! belong to even when tags are implicitly closed or misnested.
+//!
+//! Example:
+//!
+//!     let data = extract_structured_data( b"<div itemscope itemtype=https://schema.org/Person><span itemprop=name>Ada</span></div>" );
+//!     [ "https://schema.org/Person" ] == data.items[0].item_type;
+//!     ( "name", Text( "Ada" ) ) == data.items[0].properties[0];
+//!
+//! Items referenced with `itemref` are not resolved.
+//!
+//! @see https://html.spec.whatwg.org/#microdata
+//! @see https://www.w3.org/TR/json-ld11/#embedding-json-ld-in-html-documents
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{
+    html_processor::HtmlProcessor,
+    tag_name::TagName,
+    tag_processor::{AttributeValue, ParserState, ParsingNamespace, TokenType},
+};
+
+/// The value of a microdata property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MicrodataValue {
+    /// Text content or a plain attribute value, e.g. from `meta[content]` or `time[datetime]`.
+    Text(Box<[u8]>),
+
+    /// A URL from an attribute, e.g. `a[href]` or `img[src]`.
+    Url(Box<[u8]>),
+
+    /// A nested item.
+    Item(MicrodataItem),
+}
+
+/// A named property of a microdata item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MicrodataProperty {
+    /// Name of the property, e.g. `name`, or a URL for properties outside the item's vocabulary.
+    pub name: Box<[u8]>,
+
+    pub value: MicrodataValue,
+}
+
+/// A microdata item created by an element with an `itemscope` attribute.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MicrodataItem {
+    /// Types of the item from its `itemtype` attribute, e.g. `https://schema.org/Person`.
+    pub item_type: Vec<Box<[u8]>>,
+
+    /// Global identifier of the item from its `itemid` attribute.
+    pub item_id: Option<Box<[u8]>>,
+
+    /// Properties of the item in tree order.
+    pub properties: Vec<MicrodataProperty>,
+}
+
+/// Structured data found in a document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StructuredData {
+    /// Raw text of each JSON-LD script, in document order.
+    pub json_ld: Vec<Box<[u8]>>,
+
+    /// Top-level microdata items, i.e. those which aren't properties of another item.
+    pub items: Vec<MicrodataItem>,
+}
+
+/// An element whose value is being collected, and the properties it provides.
+struct Scope {
+    /// Depth of the element in the document.
+    depth: usize,
+
+    /// The item created by the element, or `None` if its value is its text content.
+    item: Option<MicrodataItem>,
+
+    /// Text content of the element, for text-valued properties.
+    text: Vec<u8>,
+
+    /// Where the value goes: the index of the enclosing item's scope and the indices of
+    /// the placeholder properties in it. `None` if the element isn't a property.
+    slots: Option<(usize, Vec<usize>)>,
+}
+
+/// Extracts the structured data from an HTML document.
+///
+/// @param string $html Input HTML.
+/// @return StructuredData|null Structured data, or `null` if the document contains
+///                             unsupported markup.
+pub fn extract_structured_data(html: &[u8]) -> Option<StructuredData> {
+    let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8")?;
+    extract_from_processor(&mut processor)
+}
+
+/// Extracts the structured data from the HTML in a processor which hasn't started processing.
+///
+/// The processor will be in the completed state afterwards.
+///
+/// @param HtmlProcessor $processor Processor for the HTML, immediately after creating it.
+/// @return StructuredData|null Structured data, or `null` if the processor had already
+///                             started processing or if it encountered unsupported markup.
+pub fn extract_from_processor(processor: &mut HtmlProcessor) -> Option<StructuredData> {
+    if processor.tag_processor.parser_state != ParserState::Ready {
+        return None;
+    }
+
+    let mut data = StructuredData::default();
+    let mut scopes: Vec<Scope> = Vec::new();
+
+    while processor.next_token() {
+        // Void elements and text nodes are never closed by a visited token, so any
+        // scope at the depth of a new node has already ended.
+        let depth = processor.get_current_depth();
+        let open_depth = if processor.is_tag_closer() {
+            depth + 1
+        } else {
+            depth
+        };
+        while scopes.last().is_some_and(|scope| scope.depth >= open_depth) {
+            close_scope(&mut scopes, &mut data);
+        }
+
+        match processor.get_token_type() {
+            Some(TokenType::Text) => {
+                let text = processor.get_modifiable_text();
+                for scope in scopes.iter_mut().filter(|scope| scope.item.is_none()) {
+                    scope.text.extend_from_slice(&text);
+                }
+            }
+
+            Some(TokenType::Tag)
+                if !processor.is_tag_closer()
+                    && *processor.get_namespace() == ParsingNamespace::Html =>
+            {
+                let tag = processor.get_tag();
+                if tag == Some(TagName::SCRIPT) {
+                    if let Some(AttributeValue::String(script_type)) =
+                        processor.get_attribute(b"type")
+                        && script_type
+                            .trim_ascii()
+                            .eq_ignore_ascii_case(b"application/ld+json")
+                    {
+                        data.json_ld.push(processor.get_modifiable_text());
+                    }
+                    continue;
+                }
+
+                open_element(processor, tag, depth, &mut scopes);
+            }
+
+            _ => {}
+        }
+    }
+
+    if processor.get_last_error().is_some() {
+        return None;
+    }
+
+    while !scopes.is_empty() {
+        close_scope(&mut scopes, &mut data);
+    }
+
+    Some(data)
+}
+
+/// Starts collecting the value of an element with microdata attributes.
+fn open_element(
+    processor: &HtmlProcessor,
+    tag: Option<TagName>,
+    depth: usize,
+    scopes: &mut Vec<Scope>,
+) {
+    let is_item = matches!(
+        processor.get_attribute(b"itemscope"),
+        Some(AttributeValue::BooleanTrue | AttributeValue::String(_))
+    );
+    let names = match processor.get_attribute(b"itemprop") {
+        Some(AttributeValue::String(names)) => split_tokens(&names),
+        _ => Vec::new(),
+    };
+    if !is_item && names.is_empty() {
+        return;
+    }
+
+    // Properties outside of any item have nowhere to go.
+    let parent_at = scopes.iter().rposition(|scope| scope.item.is_some());
+    let slots = match parent_at {
+        Some(parent_at) if !names.is_empty() => {
+            let properties = &mut scopes[parent_at].item.as_mut().unwrap().properties;
+            let first_slot = properties.len();
+            properties.extend(names.into_iter().map(|name| MicrodataProperty {
+                name,
+                value: MicrodataValue::Text(Box::default()),
+            }));
+            Some((parent_at, (first_slot..properties.len()).collect()))
+        }
+        None if !names.is_empty() => return,
+        _ => None,
+    };
+
+    if is_item {
+        let item = MicrodataItem {
+            item_type: match processor.get_attribute(b"itemtype") {
+                Some(AttributeValue::String(types)) => split_tokens(&types),
+                _ => Vec::new(),
+            },
+            item_id: url_attribute(processor, b"itemid"),
+            properties: Vec::new(),
+        };
+        scopes.push(Scope {
+            depth,
+            item: Some(item),
+            text: Vec::new(),
+            slots,
+        });
+        return;
+    }
+
+    let Some((parent_at, slots)) = slots else {
+        return;
+    };
+
+    let is_time =
+        matches!(&tag, Some(TagName::Arbitrary(name)) if name.eq_ignore_ascii_case(b"TIME"));
+    let value = match tag.as_ref().and_then(attribute_value_source) {
+        Some((attribute, is_url)) => {
+            let value = if is_url {
+                url_attribute(processor, attribute).map(MicrodataValue::Url)
+            } else {
+                match processor.get_attribute(attribute) {
+                    Some(AttributeValue::String(value)) => Some(MicrodataValue::Text(value)),
+                    _ => None,
+                }
+            };
+            value.unwrap_or_else(|| MicrodataValue::Text(Box::default()))
+        }
+
+        // The TIME element falls back to its text content without a `datetime` attribute.
+        None if is_time => match processor.get_attribute(b"datetime") {
+            Some(AttributeValue::String(datetime)) => MicrodataValue::Text(datetime),
+            _ => return text_scope(scopes, depth, parent_at, slots),
+        },
+
+        None => return text_scope(scopes, depth, parent_at, slots),
+    };
+
+    let properties = &mut scopes[parent_at].item.as_mut().unwrap().properties;
+    for slot in slots {
+        properties[slot].value = value.clone();
+    }
+}
+
+/// Starts collecting the text content of a text-valued property.
+fn text_scope(scopes: &mut Vec<Scope>, depth: usize, parent_at: usize, slots: Vec<usize>) {
+    scopes.push(Scope {
+        depth,
+        item: None,
+        text: Vec::new(),
+        slots: Some((parent_at, slots)),
+    });
+}
+
+/// Finishes the innermost scope and stores its value where it belongs.
+fn close_scope(scopes: &mut Vec<Scope>, data: &mut StructuredData) {
+    let Some(scope) = scopes.pop() else {
+        return;
+    };
+
+    let value = match scope.item {
+        Some(item) => MicrodataValue::Item(item),
+        None => MicrodataValue::Text(scope.text.into()),
+    };
+
+    match (scope.slots, value) {
+        (Some((parent_at, slots)), value) => {
+            let properties = &mut scopes[parent_at].item.as_mut().unwrap().properties;
+            for slot in slots {
+                properties[slot].value = value.clone();
+            }
+        }
+        (None, MicrodataValue::Item(item)) => data.items.push(item),
+        (None, _) => {}
+    }
+}
+
+/// Returns which attribute holds the value of a property on a given element, if any.
+///
+/// @see https://html.spec.whatwg.org/#values
+///
+/// @return array|null Attribute name and whether the value is a URL.
+fn attribute_value_source(tag: &TagName) -> Option<(&'static [u8], bool)> {
+    match tag {
+        TagName::META => Some((b"content", false)),
+        TagName::EMBED | TagName::IFRAME | TagName::IMG | TagName::SOURCE | TagName::TRACK => {
+            Some((b"src", true))
+        }
+        TagName::A | TagName::AREA | TagName::LINK => Some((b"href", true)),
+        TagName::OBJECT => Some((b"data", true)),
+        TagName::Arbitrary(name) if name.eq_ignore_ascii_case(b"AUDIO") => Some((b"src", true)),
+        TagName::Arbitrary(name) if name.eq_ignore_ascii_case(b"VIDEO") => Some((b"src", true)),
+        TagName::Arbitrary(name) if name.eq_ignore_ascii_case(b"DATA") => Some((b"value", false)),
+        TagName::Arbitrary(name) if name.eq_ignore_ascii_case(b"METER") => Some((b"value", false)),
+        _ => None,
+    }
+}
+
+/// Returns the value of a URL attribute on the matched tag, without surrounding whitespace.
+fn url_attribute(processor: &HtmlProcessor, name: &[u8]) -> Option<Box<[u8]>> {
+    match processor.get_attribute(name) {
+        Some(AttributeValue::String(url)) => Some(url.trim_ascii().into()),
+        _ => None,
+    }
+}
+
+/// Splits an unordered set of space-separated tokens, dropping duplicates.
+fn split_tokens(value: &[u8]) -> Vec<Box<[u8]>> {
+    let mut tokens: Vec<Box<[u8]>> = Vec::new();
+    for token in value
+        .split(u8::is_ascii_whitespace)
+        .filter(|token| !token.is_empty())
+    {
+        if !tokens.iter().any(|existing| existing.as_ref() == token) {
+            tokens.push(token.into());
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(value: &str) -> MicrodataValue {
+        MicrodataValue::Text(value.as_bytes().into())
+    }
+
+    fn url(value: &str) -> MicrodataValue {
+        MicrodataValue::Url(value.as_bytes().into())
+    }
+
+    fn property(name: &str, value: MicrodataValue) -> MicrodataProperty {
+        MicrodataProperty {
+            name: name.as_bytes().into(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_extract_json_ld() {
+        let data = extract_structured_data(
+            b"<head><script type=' Application/LD+JSON '>{\"@type\": \"Thing\"}</script>\
+            <script>var x = 1;</script></head><body><script type=application/ld+json>[]</script>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            data.json_ld,
+            vec![
+                b"{\"@type\": \"Thing\"}".as_slice().into(),
+                b"[]".as_slice().into()
+            ]
+        );
+        assert!(data.items.is_empty());
+    }
+
+    #[test]
+    fn test_extract_microdata() {
+        let data = extract_structured_data(
+            b"<div itemscope itemtype='https://schema.org/Person' itemid=' urn:isbn:1 '>\
+                <span itemprop='name alternateName'>Ada <b>Lovelace</b></span>\
+                <img itemprop=image src=' ada.jpg '>\
+                <a itemprop=url href=/ada>Profile</a>\
+                <meta itemprop=birthDate content=1815-12-10>\
+                <time itemprop=deathDate>1852</time><time itemprop=updated datetime=2024-01-01>Jan</time>\
+                <p itemprop=address itemscope itemtype=https://schema.org/PostalAddress>\
+                    <span itemprop=addressLocality>London</span>\
+                <p itemprop=description>Mathematician\
+            </div>\
+            <span itemprop=orphan>Ignored</span>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            data.items,
+            vec![MicrodataItem {
+                item_type: vec![b"https://schema.org/Person".as_slice().into()],
+                item_id: Some(b"urn:isbn:1".as_slice().into()),
+                properties: vec![
+                    property("name", text("Ada Lovelace")),
+                    property("alternateName", text("Ada Lovelace")),
+                    property("image", url("ada.jpg")),
+                    property("url", url("/ada")),
+                    property("birthDate", text("1815-12-10")),
+                    property("deathDate", text("1852")),
+                    property("updated", text("2024-01-01")),
+                    property(
+                        "address",
+                        MicrodataValue::Item(MicrodataItem {
+                            item_type: vec![b"https://schema.org/PostalAddress".as_slice().into()],
+                            item_id: None,
+                            properties: vec![property("addressLocality", text("London"))],
+                        })
+                    ),
+                    property("description", text("Mathematician")),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_multiple_top_level_items() {
+        let mut processor = HtmlProcessor::create_fragment(
+            b"<ul><li itemscope><span itemprop=n>1</span><li itemscope><span itemprop=n>2</ul>",
+            "<body>",
+            "UTF-8",
+        )
+        .unwrap();
+        let data = extract_from_processor(&mut processor).unwrap();
+
+        assert_eq!(
+            data.items,
+            vec![
+                MicrodataItem {
+                    properties: vec![property("n", text("1"))],
+                    ..Default::default()
+                },
+                MicrodataItem {
+                    properties: vec![property("n", text("2"))],
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(extract_from_processor(&mut processor), None);
+    }
+}
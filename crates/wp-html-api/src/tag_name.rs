@@ -1,6 +1,14 @@
 #![allow(non_camel_case_types)]
 
-use std::fmt::Display;
+use alloc::{boxed::Box, string::String, sync::Arc};
+use core::{
+    convert::Infallible,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+};
+use hashbrown::HashTable;
+use rustc_hash::FxBuildHasher;
 
 use crate::tag_processor::ParsingNamespace;
 
@@ -135,7 +143,10 @@ pub enum TagName {
     DESC,
 
     // Arbitrary tag names not listed here, e.g. <custom-tag>
-    Arbitrary(Box<[u8]>),
+    //
+    // These are shared so that copies of the name, e.g. in breadcrumbs,
+    // don't allocate. See `TagNameInterner`.
+    Arbitrary(Arc<[u8]>),
 }
 
 impl TagName {
@@ -199,17 +210,37 @@ impl TagName {
 
 impl From<(&[u8], &ParsingNamespace)> for TagName {
     fn from((value, namespace): (&[u8], &ParsingNamespace)) -> Self {
-        let upper_cased = value.to_ascii_uppercase();
+        Self::known(value, namespace)
+            .unwrap_or_else(|| Self::Arbitrary(value.to_ascii_uppercase().into()))
+    }
+}
+
+impl TagName {
+    /// Returns the tag name for the given ASCII case-insensitive name, if it
+    /// has its own variant, without allocating.
+    pub(crate) const fn known(value: &[u8], namespace: &ParsingNamespace) -> Option<Self> {
+        // No tag name with its own variant is longer than this.
+        let mut buffer = [0u8; 16];
+        if value.len() > buffer.len() {
+            return None;
+        }
+
+        let mut at = 0;
+        while at < value.len() {
+            buffer[at] = value[at].to_ascii_uppercase();
+            at += 1;
+        }
+        let (upper_cased, _) = buffer.split_at(value.len());
 
         /*
          * > A start tag whose tag name is "image"
          * > Change the token's tag name to "img" and reprocess it. (Don't ask.)
          */
-        if namespace != &ParsingNamespace::Html && upper_cased == b"IMAGE" {
-            return Self::Arbitrary(upper_cased.into());
+        if !matches!(namespace, ParsingNamespace::Html) && matches!(upper_cased, b"IMAGE") {
+            return None;
         }
 
-        match upper_cased.as_slice() {
+        Some(match upper_cased {
             b"A" => Self::A,
             b"ADDRESS" => Self::ADDRESS,
             b"APPLET" => Self::APPLET,
@@ -335,8 +366,80 @@ impl From<(&[u8], &ParsingNamespace)> for TagName {
             b"MS" => Self::MS,
             b"MTEXT" => Self::MTEXT,
 
-            _ => Self::Arbitrary(upper_cased.into()),
+            _ => return None,
+        })
+    }
+
+    /// Indicates if the tag name is a valid custom element name, e.g. `my-card`.
+    ///
+    /// Custom element names start with an ASCII letter, contain a hyphen, and
+    /// are not one of the hyphenated names reserved by SVG and MathML.
+    ///
+    /// @see https://html.spec.whatwg.org/#valid-custom-element-name
+    ///
+    /// @return bool Whether the tag name is a valid custom element name.
+    pub fn is_custom_element(&self) -> bool {
+        let Self::Arbitrary(name) = self else {
+            return false;
+        };
+
+        name.first().is_some_and(u8::is_ascii_alphabetic)
+            && name.contains(&b'-')
+            && name
+                .iter()
+                .all(|&c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'.' | b'_') || c >= 0x80)
+            && ![
+                b"ANNOTATION-XML".as_slice(),
+                b"COLOR-PROFILE",
+                b"FONT-FACE",
+                b"FONT-FACE-SRC",
+                b"FONT-FACE-URI",
+                b"FONT-FACE-FORMAT",
+                b"FONT-FACE-NAME",
+                b"MISSING-GLYPH",
+            ]
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Stores a single copy of each arbitrary tag name found in a document.
+///
+/// Documents with custom elements repeat the same few names many times. Interning
+/// them means each occurrence shares one allocation instead of boxing a new copy.
+/// Names are interned by their ASCII case-insensitive spelling in upper case.
+#[derive(Default, Clone)]
+pub(crate) struct TagNameInterner {
+    names: HashTable<Arc<[u8]>>,
+}
+
+impl TagNameInterner {
+    pub(crate) fn intern(&mut self, name: &[u8]) -> Arc<[u8]> {
+        let hash = Self::hash(name);
+        if let Some(interned) = self
+            .names
+            .find(hash, |interned| interned.eq_ignore_ascii_case(name))
+        {
+            return interned.clone();
         }
+
+        let interned: Arc<[u8]> = name.to_ascii_uppercase().into();
+        self.names
+            .insert_unique(hash, interned.clone(), |interned| Self::hash(interned));
+        interned
+    }
+
+    /// Forgets the interned names, keeping the allocated table for the next document.
+    pub(crate) fn clear(&mut self) {
+        self.names.clear();
+    }
+
+    fn hash(name: &[u8]) -> u64 {
+        let mut hasher = FxBuildHasher.build_hasher();
+        for c in name {
+            hasher.write_u8(c.to_ascii_uppercase());
+        }
+        hasher.finish()
     }
 }
 
@@ -348,7 +451,14 @@ impl From<TagName> for Box<[u8]> {
 
 impl From<&TagName> for Box<[u8]> {
     fn from(val: &TagName) -> Self {
-        match val {
+        val.as_bytes().into()
+    }
+}
+
+impl TagName {
+    /// Returns the upper-case name of the tag, as returned by `get_tag()`, without allocating.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
             TagName::A => b"A".as_slice(),
             TagName::ADDRESS => b"ADDRESS".as_slice(),
             TagName::APPLET => b"APPLET".as_slice(),
@@ -473,9 +583,8 @@ impl From<&TagName> for Box<[u8]> {
             TagName::DESC => b"DESC".as_slice(),
             TagName::FOREIGNOBJECT => b"FOREIGNOBJECT".as_slice(),
 
-            TagName::Arbitrary(arbitrary_name) => return Box::from(arbitrary_name.as_ref()),
+            TagName::Arbitrary(arbitrary_name) => arbitrary_name,
         }
-        .into()
     }
 }
 
@@ -732,14 +841,77 @@ impl PartialEq<Self> for TagName {
     }
 }
 
+/// Tag names display as their upper-case HTML name, e.g. `DIV` or `MY-CARD`, matching `get_tag()`.
 impl Display for TagName {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Arbitrary(name) => {
-                write!(f, "Arbitrary({})", String::from_utf8_lossy(name))
-            }
-            _ => write!(f, "{:?}", self),
-        }
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+/// Parses the name of an HTML tag, e.g. `"div".parse::<TagName>()`.
+///
+/// Any name can be parsed: names without their own variant become `TagName::Arbitrary`.
+impl FromStr for TagName {
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok((name.as_bytes(), &ParsingNamespace::Html).into())
+    }
+}
+
+/// Compares against an HTML tag name, ASCII case-insensitively, e.g. `tag_name == "div"`.
+impl PartialEq<str> for TagName {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes().eq_ignore_ascii_case(other.as_bytes())
+    }
+}
+
+impl PartialEq<&str> for TagName {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl TagName {
+    /// Returns the tag name for an HTML tag name with its own variant, in const contexts.
+    ///
+    /// Names are ASCII case-insensitive. Tag names without their own variant are
+    /// allocated at runtime, so they can't be created here.
+    ///
+    /// @see tag_name!()
+    ///
+    /// @param string $name Name of the tag, e.g. "div".
+    /// @return TagName|null Tag name, if it has its own variant.
+    pub const fn from_known_name(name: &str) -> Option<Self> {
+        Self::known(name.as_bytes(), &ParsingNamespace::Html)
+    }
+}
+
+/// Creates a `TagName` for an HTML tag name at compile time.
+///
+/// Only tag names with their own variant can be created this way. Other names fail to
+/// compile; parse them instead, e.g. `"my-card".parse::<TagName>()`.
+///
+/// Example:
+///
+///     tag_name!( "div" ) === TagName::DIV;
+///     tag_name!( "IMG" ) === TagName::IMG;
+#[macro_export]
+macro_rules! tag_name {
+    ($name:literal) => {{
+        const TAG_NAME: $crate::tag_name::TagName =
+            $crate::tag_name::TagName::from_known_name($name)
+                .expect(concat!("Tag name has no variant: ", $name));
+        TAG_NAME
+    }};
+}
+
+/// Tag names serialize to their uppercase HTML name, e.g. `DIV`, matching `get_tag()`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for TagName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let name: Box<[u8]> = self.into();
+        crate::serialize::bytes(&name, serializer)
     }
 }
 
@@ -784,4 +956,90 @@ mod test {
         let (a, b) = make_names("foo-custom", "foo-custom-not");
         assert_ne!(a, b);
     }
+
+    #[test]
+    fn test_readable_tag_names() {
+        use alloc::string::ToString;
+
+        assert_eq!(tag_name!("div"), TagName::DIV);
+        assert_eq!(tag_name!("ANNOTATION-XML"), TagName::ANNOTATION_XML);
+        assert_eq!(TagName::from_known_name("my-card"), None);
+
+        let custom: TagName = "my-card".parse().unwrap();
+        assert_eq!(custom.to_string(), "MY-CARD");
+        assert_eq!(TagName::ANNOTATION_XML.to_string(), "ANNOTATION-XML");
+
+        assert_eq!(custom, "My-Card");
+        assert_eq!(TagName::DIV, "div");
+        assert_ne!(TagName::DIV, "span");
+    }
+
+    #[test]
+    fn test_is_custom_element() {
+        let is_custom = |name: &str| -> bool {
+            TagName::from((name.as_bytes(), &ParsingNamespace::Html)).is_custom_element()
+        };
+
+        assert!(is_custom("my-card"));
+        assert!(is_custom("x-"));
+        assert!(is_custom("math-α"));
+        assert!(is_custom("my-el.v2_final"));
+
+        assert!(!is_custom("div"));
+        assert!(!is_custom("custom"));
+        assert!(!is_custom("-x"));
+        assert!(!is_custom("1-x"));
+        assert!(!is_custom("my-card!"));
+        assert!(!is_custom("annotation-xml"));
+        assert!(!is_custom("font-face"));
+    }
+
+    #[test]
+    fn test_custom_element_names_are_interned() {
+        let mut processor =
+            crate::tag_processor::TagProcessor::new(b"<my-card><MY-CARD></my-card><other-el><div>");
+
+        let mut names = alloc::vec::Vec::new();
+        while processor.next_token() {
+            names.push(processor.get_tag().unwrap());
+        }
+
+        let [
+            TagName::Arbitrary(a),
+            TagName::Arbitrary(b),
+            TagName::Arbitrary(c),
+            TagName::Arbitrary(d),
+            TagName::DIV,
+        ] = names.as_slice()
+        else {
+            panic!("Unexpected tag names: {names:?}");
+        };
+        assert_eq!(a.as_ref(), b"MY-CARD");
+        assert!(Arc::ptr_eq(a, b));
+        assert!(Arc::ptr_eq(a, c));
+        assert_eq!(d.as_ref(), b"OTHER-EL");
+    }
+
+    #[cfg(feature = "html-processor")]
+    #[test]
+    fn test_match_custom_elements() {
+        use crate::html_processor::{HtmlProcessor, TagQuery};
+
+        let html = b"<my-list><my-item>1</my-item><div><my-item>2</my-item></div></my-list>";
+        let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8").unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            breadcrumbs: Some(vec!["my-list", "*", "my-item"]),
+            ..Default::default()
+        })));
+        assert!(processor.get_tag().unwrap().is_custom_element());
+        assert!(processor.matches_breadcrumbs(Some(&vec!["DIV", "MY-ITEM"])));
+        assert!(!processor.matches_breadcrumbs(Some(&vec!["my-list", "my-item"])));
+
+        let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8").unwrap();
+        assert!(processor.next_tag(Some(TagQuery {
+            tag_name: Some((b"my-item".as_slice(), &ParsingNamespace::Html).into()),
+            ..Default::default()
+        })));
+        assert!(processor.matches_breadcrumbs(Some(&vec!["my-list", "my-item"])));
+    }
 }
@@ -1,19 +1,42 @@
 #![allow(dead_code, unused_variables)]
 
-use rustc_hash::FxHashMap;
+use hashbrown::{HashMap, HashTable};
+use rustc_hash::FxBuildHasher;
 
 use crate::{
-    attributes::qualified_attribute_name,
+    attributes::{AttributeNameInterner, hash_ignoring_ascii_case, qualified_attribute_name},
+    bit::Bit,
     compat_mode::CompatMode,
+    decoder::{self, CaseSensitivity},
     doctype::HtmlDoctypeInfo,
-    str_fns::{stripos, strpos, strpos_byte, substr},
+    str_fns::{esc_attr, stripos, strpos, strpos_byte, substr},
+    utf8::Utf8Policy,
 };
 
-use super::tag_name::TagName;
+#[cfg(feature = "std")]
+use crate::utf8::Utf8Validator;
 
-use std::collections::{BTreeSet, HashMap};
+use super::tag_name::{TagName, TagNameInterner};
+
+use core::{cell::RefCell, convert::Infallible};
+
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+type FxHashMap<K, V> = HashMap<K, V, FxBuildHasher>;
 
 const MAX_BOOKMARKS: usize = 1_000_000;
+pub(crate) const MAX_SEEK_OPS: usize = 1_000;
+
+/// How many bytes are requested from the reader each time more input is needed.
+#[cfg(feature = "std")]
+const READ_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TagClosers {
@@ -39,16 +62,72 @@ impl Default for NextTagQuery {
     }
 }
 
-pub struct TagProcessor {
+/// The input HTML of a Tag Processor.
+///
+/// Input the processor owns is shared with its snapshots instead of being copied for
+/// each of them; it's only copied when modified while a snapshot still refers to it.
+#[derive(Debug, Clone)]
+pub enum HtmlBytes<'html> {
+    /// Input borrowed from the caller, e.g. with `new_borrowed()`.
+    Borrowed(&'html [u8]),
+
+    /// Input owned by the processor, which may be shared with snapshots.
+    Shared(Arc<Vec<u8>>),
+}
+
+impl Default for HtmlBytes<'_> {
+    fn default() -> Self {
+        Self::Borrowed(&[])
+    }
+}
+
+impl core::ops::Deref for HtmlBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(html) => html,
+            Self::Shared(html) => html,
+        }
+    }
+}
+
+impl HtmlBytes<'_> {
+    /// Returns the input for modification, copying it first if it's borrowed or shared.
+    fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let Self::Borrowed(html) = self {
+            *self = Self::Shared(Arc::new(html.to_vec()));
+        }
+
+        match self {
+            Self::Shared(html) => Arc::make_mut(html),
+            Self::Borrowed(_) => unreachable!("Borrowed input was just copied."),
+        }
+    }
+}
+
+pub struct TagProcessor<'html> {
     attributes: Vec<AttributeToken>,
+    attribute_cache: RefCell<AttributeCache>,
+    attribute_name_interner: AttributeNameInterner,
     pub bytes_already_parsed: usize,
     comment_type: Option<CommentType>,
-    pub html_bytes: Box<[u8]>,
+    pub html_bytes: HtmlBytes<'html>,
+    classname_updates: Vec<(Box<[u8]>, ClassNameUpdate)>,
+    interned_tag_name: Option<Arc<[u8]>>,
     is_closing_tag: Option<bool>,
     lexical_updates: Vec<HtmlTextReplacement>,
+
+    /// Index of the first lexical update made while on the matched token.
+    ///
+    /// Only these updates can be for the matched token, so looking for an enqueued
+    /// attribute update never needs to search through updates for earlier tokens.
+    token_updates_start: usize,
     pub(crate) parser_state: ParserState,
     parsing_namespace: ParsingNamespace,
+    pub(crate) seek_count: usize,
     skip_newline_at: Option<usize>,
+    tag_name_interner: TagNameInterner,
     tag_name_length: Option<usize>,
     tag_name_starts_at: Option<usize>,
     text_length: Option<usize>,
@@ -56,6 +135,7 @@ pub struct TagProcessor {
     text_starts_at: Option<usize>,
     token_length: Option<usize>,
     token_starts_at: Option<usize>,
+    utf8_policy: Utf8Policy,
 
     /// Indicates if the document is in quirks mode or no-quirks mode.
     ///
@@ -79,9 +159,53 @@ pub struct TagProcessor {
 
     pub(crate) bookmarks: HashMap<Box<str>, HtmlSpan>,
     pub(crate) internal_bookmarks: FxHashMap<u32, HtmlSpan>,
+
+    /// Earliest byte offset changed by applying lexical updates, since last taken.
+    ///
+    /// Parser state recorded for later offsets no longer matches the document.
+    pub(crate) modified_from: Option<usize>,
+
+    /// Source of further input for processors created with `from_reader()`.
+    #[cfg(feature = "std")]
+    input_stream: Option<InputStream>,
+}
+
+/// State of a Tag Processor, recorded by `snapshot()` to roll back to with `restore()`.
+///
+/// @see TagProcessor::snapshot()
+pub struct TagProcessorSnapshot<'html> {
+    processor: TagProcessor<'html>,
+
+    /// How many chunks of streamed input had been read when the snapshot was taken.
+    chunks_read: usize,
+}
+
+/// Tokenizer state between two tokens, from which parsing can resume later.
+///
+/// This doesn't include the input HTML; it's only valid while the document before
+/// `at` remains unchanged.
+#[derive(Clone)]
+pub(crate) struct TokenizerCheckpoint {
+    pub(crate) at: usize,
+    compat_mode: CompatMode,
+    parsing_namespace: ParsingNamespace,
+    skip_newline_at: Option<usize>,
+}
+
+/// Reader which supplies the input HTML in chunks as the processor needs it.
+#[cfg(feature = "std")]
+struct InputStream {
+    reader: Box<dyn std::io::Read + Send>,
+    chunks_read: usize,
+    is_exhausted: bool,
+    error: Option<std::io::Error>,
+
+    /// Validates the input as it's read, when the UTF-8 policy rejects invalid input.
+    utf8_validator: Option<Utf8Validator>,
 }
 
 #[derive(Default, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ParsingNamespace {
     #[default]
     Html,
@@ -104,13 +228,68 @@ impl From<&ParsingNamespace> for String {
     }
 }
 
-struct HtmlTextReplacement {
-    start: usize,
-    length: usize,
-    text: Box<str>,
+/// Replaces a span of the input HTML with new text.
+///
+/// Every modification made through the Tag Processor is recorded as one of these,
+/// and they are applied together when producing the updated HTML.
+///
+/// @see WP_HTML_Text_Replacement
+/// @see TagProcessor::enqueue_lexical_update()
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlTextReplacement {
+    /// Byte offset into the input HTML where the replaced span starts.
+    pub start: usize,
+
+    /// Byte length of the replaced span.
+    pub length: usize,
+
+    /// Text which replaces the span.
+    pub text: Box<[u8]>,
+
+    /// Identifies updates to a specific part of the matched token.
+    ///
+    /// The PHP implementation stores these under associative keys in its list of
+    /// lexical updates so they can be found and replaced. Positional updates, such
+    /// as replacing an entire token, have no key.
+    key: Option<LexicalUpdateKey>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum LexicalUpdateKey {
+    Attribute(Box<[u8]>),
+    ModifiableText,
+}
+
+/// Decoded attribute values for the matched tag, so that repeatedly reading them,
+/// e.g. when matching selectors, doesn't repeatedly decode them.
+///
+/// Only attributes in the input HTML are cached. Enqueued updates are looked up
+/// separately, so the cache stays valid until moving on to the next token.
+#[derive(Default)]
+struct AttributeCache {
+    /// Decoded values of attributes, by the offset where the attribute starts.
+    values: Vec<(usize, Box<[u8]>)>,
+
+    /// Class names from the class attribute.
+    class_list: Option<ClassList>,
+}
+
+impl AttributeCache {
+    fn clear(&mut self) {
+        self.values.clear();
+        self.class_list = None;
+    }
+}
+
+/// Class name updates are collected until the attributes of a tag are updated.
+#[derive(Clone, Copy, PartialEq)]
+enum ClassNameUpdate {
+    Add,
+    Remove,
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub(crate) struct HtmlSpan {
     pub(crate) start: usize,
     pub(crate) length: usize,
@@ -122,24 +301,162 @@ impl HtmlSpan {
 }
 
 impl HtmlTextReplacement {
-    pub fn new(start: usize, length: usize, text: &str) -> Self {
+    /// Creates a replacement of `length` bytes, starting at `start`, with the given text.
+    ///
+    /// @param int    $start  Byte offset into the input HTML where the replaced span starts.
+    /// @param int    $length Byte length of the replaced span.
+    /// @param string $text   Text which replaces the span.
+    pub fn new(start: usize, length: usize, text: &[u8]) -> Self {
         Self {
             start,
             length,
             text: text.into(),
+            key: None,
         }
     }
 }
 
-impl TagProcessor {
+impl<'html> TagProcessor<'html> {
     pub fn new(html: &[u8]) -> Self {
-        let html_bytes = html.into();
+        let html_bytes = HtmlBytes::Shared(Arc::new(html.into()));
         Self {
             html_bytes,
             ..Default::default()
         }
     }
 
+    /// Creates a processor which borrows the input HTML instead of copying it.
+    ///
+    /// This suits input which already lives in memory for as long as it's processed,
+    /// e.g. a memory-mapped file. The document is only copied once enqueued lexical
+    /// updates are applied to it, as happens when they modify HTML ahead of the
+    /// cursor or before seeking.
+    ///
+    /// Example:
+    ///
+    ///     let file = File::open("export.html")?;
+    ///     let html = unsafe { Mmap::map(&file)? };
+    ///     let mut processor = TagProcessor::new_borrowed(&html);
+    ///
+    /// @param string $html HTML to process.
+    pub fn new_borrowed(html: &'html [u8]) -> Self {
+        Self {
+            html_bytes: HtmlBytes::Borrowed(html),
+            ..Default::default()
+        }
+    }
+
+    /// Starts processing a new document, reusing the memory allocated for the last one.
+    ///
+    /// The processor is left as if it had just been created for the new document,
+    /// except that it keeps its UTF-8 policy and the capacity of its internal buffers,
+    /// e.g. the copy of the input, the enqueued updates, and the bookmarks. This avoids
+    /// reallocating them when processing many small documents one after another.
+    ///
+    /// Example:
+    ///
+    ///     $processor = new WP_HTML_Tag_Processor( '' );
+    ///     foreach ( $posts as $post ) {
+    ///         $processor->reset_with( $post );
+    ///         while ( $processor->next_tag() ) {
+    ///             // …
+    ///         }
+    ///     }
+    ///
+    /// @param string $html HTML to process.
+    /// @return bool Whether the document is acceptable under the UTF-8 policy.
+    pub fn reset_with(&mut self, html: &[u8]) -> bool {
+        let mut html_bytes = match core::mem::take(&mut self.html_bytes) {
+            // A snapshot may still share the last document, which then can't be reused.
+            HtmlBytes::Shared(html_bytes) => Arc::try_unwrap(html_bytes).unwrap_or_default(),
+            HtmlBytes::Borrowed(_) => Vec::new(),
+        };
+        html_bytes.clear();
+        html_bytes.extend_from_slice(html);
+
+        let mut attributes = core::mem::take(&mut self.attributes);
+        let mut classname_updates = core::mem::take(&mut self.classname_updates);
+        let mut lexical_updates = core::mem::take(&mut self.lexical_updates);
+        let mut bookmarks = core::mem::take(&mut self.bookmarks);
+        let mut internal_bookmarks = core::mem::take(&mut self.internal_bookmarks);
+        attributes.clear();
+        classname_updates.clear();
+        lexical_updates.clear();
+        bookmarks.clear();
+        internal_bookmarks.clear();
+        self.attribute_cache.get_mut().clear();
+        self.attribute_name_interner.clear();
+        self.tag_name_interner.clear();
+
+        let utf8_policy = self.utf8_policy;
+        *self = Self {
+            html_bytes: HtmlBytes::Shared(Arc::new(html_bytes)),
+            attributes,
+            attribute_cache: core::mem::take(&mut self.attribute_cache),
+            attribute_name_interner: core::mem::take(&mut self.attribute_name_interner),
+            classname_updates,
+            lexical_updates,
+            tag_name_interner: core::mem::take(&mut self.tag_name_interner),
+            bookmarks,
+            internal_bookmarks,
+            ..Default::default()
+        };
+
+        self.set_utf8_policy(utf8_policy)
+    }
+
+    /// Creates a processor which reads its input HTML from a reader as it's needed.
+    ///
+    /// Rather than loading the entire document up front, the processor reads
+    /// another chunk whenever a token runs into the end of the buffered input.
+    /// Input before the current token is discarded when reading more, so large
+    /// documents can be processed in a bounded amount of memory.
+    ///
+    /// Bookmarks and enqueued lexical updates keep the input they refer to from
+    /// being discarded: while they exist, the buffer grows to hold everything after
+    /// the earliest of them. Offsets such as `bytes_already_parsed` are relative to
+    /// the buffered input and `get_updated_html()` only returns the buffered input.
+    ///
+    /// An error from the reader ends the input; it's available from `take_read_error()`.
+    ///
+    /// Example:
+    ///
+    ///     let mut processor = TagProcessor::from_reader(File::open("export.html")?);
+    ///     while processor.next_tag(None) {
+    ///         // …
+    ///     }
+    ///     if let Some(error) = processor.take_read_error() {
+    ///         return Err(error);
+    ///     }
+    ///
+    /// @param Read $reader Source of the input HTML.
+    #[cfg(feature = "std")]
+    pub fn from_reader(reader: impl std::io::Read + Send + 'static) -> Self {
+        Self {
+            input_stream: Some(InputStream {
+                reader: Box::new(reader),
+                chunks_read: 0,
+                is_exhausted: false,
+                error: None,
+                utf8_validator: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the error which ended the input from the reader, if any.
+    ///
+    /// When the UTF-8 policy rejects invalid input and the reader supplies some, the
+    /// input ends with an `InvalidData` error.
+    ///
+    /// @see self::from_reader()
+    ///
+    /// @return io::Error|null The error from the reader, if reading failed.
+    #[cfg(feature = "std")]
+    pub fn take_read_error(&mut self) -> Option<std::io::Error> {
+        self.input_stream.as_mut()?.error.take()
+    }
+
     /// Finds the next token in the HTML document.
     ///
     /// An HTML document can be viewed as a stream of tokens,
@@ -164,6 +481,11 @@ impl TagProcessor {
     ///
     /// @return bool Whether a token was parsed.
     pub fn next_token(&mut self) -> bool {
+        #[cfg(feature = "std")]
+        if self.input_stream.is_some() {
+            return self.next_streamed_token();
+        }
+
         self.base_class_next_token()
     }
 
@@ -204,6 +526,55 @@ impl TagProcessor {
         }
     }
 
+    /// Finds the next text node.
+    ///
+    /// CDATA sections, which only appear as text inside foreign content, are also matched.
+    ///
+    /// Example:
+    ///
+    ///     $processor = new WP_HTML_Tag_Processor( '<p>One <b>two</b></p>' );
+    ///     while ( $processor->next_text() ) {
+    ///         echo $processor->get_modifiable_text();
+    ///     }
+    ///     // One two
+    ///
+    /// @return bool Whether a text node was found.
+    pub fn next_text(&mut self) -> bool {
+        self.next_token_of_type(|token_type| {
+            matches!(token_type, TokenType::Text | TokenType::CdataSection)
+        })
+    }
+
+    /// Finds the next comment, including "funky comments" such as `</%invalid>`.
+    ///
+    /// @return bool Whether a comment was found.
+    pub fn next_comment(&mut self) -> bool {
+        self.next_token_of_type(|token_type| {
+            matches!(token_type, TokenType::Comment | TokenType::FunkyComment)
+        })
+    }
+
+    /// Finds the next DOCTYPE declaration.
+    ///
+    /// @return bool Whether a DOCTYPE declaration was found.
+    pub fn next_doctype(&mut self) -> bool {
+        self.next_token_of_type(|token_type| *token_type == TokenType::Doctype)
+    }
+
+    /// Advances to the next token whose type matches.
+    ///
+    /// @param callable $matches Whether to stop on a token of the given type.
+    /// @return bool Whether a matching token was found.
+    fn next_token_of_type(&mut self, matches: impl Fn(&TokenType) -> bool) -> bool {
+        while self.next_token() {
+            if self.get_token_type().is_some_and(&matches) {
+                return true;
+            }
+        }
+
+        false
+    }
+
     /// Checks whether a given tag and its attributes match the search criteria.
     ///
     /// @return bool Whether the given tag and its attribute match the search criteria.
@@ -233,6 +604,163 @@ impl TagProcessor {
         true
     }
 
+    /// Finds the next token, reading more input whenever the token might continue
+    /// past the end of the buffered input.
+    ///
+    /// Text nodes end at the end of the document, so one which reaches the end of
+    /// the buffer is reparsed along with the next chunk, as is any incomplete token.
+    /// Once a token spans more than a chunk, each further read waits for at least as much
+    /// input as is already buffered for it, so reparsing a large token takes linear time
+    /// overall instead of rescanning it for every chunk.
+    ///
+    /// @return bool Whether a token was parsed.
+    #[cfg(feature = "std")]
+    fn next_streamed_token(&mut self) -> bool {
+        // Once halted, the processor stays halted; it already read all the input it could.
+        if ParserState::Complete == self.parser_state
+            || ParserState::IncompleteInput == self.parser_state
+        {
+            return false;
+        }
+
+        // The first read for a token returns whatever the reader has available.
+        let mut min_read = 1;
+
+        loop {
+            if self.base_class_next_token() {
+                if ParserState::TextNode != self.parser_state
+                    || self.bytes_already_parsed < self.html_bytes.len()
+                {
+                    return true;
+                }
+
+                let text_ends_at = self.bytes_already_parsed;
+                self.bytes_already_parsed =
+                    self.token_starts_at.expect("text nodes must have a start");
+
+                if !self.read_more_input(min_read) {
+                    // Input rejected under the UTF-8 policy ends the document without the text.
+                    if ParserState::Complete == self.parser_state {
+                        return false;
+                    }
+
+                    self.bytes_already_parsed = text_ends_at;
+                    return true;
+                }
+            } else if !self.read_more_input(min_read) {
+                return false;
+            }
+
+            // The cursor is back at the start of the token which needs more input.
+            let buffered_token_length = self.html_bytes.len() - self.bytes_already_parsed;
+            if buffered_token_length >= READ_CHUNK_SIZE {
+                min_read = buffered_token_length;
+            }
+            self.parser_state = ParserState::Ready;
+        }
+    }
+
+    /// Appends the next input from the reader to the buffered input.
+    ///
+    /// Processed input is discarded once it's at least as long as the input that's kept,
+    /// which is everything from the token at the cursor, along with spans still referred
+    /// to by bookmarks or enqueued lexical updates. Offsets into the buffer are shifted to
+    /// account for the discarded input. Discarding only then moves each byte a bounded
+    /// number of times, while the buffer grows in place.
+    ///
+    /// @param usize $min_read Keep reading until at least this many bytes arrive, unless
+    ///                        the input ends first.
+    /// @return bool Whether any more input was read.
+    #[cfg(feature = "std")]
+    fn read_more_input(&mut self, min_read: usize) -> bool {
+        match self.input_stream.as_ref() {
+            Some(stream) if !stream.is_exhausted => {}
+            _ => return false,
+        }
+
+        let keep_from = self
+            .bookmarks
+            .values()
+            .chain(self.internal_bookmarks.values())
+            .map(|bookmark| bookmark.start)
+            .chain(self.lexical_updates.iter().map(|update| update.start))
+            .chain(self.skip_newline_at)
+            .fold(self.bytes_already_parsed, usize::min);
+
+        if keep_from >= READ_CHUNK_SIZE && keep_from >= self.html_bytes.len() - keep_from {
+            self.html_bytes.to_mut().drain(..keep_from);
+            self.attribute_name_interner.clear();
+
+            self.bytes_already_parsed -= keep_from;
+            self.skip_newline_at = self.skip_newline_at.map(|at| at - keep_from);
+            for bookmark in self
+                .bookmarks
+                .values_mut()
+                .chain(self.internal_bookmarks.values_mut())
+            {
+                bookmark.start -= keep_from;
+            }
+            for update in &mut self.lexical_updates {
+                update.start -= keep_from;
+            }
+        }
+
+        let Some(stream) = self.input_stream.as_mut() else {
+            return false;
+        };
+        let html = self.html_bytes.to_mut();
+        let buffered = html.len();
+        html.resize(buffered + min_read.max(READ_CHUNK_SIZE), 0);
+
+        let mut filled = buffered;
+        let mut reached_end = false;
+        while filled - buffered < min_read {
+            match stream.reader.read(&mut html[filled..]) {
+                Ok(0) => reached_end = true,
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    stream.error = Some(error);
+                    reached_end = true;
+                }
+            }
+
+            if reached_end {
+                break;
+            }
+        }
+        html.truncate(filled);
+        let read = filled - buffered;
+
+        let is_valid = match stream.utf8_validator.as_mut() {
+            Some(validator) => {
+                (0 == read || validator.push(&html[buffered..]))
+                    && (!reached_end || validator.finish())
+            }
+            None => true,
+        };
+        if !is_valid {
+            html.truncate(buffered);
+            stream.is_exhausted = true;
+            stream.error = Some(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid-utf8",
+            ));
+            self.parser_state = ParserState::Complete;
+            return false;
+        }
+
+        if reached_end {
+            stream.is_exhausted = true;
+        }
+        if 0 == read {
+            return false;
+        }
+        stream.chunks_read += 1;
+
+        true
+    }
+
     /// Internal method which finds the next token in the HTML document.
     ///
     /// This method is a protected internal function which implements the logic for
@@ -446,25 +974,22 @@ impl TagProcessor {
         self.class_name_updates_to_attributes_updates();
 
         /*
-         * Purge updates if there are too many. The actual count isn't
-         * scientific, but a few values from 100 to a few thousand were
-         * tests to find a practically-useful limit.
+         * Updates to the part of the document which hasn't been parsed yet must be
+         * applied before parsing it, otherwise the parser would find the original HTML.
+         * Any such update was made on this token, as earlier ones were already applied.
          *
-         * If the update queue grows too big, then the Tag Processor
-         * will spend more time iterating through them and lose the
-         * efficiency gains of deferring applying them.
+         * Otherwise, updates are left enqueued no matter how many there are. They are
+         * applied all at once in a single pass over the document, and since lookups of
+         * enqueued updates only consider those for the matched token, a long queue
+         * doesn't slow down processing.
          */
-        if 1_000 < self.lexical_updates.len() {
-            self.get_updated_html();
-        }
-
-        if self
-            .lexical_updates
+        if self.lexical_updates[self.token_updates_start..]
             .iter()
             .any(|update| update.start >= self.bytes_already_parsed)
         {
-            self.get_updated_html();
+            self.apply_lexical_updates();
         }
+        self.token_updates_start = self.lexical_updates.len();
 
         self.token_starts_at = None;
         self.token_length = None;
@@ -473,79 +998,651 @@ impl TagProcessor {
         self.text_starts_at = None;
         self.text_length = None;
         self.is_closing_tag = None;
+        self.interned_tag_name = None;
         self.attributes.clear();
+        self.attribute_cache.get_mut().clear();
         self.comment_type = None;
         self.text_node_classification = TextNodeClassification::Generic;
     }
 
-    fn class_name_updates_to_attributes_updates(&self) {
-        // Implement me!
+    /// Converts class name updates into tag attributes updates
+    /// (they are accumulated in different data formats for performance).
+    fn class_name_updates_to_attributes_updates(&mut self) {
+        let Some(updated_class) = self.get_updated_class_attribute() else {
+            self.classname_updates.clear();
+            return;
+        };
+
+        let updates = self.attribute_updates(b"class", updated_class.as_deref());
+        self.classname_updates.clear();
+        self.merge_updates(b"class", updates);
     }
 
-    /// Returns the string representation of the HTML Tag Processor.
+    /// Returns the value of the class attribute after applying enqueued class name updates.
     ///
-    /// @return string The processed HTML.
-    pub fn get_updated_html(&self) -> Box<[u8]> {
-        self.html_bytes.clone()
-    }
+    /// Existing class names are kept in order, skipping removed and duplicated class names,
+    /// and then added class names are appended. The whitespace between existing class names
+    /// is preserved, which produces fewer changes to the HTML and clarifies the before/after
+    /// content when debugging the modified output.
+    ///
+    /// @return string|null|false New class attribute value, `null` if the attribute should
+    ///                           be removed, or `false` if the class names are unchanged.
+    fn get_updated_class_attribute(&self) -> Option<Option<Box<[u8]>>> {
+        if self.classname_updates.is_empty() {
+            return None;
+        }
 
-    fn parse_next_tag(&mut self) -> bool {
-        self.after_tag();
+        let existing_class: Box<[u8]> = match self.get_enqueued_attribute_value(b"class") {
+            Some(AttributeValue::String(value)) => value,
+            Some(_) => Box::new([]),
+            None => match self.find_attribute(b"class") {
+                Some(token) if !token.is_true => self.cached_attribute_value(token),
+                _ => Box::new([]),
+            },
+        };
 
-        let doc_length = self.html_bytes.len();
-        let was_at = self.bytes_already_parsed;
-        let mut at = was_at;
+        let is_quirks = self.compat_mode == CompatMode::Quirks;
+        let is_same_class = |a: &[u8], b: &[u8]| {
+            if is_quirks {
+                a.eq_ignore_ascii_case(b)
+            } else {
+                a == b
+            }
+        };
 
-        while at < doc_length {
-            let next_at = strpos_byte(&self.html_bytes, b'<', at);
-            if next_at.is_none() {
+        let mut class: Vec<u8> = Vec::with_capacity(existing_class.len());
+        let mut seen: Vec<&[u8]> = Vec::new();
+        let mut modified = false;
+        let mut at = 0;
+
+        // Remove unwanted classes by only copying the new ones.
+        while at < existing_class.len() {
+            // Skip to the first non-whitespace character.
+            let ws_at = at;
+            let ws_length = strspn!(existing_class, b' ' | b'\t' | 0x0c | b'\r' | b'\n', ws_at);
+            at += ws_length;
+
+            // Capture the class name – it's everything until the next whitespace.
+            let name_length = strcspn!(existing_class, b' ' | b'\t' | 0x0c | b'\r' | b'\n', at);
+            if 0 == name_length {
+                // If no more class names are found then that's the end.
                 break;
             }
-            at = next_at.unwrap();
 
-            if at > was_at {
-                /*
-                 * A "<" normally starts a new HTML tag or syntax token, but in cases where the
-                 * following character can't produce a valid token, the "<" is instead treated
-                 * as plaintext and the parser should skip over it. This avoids a problem when
-                 * following earlier practices of typing emoji with text, e.g. "<3". This
-                 * should be a heart, not a tag. It's supposed to be rendered, not hidden.
-                 *
-                 * At this point the parser checks if this is one of those cases and if it is
-                 * will continue searching for the next "<" in search of a token boundary.
-                 *
-                 * @see https://html.spec.whatwg.org/#tag-open-state
-                 */
-                if !matches!( self.html_bytes[at + 1], b'!'| b'/'| b'?'| b'a'..=b'z' | b'A'..=b'Z')
-                {
-                    at += 1;
-                    continue;
-                }
+            let name = &existing_class[at..at + name_length];
+            at += name_length;
 
-                self.parser_state = ParserState::TextNode;
-                self.token_starts_at = Some(was_at);
-                self.token_length = Some(at - was_at);
-                self.text_starts_at = Some(was_at);
-                self.text_length = Some(self.token_length.unwrap());
-                self.bytes_already_parsed = at;
-                return true;
+            // If this class is marked for removal, remove it and move on to the next one.
+            if self.classname_updates.iter().any(|(updated_name, update)| {
+                ClassNameUpdate::Remove == *update && is_same_class(updated_name, name)
+            }) {
+                modified = true;
+                continue;
             }
 
-            self.token_starts_at = Some(at);
+            // If a class has already been seen then skip it; it should not be added twice.
+            if seen.iter().any(|seen_name| is_same_class(seen_name, name)) {
+                continue;
+            }
+            seen.push(name);
 
-            if at + 1 < doc_length && b'/' == self.html_bytes[at + 1] {
-                self.is_closing_tag = Some(true);
-                at += 1;
-            } else {
-                self.is_closing_tag = Some(false);
+            if !class.is_empty() {
+                class.extend_from_slice(&existing_class[ws_at..ws_at + ws_length]);
             }
+            class.extend_from_slice(name);
+        }
 
-            /*
-             * HTML tag names must start with [a-zA-Z] otherwise they are not tags.
-             * For example, "<3" is rendered as text, not a tag opener. If at least
-             * one letter follows the "<" then _it is_ a tag, but if the following
-             * character is anything else it _is not a tag_.
-             *
+        // Add new classes by appending those which haven't already been seen.
+        for (name, update) in &self.classname_updates {
+            if ClassNameUpdate::Add == *update
+                && !seen.iter().any(|seen_name| is_same_class(seen_name, name))
+            {
+                modified = true;
+                if !class.is_empty() {
+                    class.push(b' ');
+                }
+                class.extend_from_slice(name);
+            }
+        }
+
+        if !modified {
+            return None;
+        }
+
+        Some((!class.is_empty()).then(|| class.into()))
+    }
+
+    /// Sets how byte sequences which aren't valid UTF-8 are handled.
+    ///
+    /// The policy applies to the modifiable text, decoded attribute values, and the
+    /// updated HTML. With `Utf8Policy::Reject`, the entire document is validated and,
+    /// if it contains invalid UTF-8, the processor won't find any tokens in it. Set the
+    /// policy before scanning the document. Input streamed with `from_reader()` is
+    /// validated as it's read instead, ending the document at the first invalid chunk.
+    ///
+    /// Example:
+    ///
+    ///     $processor = new WP_HTML_Tag_Processor( "<p>\xE2\x82</p>" );
+    ///     true  === $processor->set_utf8_policy( Utf8Policy::Replace );
+    ///     false === $processor->set_utf8_policy( Utf8Policy::Reject );
+    ///     false === $processor->next_token();
+    ///
+    /// @see Utf8Policy
+    ///
+    /// @param Utf8Policy $policy How to handle invalid UTF-8.
+    /// @return bool Whether the document is acceptable under the policy.
+    pub fn set_utf8_policy(&mut self, policy: Utf8Policy) -> bool {
+        self.utf8_policy = policy;
+
+        /*
+         * Streamed input is validated as it's read, and the input read so far
+         * may end in the middle of a multi-byte sequence.
+         */
+        #[cfg(feature = "std")]
+        if let Some(stream) = self.input_stream.as_mut() {
+            stream.utf8_validator = None;
+            if Utf8Policy::Reject != policy {
+                return true;
+            }
+
+            let mut validator = Utf8Validator::default();
+            if !validator.push(&self.html_bytes) {
+                self.parser_state = ParserState::Complete;
+                return false;
+            }
+            stream.utf8_validator = Some(validator);
+            return true;
+        }
+
+        if Utf8Policy::Reject == policy && core::str::from_utf8(&self.html_bytes).is_err() {
+            self.parser_state = ParserState::Complete;
+            return false;
+        }
+
+        true
+    }
+
+    /// Returns how byte sequences which aren't valid UTF-8 are handled.
+    ///
+    /// @see self::set_utf8_policy()
+    ///
+    /// @return Utf8Policy Current policy for the document.
+    pub fn get_utf8_policy(&self) -> Utf8Policy {
+        self.utf8_policy
+    }
+
+    /// Records the state of the processor so that it can be rolled back later.
+    ///
+    /// This allows speculative parsing: try parsing ahead, possibly modifying the
+    /// document along the way, and restore the snapshot if the attempt fails. The
+    /// snapshot includes the document, along with enqueued updates and bookmarks.
+    /// The document isn't copied: the snapshot shares it with the processor, which
+    /// only copies it if applying updates or reading more input modifies it later.
+    ///
+    /// The count of seek operations isn't part of the snapshot, so restoring one doesn't
+    /// reset the limit on how many times the processor may seek.
+    ///
+    /// Example:
+    ///
+    ///     $snapshot = $processor->snapshot();
+    ///     if ( ! try_to_parse_a_widget( $processor ) ) {
+    ///         $processor->restore( $snapshot );
+    ///     }
+    ///
+    /// @return TagProcessorSnapshot State to pass to `restore()`.
+    pub fn snapshot(&self) -> TagProcessorSnapshot<'html> {
+        let processor = Self {
+            attributes: self.attributes.clone(),
+            attribute_cache: RefCell::default(),
+            attribute_name_interner: self.attribute_name_interner.clone(),
+            bytes_already_parsed: self.bytes_already_parsed,
+            comment_type: self.comment_type.clone(),
+            html_bytes: self.html_bytes.clone(),
+            classname_updates: self.classname_updates.clone(),
+            interned_tag_name: self.interned_tag_name.clone(),
+            is_closing_tag: self.is_closing_tag,
+            lexical_updates: self.lexical_updates.clone(),
+            token_updates_start: self.token_updates_start,
+            parser_state: self.parser_state.clone(),
+            parsing_namespace: self.parsing_namespace.clone(),
+            seek_count: self.seek_count,
+            skip_newline_at: self.skip_newline_at,
+            tag_name_interner: self.tag_name_interner.clone(),
+            tag_name_length: self.tag_name_length,
+            tag_name_starts_at: self.tag_name_starts_at,
+            text_length: self.text_length,
+            text_node_classification: self.text_node_classification.clone(),
+            text_starts_at: self.text_starts_at,
+            token_length: self.token_length,
+            token_starts_at: self.token_starts_at,
+            utf8_policy: self.utf8_policy,
+            compat_mode: self.compat_mode.clone(),
+            bookmarks: self.bookmarks.clone(),
+            internal_bookmarks: self.internal_bookmarks.clone(),
+            modified_from: self.modified_from,
+            #[cfg(feature = "std")]
+            input_stream: None,
+        };
+
+        TagProcessorSnapshot {
+            processor,
+            chunks_read: self.chunks_read(),
+        }
+    }
+
+    /// Rolls the processor back to a snapshot.
+    ///
+    /// A processor reading its input with `from_reader()` can't be rolled back once it
+    /// has read more input since the snapshot, as the earlier input may be gone.
+    ///
+    /// @see self::snapshot()
+    ///
+    /// @param TagProcessorSnapshot $snapshot State recorded by `snapshot()`.
+    /// @return bool Whether the processor was rolled back.
+    pub fn restore(&mut self, snapshot: TagProcessorSnapshot<'html>) -> bool {
+        if snapshot.chunks_read != self.chunks_read() {
+            return false;
+        }
+
+        #[cfg(feature = "std")]
+        let input_stream = self.input_stream.take();
+        let seek_count = self.seek_count;
+        *self = snapshot.processor;
+        self.seek_count = seek_count;
+        #[cfg(feature = "std")]
+        {
+            self.input_stream = input_stream;
+        }
+
+        true
+    }
+
+    /// Returns how many chunks of input have been read with `from_reader()`.
+    fn chunks_read(&self) -> usize {
+        #[cfg(feature = "std")]
+        if let Some(stream) = self.input_stream.as_ref() {
+            return stream.chunks_read;
+        }
+
+        0
+    }
+
+    /// Returns the string representation of the HTML Tag Processor.
+    ///
+    /// @return string The processed HTML.
+    pub fn get_updated_html(&self) -> Box<[u8]> {
+        let updated_html =
+            self.with_pending_updates(|updates| Self::apply_updates(&self.html_bytes, updates));
+
+        self.utf8_policy.apply(updated_html)
+    }
+
+    /// Writes the updated HTML into a writer, without first building it in memory.
+    ///
+    /// The output is the same as from `get_updated_html()`, but large documents can be
+    /// rewritten straight into a file or socket. Only the invalid UTF-8 replacement of
+    /// `Utf8Policy::Replace` requires building the updated HTML before writing it.
+    ///
+    /// Example:
+    ///
+    ///     $processor->set_attribute( 'class', 'rewritten' );
+    ///     $processor->write_updated_html( $socket )?;
+    ///
+    /// @param Write $writer Where to write the updated HTML.
+    /// @return Result Any error from the writer.
+    #[cfg(feature = "std")]
+    pub fn write_updated_html(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        if self.utf8_policy == Utf8Policy::Replace {
+            return writer.write_all(&self.get_updated_html());
+        }
+
+        self.with_pending_updates(|updates| {
+            Self::for_each_updated_chunk(&self.html_bytes, updates, |chunk| writer.write_all(chunk))
+        })
+    }
+
+    /// Writes the updated HTML into a string writer, e.g. a `String` or a `Formatter`.
+    ///
+    /// Like `write_updated_html()`, but for string sinks, so any invalid UTF-8 is
+    /// written as U+FFFD.
+    ///
+    /// @param Write $writer Where to write the updated HTML.
+    /// @return Result Any error from the writer.
+    pub fn write_updated_html_fmt(&self, writer: &mut impl core::fmt::Write) -> core::fmt::Result {
+        self.with_pending_updates(|updates| {
+            Self::for_each_updated_chunk(&self.html_bytes, updates, |chunk| {
+                writer.write_str(&String::from_utf8_lossy(chunk))
+            })
+        })
+    }
+
+    /// Calls a function with the lexical updates which produce the updated HTML.
+    ///
+    /// Class name updates are only converted into attribute updates after a tag is parsed,
+    /// so they are added here, replacing any other updates to the class attribute.
+    ///
+    /// @param callable $apply Called with the updates to apply.
+    /// @return mixed Whatever the function returns.
+    fn with_pending_updates<R>(&self, apply: impl FnOnce(Vec<&HtmlTextReplacement>) -> R) -> R {
+        let Some(updated_class) = self.get_updated_class_attribute() else {
+            return apply(Self::sorted_updates(&self.lexical_updates));
+        };
+
+        let class_updates = self.attribute_updates(b"class", updated_class.as_deref());
+        let (earlier_updates, token_updates) =
+            self.lexical_updates.split_at(self.token_updates_start);
+        let token_updates = token_updates
+            .iter()
+            .filter(|update| !Self::is_superseded(update, b"class", &class_updates));
+        apply(Self::sorted_updates(
+            earlier_updates
+                .iter()
+                .chain(token_updates)
+                .chain(&class_updates),
+        ))
+    }
+
+    /// Enqueues a replacement of a span of the input HTML.
+    ///
+    /// The replacement appears in the output of `get_updated_html()`. The span is given
+    /// in offsets into the input HTML, e.g. from `HtmlTextReplacement::new()`, and it
+    /// must not overlap any update already enqueued, including class names added to or
+    /// removed from the matched tag. Later changes to attributes replace the update if
+    /// they overlap it. If the span hasn't been parsed yet,
+    /// the replacement is applied before reaching it, so the Tag Processor then finds
+    /// the replacement text instead of the original HTML.
+    ///
+    /// Bookmarks are shifted to account for the replacement once it's applied, and any
+    /// bookmark inside the replaced span is released.
+    ///
+    /// Example:
+    ///
+    ///     $processor = new WP_HTML_Tag_Processor( '<p>Hello</p>' );
+    ///     $processor->enqueue_lexical_update( new WP_HTML_Text_Replacement( 3, 5, 'Howdy' ) );
+    ///     '<p>Howdy</p>' === $processor->get_updated_html();
+    ///
+    /// @param HtmlTextReplacement $update Replacement to enqueue.
+    /// @return bool Whether the replacement was enqueued, or `false` if its span extends
+    ///              past the end of the document or overlaps another update.
+    pub fn enqueue_lexical_update(&mut self, update: HtmlTextReplacement) -> bool {
+        let end = update.start.saturating_add(update.length);
+        if end > self.html_bytes.len() {
+            return false;
+        }
+
+        // Class name changes on the matched tag become updates to its class attribute later.
+        let class_updates = match self.get_updated_class_attribute() {
+            Some(updated_class) => self.attribute_updates(b"class", updated_class.as_deref()),
+            None => Vec::new(),
+        };
+        if self
+            .lexical_updates
+            .iter()
+            .chain(&class_updates)
+            .any(|existing| Self::updates_overlap(existing, &update))
+        {
+            return false;
+        }
+
+        self.lexical_updates.push(HtmlTextReplacement {
+            key: None,
+            ..update
+        });
+        true
+    }
+
+    /// Returns the updates enqueued for the document, in the order they were made.
+    ///
+    /// Class names added or removed on the currently-matched tag only appear here after
+    /// the Tag Processor moves on to the next token.
+    ///
+    /// @return HtmlTextReplacement[] Enqueued updates.
+    pub fn get_lexical_updates(&self) -> &[HtmlTextReplacement] {
+        &self.lexical_updates
+    }
+
+    /// Applies enqueued lexical updates to the document itself.
+    ///
+    /// Afterwards, the cursor and bookmarks point to the same places in the updated
+    /// document as they did in the original. Bookmarks whose tokens were replaced are
+    /// released, as what they referred to no longer exists.
+    ///
+    /// This leaves the currently-matched token pointing into the original document,
+    /// so it must be followed by moving the cursor, e.g. when seeking.
+    pub(crate) fn apply_lexical_updates(&mut self) {
+        self.class_name_updates_to_attributes_updates();
+        if self.lexical_updates.is_empty() {
+            return;
+        }
+
+        let lexical_updates = core::mem::take(&mut self.lexical_updates);
+        self.token_updates_start = 0;
+        let updates: Vec<HtmlTextReplacement> = Self::sorted_updates(&lexical_updates)
+            .into_iter()
+            .cloned()
+            .collect();
+        if let Some(first_update) = updates.first() {
+            self.modified_from = Some(
+                self.modified_from
+                    .map_or(first_update.start, |at| at.min(first_update.start)),
+            );
+        }
+        self.html_bytes = HtmlBytes::Shared(Arc::new(
+            Self::apply_updates(&self.html_bytes, &updates).into_vec(),
+        ));
+
+        // Adjust the cursor position by however much an update affects it.
+        let mut cursor_shift: isize = 0;
+        for update in updates.iter() {
+            if update.start < self.bytes_already_parsed {
+                cursor_shift += update.text.len() as isize - update.length as isize;
+            }
+        }
+        self.bytes_already_parsed = self
+            .bytes_already_parsed
+            .saturating_add_signed(cursor_shift);
+
+        // A leading newline to skip is at a fixed position which shifts along with the HTML.
+        if let Some(skip_newline_at) = self.skip_newline_at {
+            let shift: isize = updates
+                .iter()
+                .filter(|update| update.start < skip_newline_at)
+                .map(|update| update.text.len() as isize - update.length as isize)
+                .sum();
+            self.skip_newline_at = Some(skip_newline_at.saturating_add_signed(shift));
+        }
+
+        /*
+         * Adjust bookmark locations to account for how the text
+         * replacements adjust offsets in the input document.
+         */
+        self.bookmarks
+            .retain(|_, bookmark| Self::shift_bookmark(bookmark, &updates));
+        self.internal_bookmarks
+            .retain(|_, bookmark| Self::shift_bookmark(bookmark, &updates));
+    }
+
+    /// Shifts a bookmark by the lexical updates which appear before its endpoints.
+    ///
+    /// @param HtmlSpan               $bookmark Bookmark to shift.
+    /// @param HtmlTextReplacement[]  $updates  Applied updates, sorted by their start.
+    /// @return bool Whether the bookmark still exists, or `false` if it was replaced.
+    fn shift_bookmark(bookmark: &mut HtmlSpan, updates: &[HtmlTextReplacement]) -> bool {
+        let bookmark_end = bookmark.start + bookmark.length;
+
+        /*
+         * Each lexical update which appears before the bookmark's endpoints
+         * might shift the offsets for those endpoints. Loop through each change
+         * and accumulate the total shift for each bookmark, then apply that
+         * shift after tallying the full delta.
+         */
+        let mut head_delta: isize = 0;
+        let mut tail_delta: isize = 0;
+        for update in updates {
+            let update_end = update.start + update.length;
+            if bookmark.start < update.start && bookmark_end < update.start {
+                break;
+            }
+
+            if bookmark.start >= update.start && bookmark_end < update_end {
+                return false;
+            }
+
+            let delta = update.text.len() as isize - update.length as isize;
+            if bookmark.start >= update.start {
+                head_delta += delta;
+            }
+            if bookmark_end >= update_end {
+                tail_delta += delta;
+            }
+        }
+
+        bookmark.start = bookmark.start.saturating_add_signed(head_delta);
+        bookmark.length = bookmark
+            .length
+            .saturating_add_signed(tail_delta - head_delta);
+        true
+    }
+
+    /// Applies lexical updates to an HTML document.
+    ///
+    /// The updated document is built in a single pass over the input, into an output
+    /// allocated up front at its final size.
+    fn apply_updates<'a>(
+        html: &[u8],
+        lexical_updates: impl IntoIterator<Item = &'a HtmlTextReplacement>,
+    ) -> Box<[u8]> {
+        let updates = Self::sorted_updates(lexical_updates);
+        if updates.is_empty() {
+            return html.into();
+        }
+
+        let removed: usize = updates.iter().map(|update| update.length).sum();
+        let added: usize = updates.iter().map(|update| update.text.len()).sum();
+        let mut output = Vec::with_capacity(html.len() - removed + added);
+        Self::for_each_updated_chunk(html, updates, |chunk| {
+            output.extend_from_slice(chunk);
+            Ok::<_, Infallible>(())
+        })
+        .unwrap_or_else(|never| match never {});
+
+        output.into()
+    }
+
+    /// Passes each chunk of the updated HTML, in order, to a function.
+    ///
+    /// Chunks alternate between spans of the input HTML and the text of the updates.
+    ///
+    /// @param string                $html    Input HTML.
+    /// @param HtmlTextReplacement[] $updates Non-overlapping updates, sorted by their start.
+    /// @param callable              $write   Called with each chunk; stops on the first error.
+    /// @return Result The first error from the function, if any.
+    fn for_each_updated_chunk<'a, E>(
+        html: &[u8],
+        updates: impl IntoIterator<Item = &'a HtmlTextReplacement>,
+        mut write: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut bytes_already_copied = 0;
+        for update in updates {
+            write(&html[bytes_already_copied..update.start])?;
+            write(&update.text)?;
+            bytes_already_copied = update.start + update.length;
+        }
+        write(&html[bytes_already_copied..])
+    }
+
+    /// Returns lexical updates in the order they're applied.
+    ///
+    /// Updates are applied in document order. They are enqueued in the order
+    /// they're made, which need not be the order in which they appear. Inserted
+    /// text comes before a replacement starting at the same place, and text
+    /// inserted at the same place is inserted in the order it was enqueued.
+    ///
+    /// Enqueued updates never overlap: `enqueue_lexical_update()` rejects a span which
+    /// overlaps another update, and updates to attributes replace any they overlap.
+    ///
+    /// @param HtmlTextReplacement[] $lexical_updates Enqueued updates.
+    /// @return HtmlTextReplacement[] Updates, sorted by their start.
+    fn sorted_updates<'a>(
+        lexical_updates: impl IntoIterator<Item = &'a HtmlTextReplacement>,
+    ) -> Vec<&'a HtmlTextReplacement> {
+        let mut updates: Vec<&HtmlTextReplacement> = lexical_updates.into_iter().collect();
+        updates.sort_by_key(|update| (update.start, update.length));
+        debug_assert!(
+            updates
+                .windows(2)
+                .all(|pair| !Self::updates_overlap(pair[0], pair[1])),
+            "Enqueued lexical updates must not overlap."
+        );
+        updates
+    }
+
+    /// Indicates if two updates replace any of the same input HTML.
+    ///
+    /// Text inserted at the start or end of a replaced span doesn't overlap it.
+    fn updates_overlap(a: &HtmlTextReplacement, b: &HtmlTextReplacement) -> bool {
+        a.start < b.start + b.length && b.start < a.start + a.length
+    }
+
+    fn parse_next_tag(&mut self) -> bool {
+        self.after_tag();
+
+        let doc_length = self.html_bytes.len();
+        let was_at = self.bytes_already_parsed;
+        let mut at = was_at;
+
+        while at < doc_length {
+            let next_at = strpos_byte(&self.html_bytes, b'<', at);
+            if next_at.is_none() {
+                break;
+            }
+            at = next_at.unwrap();
+
+            if at > was_at {
+                /*
+                 * A "<" normally starts a new HTML tag or syntax token, but in cases where the
+                 * following character can't produce a valid token, the "<" is instead treated
+                 * as plaintext and the parser should skip over it. This avoids a problem when
+                 * following earlier practices of typing emoji with text, e.g. "<3". This
+                 * should be a heart, not a tag. It's supposed to be rendered, not hidden.
+                 *
+                 * At this point the parser checks if this is one of those cases and if it is
+                 * will continue searching for the next "<" in search of a token boundary.
+                 *
+                 * @see https://html.spec.whatwg.org/#tag-open-state
+                 */
+                if !matches!(
+                    self.html_bytes.get(at + 1),
+                    Some(b'!' | b'/' | b'?' | b'a'..=b'z' | b'A'..=b'Z')
+                ) {
+                    at += 1;
+                    continue;
+                }
+
+                self.parser_state = ParserState::TextNode;
+                self.token_starts_at = Some(was_at);
+                self.token_length = Some(at - was_at);
+                self.text_starts_at = Some(was_at);
+                self.text_length = Some(self.token_length.unwrap());
+                self.bytes_already_parsed = at;
+                return true;
+            }
+
+            self.token_starts_at = Some(at);
+
+            if at + 1 < doc_length && b'/' == self.html_bytes[at + 1] {
+                self.is_closing_tag = Some(true);
+                at += 1;
+            } else {
+                self.is_closing_tag = Some(false);
+            }
+
+            /*
+             * HTML tag names must start with [a-zA-Z] otherwise they are not tags.
+             * For example, "<3" is rendered as text, not a tag opener. If at least
+             * one letter follows the "<" then _it is_ a tag, but if the following
+             * character is anything else it _is not a tag_.
+             *
              * It's not uncommon to find non-tags starting with `<` in an HTML
              * document, so it's good for performance to make this pre-check before
              * continuing to attempt to parse a tag name.
@@ -570,6 +1667,11 @@ impl TagProcessor {
                         ),
                 );
                 self.bytes_already_parsed = at + self.tag_name_length.unwrap();
+
+                let tag_name = &self.html_bytes[at..self.bytes_already_parsed];
+                if TagName::known(tag_name, &ParsingNamespace::Html).is_none() {
+                    self.interned_tag_name = Some(self.tag_name_interner.intern(tag_name));
+                }
                 return true;
             }
 
@@ -1042,13 +2144,45 @@ impl TagProcessor {
             {
                 Some(TagName::Arbitrary(tag_name.into()))
             } else {
-                Some((tag_name, &self.parsing_namespace).into())
+                Some(
+                    TagName::known(tag_name, &self.parsing_namespace).unwrap_or_else(|| {
+                        TagName::Arbitrary(match &self.interned_tag_name {
+                            Some(interned) if interned.eq_ignore_ascii_case(tag_name) => {
+                                interned.clone()
+                            }
+                            _ => tag_name.to_ascii_uppercase().into(),
+                        })
+                    }),
+                )
             }
         } else {
             None
         }
     }
 
+    /// Returns the name of the matched tag as it's spelled in the input HTML.
+    ///
+    /// Tag names are ASCII case-insensitive and `get_tag()` normalizes them. This
+    /// preserves the author's spelling, e.g. for formatters and diffing tools.
+    ///
+    /// Example:
+    ///
+    ///     $p = new WP_HTML_Tag_Processor( '<DiV class="test">Test</div>' );
+    ///     $p->next_tag() === true;
+    ///     $p->get_tag() === 'DIV';
+    ///     $p->get_tag_raw() === 'DiV';
+    ///
+    /// @return string|null Name of currently matched tag in input HTML, or `null` if none found.
+    pub fn get_tag_raw(&self) -> Option<&[u8]> {
+        if self.parser_state != ParserState::MatchedTag {
+            return None;
+        }
+
+        let at = self.tag_name_starts_at?;
+        let length = self.tag_name_length?;
+        Some(&self.html_bytes[at..at + length])
+    }
+
     /// Returns the adjusted tag name for a given token, taking into
     /// account the current parsing context, whether HTML, SVG, or MathML.
     ///
@@ -1276,7 +2410,8 @@ impl TagProcessor {
             _ => unreachable!("skipping RCDATA at unexpected tag."),
         };
 
-        while at + match_end_tag.len() + 1 < doc_length {
+        // There must be room for at least the end tag and its closing `>`.
+        while at + match_end_tag.len() < doc_length {
             at = if let Some(end_candidate_pos) = stripos(&self.html_bytes, match_end_tag, at) {
                 self.tag_name_starts_at = Some(end_candidate_pos);
                 end_candidate_pos + match_end_tag.len()
@@ -1377,16 +2512,11 @@ impl TagProcessor {
             return None;
         }
 
-        let case_insensitive = self.compat_mode == CompatMode::Quirks;
+        if self.has_class_updates() {
+            return Some(self.class_list().contains(wanted_class.as_bytes()));
+        }
 
-        let wanted_bytes = wanted_class.as_bytes();
-        Some(self.class_list().any(|class_name| {
-            if case_insensitive {
-                class_name.eq_ignore_ascii_case(wanted_bytes)
-            } else {
-                class_name.as_ref() == wanted_bytes
-            }
-        }))
+        Some(self.with_cached_class_list(|class_list| class_list.contains(wanted_class.as_bytes())))
     }
 
     /// Adds a new class name to the currently matched tag.
@@ -1394,7 +2524,7 @@ impl TagProcessor {
     /// @param class_name The class name to add.
     /// @return bool Whether the class was set to be added.
     pub fn add_class(&mut self, class_name: &str) -> bool {
-        todo!()
+        self.enqueue_class_name_update(class_name, ClassNameUpdate::Add)
     }
 
     /// Removes a class name from the currently matched tag.
@@ -1402,7 +2532,63 @@ impl TagProcessor {
     /// @param class_name The class name to remove.
     /// @return bool Whether the class was set to be removed.
     pub fn remove_class(&mut self, class_name: &str) -> bool {
-        todo!()
+        self.enqueue_class_name_update(class_name, ClassNameUpdate::Remove)
+    }
+
+    fn enqueue_class_name_update(&mut self, class_name: &str, update: ClassNameUpdate) -> bool {
+        if self.parser_state != ParserState::MatchedTag || self.is_closing_tag.unwrap_or(false) {
+            return false;
+        }
+
+        /*
+         * Because class names are matched ASCII-case-insensitively in quirks mode,
+         * this needs to see if a case variant of the given class name is already
+         * enqueued and update that existing entry, if so. This picks the casing of
+         * the first-provided class name for all lexical variations.
+         */
+        let is_quirks = self.compat_mode == CompatMode::Quirks;
+        let class_name = class_name.as_bytes();
+        match self.classname_updates.iter_mut().find(|(updated_name, _)| {
+            if is_quirks {
+                updated_name.eq_ignore_ascii_case(class_name)
+            } else {
+                updated_name.as_ref() == class_name
+            }
+        }) {
+            Some((_, existing_update)) => *existing_update = update,
+            None => self.classname_updates.push((class_name.into(), update)),
+        }
+
+        true
+    }
+
+    /// Replaces the class names of the currently matched tag.
+    ///
+    /// The class attribute is written with the class names in order, separated by a single
+    /// space. If the class list is empty, the class attribute is removed.
+    ///
+    /// Example:
+    ///
+    ///     $p = new WP_HTML_Tag_Processor( '<div class="wp-block is-large">' );
+    ///     $p->next_tag();
+    ///     let mut class_list = $p->class_list();
+    ///     class_list.toggle( b"is-large" );
+    ///     class_list.add( b"is-small" );
+    ///     $p->set_class_list( &class_list );
+    ///     '<div class="wp-block is-small">' === $p->get_updated_html();
+    ///
+    /// @param ClassList $class_list The new class names.
+    /// @return bool Whether the class attribute was set to be updated.
+    pub fn set_class_list(&mut self, class_list: &ClassList) -> bool {
+        if self.parser_state != ParserState::MatchedTag || self.is_closing_tag.unwrap_or(false) {
+            return false;
+        }
+
+        self.classname_updates.clear();
+        let class = class_list.serialize();
+        let updates = self.attribute_updates(b"class", (!class.is_empty()).then_some(&class));
+        self.merge_updates(b"class", updates);
+        true
     }
 
     /// Whether the processor paused because the input HTML document ended
@@ -1436,15 +2622,38 @@ impl TagProcessor {
             return ClassList::empty();
         }
 
+        if !self.has_class_updates() {
+            return self.with_cached_class_list(ClassList::clone);
+        }
+
+        self.parse_class_list()
+    }
+
+    /// Calls the given function with the class names from the class attribute in the
+    /// input HTML, parsing them only the first time they're requested on the matched tag.
+    ///
+    /// @param callable $callback Receives the class list.
+    /// @return mixed Return value of the callback.
+    fn with_cached_class_list<T>(&self, callback: impl FnOnce(&ClassList) -> T) -> T {
+        if let Some(class_list) = &self.attribute_cache.borrow().class_list {
+            return callback(class_list);
+        }
+
+        let class_list = self.parse_class_list();
+        let result = callback(&class_list);
+        self.attribute_cache.borrow_mut().class_list = Some(class_list);
+        result
+    }
+
+    /// Parses the class names of the matched tag, including enqueued updates.
+    fn parse_class_list(&self) -> ClassList {
         let is_quirks = self.compat_mode == CompatMode::Quirks;
 
-        if let Some(class_attribute) = self.get_attribute(b"class") {
-            match class_attribute {
-                AttributeValue::String(class_attribute) => ClassList::new(class_attribute),
-                _ => ClassList::empty(),
+        match self.get_attribute(b"class") {
+            Some(AttributeValue::String(class_attribute)) => {
+                ClassList::parse(&class_attribute, is_quirks)
             }
-        } else {
-            ClassList::empty()
+            _ => ClassList::parse(b"", is_quirks),
         }
     }
 
@@ -1453,7 +2662,34 @@ impl TagProcessor {
     /// @param name The attribute name to remove.
     /// @return bool Whether the attribute was set to be removed.
     pub fn remove_attribute(&mut self, name: &str) -> bool {
-        todo!()
+        if self.parser_state != ParserState::MatchedTag || self.is_closing_tag.unwrap_or(false) {
+            return false;
+        }
+
+        /*
+         * > There must never be two or more attributes on
+         * > the same start tag whose names are an ASCII
+         * > case-insensitive match for each other.
+         *     - HTML 5 spec
+         *
+         * @see https://html.spec.whatwg.org/multipage/syntax.html#attributes-2:ascii-case-insensitive
+         */
+        let comparable_name = name.as_bytes().to_ascii_lowercase();
+
+        // Removing the class attribute overrides any enqueued class name updates.
+        if comparable_name == b"class" {
+            self.classname_updates.clear();
+        }
+
+        /*
+         * If the attribute doesn't exist on the tag then there's nothing to remove,
+         * but any enqueued update which would have added it must be dropped.
+         */
+        let updates = self.attribute_updates(&comparable_name, None);
+        let existed = !updates.is_empty();
+        self.merge_updates(&comparable_name, updates);
+
+        existed
     }
 
     /// Sets a bookmark in the HTML document.
@@ -1598,34 +2834,183 @@ impl TagProcessor {
     ///
     ///     $p->next_tag() === false;
     ///     $p->get_attribute_names_with_prefix( 'data-' ) === null;
+    ///
+    /// Enqueued updates are reflected: added attributes come first, as they're written
+    /// right after the tag name, and removed attributes are left out.
+    ///
+    /// @see self::get_interned_attribute_names_with_prefix()
     pub fn get_attribute_names_with_prefix(&self, prefix: &[u8]) -> Option<Vec<Box<[u8]>>> {
+        Some(
+            self.attribute_names_with_prefix(prefix)?
+                .into_iter()
+                .map(|name| name.to_ascii_lowercase().into())
+                .collect(),
+        )
+    }
+
+    /// Gets lowercase names of all attributes matching a given prefix in the current tag,
+    /// sharing one copy of each name across the document.
+    ///
+    /// Real documents repeat the same handful of attribute names many times, so each
+    /// name is only allocated the first time it's returned and later occurrences share
+    /// it, e.g. `Arc::ptr_eq()` holds for them. A few thousand distinct names at most
+    /// are kept, and streamed input forgets them as it discards processed input.
+    ///
+    /// @see self::get_attribute_names_with_prefix()
+    ///
+    /// @param string $prefix Prefix of requested attribute names.
+    /// @return array|null List of attribute names, or `null` when no tag opener is matched.
+    pub fn get_interned_attribute_names_with_prefix(
+        &mut self,
+        prefix: &[u8],
+    ) -> Option<Vec<Arc<[u8]>>> {
+        let mut interner = core::mem::take(&mut self.attribute_name_interner);
+        let names = self.attribute_names_with_prefix(prefix).map(|names| {
+            names
+                .into_iter()
+                .map(|name| interner.intern(name))
+                .collect()
+        });
+        self.attribute_name_interner = interner;
+        names
+    }
+
+    /// Returns the names of the attributes matching a prefix in the current tag,
+    /// as spelled in the input HTML or in enqueued updates.
+    ///
+    /// @see self::get_attribute_names_with_prefix()
+    fn attribute_names_with_prefix(&self, prefix: &[u8]) -> Option<Vec<&[u8]>> {
         if self.parser_state != ParserState::MatchedTag || self.is_closing_tag.unwrap_or(false) {
             return None;
         }
 
-        Some(
-            self.attributes
-                .iter()
-                .filter_map(
-                    |AttributeToken {
-                         start, name_length, ..
-                     }| {
-                        if *name_length < prefix.len() {
-                            None
-                        } else {
-                            let name = &self.html_bytes[*start..start + prefix.len()];
-                            if name.eq_ignore_ascii_case(prefix) {
-                                let slice = self.html_bytes[*start..start + name_length]
-                                    .to_ascii_lowercase();
-                                Some(slice.into_boxed_slice())
-                            } else {
-                                None
-                            }
-                        }
-                    },
-                )
-                .collect::<Vec<_>>(),
-        )
+        let has_prefix = |name: &[u8]| {
+            name.len() >= prefix.len() && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+        };
+        let existing_names = self
+            .attributes
+            .iter()
+            .map(|token| &self.html_bytes[token.start..token.start + token.name_length]);
+
+        /*
+         * Collects each enqueued attribute once, in the order it was first enqueued,
+         * noting whether it's removed. Like `decoded_attribute()`, the first update for
+         * a name applies and class name updates take precedence over the class attribute.
+         */
+        let mut enqueued: Vec<(&[u8], bool)> = Vec::new();
+        let mut enqueued_index: HashTable<usize> = HashTable::new();
+        let keyed_updates = self.lexical_updates[self.token_updates_start..]
+            .iter()
+            .filter_map(|update| match &update.key {
+                Some(LexicalUpdateKey::Attribute(name)) => {
+                    Some((name.as_ref(), update.text.is_empty()))
+                }
+                _ => None,
+            });
+        let class_update = self
+            .get_updated_class_attribute()
+            .map(|class| (b"class".as_slice(), class.is_none()));
+        for (name, is_removed) in keyed_updates.chain(class_update) {
+            let hash = hash_ignoring_ascii_case(name);
+            match enqueued_index.find(hash, |&at| enqueued[at].0.eq_ignore_ascii_case(name)) {
+                Some(&at) if name == b"class" => enqueued[at].1 = is_removed,
+                Some(_) => {}
+                None => {
+                    enqueued_index.insert_unique(hash, enqueued.len(), |&at| {
+                        hash_ignoring_ascii_case(enqueued[at].0)
+                    });
+                    enqueued.push((name, is_removed));
+                }
+            }
+        }
+        let find_enqueued = |name: &[u8]| {
+            enqueued_index
+                .find(hash_ignoring_ascii_case(name), |&at| {
+                    enqueued[at].0.eq_ignore_ascii_case(name)
+                })
+                .copied()
+        };
+
+        let mut is_existing = vec![false; enqueued.len()];
+        for at in existing_names.clone().filter_map(find_enqueued) {
+            is_existing[at] = true;
+        }
+
+        let mut names: Vec<&[u8]> = Vec::new();
+        for (&(name, is_removed), is_existing) in enqueued.iter().zip(is_existing) {
+            if !is_existing && !is_removed && has_prefix(name) {
+                names.push(name);
+            }
+        }
+        for name in existing_names {
+            let is_removed = find_enqueued(name).is_some_and(|at| enqueued[at].1);
+            if !is_removed && has_prefix(name) {
+                names.push(name);
+            }
+        }
+
+        Some(names)
+    }
+
+    /// Returns an iterator over the attributes of the matched tag, without allocating.
+    ///
+    /// Attributes are visited in the order they appear in the input HTML. Like browsers,
+    /// only the first of any duplicated attributes is visited. Names are borrowed as
+    /// spelled in the input HTML, and values are only decoded when requested. Enqueued
+    /// updates, e.g. from `set_attribute()`, aren't reflected; use `get_attribute()` to
+    /// read those back.
+    ///
+    /// When not matched on a tag opener, there are no attributes to visit.
+    ///
+    /// Example:
+    ///
+    ///     $p = new WP_HTML_Tag_Processor( '<img SRC="a.png" alt="A &amp; B" hidden src=b.png>' );
+    ///     $p->next_tag();
+    ///     foreach ( $p->attributes() as $attribute ) {
+    ///         // 'SRC' => 'a.png', 'alt' => 'A & B', 'hidden' => true
+    ///     }
+    ///
+    /// @return Attributes Iterator over the attributes of the matched tag.
+    pub fn attributes(&self) -> Attributes<'_> {
+        let tokens: &[AttributeToken] = if self.parser_state == ParserState::MatchedTag
+            && !self.is_closing_tag.unwrap_or(true)
+        {
+            &self.attributes
+        } else {
+            &[]
+        };
+
+        Attributes {
+            html: &self.html_bytes,
+            tokens,
+            at: 0,
+            utf8_policy: self.utf8_policy,
+        }
+    }
+
+    /// Returns the name of an attribute on the matched tag as it's spelled in the input HTML.
+    ///
+    /// Attribute names are ASCII case-insensitive, so the given name matches any spelling.
+    /// Only attributes in the input HTML are considered, not those which are enqueued to be
+    /// added with `set_attribute()`.
+    ///
+    /// Example:
+    ///
+    ///     $p = new WP_HTML_Tag_Processor( '<a HREF="/" Data-Id=3>' );
+    ///     $p->next_tag() === true;
+    ///     $p->get_attribute_name_raw( 'href' ) === 'HREF';
+    ///     $p->get_attribute_name_raw( 'data-id' ) === 'Data-Id';
+    ///     $p->get_attribute_name_raw( 'title' ) === null;
+    ///
+    /// @param string $name Name of attribute whose spelling is requested.
+    /// @return string|null Attribute name as found in the input HTML, or `null` if not present.
+    pub fn get_attribute_name_raw(&self, name: &[u8]) -> Option<&[u8]> {
+        if self.parser_state != ParserState::MatchedTag || self.is_closing_tag.unwrap_or(false) {
+            return None;
+        }
+
+        let token = self.find_attribute(name)?;
+        Some(&self.html_bytes[token.start..token.start + token.name_length])
     }
 
     /// Returns the namespace of the matched token.
@@ -1657,9 +3042,71 @@ impl TagProcessor {
     ///
     /// @return string
     pub fn get_modifiable_text(&self) -> Box<[u8]> {
-        // TODO: This isn't the same, PHP uses mixed numeric/associative array keys for lexical
-        // updates and a named key "modifiable text".
-        let has_enqueued_update = !self.lexical_updates.is_empty();
+        self.utf8_policy.apply(self.decoded_modifiable_text())
+    }
+
+    /// Indicates if a leading newline in the modifiable text of the matched token
+    /// would be ignored, i.e. for TEXTAREA elements and for text nodes immediately
+    /// following a LISTING or PRE opening tag.
+    ///
+    /// Serializers must write an additional newline before such text to preserve it.
+    pub(crate) fn ignores_leading_newline(&self) -> bool {
+        match self.get_token_name() {
+            Some(NodeName::Token(TokenType::Text)) => {
+                self.skip_newline_at.is_some() && self.skip_newline_at == self.token_starts_at
+            }
+            Some(NodeName::Tag(TagName::TEXTAREA)) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the raw HTML of the matched token, exactly as it appears in the input.
+    ///
+    /// Unlike `get_modifiable_text()`, nothing is decoded or normalized: tags include
+    /// their attributes and all of their syntax, comments include their delimiters, and
+    /// SCRIPT, STYLE, TEXTAREA, and the other special elements include their contents
+    /// up through their closing tag. Enqueued updates to the token aren't reflected.
+    ///
+    /// Example:
+    ///
+    ///     $processor = new WP_HTML_Tag_Processor( '<img  src=a.png ALT="A"><!-- note -->' );
+    ///     $processor->next_tag();
+    ///     '<img  src=a.png ALT="A">' === $processor->get_token_html();
+    ///     $processor->next_token();
+    ///     '<!-- note -->' === $processor->get_token_html();
+    ///
+    /// @return string|null Raw HTML of the matched token, or `null` if not on a token.
+    pub fn get_token_html(&self) -> Option<&[u8]> {
+        let span = self.get_token_span()?;
+        self.html_bytes.get(span.start..span.start + span.length)
+    }
+
+    /// Returns a copy of the matched token, including its attributes and modifiable text.
+    ///
+    /// @see TokenSnapshot
+    ///
+    /// @return TokenSnapshot|null Copy of the matched token, or `null` if not on a token.
+    pub fn get_token_snapshot(&self) -> Option<TokenSnapshot> {
+        Some(TokenSnapshot::new(
+            self.get_token_type()?.clone(),
+            self.get_token_name()?,
+            self.is_tag_closer(),
+            self.attributes(),
+            self.get_token_span(),
+            self.get_modifiable_text(),
+        ))
+    }
+
+    /// Returns where the matched token appears in the input HTML.
+    pub(crate) fn get_token_span(&self) -> Option<HtmlSpan> {
+        Some(HtmlSpan::new(self.token_starts_at?, self.token_length?))
+    }
+
+    /// Returns the modifiable text for the matched token before applying the UTF-8 policy.
+    fn decoded_modifiable_text(&self) -> Box<[u8]> {
+        let has_enqueued_update = self.lexical_updates[self.token_updates_start..]
+            .iter()
+            .any(|update| update.key == Some(LexicalUpdateKey::ModifiableText));
 
         if !has_enqueued_update
             && (self.text_starts_at.is_none() || self.text_length.is_none_or(|x| x == 0))
@@ -1763,7 +3210,7 @@ impl TagProcessor {
             }
         }
 
-        let mut text = entities::decode(&entities::HtmlContext::BodyText, &text).to_vec();
+        let mut text = entities::decode_cow(&entities::HtmlContext::BodyText, &text).into_owned();
 
         /*
          * Skip the first line feed after LISTING, PRE, and TEXTAREA opening tags.
@@ -1877,7 +3324,46 @@ impl TagProcessor {
     /// @param bookmark_name Jump to the place in the document identified by this bookmark name.
     /// @return Whether the internal cursor was successfully moved to the bookmark's location.
     pub fn seek(&mut self, bookmark_name: &str) -> bool {
-        todo!()
+        if !self.bookmarks.contains_key(bookmark_name) {
+            return false;
+        }
+
+        self.seek_count += 1;
+        if self.seek_count > MAX_SEEK_OPS {
+            // @todo _doing_it_wrong( __METHOD__, __( 'Too many calls to seek() - this can lead to performance issues.' ), '6.2.0' );
+            return false;
+        }
+
+        // Flush out any pending updates to the document.
+        self.apply_lexical_updates();
+
+        // Point this tag processor before the sought tag opener and consume it.
+        self.bytes_already_parsed = self.bookmarks[bookmark_name].start;
+        self.parser_state = ParserState::Ready;
+        self.next_token()
+    }
+
+    /// Returns what kind of characters the matched text node contains.
+    ///
+    /// Text nodes are only classified once they are subdivided, so this reports `Generic`
+    /// unless `subdivide_text_appropriately()` split off a leading span of NULL bytes or
+    /// whitespace. The HTML Processor subdivides every text node.
+    ///
+    /// Example:
+    ///
+    ///     $processor = new WP_HTML_Tag_Processor( "\n\t<p>" );
+    ///     $processor->next_token();
+    ///     $processor->subdivide_text_appropriately();
+    ///     TextNodeClassification::Whitespace === $processor->get_text_node_classification();
+    ///
+    /// @return TextNodeClassification|null Classification of the text node, or `null` if
+    ///                                     not on a text node.
+    pub fn get_text_node_classification(&self) -> Option<&TextNodeClassification> {
+        if self.parser_state != ParserState::TextNode {
+            None
+        } else {
+            Some(&self.text_node_classification)
+        }
     }
 
     pub fn get_comment_type(&self) -> Option<&CommentType> {
@@ -1948,108 +3434,483 @@ impl TagProcessor {
         })
     }
 
-    pub fn set_attribute(&mut self, name: &str, value: &str) -> bool {
-        todo!()
-    }
-
-    pub fn get_attribute(&self, name: &[u8]) -> Option<AttributeValue> {
-        if self.parser_state != ParserState::MatchedTag {
+    /// Parses the currently-matched funky comment as a WordPress "bit," if it is one.
+    ///
+    /// Example:
+    ///
+    ///     $processor = new WP_HTML_Tag_Processor( '<p>Published </%core/post-date format="Y">.</p>' );
+    ///     $processor->next_token(); // P
+    ///     $processor->next_token(); // "Published "
+    ///     $processor->next_token(); // The bit.
+    ///     'core/post-date' === $processor->get_bit()->name;
+    ///
+    /// @see Bit
+    ///
+    /// @return Bit|null The parsed bit, or null if not on a funky comment which is a bit.
+    pub fn get_bit(&self) -> Option<Bit> {
+        if self.parser_state != ParserState::FunkyComment {
             return None;
         }
 
-        if !self.lexical_updates.is_empty() {
-            todo!("Get attribute lexical update handling.");
+        let text_starts_at = self.text_starts_at?;
+        let text_length = self.text_length?;
+        Bit::parse(&self.html_bytes[text_starts_at..text_starts_at + text_length])
+    }
+
+    /// Replaces the currently-matched funky comment with the given HTML.
+    ///
+    /// This is how rendered bits are substituted into the document. The replacement
+    /// appears in the output of `get_updated_html()` and is not itself processed.
+    ///
+    /// @param string $html Replacement HTML, which is not escaped.
+    ///
+    /// @return bool Whether the funky comment was replaced.
+    pub fn replace_funky_comment(&mut self, html: &[u8]) -> bool {
+        if self.parser_state != ParserState::FunkyComment {
+            return false;
         }
 
-        Some(
-            if let Some(attr_token) = self.attributes.iter().find(|&token| {
-                let attr_name = &self.html_bytes[token.start..token.start + token.name_length];
-                attr_name.eq_ignore_ascii_case(name)
-            }) {
-                if attr_token.is_true {
-                    AttributeValue::BooleanTrue
-                } else {
-                    let raw_value = &self.html_bytes[attr_token.value_starts_at
-                        ..attr_token.value_starts_at + attr_token.value_length];
-                    let decoded = entities::decode(&entities::HtmlContext::Attribute, raw_value);
-                    AttributeValue::String(Box::from(decoded))
-                }
-            } else {
-                AttributeValue::BooleanFalse
-            },
-        )
+        let (Some(start), Some(length)) = (self.token_starts_at, self.token_length) else {
+            return false;
+        };
+        self.lexical_updates
+            .push(HtmlTextReplacement::new(start, length, html));
+        true
     }
 
-    /// Indicates if the currently matched tag contains the self-closing flag.
+    /// Updates or creates a new attribute on the currently matched tag with the passed value.
     ///
-    /// No HTML elements ought to have the self-closing flag and for those, the self-closing
-    /// flag will be ignored. For void elements this is benign because they "self close"
-    /// automatically. For non-void HTML elements though problems will appear if someone
-    /// intends to use a self-closing element in place of that element with an empty body.
-    /// For HTML foreign elements and custom elements the self-closing flag determines if
-    /// they self-close or not.
+    /// The value is escaped so that it's read back as given from `get_attribute()`. The new
+    /// attribute is always written with a double-quoted value, regardless of how the
+    /// attribute it replaces was written, and it replaces only the first occurrence of a
+    /// duplicated attribute, which is the one browsers read.
     ///
-    /// This function does not determine if a tag is self-closing,
-    /// but only if the self-closing flag is present in the syntax.
+    /// Example:
     ///
-    /// @return bool Whether the currently matched tag contains the self-closing flag.
-    pub fn has_self_closing_flag(&self) -> bool {
-        if self.parser_state != ParserState::MatchedTag {
+    ///     $p = new WP_HTML_Tag_Processor( '<div id=main>' );
+    ///     $p->next_tag();
+    ///     $p->set_attribute( 'title', 'Tom & Jerry' );
+    ///     '<div title="Tom &amp; Jerry" id=main>' === $p->get_updated_html();
+    ///
+    /// @param string $name  The attribute name to target.
+    /// @param string $value The new attribute value.
+    /// @return bool Whether an attribute value was set.
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> bool {
+        if self.parser_state != ParserState::MatchedTag || self.is_closing_tag.unwrap_or(false) {
             return false;
         }
 
         /*
-         * The self-closing flag is the solidus at the _end_ of the tag, not the beginning.
-         *
-         * Example:
+         * Verify that the attribute name is allowable. Names which couldn't be parsed
+         * back as the same attribute would change the structure of the tag.
          *
-         *     <figure />
-         *             ^ this appears one character before the end of the closing ">".
+         * @see https://html.spec.whatwg.org/#attributes-2
          */
-        b'/' == self.html_bytes[self.token_starts_at.unwrap() + self.token_length.unwrap() - 2]
-    }
-
-    /**
-     * Subdivides a matched text node, splitting NULL byte sequences and decoded whitespace as
-     * distinct nodes prefixes.
-     *
-     * Note that once anything that's neither a NULL byte nor decoded whitespace is
-     * encountered, then the remainder of the text node is left intact as generic text.
-     *
-     *  - The HTML Processor uses this to apply distinct rules for different kinds of text.
-     *  - Inter-element whitespace can be detected and skipped with this method.
-     *
-     * Text nodes aren't eagerly subdivided because there's no need to split them unless
-     * decisions are being made on NULL byte sequences or whitespace-only text.
-     *
-     * Example:
-     *
-     *     $processor = new WP_HTML_Tag_Processor( "\x00Apples & Oranges" );
-     *     true  === $processor->next_token();                   // Text is "Apples & Oranges".
-     *     true  === $processor->subdivide_text_appropriately(); // Text is "".
-     *     true  === $processor->next_token();                   // Text is "Apples & Oranges".
-     *     false === $processor->subdivide_text_appropriately();
-     *
-     *     $processor = new WP_HTML_Tag_Processor( "&#x13; \r\n\tMore" );
-     *     true  === $processor->next_token();                   // Text is "␤ ␤␉More".
-     *     true  === $processor->subdivide_text_appropriately(); // Text is "␤ ␤␉".
-     *     true  === $processor->next_token();                   // Text is "More".
-     *     false === $processor->subdivide_text_appropriately();
-     *
-     * @return bool Whether the text node was subdivided.
-     */
-    pub fn subdivide_text_appropriately(&mut self) -> bool {
-        if self.parser_state != ParserState::TextNode {
+        if name.is_empty()
+            || name.bytes().any(|c| {
+                matches!(
+                    c,
+                    0x00..=0x20 | 0x7F | b'"' | b'\'' | b'>' | b'&' | b'<' | b'/' | b'='
+                )
+            })
+        {
             return false;
         }
 
-        self.text_node_classification = TextNodeClassification::Generic;
+        let comparable_name = name.as_bytes().to_ascii_lowercase();
 
-        /*
-         * NULL bytes are treated categorically different than numeric character
-         * references whose number is zero. `&#x00;` is not the same as `"\x00"`.
-         */
-        let leading_nulls = strspn!(
+        // Setting the class attribute overrides any enqueued class name updates.
+        if comparable_name == b"class" {
+            self.classname_updates.clear();
+        }
+
+        let updates = self.attribute_updates(name.as_bytes(), Some(value.as_bytes()));
+        self.merge_updates(&comparable_name, updates);
+
+        true
+    }
+
+    /// Returns the first attribute on the matched tag with the given name.
+    fn find_attribute(&self, name: &[u8]) -> Option<&AttributeToken> {
+        self.attributes.iter().find(|token| {
+            self.html_bytes[token.start..token.start + token.name_length].eq_ignore_ascii_case(name)
+        })
+    }
+
+    /// Returns the decoded value of an attribute in the input HTML, decoding it only
+    /// the first time it's requested on the matched tag.
+    ///
+    /// @param AttributeToken $token Attribute with a value.
+    /// @return string Decoded attribute value, before applying the UTF-8 policy.
+    fn cached_attribute_value(&self, token: &AttributeToken) -> Box<[u8]> {
+        let mut cache = self.attribute_cache.borrow_mut();
+        if let Some((_, value)) = cache.values.iter().find(|(start, _)| *start == token.start) {
+            return value.clone();
+        }
+
+        let value = decoder::decode_attribute(
+            &self.html_bytes[token.value_starts_at..token.value_starts_at + token.value_length],
+        );
+        cache.values.push((token.start, value.clone()));
+        value
+    }
+
+    /// Indicates if class names have been added, removed, or replaced on the matched tag,
+    /// in which case the class attribute in the input HTML is out of date.
+    fn has_class_updates(&self) -> bool {
+        !self.classname_updates.is_empty() || self.get_enqueued_attribute_value(b"class").is_some()
+    }
+
+    /// Returns where the first attribute with the given name appears in the input HTML.
+    ///
+    /// Enqueued updates are not considered; spans always refer to the original document.
+    ///
+    ///     <img src="a.jpg">
+    ///          ---------    first span, the entire attribute.
+    ///               -----   second span, the raw value without quotes.
+    ///
+    /// @param string $name Attribute name, ASCII case-insensitive.
+    /// @return array|null Spans of the attribute and its raw value, if the attribute exists.
+    pub(crate) fn get_attribute_spans(&self, name: &[u8]) -> Option<(HtmlSpan, HtmlSpan)> {
+        if self.parser_state != ParserState::MatchedTag {
+            return None;
+        }
+
+        let token = self.find_attribute(name)?;
+        Some((
+            HtmlSpan::new(token.start, token.length),
+            HtmlSpan::new(token.value_starts_at, token.value_length),
+        ))
+    }
+
+    /// Creates the lexical updates which set or remove an attribute on the matched tag.
+    ///
+    /// When setting an attribute, the first update replaces the existing attribute or
+    /// inserts the new attribute directly after the tag name. When removing an attribute,
+    /// the first update removes the existing attribute and the rest remove any duplicates
+    /// of it. No updates are returned when removing an attribute which doesn't exist.
+    ///
+    ///     <div id="main"/>
+    ///         ^-- new attributes are inserted here.
+    ///
+    /// @param string      $name  Attribute name, as it should be written.
+    /// @param string|null $value New attribute value, or `null` to remove the attribute.
+    /// @return WP_HTML_Text_Replacement[] Updates, with the first keyed by the attribute name.
+    fn attribute_updates(&self, name: &[u8], value: Option<&[u8]>) -> Vec<HtmlTextReplacement> {
+        let key = Some(LexicalUpdateKey::Attribute(
+            name.to_ascii_lowercase().into(),
+        ));
+        let mut matching_attributes = self.attributes.iter().filter(|token| {
+            self.html_bytes[token.start..token.start + token.name_length].eq_ignore_ascii_case(name)
+        });
+        let existing_attribute = matching_attributes.next();
+
+        let Some(value) = value else {
+            let Some(existing_attribute) = existing_attribute else {
+                return Vec::new();
+            };
+
+            let mut updates = vec![HtmlTextReplacement {
+                start: existing_attribute.start,
+                length: existing_attribute.length,
+                text: Box::new([]),
+                key,
+            }];
+
+            // Removes any duplicated attributes if they were also present.
+            updates.extend(
+                matching_attributes.map(|duplicate| {
+                    HtmlTextReplacement::new(duplicate.start, duplicate.length, b"")
+                }),
+            );
+            return updates;
+        };
+
+        let mut updated_attribute = Vec::with_capacity(name.len() + value.len() + 4);
+        if existing_attribute.is_none() {
+            updated_attribute.push(b' ');
+        }
+        updated_attribute.extend_from_slice(name);
+        updated_attribute.extend_from_slice(b"=\"");
+        updated_attribute.extend_from_slice(&esc_attr(value));
+        updated_attribute.push(b'"');
+
+        let (start, length) = match existing_attribute {
+            Some(existing_attribute) => (existing_attribute.start, existing_attribute.length),
+            None => (
+                self.tag_name_starts_at.unwrap() + self.tag_name_length.unwrap(),
+                0,
+            ),
+        };
+
+        vec![HtmlTextReplacement {
+            start,
+            length,
+            text: updated_attribute.into(),
+            key,
+        }]
+    }
+
+    /// Replaces any enqueued updates for an attribute of the matched tag with the given updates.
+    ///
+    /// Positional updates at the same place as a new update are also replaced so
+    /// that duplicate attributes aren't removed more than once, as is any update
+    /// overlapping a new one: the latest change to a span of the HTML wins.
+    fn merge_updates(&mut self, comparable_name: &[u8], updates: Vec<HtmlTextReplacement>) {
+        let token_updates = self.lexical_updates.split_off(self.token_updates_start);
+        self.lexical_updates.extend(
+            token_updates
+                .into_iter()
+                .filter(|update| !Self::is_superseded(update, comparable_name, &updates)),
+        );
+        self.lexical_updates.extend(updates);
+    }
+
+    /// Indicates if an enqueued update is replaced by new updates for an attribute.
+    ///
+    /// @see self::merge_updates()
+    ///
+    /// @param HtmlTextReplacement   $existing_update Update already enqueued for the matched tag.
+    /// @param string                $comparable_name Attribute name in its comparable form.
+    /// @param HtmlTextReplacement[] $updates         New updates for the attribute.
+    /// @return bool Whether the existing update is replaced.
+    fn is_superseded(
+        existing_update: &HtmlTextReplacement,
+        comparable_name: &[u8],
+        updates: &[HtmlTextReplacement],
+    ) -> bool {
+        let is_same_attribute = matches!(
+            &existing_update.key,
+            Some(LexicalUpdateKey::Attribute(name)) if name.as_ref() == comparable_name
+        );
+        let is_same_span = existing_update.key.is_none()
+            && updates
+                .iter()
+                .any(|update| update.key.is_none() && update.start == existing_update.start);
+        let is_overlapped = updates
+            .iter()
+            .any(|update| Self::updates_overlap(existing_update, update));
+        is_same_attribute || is_same_span || is_overlapped
+    }
+
+    /// Returns the value of an enqueued update for the given attribute, if there is one.
+    ///
+    /// @param string $comparable_name The attribute name in its comparable form.
+    /// @return string|boolean|null Value of enqueued update if present, otherwise false.
+    fn get_enqueued_attribute_value(&self, comparable_name: &[u8]) -> Option<AttributeValue> {
+        let update = self.lexical_updates[self.token_updates_start..].iter().find(|update| {
+            matches!(&update.key, Some(LexicalUpdateKey::Attribute(attribute_name)) if attribute_name.as_ref() == comparable_name)
+        })?;
+
+        // Removed attributes erase the entire span.
+        if update.text.is_empty() {
+            return Some(AttributeValue::BooleanFalse);
+        }
+
+        /*
+         * Enqueued attributes are always written as `name="value"`, with the name
+         * possibly prefixed by a space when the attribute is being added.
+         */
+        let enqueued_text = &update.text;
+        let Some(equals_at) = strpos_byte(enqueued_text, b'=', 0) else {
+            return Some(AttributeValue::BooleanTrue);
+        };
+        let enqueued_value = &enqueued_text[equals_at + 2..enqueued_text.len() - 1];
+        Some(AttributeValue::String(decoder::decode_attribute(
+            enqueued_value,
+        )))
+    }
+
+    pub fn get_attribute(&self, name: &[u8]) -> Option<AttributeValue> {
+        match self.decoded_attribute(name)? {
+            AttributeValue::String(value) => {
+                Some(AttributeValue::String(self.utf8_policy.apply(value)))
+            }
+            value => Some(value),
+        }
+    }
+
+    /// Indicates if the value of an attribute on the matched tag starts with the given text.
+    ///
+    /// Unlike comparing the result of `get_attribute()`, this reads the raw value from the
+    /// input HTML and decodes only the character references needed to decide, so it's cheap
+    /// even for large values, such as `data:` URLs in an image's `src`. Boolean attributes
+    /// have an empty value, so they only start with empty search text. A missing attribute
+    /// has no value at all and never starts with anything, not even empty search text.
+    ///
+    /// Example:
+    ///
+    ///     $p = new WP_HTML_Tag_Processor( '<img src="&#x68;ttps://example.com/a.jpg">' );
+    ///     $p->next_tag();
+    ///     true  === $p->attribute_starts_with( 'src', 'HTTPS:', 'ascii-case-insensitive' );
+    ///     false === $p->attribute_starts_with( 'src', 'data:', 'ascii-case-insensitive' );
+    ///
+    /// @see WP_HTML_Decoder::attribute_starts_with()
+    ///
+    /// @param string $name             Attribute name, ASCII case-insensitive.
+    /// @param string $search_text      Does the attribute value start with this plain string.
+    /// @param string $case_sensitivity Whether to ignore ASCII case when matching.
+    /// @return bool|null Whether the attribute value starts with the given text,
+    ///                   or `null` if not matched on a tag opener.
+    pub fn attribute_starts_with(
+        &self,
+        name: &[u8],
+        search_text: &[u8],
+        case_sensitivity: CaseSensitivity,
+    ) -> Option<bool> {
+        if self.parser_state != ParserState::MatchedTag || self.is_closing_tag.unwrap_or(false) {
+            return None;
+        }
+
+        // Enqueued updates aren't in the input HTML, so their values must be compared instead.
+        let comparable_name = name.to_ascii_lowercase();
+        let has_updates = if comparable_name == b"class" {
+            self.has_class_updates()
+        } else {
+            self.get_enqueued_attribute_value(&comparable_name)
+                .is_some()
+        };
+        if has_updates {
+            let value = match self.decoded_attribute(name)? {
+                AttributeValue::String(value) => value,
+                AttributeValue::BooleanTrue => Box::default(),
+                AttributeValue::BooleanFalse => return Some(false),
+            };
+            let prefix = value.get(..search_text.len());
+            return Some(match case_sensitivity {
+                CaseSensitivity::CaseSensitive => prefix == Some(search_text),
+                CaseSensitivity::AsciiCaseInsensitive => {
+                    prefix.is_some_and(|prefix| prefix.eq_ignore_ascii_case(search_text))
+                }
+            });
+        }
+
+        let Some(attr_token) = self.attributes.iter().find(|&token| {
+            let attr_name = &self.html_bytes[token.start..token.start + token.name_length];
+            attr_name.eq_ignore_ascii_case(name)
+        }) else {
+            return Some(false);
+        };
+
+        if attr_token.is_true {
+            return Some(search_text.is_empty());
+        }
+
+        let raw_value = &self.html_bytes
+            [attr_token.value_starts_at..attr_token.value_starts_at + attr_token.value_length];
+        Some(decoder::attribute_starts_with(
+            raw_value,
+            search_text,
+            case_sensitivity,
+        ))
+    }
+
+    /// Returns the value of an attribute on the matched tag before applying the UTF-8 policy.
+    fn decoded_attribute(&self, name: &[u8]) -> Option<AttributeValue> {
+        if self.parser_state != ParserState::MatchedTag {
+            return None;
+        }
+
+        let comparable_name = name.to_ascii_lowercase();
+        if comparable_name == b"class"
+            && let Some(updated_class) = self.get_updated_class_attribute()
+        {
+            return Some(match updated_class {
+                Some(class) => AttributeValue::String(class),
+                None => AttributeValue::BooleanFalse,
+            });
+        }
+
+        if let Some(value) = self.get_enqueued_attribute_value(&comparable_name) {
+            return Some(value);
+        }
+
+        Some(
+            if let Some(attr_token) = self.attributes.iter().find(|&token| {
+                let attr_name = &self.html_bytes[token.start..token.start + token.name_length];
+                attr_name.eq_ignore_ascii_case(name)
+            }) {
+                if attr_token.is_true {
+                    AttributeValue::BooleanTrue
+                } else {
+                    AttributeValue::String(self.cached_attribute_value(attr_token))
+                }
+            } else {
+                AttributeValue::BooleanFalse
+            },
+        )
+    }
+
+    /// Indicates if the currently matched tag contains the self-closing flag.
+    ///
+    /// No HTML elements ought to have the self-closing flag and for those, the self-closing
+    /// flag will be ignored. For void elements this is benign because they "self close"
+    /// automatically. For non-void HTML elements though problems will appear if someone
+    /// intends to use a self-closing element in place of that element with an empty body.
+    /// For HTML foreign elements and custom elements the self-closing flag determines if
+    /// they self-close or not.
+    ///
+    /// This function does not determine if a tag is self-closing,
+    /// but only if the self-closing flag is present in the syntax.
+    ///
+    /// @return bool Whether the currently matched tag contains the self-closing flag.
+    pub fn has_self_closing_flag(&self) -> bool {
+        if self.parser_state != ParserState::MatchedTag {
+            return false;
+        }
+
+        /*
+         * The self-closing flag is the solidus at the _end_ of the tag, not the beginning.
+         *
+         * Example:
+         *
+         *     <figure />
+         *             ^ this appears one character before the end of the closing ">".
+         */
+        b'/' == self.html_bytes[self.token_starts_at.unwrap() + self.token_length.unwrap() - 2]
+    }
+
+    /**
+     * Subdivides a matched text node, splitting NULL byte sequences and decoded whitespace as
+     * distinct nodes prefixes.
+     *
+     * Note that once anything that's neither a NULL byte nor decoded whitespace is
+     * encountered, then the remainder of the text node is left intact as generic text.
+     *
+     *  - The HTML Processor uses this to apply distinct rules for different kinds of text.
+     *  - Inter-element whitespace can be detected and skipped with this method.
+     *
+     * Text nodes aren't eagerly subdivided because there's no need to split them unless
+     * decisions are being made on NULL byte sequences or whitespace-only text.
+     *
+     * Example:
+     *
+     *     $processor = new WP_HTML_Tag_Processor( "\x00Apples & Oranges" );
+     *     true  === $processor->next_token();                   // Text is "Apples & Oranges".
+     *     true  === $processor->subdivide_text_appropriately(); // Text is "".
+     *     true  === $processor->next_token();                   // Text is "Apples & Oranges".
+     *     false === $processor->subdivide_text_appropriately();
+     *
+     *     $processor = new WP_HTML_Tag_Processor( "&#x13; \r\n\tMore" );
+     *     true  === $processor->next_token();                   // Text is "␤ ␤␉More".
+     *     true  === $processor->subdivide_text_appropriately(); // Text is "␤ ␤␉".
+     *     true  === $processor->next_token();                   // Text is "More".
+     *     false === $processor->subdivide_text_appropriately();
+     *
+     * @return bool Whether the text node was subdivided.
+     */
+    pub fn subdivide_text_appropriately(&mut self) -> bool {
+        if self.parser_state != ParserState::TextNode {
+            return false;
+        }
+
+        self.text_node_classification = TextNodeClassification::Generic;
+
+        /*
+         * NULL bytes are treated categorically different than numeric character
+         * references whose number is zero. `&#x00;` is not the same as `"\x00"`.
+         */
+        let leading_nulls = strspn!(
             &self.html_bytes,
             b'\x00',
             self.text_starts_at.unwrap(),
@@ -2107,6 +3968,31 @@ impl TagProcessor {
         true
     }
 
+    /// Records the tokenizer state at the cursor, which must be between two tokens.
+    ///
+    /// @return TokenizerCheckpoint State from which parsing can resume at the cursor.
+    pub(crate) fn checkpoint(&self) -> TokenizerCheckpoint {
+        TokenizerCheckpoint {
+            at: self.bytes_already_parsed,
+            compat_mode: self.compat_mode.clone(),
+            parsing_namespace: self.parsing_namespace.clone(),
+            skip_newline_at: self.skip_newline_at,
+        }
+    }
+
+    /// Moves the cursor back to a checkpoint so the next token is parsed from there.
+    ///
+    /// Pending lexical updates must be applied beforehand.
+    ///
+    /// @param TokenizerCheckpoint $checkpoint State recorded by `checkpoint()`.
+    pub(crate) fn resume_from(&mut self, checkpoint: &TokenizerCheckpoint) {
+        self.bytes_already_parsed = checkpoint.at;
+        self.compat_mode = checkpoint.compat_mode.clone();
+        self.parsing_namespace = checkpoint.parsing_namespace.clone();
+        self.skip_newline_at = checkpoint.skip_newline_at;
+        self.parser_state = ParserState::Ready;
+    }
+
     /// Gets DOCTYPE declaration info from a DOCTYPE token.
     ///
     /// DOCTYPE tokens may appear in many places in an HTML document. In most places, they are
@@ -2135,18 +4021,25 @@ impl TagProcessor {
     }
 }
 
-impl Default for TagProcessor {
+impl Default for TagProcessor<'_> {
     fn default() -> Self {
         Self {
             attributes: vec![],
+            attribute_cache: RefCell::default(),
+            attribute_name_interner: AttributeNameInterner::default(),
             bytes_already_parsed: 0,
             comment_type: None,
-            html_bytes: Box::new([]),
+            classname_updates: Vec::new(),
+            html_bytes: HtmlBytes::default(),
+            interned_tag_name: None,
             is_closing_tag: None,
             lexical_updates: Vec::new(),
+            token_updates_start: 0,
             parser_state: Default::default(),
             parsing_namespace: Default::default(),
+            seek_count: 0,
             skip_newline_at: None,
+            tag_name_interner: Default::default(),
             tag_name_length: None,
             tag_name_starts_at: None,
             text_length: None,
@@ -2154,14 +4047,19 @@ impl Default for TagProcessor {
             text_starts_at: None,
             token_length: None,
             token_starts_at: None,
+            utf8_policy: Default::default(),
             compat_mode: Default::default(),
             bookmarks: HashMap::new(),
             internal_bookmarks: FxHashMap::default(),
+            modified_from: None,
+            #[cfg(feature = "std")]
+            input_stream: None,
         }
     }
 }
 
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ParserState {
     #[default]
     Ready,
@@ -2176,14 +4074,25 @@ pub enum ParserState {
     FunkyComment,
 }
 
+/// What kind of characters a text node contains.
+///
+/// @see WP_HTML_Tag_Processor::subdivide_text_appropriately()
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum TextNodeClassification {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TextNodeClassification {
+    /// Any text, which may contain anything, including whitespace.
     Generic,
+
+    /// Only NULL bytes, which are ignored in most places in an HTML document.
     NullSequence,
+
+    /// Only whitespace, including character references to whitespace,
+    /// e.g. the whitespace between elements.
     Whitespace,
 }
 
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CommentType {
     /**
      * Indicates that a comment was created when encountering abruptly-closed HTML comment.
@@ -2279,7 +4188,17 @@ impl From<&TokenType> for &str {
     }
 }
 
+/// Token types serialize to their token names, e.g. `#text`, matching `get_token_name()`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for TokenType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let name: &str = self.into();
+        serializer.serialize_str(name)
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 struct AttributeToken {
     // The byte length of the name.
     pub name_length: usize,
@@ -2319,6 +4238,169 @@ struct AttributeToken {
     pub is_true: bool,
 }
 
+/// An attribute on a tag, as it appears in the input HTML.
+///
+/// @see TagProcessor::attributes()
+#[derive(Debug, Clone, Copy)]
+pub struct Attribute<'a> {
+    name: &'a [u8],
+    raw_value: Option<&'a [u8]>,
+    utf8_policy: Utf8Policy,
+}
+
+impl<'a> Attribute<'a> {
+    /// Returns the name of the attribute as it's spelled in the input HTML.
+    ///
+    /// Attribute names are ASCII case-insensitive, so compare them with
+    /// `eq_ignore_ascii_case()`, e.g. `attribute.name().eq_ignore_ascii_case(b"href")`.
+    ///
+    /// @return string Attribute name.
+    pub fn name(&self) -> &'a [u8] {
+        self.name
+    }
+
+    /// Returns the attribute value as it's written in the input HTML, before decoding
+    /// character references, or `null` for an attribute without a value.
+    ///
+    /// @return string|null Raw attribute value.
+    pub fn raw_value(&self) -> Option<&'a [u8]> {
+        self.raw_value
+    }
+
+    /// Returns the decoded attribute value, as `get_attribute()` would for this attribute.
+    ///
+    /// @return string|true Decoded value, or `true` for an attribute without a value.
+    pub fn value(&self) -> AttributeValue {
+        match self.raw_value {
+            Some(raw_value) => {
+                AttributeValue::String(self.utf8_policy.apply(decoder::decode_attribute(raw_value)))
+            }
+            None => AttributeValue::BooleanTrue,
+        }
+    }
+}
+
+/// Iterates over the attributes of a tag.
+///
+/// The default iterator visits no attributes.
+///
+/// @see TagProcessor::attributes()
+#[derive(Debug, Clone, Default)]
+pub struct Attributes<'a> {
+    html: &'a [u8],
+    tokens: &'a [AttributeToken],
+    at: usize,
+    utf8_policy: Utf8Policy,
+}
+
+impl<'a> Attributes<'a> {
+    /// Returns the name of an attribute token in the input HTML.
+    fn name_of(&self, token: &AttributeToken) -> &'a [u8] {
+        &self.html[token.start..token.start + token.name_length]
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Attribute<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(token) = self.tokens.get(self.at) {
+            self.at += 1;
+
+            let name = self.name_of(token);
+            let is_duplicate = self.tokens[..self.at - 1]
+                .iter()
+                .any(|earlier| self.name_of(earlier).eq_ignore_ascii_case(name));
+            if is_duplicate {
+                continue;
+            }
+
+            return Some(Attribute {
+                name,
+                raw_value: (!token.is_true).then(|| {
+                    &self.html[token.value_starts_at..token.value_starts_at + token.value_length]
+                }),
+                utf8_policy: self.utf8_policy,
+            });
+        }
+
+        None
+    }
+}
+
+/// A copy of the matched token, which outlives the processor.
+///
+/// This collects what's known about a token into one value, e.g. to record a token stream
+/// or, with the `serde` feature, to dump one as JSON. Names serialize the way the PHP API
+/// reports them, attributes as a map from lowercase name to value, and byte strings as
+/// strings when they hold valid UTF-8.
+///
+/// Example:
+///
+///     $processor = new WP_HTML_Tag_Processor( '<img SRC=a.png hidden>' );
+///     $processor->next_token();
+///     // {"token_type":"#tag","name":"IMG","is_closer":false,
+///     //  "attributes":{"src":"a.png","hidden":true},"span":{"start":0,"end":22},
+///     //  "modifiable_text":""}
+///     serde_json::to_string( $processor->get_token_snapshot() );
+///
+/// @see TagProcessor::get_token_snapshot()
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TokenSnapshot {
+    /// What kind of token this is.
+    pub token_type: TokenType,
+
+    /// Tag name for tags, otherwise the token name, e.g. `#text`.
+    pub name: NodeName,
+
+    /// Whether the token is a tag closer, e.g. `</div>`.
+    pub is_closer: bool,
+
+    /// Decoded attributes of a tag opener, in document order, skipping duplicates.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::attributes")
+    )]
+    pub attributes: Vec<(Box<[u8]>, AttributeValue)>,
+
+    /// Byte range of the token in the input HTML, or `None` for tokens which aren't
+    /// found in it, e.g. elements implied by the HTML Processor. Streamed input is
+    /// partly discarded while parsing, so its offsets refer to the buffered input.
+    pub span: Option<core::ops::Range<usize>>,
+
+    /// Decoded modifiable text, e.g. the contents of a text node or comment.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::bytes"))]
+    pub modifiable_text: Box<[u8]>,
+}
+
+impl TokenSnapshot {
+    pub(crate) fn new(
+        token_type: TokenType,
+        name: NodeName,
+        is_closer: bool,
+        attributes: Attributes<'_>,
+        span: Option<HtmlSpan>,
+        modifiable_text: Box<[u8]>,
+    ) -> Self {
+        Self {
+            token_type,
+            name,
+            is_closer,
+            attributes: attributes
+                .map(|attribute| {
+                    (
+                        attribute.name().to_ascii_lowercase().into(),
+                        attribute.value(),
+                    )
+                })
+                .collect(),
+            span: span.map(|span| span.start..span.start + span.length),
+            modifiable_text,
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum ScriptState {
     Unescaped,
@@ -2345,17 +4427,1010 @@ mod test {
         assert_eq!(processor.get_token_name().unwrap(), TagName::P.into());
         assert!(processor.is_tag_closer());
     }
-}
-#[derive(PartialEq, Clone, Debug)]
-pub enum NodeName {
-    Tag(TagName),
-    Token(TokenType),
-}
-impl NodeName {
-    pub fn tag(&self) -> Option<&TagName> {
-        match self {
-            NodeName::Tag(tag_name) => Some(tag_name),
-            _ => None,
+
+    #[test]
+    fn test_empty_rcdata_at_end_of_document() {
+        for html in [
+            b"<textarea></textarea>".as_slice(),
+            b"<title></title>",
+            b"<iframe></iframe>",
+            b"<style></style >",
+        ] {
+            let mut processor = TagProcessor::new(html);
+            assert!(processor.next_token());
+            assert!(processor.get_modifiable_text().is_empty());
+            assert!(!processor.paused_at_incomplete_token());
+        }
+
+        let mut processor = TagProcessor::new(b"<textarea></textarea");
+        assert!(!processor.next_token());
+        assert!(processor.paused_at_incomplete_token());
+    }
+
+    #[test]
+    fn test_raw_names() {
+        let mut processor = TagProcessor::new(b"<DiV HREF=/ data-ID=3 href=x></dIv><!-- c -->");
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_tag(), Some(TagName::DIV));
+        assert_eq!(processor.get_tag_raw(), Some(b"DiV".as_slice()));
+        assert_eq!(
+            processor.get_attribute_name_raw(b"href"),
+            Some(b"HREF".as_slice())
+        );
+        assert_eq!(
+            processor.get_attribute_name_raw(b"DATA-id"),
+            Some(b"data-ID".as_slice())
+        );
+        assert_eq!(processor.get_attribute_name_raw(b"title"), None);
+
+        assert!(processor.next_token());
+        assert_eq!(processor.get_tag_raw(), Some(b"dIv".as_slice()));
+        assert_eq!(processor.get_attribute_name_raw(b"href"), None);
+
+        assert!(processor.next_token());
+        assert_eq!(processor.get_tag_raw(), None);
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_set_attribute() {
+        let mut processor = TagProcessor::new(b"<div ID=main hidden><p class='a'>");
+        assert!(processor.next_tag(None));
+        assert!(processor.set_attribute("id", "\"quoted\" & <escaped>"));
+        assert!(processor.set_attribute("title", "Tom's"));
+        assert!(processor.set_attribute("title", "Jerry's"));
+        assert!(!processor.set_attribute("bad name", "x"));
+        assert!(!processor.set_attribute("", "x"));
+
+        assert_eq!(
+            processor.get_attribute(b"id"),
+            Some(AttributeValue::String(
+                b"\"quoted\" & <escaped>".as_slice().into()
+            ))
+        );
+        assert_eq!(
+            processor.get_attribute(b"TITLE"),
+            Some(AttributeValue::String(b"Jerry's".as_slice().into()))
+        );
+
+        assert!(processor.next_tag(None));
+        assert_eq!(
+            processor.get_attribute(b"id"),
+            Some(AttributeValue::BooleanFalse)
+        );
+        assert!(processor.set_attribute("class", "b"));
+
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<div title=\"Jerry&#039;s\" id=\"&quot;quoted&quot; &amp; &lt;escaped&gt;\" hidden><p class=\"b\">"
+        );
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut processor = TagProcessor::new(b"<ul><li>One<li class=two>Two</ul>");
+        assert!(processor.next_tag(None));
+        assert!(processor.set_bookmark("list").is_ok());
+        assert!(processor.set_attribute("data-start", "1"));
+
+        assert!(processor.next_tag(None));
+        assert!(processor.next_tag(None));
+        assert!(processor.set_bookmark("second").is_ok());
+        assert!(processor.add_class("last"));
+
+        assert!(processor.seek("list"));
+        assert_eq!(processor.get_tag(), Some(TagName::UL));
+        assert_eq!(
+            processor.get_attribute(b"data-start"),
+            Some(AttributeValue::String(b"1".as_slice().into()))
+        );
+
+        assert!(processor.seek("second"));
+        assert_eq!(
+            processor.get_attribute(b"class"),
+            Some(AttributeValue::String(b"two last".as_slice().into()))
+        );
+        assert!(processor.remove_attribute("class"));
+
+        assert!(!processor.seek("missing"));
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<ul data-start=\"1\"><li>One<li >Two</ul>"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_reader() {
+        /// Reader which returns at most a few bytes on every read.
+        struct ChunkedReader {
+            html: &'static [u8],
+            chunk_size: usize,
+        }
+        impl std::io::Read for ChunkedReader {
+            fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+                let length = self.chunk_size.min(buffer.len()).min(self.html.len());
+                buffer[..length].copy_from_slice(&self.html[..length]);
+                self.html = &self.html[length..];
+                Ok(length)
+            }
+        }
+
+        fn tokens(mut processor: TagProcessor) -> Vec<String> {
+            let mut tokens = vec![];
+            while processor.next_token() {
+                tokens.push(format!(
+                    "{:?} {:?} {:?}",
+                    processor.get_token_type(),
+                    processor.get_token_name(),
+                    processor.get_modifiable_text()
+                ));
+            }
+            assert!(!processor.paused_at_incomplete_token());
+            tokens
+        }
+
+        let html: &[u8] = b"<!DOCTYPE html><pre>\nx</pre><p class=\"a b\">a <3 b</p><!-- c -->\
+            <script>if (a < b) {}</script><textarea>\n</textarea>tail <";
+        let expected = tokens(TagProcessor::new(html));
+        for chunk_size in 1..8 {
+            let reader = ChunkedReader { html, chunk_size };
+            assert_eq!(tokens(TagProcessor::from_reader(reader)), expected);
+        }
+
+        // A token spanning many reads waits for more input each time, instead of being
+        // reparsed after every small read.
+        let comment_length = 4 * 1024 * 1024;
+        let html = [b"<!--".as_slice(), &vec![b'x'; comment_length], b"--><p>"]
+            .concat()
+            .leak();
+        let mut processor = TagProcessor::from_reader(ChunkedReader {
+            html,
+            chunk_size: 4096,
+        });
+        assert!(processor.next_token());
+        assert_eq!(processor.get_token_type(), Some(&TokenType::Comment));
+        assert_eq!(processor.get_modifiable_text().len(), comment_length);
+        assert!(processor.chunks_read() < 40);
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+
+        // Processed input is discarded, unless a bookmark refers to it.
+        let html = b"<p>x</p>".repeat(100_000);
+        let mut processor = TagProcessor::from_reader(std::io::Cursor::new(html.clone()));
+        while processor.next_token() {}
+        assert!(processor.html_bytes.len() < 2 * READ_CHUNK_SIZE);
+
+        let mut processor = TagProcessor::from_reader(std::io::Cursor::new(html.clone()));
+        assert!(processor.next_tag(None));
+        assert!(processor.set_bookmark("first").is_ok());
+        while processor.next_token() {}
+        assert_eq!(processor.html_bytes.len(), html.len());
+        assert!(processor.seek("first"));
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+        assert!(processor.take_read_error().is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_reader_rejects_invalid_utf8() {
+        /// Reader which returns the given chunks, one per read.
+        struct ChunksReader(std::collections::VecDeque<&'static [u8]>);
+        impl std::io::Read for ChunksReader {
+            fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+                let chunk = self.0.pop_front().unwrap_or_default();
+                buffer[..chunk.len()].copy_from_slice(chunk);
+                Ok(chunk.len())
+            }
+        }
+
+        fn texts(chunks: &[&'static [u8]]) -> (Vec<Vec<u8>>, Option<std::io::ErrorKind>) {
+            let reader = ChunksReader(chunks.iter().copied().collect());
+            let mut processor = TagProcessor::from_reader(reader);
+            assert!(processor.set_utf8_policy(Utf8Policy::Reject));
+
+            let mut texts = vec![];
+            while processor.next_token() {
+                if processor.get_token_type() == Some(&TokenType::Text) {
+                    texts.push(processor.get_modifiable_text().to_vec());
+                }
+            }
+            (texts, processor.take_read_error().map(|error| error.kind()))
+        }
+
+        // "✓" is split across three chunks.
+        assert_eq!(
+            texts(&[b"<p>ok \xE2", b"\x9C", b"\x93</p>", b"<p>two</p>"]),
+            (vec![b"ok \xE2\x9C\x93".to_vec(), b"two".to_vec()], None)
+        );
+
+        // Invalid bytes in a later chunk end the document.
+        assert_eq!(
+            texts(&[b"<p>one</p><p>ok \xE2", b"\x9C\xFF</p>", b"<p>two</p>"]),
+            (vec![b"one".to_vec()], Some(std::io::ErrorKind::InvalidData))
+        );
+
+        // So does input which ends in the middle of a sequence.
+        assert_eq!(
+            texts(&[b"<p>one</p>\xE2", b"\x9C"]),
+            (vec![b"one".to_vec()], Some(std::io::ErrorKind::InvalidData))
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut processor = TagProcessor::new(b"<div><img><p>Text</p></div>");
+        assert!(processor.next_tag(None));
+        let snapshot = processor.snapshot();
+
+        assert!(processor.next_tag(None));
+        assert!(processor.set_attribute("alt", ""));
+        assert!(processor.set_bookmark("image").is_ok());
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+
+        assert!(processor.restore(snapshot));
+        assert_eq!(processor.get_tag(), Some(TagName::DIV));
+        assert!(processor.get_lexical_updates().is_empty());
+        assert!(!processor.seek("image"));
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_tag(), Some(TagName::IMG));
+        assert_eq!(
+            processor.get_attribute(b"alt"),
+            Some(AttributeValue::BooleanFalse)
+        );
+
+        // The document is shared with the snapshot until it's modified.
+        let snapshot = processor.snapshot();
+        let (HtmlBytes::Shared(html), HtmlBytes::Shared(snapshot_html)) =
+            (&processor.html_bytes, &snapshot.processor.html_bytes)
+        else {
+            panic!("Expected shared input.");
+        };
+        assert!(Arc::ptr_eq(html, snapshot_html));
+
+        assert!(processor.set_bookmark("image").is_ok());
+        assert!(processor.set_attribute("alt", ""));
+        assert!(processor.seek("image"));
+        assert!(
+            processor
+                .get_updated_html()
+                .starts_with(b"<div><img alt=\"\">")
+        );
+        assert!(processor.restore(snapshot));
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<div><img><p>Text</p></div>"
+        );
+
+        // Restoring a snapshot doesn't reset the count of seek operations.
+        assert!(processor.set_bookmark("image").is_ok());
+        let snapshot = processor.snapshot();
+        let mut seeks = 0;
+        while processor.seek("image") {
+            seeks += 1;
+        }
+        assert!(seeks > 0 && seeks <= MAX_SEEK_OPS);
+        assert!(processor.restore(snapshot));
+        assert!(!processor.seek("image"));
+
+        // Streamed input can't be rolled back past input which was read after the snapshot.
+        #[cfg(feature = "std")]
+        {
+            let mut processor =
+                TagProcessor::from_reader(std::io::Cursor::new(b"<p>".repeat(READ_CHUNK_SIZE)));
+            assert!(processor.next_tag(None));
+            let snapshot = processor.snapshot();
+            while processor.next_tag(None) {}
+            assert!(!processor.restore(snapshot));
+        }
+    }
+
+    #[test]
+    fn test_new_borrowed() {
+        let html = b"<p>One</p><p>Two</p>".to_vec();
+        let mut processor = TagProcessor::new_borrowed(&html);
+        assert!(processor.next_tag(None));
+        assert!(processor.set_bookmark("first").is_ok());
+        while processor.next_token() {}
+        assert!(processor.seek("first"));
+        assert!(matches!(processor.html_bytes, HtmlBytes::Borrowed(_)));
+
+        // Applying updates copies the document, leaving the input untouched.
+        assert!(processor.set_attribute("id", "one"));
+        assert!(processor.seek("first"));
+        assert!(matches!(processor.html_bytes, HtmlBytes::Shared(_)));
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<p id=\"one\">One</p><p>Two</p>"
+        );
+        assert_eq!(html, b"<p>One</p><p>Two</p>");
+    }
+
+    #[test]
+    fn test_enqueue_lexical_update() {
+        let mut processor = TagProcessor::new(b"<p>Hello</p><b>Bold</b><pre>\nx</pre>");
+        assert!(processor.next_tag(None));
+        assert!(processor.set_bookmark("p").is_ok());
+        assert!(processor.set_attribute("id", "a"));
+
+        // Replaces "Hello" and rejects overlapping or out-of-bounds spans.
+        assert!(processor.enqueue_lexical_update(HtmlTextReplacement::new(3, 5, b"Howdy!")));
+        assert!(!processor.enqueue_lexical_update(HtmlTextReplacement::new(4, 1, b"")));
+        assert!(!processor.enqueue_lexical_update(HtmlTextReplacement::new(30, 10, b"")));
+        assert_eq!(processor.get_lexical_updates().len(), 2);
+
+        // Updates ahead of the cursor are applied before parsing them.
+        assert!(processor.enqueue_lexical_update(HtmlTextReplacement::new(13, 1, b"em")));
+        assert!(processor.enqueue_lexical_update(HtmlTextReplacement::new(21, 1, b"em")));
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_tag(), Some(TagName::EM));
+        assert!(processor.get_lexical_updates().is_empty());
+
+        assert!(processor.next_token());
+        assert_eq!(processor.get_modifiable_text().as_ref(), b"Bold");
+
+        // The leading newline in the PRE element is still skipped after shifting.
+        assert!(processor.next_tag(Some(NextTagQuery {
+            tag_name: Some(TagName::PRE),
+            ..Default::default()
+        })));
+        assert!(processor.next_token());
+        assert_eq!(processor.get_modifiable_text().as_ref(), b"x");
+
+        assert!(processor.seek("p"));
+        assert_eq!(
+            processor.get_attribute(b"id"),
+            Some(AttributeValue::String(b"a".as_slice().into()))
+        );
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<p id=\"a\">Howdy!</p><em>Bold</em><pre>\nx</pre>"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_updates() {
+        // Attribute changes replace an enqueued span they overlap, as the later change.
+        let mut processor = TagProcessor::new(b"<p id=a title=b>x</p>");
+        assert!(processor.next_tag(None));
+        assert!(processor.enqueue_lexical_update(HtmlTextReplacement::new(3, 12, b"lang=en")));
+        assert!(processor.set_attribute("title", "c"));
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<p id=a title=\"c\">x</p>"
+        );
+        assert!(processor.next_token());
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<p id=a title=\"c\">x</p>"
+        );
+
+        // Enqueued spans which overlap pending class name changes are rejected.
+        let mut processor = TagProcessor::new(b"<p class=a id=b>x</p>");
+        assert!(processor.next_tag(None));
+        assert!(processor.add_class("c"));
+        assert!(!processor.enqueue_lexical_update(HtmlTextReplacement::new(3, 7, b"")));
+        assert!(processor.enqueue_lexical_update(HtmlTextReplacement::new(11, 4, b"")));
+        assert!(processor.next_token());
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<p class=\"a c\" >x</p>"
+        );
+    }
+
+    #[test]
+    fn test_many_updates_apply_in_one_pass() {
+        let html = "<li class=item>x</li>".repeat(3_000);
+        let mut processor = TagProcessor::new(html.as_bytes());
+        let mut count = 0;
+        while processor.next_tag(None) {
+            count += 1;
+            assert!(processor.set_attribute("data-n", &count.to_string()));
+            assert!(processor.set_attribute("data-n", &(count * 10).to_string()));
+            if count % 2 == 0 {
+                assert!(processor.add_class("even"));
+            } else {
+                assert!(processor.remove_attribute("class"));
+            }
+        }
+
+        // Repeated updates to an attribute replace each other in the queue.
+        assert_eq!(processor.get_lexical_updates().len(), 2 * 3_000);
+
+        let expected: String = (1..=3_000)
+            .map(|n| {
+                if n % 2 == 0 {
+                    format!("<li data-n=\"{}\" class=\"item even\">x</li>", n * 10)
+                } else {
+                    format!("<li data-n=\"{}\" >x</li>", n * 10)
+                }
+            })
+            .collect();
+        assert_eq!(
+            core::str::from_utf8(&processor.get_updated_html()).unwrap(),
+            expected
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_attributes() {
+        let mut processor =
+            TagProcessor::new(b"<img SRC=\"a.png\" alt='A &amp; B' hidden src=b.png></img>");
+        assert!(processor.next_tag(None));
+        assert!(processor.set_attribute("alt", "Changed"));
+
+        let attributes: Vec<_> = processor
+            .attributes()
+            .map(|attribute| (attribute.name(), attribute.raw_value(), attribute.value()))
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![
+                (
+                    b"SRC".as_slice(),
+                    Some(b"a.png".as_slice()),
+                    AttributeValue::String(b"a.png".as_slice().into())
+                ),
+                (
+                    b"alt",
+                    Some(b"A &amp; B"),
+                    AttributeValue::String(b"A & B".as_slice().into())
+                ),
+                (b"hidden", None, AttributeValue::BooleanTrue),
+            ]
+        );
+
+        assert!(processor.next_token());
+        assert!(processor.is_tag_closer());
+        assert_eq!(processor.attributes().count(), 0);
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_cached_attribute_values() {
+        let mut processor =
+            TagProcessor::new(b"<p class='a&amp;b c' title=&lt;x&gt;><p class=d title=y>");
+        assert!(processor.next_tag(None));
+        for _ in 0..2 {
+            assert_eq!(processor.has_class("a&b"), Some(true));
+            assert_eq!(
+                processor.get_attribute(b"title"),
+                Some(AttributeValue::String(b"<x>".as_slice().into()))
+            );
+        }
+        assert_eq!(processor.attribute_cache.borrow().values.len(), 2);
+
+        // Updates are read back instead of the cached values.
+        assert!(processor.remove_class("c"));
+        assert_eq!(processor.has_class("c"), Some(false));
+        assert!(processor.set_attribute("title", "z"));
+        assert_eq!(
+            processor.get_attribute(b"title"),
+            Some(AttributeValue::String(b"z".as_slice().into()))
+        );
+
+        // The cache only lasts while on the same tag.
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.has_class("a&b"), Some(false));
+        assert_eq!(processor.has_class("d"), Some(true));
+        assert_eq!(
+            processor.get_attribute(b"title"),
+            Some(AttributeValue::String(b"y".as_slice().into()))
+        );
+    }
+
+    #[test]
+    fn test_attribute_names_are_interned() {
+        let mut processor = TagProcessor::new(b"<a HREF=x data-a=1><a href=y DATA-A=2>");
+        assert!(processor.next_tag(None));
+        let first = processor
+            .get_interned_attribute_names_with_prefix(b"")
+            .unwrap();
+        assert!(processor.next_tag(None));
+        let second = processor
+            .get_interned_attribute_names_with_prefix(b"")
+            .unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(first[0].as_ref(), b"href");
+        assert_eq!(second[1].as_ref(), b"data-a");
+        assert!(first.iter().zip(&second).all(|(a, b)| Arc::ptr_eq(a, b)));
+        assert_eq!(
+            processor.get_attribute_names_with_prefix(b"").unwrap(),
+            vec![
+                Box::from(b"href".as_slice()),
+                Box::from(b"data-a".as_slice())
+            ]
+        );
+
+        // Endless distinct names don't grow the table without bound.
+        let html: String = (0..10_000).map(|i| format!("<p data-{i}>")).collect();
+        let mut processor = TagProcessor::new(html.as_bytes());
+        while processor.next_tag(None) {
+            assert!(
+                processor
+                    .get_interned_attribute_names_with_prefix(b"")
+                    .is_some()
+            );
+        }
+        assert!(processor.attribute_name_interner.len() < 10_000);
+    }
+
+    #[test]
+    fn test_attribute_names_reflect_enqueued_updates() {
+        let mut processor =
+            TagProcessor::new(b"<div class=a data-x DATA-X data-y data-w=1 id=main>");
+        assert!(processor.next_tag(None));
+        assert!(processor.remove_attribute("data-x"));
+        assert!(processor.set_attribute("DATA-Z", "1"));
+        assert!(processor.set_attribute("data-w", "2"));
+        assert!(processor.remove_class("a"));
+        assert!(processor.add_class("b"));
+
+        let names = |processor: &TagProcessor, prefix: &[u8]| {
+            processor
+                .get_attribute_names_with_prefix(prefix)
+                .unwrap()
+                .into_iter()
+                .map(|name| String::from_utf8(name.into_vec()).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&processor, b"data-"), ["data-z", "data-y", "data-w"]);
+        assert_eq!(
+            names(&processor, b""),
+            ["data-z", "class", "data-y", "data-w", "id"]
+        );
+
+        assert!(processor.remove_class("b"));
+        assert_eq!(names(&processor, b""), ["data-z", "data-y", "data-w", "id"]);
+    }
+
+    #[test]
+    fn test_remove_attribute() {
+        let mut processor =
+            TagProcessor::new(b"<input type=text disabled TYPE=checkbox value='x'></input>");
+        assert!(processor.next_tag(None));
+        assert!(processor.remove_attribute("type"));
+        assert!(processor.remove_attribute("type"));
+        assert!(!processor.remove_attribute("missing"));
+        assert_eq!(
+            processor.get_attribute(b"type"),
+            Some(AttributeValue::BooleanFalse)
+        );
+
+        // Removing an attribute which was only just added drops the addition.
+        assert!(processor.set_attribute("name", "n"));
+        assert!(!processor.remove_attribute("name"));
+        assert_eq!(
+            processor.get_attribute(b"name"),
+            Some(AttributeValue::BooleanFalse)
+        );
+
+        assert!(processor.set_attribute("value", "y"));
+        assert!(processor.remove_attribute("VALUE"));
+
+        assert!(processor.next_tag(Some(NextTagQuery {
+            tag_closers: TagClosers::Visit,
+            ..Default::default()
+        })));
+        assert!(!processor.remove_attribute("type"));
+        assert!(!processor.set_attribute("type", "text"));
+
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<input  disabled  ></input>"
+        );
+    }
+
+    #[test]
+    fn test_add_and_remove_class() {
+        let mut processor =
+            TagProcessor::new(b"<div class=\"one\ttwo  one three\"><p CLASS=a class=b><span>");
+        assert!(processor.next_tag(None));
+        assert!(processor.add_class("four"));
+        assert!(processor.remove_class("two"));
+        assert!(processor.add_class("one"));
+        assert_eq!(processor.has_class("four"), Some(true));
+        assert_eq!(processor.has_class("two"), Some(false));
+        assert_eq!(
+            processor.get_attribute(b"class"),
+            Some(AttributeValue::String(b"one three four".as_slice().into()))
+        );
+
+        assert!(processor.next_tag(None));
+        assert!(processor.remove_class("a"));
+
+        assert!(processor.next_tag(None));
+        assert!(processor.add_class("x"));
+        assert!(processor.remove_class("x"));
+        assert!(processor.add_class("y"));
+
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<div class=\"one three four\"><p  ><span class=\"y\">"
+        );
+    }
+
+    #[test]
+    fn test_set_attribute_overrides_class_updates() {
+        let mut processor = TagProcessor::new(b"<div class=a>");
+        assert!(processor.next_tag(None));
+        assert!(processor.add_class("b"));
+        assert!(processor.set_attribute("class", "c"));
+        assert!(processor.add_class("d"));
+
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<div class=\"c d\">"
+        );
+    }
+
+    #[test]
+    fn test_class_names_in_quirks_mode() {
+        let mut processor = TagProcessor::new(b"<div class=\"One two\">");
+        processor.compat_mode = CompatMode::Quirks;
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.has_class("one"), Some(true));
+        assert!(processor.remove_class("ONE"));
+        assert!(processor.add_class("Two"));
+        assert!(processor.add_class("three"));
+        assert!(processor.add_class("THREE"));
+
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<div class=\"two three\">"
+        );
+    }
+
+    #[test]
+    fn test_class_list() {
+        let mut class_list = ClassList::new(b" a\tb  a c\0 ".as_slice().into());
+        assert_eq!(
+            class_list.iter().map(|c| c.as_ref()).collect::<Vec<_>>(),
+            vec![b"a".as_slice(), b"b", "c\u{FFFD}".as_bytes()]
+        );
+        assert!(class_list.contains(b"b"));
+        assert!(!class_list.contains(b"B"));
+
+        assert!(class_list.add(b"d"));
+        assert!(class_list.add(b"a"));
+        assert!(!class_list.add(b""));
+        assert!(!class_list.add(b"e f"));
+        assert!(class_list.remove(b"b"));
+        assert!(!class_list.remove(b"b"));
+        assert!(!class_list.toggle(b"a"));
+        assert!(class_list.toggle(b"a"));
+
+        assert_eq!(class_list.len(), 3);
+        assert_eq!(class_list.serialize().as_ref(), "c\u{FFFD} d a".as_bytes());
+    }
+
+    #[test]
+    fn test_set_class_list() {
+        let mut processor = TagProcessor::new(b"<div class=\"wp-block  is-large\"><p class=x>");
+        assert!(processor.next_tag(None));
+        let mut class_list = processor.class_list();
+        assert!(!class_list.toggle(b"is-large"));
+        assert!(class_list.add(b"is-small"));
+        assert!(processor.set_class_list(&class_list));
+        assert_eq!(processor.class_list(), class_list);
+
+        assert!(processor.next_tag(None));
+        let mut class_list = processor.class_list();
+        class_list.remove(b"x");
+        assert!(processor.set_class_list(&class_list));
+        assert_eq!(
+            processor.get_attribute(b"class"),
+            Some(AttributeValue::BooleanFalse)
+        );
+
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<div class=\"wp-block is-small\"><p >"
+        );
+    }
+
+    #[test]
+    fn test_attribute_value_conversions() {
+        let mut processor = TagProcessor::new(b"<input disabled value=\"caf\xC3\xA9 \xFF\">");
+        assert!(processor.next_tag(None));
+
+        let missing = processor.get_attribute(b"title").unwrap();
+        assert_eq!(missing.as_bytes(), None);
+        assert!(!missing.is_truthy());
+        assert_eq!(missing.to_string(), "");
+
+        let disabled = processor.get_attribute(b"disabled").unwrap();
+        assert_eq!(disabled.as_bytes(), Some(b"".as_slice()));
+        assert!(disabled.is_truthy());
+
+        let value = processor.get_attribute(b"value").unwrap();
+        assert_eq!(value.as_str_lossy().as_deref(), Some("café \u{FFFD}"));
+        assert_eq!(value.to_string(), "café \u{FFFD}");
+        assert_eq!(
+            Option::<String>::from(value),
+            Some(String::from("café \u{FFFD}"))
+        );
+        assert_eq!(Option::<String>::from(missing), None);
+    }
+
+    #[test]
+    fn test_reset_with() {
+        let mut processor = TagProcessor::new(b"<div class=a><custom-element>");
+        assert!(processor.next_tag(None));
+        assert!(processor.add_class("b"));
+        processor.set_bookmark("div").unwrap();
+        assert!(processor.next_tag(None));
+        processor.compat_mode = CompatMode::Quirks;
+
+        assert!(processor.reset_with(b"<p>Hi<custom-element id=x>"));
+        assert!(!processor.has_bookmark("div"));
+        assert_eq!(processor.compat_mode, CompatMode::NoQuirks);
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            b"<p>Hi<custom-element id=x>"
+        );
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_tag(), Some(TagName::P));
+        assert!(processor.next_tag(None));
+        assert_eq!(
+            processor.get_attribute(b"id").unwrap().as_bytes(),
+            Some(b"x".as_slice())
+        );
+        assert!(!processor.next_token());
+
+        // The UTF-8 policy applies to the new document.
+        let mut processor = TagProcessor::new_borrowed(b"<p>");
+        assert!(processor.set_utf8_policy(Utf8Policy::Reject));
+        assert!(!processor.reset_with(b"<p>\xFF"));
+        assert!(!processor.next_token());
+        assert!(processor.reset_with(b"<p>"));
+        assert!(processor.next_tag(None));
+    }
+
+    #[test]
+    fn test_next_text_comment_and_doctype() {
+        let html = b"<!DOCTYPE html><p>One <!-- a --><b>two</b></p></%b>";
+
+        let mut processor = TagProcessor::new(html);
+        let mut texts = Vec::new();
+        while processor.next_text() {
+            texts.push(processor.get_modifiable_text());
+        }
+        assert_eq!(
+            texts,
+            vec![b"One ".as_slice().into(), b"two".as_slice().into()]
+        );
+
+        let mut processor = TagProcessor::new(html);
+        assert!(processor.next_comment());
+        assert_eq!(processor.get_modifiable_text().as_ref(), b" a ");
+        assert!(processor.next_comment());
+        assert_eq!(processor.get_token_type(), Some(&TokenType::FunkyComment));
+        assert!(!processor.next_comment());
+
+        let mut processor = TagProcessor::new(html);
+        assert!(processor.next_doctype());
+        assert!(!processor.next_doctype());
+    }
+
+    #[test]
+    fn test_get_text_node_classification() {
+        let mut processor = TagProcessor::new(b"\0\0 &#x0A;\tText<p>");
+        assert!(processor.next_token());
+        assert_eq!(
+            processor.get_text_node_classification(),
+            Some(&TextNodeClassification::Generic)
+        );
+        assert!(processor.subdivide_text_appropriately());
+        assert_eq!(
+            processor.get_text_node_classification(),
+            Some(&TextNodeClassification::NullSequence)
+        );
+
+        assert!(processor.next_token());
+        assert!(processor.subdivide_text_appropriately());
+        assert_eq!(
+            processor.get_text_node_classification(),
+            Some(&TextNodeClassification::Whitespace)
+        );
+
+        assert!(processor.next_token());
+        assert!(!processor.subdivide_text_appropriately());
+        assert_eq!(
+            processor.get_text_node_classification(),
+            Some(&TextNodeClassification::Generic)
+        );
+
+        assert!(processor.next_token());
+        assert_eq!(processor.get_text_node_classification(), None);
+    }
+
+    #[test]
+    fn test_attribute_starts_with() {
+        use CaseSensitivity::*;
+
+        let mut processor = TagProcessor::new(
+            b"<img SRC=\"&#x68;ttps://example.com/a.jpg\" loading class=\"wp-image-1\" alt=x>",
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"https:", CaseSensitive),
+            None
+        );
+        assert!(processor.next_tag(None));
+
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"https:", CaseSensitive),
+            Some(true)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"HTTPS:", CaseSensitive),
+            Some(false)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"HTTPS:", AsciiCaseInsensitive),
+            Some(true)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"data:", CaseSensitive),
+            Some(false)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"loading", b"", CaseSensitive),
+            Some(true)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"loading", b"l", CaseSensitive),
+            Some(false)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"missing", b"", CaseSensitive),
+            Some(false)
+        );
+
+        // Enqueued updates are compared instead of the input HTML.
+        assert!(processor.set_attribute("src", "data:image/png;base64,"));
+        assert!(processor.add_class("size-full"));
+        assert!(processor.remove_attribute("alt"));
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"data:", CaseSensitive),
+            Some(true)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"https:", CaseSensitive),
+            Some(false)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"class", b"WP-IMAGE-1 SIZE", AsciiCaseInsensitive),
+            Some(true)
+        );
+        assert_eq!(
+            processor.attribute_starts_with(b"alt", b"", CaseSensitive),
+            Some(false)
+        );
+
+        let mut processor = TagProcessor::new(b"</img src=x>");
+        assert!(processor.next_tag(Some(NextTagQuery {
+            tag_closers: TagClosers::Visit,
+            ..Default::default()
+        })));
+        assert_eq!(
+            processor.attribute_starts_with(b"src", b"", CaseSensitive),
+            None
+        );
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_attribute_starts_with_agrees_with_get_attribute() {
+        use CaseSensitivity::*;
+
+        let raw_values: [&[u8]; 8] = [
+            b"&#38x",
+            b"&#x26=1",
+            b"&#106avascript:x",
+            b"&copy=2",
+            b"&copy 2",
+            b"&copyx",
+            b"&notin",
+            b"?a=1&amp;b=2&not=3",
+        ];
+        for raw_value in raw_values {
+            let html = [b"<a href='", raw_value, b"'>"].concat();
+            let mut processor = TagProcessor::new(&html);
+            assert!(processor.next_tag(None));
+            let Some(AttributeValue::String(value)) = processor.get_attribute(b"href") else {
+                panic!("Expected a string value for {:?}", raw_value);
+            };
+
+            // A character reference only matches in its entirety, so compare whole characters.
+            let text = core::str::from_utf8(&value).unwrap();
+            for length in (0..=value.len()).filter(|&at| text.is_char_boundary(at)) {
+                assert_eq!(
+                    processor.attribute_starts_with(b"href", &value[..length], CaseSensitive),
+                    Some(true),
+                    "{:?} should start with {:?}",
+                    raw_value,
+                    &value[..length]
+                );
+            }
+            let longer = [&value[..], b"!"].concat();
+            assert_eq!(
+                processor.attribute_starts_with(b"href", &longer, CaseSensitive),
+                Some(false)
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_token_html() {
+        let html =
+            b"<img  src=a.png ALT=\"A\"><!-- note -->&amp; text<script>if (a<b) {}</script></P>";
+        let mut processor = TagProcessor::new(html);
+        assert_eq!(processor.get_token_html(), None);
+
+        let mut tokens = Vec::new();
+        while processor.next_token() {
+            tokens.push(processor.get_token_html().unwrap().to_vec());
+        }
+        assert_eq!(
+            tokens,
+            vec![
+                b"<img  src=a.png ALT=\"A\">".to_vec(),
+                b"<!-- note -->".to_vec(),
+                b"&amp; text".to_vec(),
+                b"<script>if (a<b) {}</script>".to_vec(),
+                b"</P>".to_vec(),
+            ]
+        );
+        assert_eq!(tokens.concat(), html);
+
+        // Enqueued updates don't change the raw token.
+        let mut processor = TagProcessor::new(b"<div id=a>");
+        assert!(processor.next_tag(None));
+        assert!(processor.set_attribute("id", "b"));
+        assert_eq!(processor.get_token_html(), Some(b"<div id=a>".as_slice()));
+    }
+
+    #[test]
+    fn test_write_updated_html() {
+        let mut processor = TagProcessor::new(b"<div class=a id=x><p title=\"\xFF\">text</p>");
+        assert!(processor.next_tag(None));
+        assert!(processor.add_class("b"));
+        assert!(processor.set_attribute("data-new", "1"));
+        assert!(processor.next_tag(None));
+        assert!(processor.remove_attribute("title"));
+        assert!(processor.add_class("c"));
+
+        let mut written = String::new();
+        processor.write_updated_html_fmt(&mut written).unwrap();
+        assert_eq!(
+            written,
+            String::from_utf8_lossy(&processor.get_updated_html())
+        );
+
+        #[cfg(feature = "std")]
+        {
+            let mut written = Vec::new();
+            processor.write_updated_html(&mut written).unwrap();
+            assert_eq!(written.as_slice(), processor.get_updated_html().as_ref());
+        }
+    }
+}
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum NodeName {
+    Tag(TagName),
+    Token(TokenType),
+}
+impl NodeName {
+    pub fn tag(&self) -> Option<&TagName> {
+        match self {
+            NodeName::Tag(tag_name) => Some(tag_name),
+            _ => None,
         }
     }
 }
@@ -2378,79 +5453,236 @@ pub enum AttributeValue {
     String(Box<[u8]>),
 }
 
+/// Attribute values serialize like their PHP counterparts: `false`, `true`, or a string.
+#[cfg(feature = "serde")]
+impl serde::Serialize for AttributeValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            AttributeValue::BooleanFalse => serializer.serialize_bool(false),
+            AttributeValue::BooleanTrue => serializer.serialize_bool(true),
+            AttributeValue::String(value) => crate::serialize::bytes(value, serializer),
+        }
+    }
+}
+
+impl AttributeValue {
+    /// Returns the value of the attribute as the DOM would report it.
+    ///
+    /// Boolean attributes, e.g. `<input disabled>`, have an empty value.
+    ///
+    /// @return string|null Decoded attribute value, or `null` if the attribute is missing.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            AttributeValue::BooleanFalse => None,
+            AttributeValue::BooleanTrue => Some(b""),
+            AttributeValue::String(value) => Some(value),
+        }
+    }
+
+    /// Returns the value of the attribute as a string, replacing invalid UTF-8.
+    ///
+    /// @see self::as_bytes()
+    ///
+    /// @return string|null Decoded attribute value, or `null` if the attribute is missing.
+    pub fn as_str_lossy(&self) -> Option<Cow<'_, str>> {
+        self.as_bytes().map(String::from_utf8_lossy)
+    }
+
+    /// Indicates if the attribute is present on the tag.
+    ///
+    /// Unlike PHP's truthiness, empty and "0" values are truthy: boolean attributes
+    /// like `disabled` are enabled by their presence alone.
+    ///
+    /// @return bool Whether the attribute is present.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, AttributeValue::BooleanFalse)
+    }
+}
+
+impl From<AttributeValue> for Option<String> {
+    fn from(value: AttributeValue) -> Self {
+        value.as_str_lossy().map(Cow::into_owned)
+    }
+}
+
+impl From<AttributeValue> for Option<Box<[u8]>> {
+    fn from(value: AttributeValue) -> Self {
+        match value {
+            AttributeValue::BooleanFalse => None,
+            AttributeValue::BooleanTrue => Some(Box::default()),
+            AttributeValue::String(value) => Some(value),
+        }
+    }
+}
+
+/// Attribute values display as the DOM would report them, with missing attributes empty.
+impl core::fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.as_str_lossy().unwrap_or_default())
+    }
+}
+
+/// The class names of a tag, in order and without duplicates.
+///
+/// Class names are separated by ASCII whitespace. They are compared byte-for-byte,
+/// except in quirks mode where they are compared ASCII case-insensitively.
+///
+/// A class list is a snapshot of the class attribute; changes are written back to
+/// the tag with `set_class_list()`.
+///
+/// @see https://dom.spec.whatwg.org/#interface-domtokenlist
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ClassList {
-    attribute_value: Box<[u8]>,
-    seen: BTreeSet<Box<[u8]>>,
-    at: usize,
+    class_names: Vec<Box<[u8]>>,
+    is_quirks: bool,
 }
 
 impl ClassList {
+    /// Creates a class list from a decoded class attribute value.
     pub fn new(attribute_value: Box<[u8]>) -> Self {
-        let mut s = Self {
-            attribute_value,
-            seen: BTreeSet::new(),
-            at: 0,
+        Self::parse(&attribute_value, false)
+    }
+
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn parse(attribute_value: &[u8], is_quirks: bool) -> Self {
+        let mut class_list = Self {
+            class_names: Vec::new(),
+            is_quirks,
         };
 
-        // Start by skipping whitespace.
-        s.at = strspn!(s.attribute_value, b' ' | b'\t' | 0x0c | b'\r' | b'\n', s.at);
+        let mut at = 0;
+        loop {
+            at += strspn!(attribute_value, b' ' | b'\t' | 0x0c | b'\r' | b'\n', at);
+            if at >= attribute_value.len() {
+                break;
+            }
 
-        s
+            // Find the byte length until the next boundary.
+            let len = strcspn!(attribute_value, b' ' | b'\t' | 0x0c | b'\r' | b'\n', at);
+            let class_name: Vec<u8> = attribute_value[at..at + len]
+                .iter()
+                .flat_map(|&byte| {
+                    if byte == 0x00 {
+                        b"\xEF\xBF\xBD".to_vec()
+                    } else {
+                        vec![byte]
+                    }
+                })
+                .collect();
+            at += len;
+
+            if !class_list.contains(&class_name) {
+                class_list.class_names.push(class_name.into());
+            }
+        }
+
+        class_list
     }
 
-    pub fn empty() -> Self {
-        Self {
-            attribute_value: Box::new([]),
-            at: 0,
-            seen: BTreeSet::new(),
+    /// Indicates if the class list contains the given class name.
+    pub fn contains(&self, class_name: &[u8]) -> bool {
+        self.position(class_name).is_some()
+    }
+
+    /// Appends a class name unless it's already in the list.
+    ///
+    /// Returns `false` if the class name is empty or contains whitespace, which
+    /// would make it into more than one class name.
+    ///
+    /// @param string $class_name Class name to add.
+    /// @return bool Whether the class name is valid.
+    pub fn add(&mut self, class_name: &[u8]) -> bool {
+        if !Self::is_valid_class_name(class_name) {
+            return false;
         }
+
+        if !self.contains(class_name) {
+            self.class_names.push(class_name.into());
+        }
+        true
     }
-}
 
-impl Iterator for ClassList {
-    type Item = Box<[u8]>;
+    /// Removes a class name from the list.
+    ///
+    /// @param string $class_name Class name to remove.
+    /// @return bool Whether the class name was in the list.
+    pub fn remove(&mut self, class_name: &[u8]) -> bool {
+        match self.position(class_name) {
+            Some(at) => {
+                self.class_names.remove(at);
+                true
+            }
+            None => false,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.at >= self.attribute_value.len() {
-            return None;
+    /// Removes a class name if it's in the list, otherwise appends it.
+    ///
+    /// @param string $class_name Class name to toggle.
+    /// @return bool Whether the class name is in the list afterwards.
+    pub fn toggle(&mut self, class_name: &[u8]) -> bool {
+        if self.remove(class_name) {
+            false
+        } else {
+            self.add(class_name)
         }
+    }
 
-        // Find the byte length until the next boundary.
-        let len = strcspn!(
-            self.attribute_value,
-            b' ' | b'\t' | 0x0c | b'\r' | b'\n',
-            self.at
-        );
+    pub fn len(&self) -> usize {
+        self.class_names.len()
+    }
 
-        let value = &self.attribute_value[self.at..self.at + len];
+    pub fn is_empty(&self) -> bool {
+        self.class_names.is_empty()
+    }
 
-        // Move past trailing whitespace.
-        self.at += len
-            + strspn!(
-                self.attribute_value,
-                b' ' | b'\t' | 0x0c | b'\r' | b'\n',
-                self.at + len
-            );
+    pub fn iter(&self) -> core::slice::Iter<'_, Box<[u8]>> {
+        self.class_names.iter()
+    }
 
-        let value: Vec<u8> = value
-            .iter()
-            .flat_map(|&byte| {
-                if byte == 0x00 {
-                    b"\xEF\xBF\xBD".to_vec()
-                } else {
-                    vec![byte]
-                }
-            })
-            .collect();
+    /// Serializes the class names for the class attribute, separated by a single space.
+    ///
+    /// @return string Value for the class attribute, not yet HTML-encoded.
+    pub fn serialize(&self) -> Box<[u8]> {
+        self.class_names.join(b" ".as_slice()).into()
+    }
 
-        let value = value.into_boxed_slice();
+    fn position(&self, class_name: &[u8]) -> Option<usize> {
+        self.class_names.iter().position(|existing| {
+            if self.is_quirks {
+                existing.eq_ignore_ascii_case(class_name)
+            } else {
+                existing.as_ref() == class_name
+            }
+        })
+    }
 
-        if self.seen.contains(&value) {
-            self.next()
-        } else {
-            self.seen.insert(value.clone());
-            Some(value)
-        }
+    fn is_valid_class_name(class_name: &[u8]) -> bool {
+        !class_name.is_empty()
+            && !class_name
+                .iter()
+                .any(|c| matches!(c, b' ' | b'\t' | 0x0c | b'\r' | b'\n'))
+    }
+}
+
+impl IntoIterator for ClassList {
+    type Item = Box<[u8]>;
+    type IntoIter = vec::IntoIter<Box<[u8]>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.class_names.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ClassList {
+    type Item = &'a Box<[u8]>;
+    type IntoIter = core::slice::Iter<'a, Box<[u8]>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.class_names.iter()
     }
 }
 
@@ -0,0 +1,309 @@
+//! Extracts the text of a document as it would appear when rendered, similar to `innerText`.
+//!
+//! Browsers compute `innerText` from the rendered layout, which depends on CSS. Without
+//! a style sheet, this approximation assumes the default display of each element:
+//!
+//!  - Runs of whitespace collapse to a single space, and whitespace at the start and
+//!    end of lines is removed, except inside PRE, LISTING, TEXTAREA, and XMP elements.
+//!  - Block elements start and end on their own lines. P elements are separated from
+//!    surrounding text by a blank line. BR elements break the line.
+//!  - Table cells in a row are separated by tabs, and each row starts a new line.
+//!  - The HEAD element, and SCRIPT, STYLE, TEMPLATE, NOSCRIPT, IFRAME, and elements with
+//!    the `hidden` attribute aren't rendered, so their text is left out.
+//!  - Character references are decoded.
+//!
+//! Example:
+//!
+//!     "Title\n\nOne two\nthree" == extract_text( b"<h1>Title</h1><p>One\n  two<br>three<script>x()</script>" );
+//!
+//! @see https://html.spec.whatwg.org/#the-innertext-idl-attribute
+//! @see https://drafts.csswg.org/css-text/#white-space-phase-2
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{
+    html_processor::HtmlProcessor,
+    tag_name::TagName,
+    tag_processor::{AttributeValue, ParserState, ParsingNamespace, TokenType},
+};
+
+/// Collects rendered text, collapsing whitespace and line breaks as it goes.
+#[derive(Default)]
+struct TextWriter {
+    output: Vec<u8>,
+
+    /// Number of line breaks required before the next text, as for `innerText`.
+    pending_breaks: usize,
+
+    /// Whether a tab separates the next text from the previous table cell.
+    pending_tab: bool,
+
+    /// Whether collapsible whitespace separates the next text from the previous text.
+    pending_space: bool,
+}
+
+impl TextWriter {
+    /// Requires at least the given number of line breaks before any following text.
+    fn require_breaks(&mut self, count: usize) {
+        self.pending_breaks = self.pending_breaks.max(count);
+    }
+
+    /// Writes whatever separates the previous text from the next.
+    fn write_separator(&mut self) {
+        if !self.output.is_empty() {
+            if self.pending_breaks > 0 {
+                self.output
+                    .extend(core::iter::repeat_n(b'\n', self.pending_breaks));
+            } else if self.pending_tab {
+                self.output.push(b'\t');
+            } else if self.pending_space {
+                self.output.push(b' ');
+            }
+        }
+
+        self.pending_breaks = 0;
+        self.pending_tab = false;
+        self.pending_space = false;
+    }
+
+    /// Writes text whose whitespace collapses.
+    fn write_text(&mut self, text: &[u8]) {
+        for (i, word) in text.split(u8::is_ascii_whitespace).enumerate() {
+            // Each split is at a whitespace character.
+            if i > 0 {
+                self.pending_space = true;
+            }
+            if word.is_empty() {
+                continue;
+            }
+
+            self.write_separator();
+            self.output.extend_from_slice(word);
+        }
+    }
+
+    /// Writes text whose whitespace is preserved.
+    fn write_preformatted(&mut self, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
+
+        self.write_separator();
+        self.output.extend_from_slice(text);
+    }
+
+    /// Writes a forced line break, as from a BR element.
+    fn write_line_break(&mut self) {
+        if self.pending_breaks > 0 && !self.output.is_empty() {
+            self.write_separator();
+        }
+
+        self.pending_breaks = 0;
+        self.pending_tab = false;
+        self.pending_space = false;
+        self.output.push(b'\n');
+    }
+}
+
+/// Extracts the rendered text of an HTML document.
+///
+/// @param string $html Input HTML.
+/// @return string|null Rendered text, or `null` if the document contains unsupported markup.
+pub fn extract_text(html: &[u8]) -> Option<Box<[u8]>> {
+    let mut processor = HtmlProcessor::create_full_parser(html, "UTF-8")?;
+    extract_from_processor(&mut processor)
+}
+
+/// Extracts the rendered text of the HTML in a processor which hasn't started processing.
+///
+/// The processor will be in the completed state afterwards.
+///
+/// @param HtmlProcessor $processor Processor for the HTML, immediately after creating it.
+/// @return string|null Rendered text, or `null` if the processor had already started
+///                     processing or if it encountered unsupported markup.
+pub fn extract_from_processor(processor: &mut HtmlProcessor) -> Option<Box<[u8]>> {
+    if processor.tag_processor.parser_state != ParserState::Ready {
+        return None;
+    }
+
+    let mut writer = TextWriter::default();
+
+    // Depth of the PRE, LISTING, or PLAINTEXT element whose text is preserved, if any.
+    let mut preformatted_depth: Option<usize> = None;
+
+    while processor.next_token() {
+        let depth = processor.get_current_depth();
+        if preformatted_depth.is_some_and(|pre_depth| depth < pre_depth) {
+            preformatted_depth = None;
+        }
+
+        match processor.get_token_type() {
+            Some(TokenType::Text) => {
+                let text = processor.get_modifiable_text();
+                if preformatted_depth.is_some() {
+                    writer.write_preformatted(&text);
+                } else {
+                    writer.write_text(&text);
+                }
+            }
+
+            Some(TokenType::CdataSection) => {
+                writer.write_text(&processor.get_modifiable_text());
+            }
+
+            Some(TokenType::Tag) if *processor.get_namespace() == ParsingNamespace::Html => {
+                let Some(tag) = processor.get_tag() else {
+                    continue;
+                };
+                let is_closer = processor.is_tag_closer();
+
+                if !is_closer
+                    && (matches!(tag, TagName::HEAD | TagName::TEMPLATE | TagName::NOSCRIPT)
+                        || matches!(
+                            processor.get_attribute(b"hidden"),
+                            Some(AttributeValue::BooleanTrue | AttributeValue::String(_))
+                        ))
+                {
+                    processor.skip_current_subtree();
+                    continue;
+                }
+
+                match tag {
+                    TagName::BR if !is_closer => writer.write_line_break(),
+
+                    TagName::TEXTAREA | TagName::XMP if !is_closer => {
+                        writer.write_preformatted(&processor.get_modifiable_text());
+                    }
+
+                    TagName::PRE | TagName::LISTING | TagName::PLAINTEXT => {
+                        writer.require_breaks(1);
+                        if !is_closer && preformatted_depth.is_none() {
+                            preformatted_depth = Some(depth);
+                        }
+                    }
+
+                    TagName::P => writer.require_breaks(2),
+
+                    TagName::TD | TagName::TH if is_closer => writer.pending_tab = true,
+
+                    _ if is_block(&tag) => writer.require_breaks(1),
+
+                    _ => {}
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    if processor.get_last_error().is_some() {
+        return None;
+    }
+
+    Some(writer.output.into())
+}
+
+/// Indicates if an element is displayed as a block by default, starting a new line.
+///
+/// @see https://html.spec.whatwg.org/#rendering
+fn is_block(tag: &TagName) -> bool {
+    match tag {
+        TagName::ADDRESS
+        | TagName::ARTICLE
+        | TagName::ASIDE
+        | TagName::BLOCKQUOTE
+        | TagName::CAPTION
+        | TagName::CENTER
+        | TagName::DD
+        | TagName::DETAILS
+        | TagName::DIALOG
+        | TagName::DIR
+        | TagName::DIV
+        | TagName::DL
+        | TagName::DT
+        | TagName::FIELDSET
+        | TagName::FIGCAPTION
+        | TagName::FIGURE
+        | TagName::FOOTER
+        | TagName::FORM
+        | TagName::H1
+        | TagName::H2
+        | TagName::H3
+        | TagName::H4
+        | TagName::H5
+        | TagName::H6
+        | TagName::HEADER
+        | TagName::HGROUP
+        | TagName::HR
+        | TagName::LI
+        | TagName::MAIN
+        | TagName::MENU
+        | TagName::NAV
+        | TagName::OL
+        | TagName::SECTION
+        | TagName::SUMMARY
+        | TagName::TABLE
+        | TagName::TR
+        | TagName::UL => true,
+        TagName::Arbitrary(name) => name.as_ref() == b"LEGEND" || name.as_ref() == b"OPTION",
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(html: &str) -> String {
+        String::from_utf8(extract_text(html.as_bytes()).unwrap().into_vec()).unwrap()
+    }
+
+    #[cfg(feature = "named-character-references")]
+    #[test]
+    fn test_collapses_whitespace() {
+        assert_eq!(text("  Hello \n\t <b> big </b>world  "), "Hello big world");
+        assert_eq!(text("a<span> </span>b"), "a b");
+        assert_eq!(text("a<span></span>b"), "ab");
+        assert_eq!(text("Fish &amp; chips&nbsp;!"), "Fish & chips\u{A0}!");
+    }
+
+    #[test]
+    fn test_block_boundaries() {
+        assert_eq!(
+            text("<h1> Title </h1>Intro<p>First</p><p>Second<div>Nested</div>After</p>End"),
+            "Title\nIntro\n\nFirst\n\nSecond\n\nNested\nAfter\n\nEnd"
+        );
+        assert_eq!(text("<ul><li>One<li>Two</ul>"), "One\nTwo");
+        assert_eq!(text("a<br>b<br><br>c<p>d"), "a\nb\n\nc\n\nd");
+        assert_eq!(text("<br>x"), "\nx");
+    }
+
+    #[test]
+    fn test_tables() {
+        assert_eq!(
+            text("<table><tr><th>Name<th>Age<tr><td>Ada<td> 36 </table>After"),
+            "Name\tAge\nAda\t36\nAfter"
+        );
+    }
+
+    #[test]
+    fn test_preformatted_text() {
+        assert_eq!(
+            text("<p>Code:</p><pre>\nfn  main() {\n    x();\n}</pre>after  it"),
+            "Code:\n\nfn  main() {\n    x();\n}\nafter it"
+        );
+        assert_eq!(text("Say <textarea>\n  hi  </textarea>!"), "Say   hi  !");
+    }
+
+    #[test]
+    fn test_skips_hidden_content() {
+        assert_eq!(
+            text(
+                "<head><title>Title</title><style>p {}</style></head>\
+                <p>Shown<script>hidden()</script><template>Hidden</template>\
+                <span hidden>Hidden</span><noscript>Hidden</noscript> text"
+            ),
+            "Shown text"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Handling for documents which aren't valid UTF-8.
+//!
+//! The HTML API operates on bytes. It assumes UTF-8 input but doesn't require it, and by
+//! default invalid byte sequences flow through to text, attribute values, and the updated
+//! HTML untouched. Code which hands these values to something expecting valid UTF-8, e.g.
+//! a JSON encoder or a Rust `str`, may instead choose a stricter policy for the document.
+//!
+//! @see https://encoding.spec.whatwg.org/#utf-8-decode
+
+use alloc::{boxed::Box, string::String};
+
+/// How the processors treat byte sequences which aren't valid UTF-8.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Utf8Policy {
+    /// Invalid byte sequences are passed through untouched.
+    #[default]
+    PassThrough,
+
+    /// Invalid byte sequences are replaced with U+FFFD in modifiable text, decoded
+    /// attribute values, and the updated HTML, the way a browser decodes them.
+    Replace,
+
+    /// Documents containing invalid byte sequences are rejected before any parsing.
+    Reject,
+}
+
+impl Utf8Policy {
+    /// Applies the policy to text taken from the document.
+    ///
+    /// Rejected documents are never parsed, so their text passes through unchanged.
+    ///
+    /// @param string $text Text from the document, e.g. a decoded attribute value.
+    /// @return string Text conforming to the policy.
+    pub(crate) fn apply(&self, text: Box<[u8]>) -> Box<[u8]> {
+        match self {
+            Utf8Policy::Replace => replace_invalid_utf8(text),
+            Utf8Policy::PassThrough | Utf8Policy::Reject => text,
+        }
+    }
+}
+
+/// Replaces every maximal invalid subsequence of UTF-8 with U+FFFD.
+///
+/// Valid input is returned without copying.
+///
+/// Example:
+///
+///     "a\u{FFFD}b" == replace_invalid_utf8( b"a\xF0\x9Fb" );
+///
+/// @param string $text Text which may contain invalid UTF-8.
+/// @return string Valid UTF-8 text.
+pub fn replace_invalid_utf8(text: Box<[u8]>) -> Box<[u8]> {
+    if core::str::from_utf8(&text).is_ok() {
+        return text;
+    }
+
+    String::from_utf8_lossy(&text)
+        .into_owned()
+        .into_bytes()
+        .into_boxed_slice()
+}
+
+/// Validates UTF-8 which arrives in chunks, e.g. from a reader.
+///
+/// A chunk may end in the middle of a multi-byte sequence, so an incomplete sequence at
+/// the end of one chunk is held until the next chunk completes it.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub(crate) struct Utf8Validator {
+    /// Start of a multi-byte sequence which the previous chunk ended in.
+    incomplete: alloc::vec::Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl Utf8Validator {
+    /// Validates the next chunk of input.
+    ///
+    /// @param string $chunk Next bytes of the input.
+    /// @return bool Whether the input so far is valid, or may become valid with more input.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> bool {
+        let mut rest = chunk;
+
+        // First complete the sequence which the previous chunk ended in.
+        if !self.incomplete.is_empty() {
+            let taken = chunk.len().min(4);
+            let mut joined = core::mem::take(&mut self.incomplete);
+            let held = joined.len();
+            joined.extend_from_slice(&chunk[..taken]);
+
+            let valid_up_to = match core::str::from_utf8(&joined) {
+                Ok(_) => joined.len(),
+                Err(error) if error.error_len().is_some() => return false,
+                Err(error) => error.valid_up_to(),
+            };
+
+            if valid_up_to < held {
+                // The chunk was too short to complete the sequence.
+                self.incomplete = joined;
+                return true;
+            }
+            rest = &chunk[valid_up_to - held..];
+        }
+
+        match core::str::from_utf8(rest) {
+            Ok(_) => true,
+            Err(error) if error.error_len().is_some() => false,
+            Err(error) => {
+                self.incomplete = rest[error.valid_up_to()..].to_vec();
+                true
+            }
+        }
+    }
+
+    /// Indicates if the input ended without cutting off a multi-byte sequence.
+    ///
+    /// @return bool Whether the complete input is valid.
+    pub(crate) fn finish(&self) -> bool {
+        self.incomplete.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tag_processor::{AttributeValue, TagProcessor};
+
+    #[test]
+    fn test_replace_invalid_utf8() {
+        assert_eq!(
+            replace_invalid_utf8(b"a\xF0\x9Fb".as_slice().into()).as_ref(),
+            "a\u{FFFD}b".as_bytes()
+        );
+        assert_eq!(
+            replace_invalid_utf8(b"\xC0\x80".as_slice().into()).as_ref(),
+            "\u{FFFD}\u{FFFD}".as_bytes()
+        );
+        assert_eq!(
+            replace_invalid_utf8("ok 😄".as_bytes().into()).as_ref(),
+            "ok 😄".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_policies() {
+        let html = b"<p title='\xFFtitle'>\xE2\x82text</p>";
+
+        let mut processor = TagProcessor::new(html);
+        assert!(processor.set_utf8_policy(Utf8Policy::PassThrough));
+        assert!(processor.next_tag(None));
+        assert_eq!(
+            processor.get_attribute(b"title"),
+            Some(AttributeValue::String(b"\xFFtitle".as_slice().into()))
+        );
+        assert!(processor.next_token());
+        assert_eq!(processor.get_modifiable_text().as_ref(), b"\xE2\x82text");
+        assert_eq!(processor.get_updated_html().as_ref(), html);
+
+        let mut processor = TagProcessor::new(html);
+        assert!(processor.set_utf8_policy(Utf8Policy::Replace));
+        assert!(processor.next_tag(None));
+        assert_eq!(
+            processor.get_attribute(b"title"),
+            Some(AttributeValue::String("\u{FFFD}title".as_bytes().into()))
+        );
+        assert!(processor.next_token());
+        assert_eq!(
+            processor.get_modifiable_text().as_ref(),
+            "\u{FFFD}text".as_bytes()
+        );
+        assert_eq!(
+            processor.get_updated_html().as_ref(),
+            "<p title='\u{FFFD}title'>\u{FFFD}text</p>".as_bytes()
+        );
+
+        let mut processor = TagProcessor::new(html);
+        assert!(!processor.set_utf8_policy(Utf8Policy::Reject));
+        assert!(!processor.next_token());
+
+        let mut processor = TagProcessor::new(b"<p>Valid \xE2\x9C\x93</p>");
+        assert!(processor.set_utf8_policy(Utf8Policy::Reject));
+        assert!(processor.next_tag(None));
+    }
+}
@@ -0,0 +1,1194 @@
+//! Streaming XML tokenizer modeled on WP_XML_Processor.
+//!
+//! The XML processor scans an XML document token by token with the same API shape as the
+//! HTML Tag Processor: call `next_token()` or `next_tag()` to advance, then inspect the
+//! matched token with the `get_*()` methods.
+//!
+//! Unlike HTML, XML has no error recovery. The processor stops at the first well-formedness
+//! violation and reports it through `get_last_error()`. When created for streaming, the
+//! processor pauses at the start of any token which isn't fully available yet; append more
+//! input with `append_bytes()` and continue calling `next_token()`.
+//!
+//! # Examples
+//!
+//! ```
+//! use wp_html_api::xml_processor::XmlProcessor;
+//!
+//! let mut processor = XmlProcessor::create_from_string(
+//!     b"<?xml version=\"1.0\"?><urlset><url><loc>https://example.com/?a=1&amp;b=2</loc></url></urlset>",
+//! );
+//!
+//! assert!(processor.next_tag(Some(b"loc")));
+//! assert!(processor.next_token());
+//! assert_eq!(
+//!     processor.get_modifiable_text().as_ref(),
+//!     b"https://example.com/?a=1&b=2"
+//! );
+//! ```
+//!
+//! Limitations:
+//!
+//!  - Only the predefined entities (`&lt;`, `&gt;`, `&amp;`, `&apos;`, `&quot;`) and numeric
+//!    character references are recognized. Entities declared in a DTD are reported as
+//!    invalid character references.
+//!  - Non-ASCII bytes are accepted as name characters without checking the full XML
+//!    `NameChar` production.
+//!  - Namespaces are not processed; tag and attribute names are returned as written.
+
+pub mod errors;
+
+use alloc::{boxed::Box, vec::Vec};
+use memchr::{memchr, memmem};
+
+use errors::XmlProcessorError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum XmlTokenType {
+    Tag,
+    Text,
+    CdataSection,
+    Comment,
+    ProcessingInstruction,
+    XmlDeclaration,
+    Doctype,
+}
+impl From<&XmlTokenType> for &str {
+    fn from(val: &XmlTokenType) -> Self {
+        match val {
+            XmlTokenType::Tag => "#tag",
+            XmlTokenType::Text => "#text",
+            XmlTokenType::CdataSection => "#cdata-section",
+            XmlTokenType::Comment => "#comment",
+            XmlTokenType::ProcessingInstruction => "#processing-instruction",
+            XmlTokenType::XmlDeclaration => "#xml-declaration",
+            XmlTokenType::Doctype => "#doctype",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum ParserState {
+    #[default]
+    Ready,
+    Complete,
+    IncompleteInput,
+    MatchedToken,
+}
+
+/// Where in the document the processor is, which determines the allowed tokens.
+///
+/// @see https://www.w3.org/TR/xml/#sec-well-formed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum ParserContext {
+    /// Before the root element: XML declaration, DOCTYPE, comments, PIs, whitespace.
+    #[default]
+    Prolog,
+    /// Inside the root element.
+    Element,
+    /// After the root element closes: comments, PIs, whitespace.
+    Misc,
+}
+
+/// Result of scanning a single token.
+enum Step {
+    /// A token was found and is ready to be inspected.
+    Token,
+    /// Syntax was consumed which isn't reported as a token, e.g. whitespace outside the root.
+    Skip,
+    /// The input ended before the token could be fully scanned.
+    Incomplete,
+}
+
+#[derive(Debug, Clone)]
+struct XmlAttributeToken {
+    name_starts_at: usize,
+    name_length: usize,
+    value_starts_at: usize,
+    value_length: usize,
+}
+
+pub struct XmlProcessor {
+    xml: Vec<u8>,
+
+    /// Whether more input may be appended to the document.
+    ///
+    /// When expecting more input, tokens which run into the end of the
+    /// document pause the processor instead of failing.
+    expecting_more_input: bool,
+
+    bytes_already_parsed: usize,
+    parser_state: ParserState,
+    parser_context: ParserContext,
+    last_error: Option<XmlProcessorError>,
+
+    token_type: Option<XmlTokenType>,
+    token_starts_at: usize,
+    token_length: usize,
+
+    /// Tag name, or processing instruction target.
+    name_starts_at: usize,
+    name_length: usize,
+
+    text_starts_at: usize,
+    text_length: usize,
+
+    is_closing_tag: bool,
+    is_empty_element: bool,
+    attributes: Vec<XmlAttributeToken>,
+
+    stack_of_open_elements: Vec<Box<[u8]>>,
+
+    /// Closing and empty element tags remain on the stack of open elements
+    /// while they are matched, and are popped before the next token.
+    pop_before_next_token: bool,
+    seen_doctype: bool,
+}
+
+impl XmlProcessor {
+    /// Creates a processor for a complete XML document.
+    pub fn create_from_string(xml: &[u8]) -> Self {
+        Self::new(xml, false)
+    }
+
+    /// Creates a processor for a document which will arrive in chunks.
+    ///
+    /// Call `append_bytes()` to add input and `input_finished()` once the
+    /// entire document has been provided.
+    pub fn create_for_streaming(xml: &[u8]) -> Self {
+        Self::new(xml, true)
+    }
+
+    fn new(xml: &[u8], expecting_more_input: bool) -> Self {
+        Self {
+            xml: xml.to_vec(),
+            expecting_more_input,
+            bytes_already_parsed: 0,
+            parser_state: ParserState::Ready,
+            parser_context: ParserContext::Prolog,
+            last_error: None,
+            token_type: None,
+            token_starts_at: 0,
+            token_length: 0,
+            name_starts_at: 0,
+            name_length: 0,
+            text_starts_at: 0,
+            text_length: 0,
+            is_closing_tag: false,
+            is_empty_element: false,
+            attributes: Vec::new(),
+            stack_of_open_elements: Vec::new(),
+            pop_before_next_token: false,
+            seen_doctype: false,
+        }
+    }
+
+    /// Appends more of the document to a streaming processor.
+    ///
+    /// If the processor paused at an incomplete token, the next call to
+    /// `next_token()` resumes at the start of that token.
+    pub fn append_bytes(&mut self, xml: &[u8]) {
+        if !self.expecting_more_input {
+            return;
+        }
+
+        self.xml.extend_from_slice(xml);
+        if self.parser_state == ParserState::IncompleteInput {
+            self.parser_state = ParserState::Ready;
+        }
+    }
+
+    /// Indicates that no more input will be appended.
+    ///
+    /// Any token left incomplete at the end of the document becomes an error.
+    pub fn input_finished(&mut self) {
+        self.expecting_more_input = false;
+        if self.parser_state == ParserState::IncompleteInput {
+            self.parser_state = ParserState::Ready;
+        }
+    }
+
+    /// Whether the processor paused because the input ended in the middle of a token.
+    pub fn paused_at_incomplete_input(&self) -> bool {
+        self.parser_state == ParserState::IncompleteInput
+    }
+
+    /// Returns the well-formedness error which stopped the processor, if any.
+    pub fn get_last_error(&self) -> Option<&XmlProcessorError> {
+        self.last_error.as_ref()
+    }
+
+    /// Finds the next opening tag, optionally with the given case-sensitive name.
+    ///
+    /// Closing tags are skipped; use `next_token()` to visit them.
+    pub fn next_tag(&mut self, tag_name: Option<&[u8]>) -> bool {
+        while self.next_token() {
+            if self.token_type != Some(XmlTokenType::Tag) || self.is_closing_tag {
+                continue;
+            }
+
+            if tag_name.is_none_or(|tag_name| tag_name == self.name()) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Finds the next token in the document.
+    ///
+    /// @return bool Whether a token was found.
+    pub fn next_token(&mut self) -> bool {
+        if self.last_error.is_some() || self.parser_state == ParserState::Complete {
+            return false;
+        }
+
+        if self.pop_before_next_token {
+            self.pop_before_next_token = false;
+            self.stack_of_open_elements.pop();
+            if self.stack_of_open_elements.is_empty() {
+                self.parser_context = ParserContext::Misc;
+            }
+        }
+
+        loop {
+            self.reset_token();
+
+            let at = self.bytes_already_parsed;
+            if at >= self.xml.len() {
+                return self.finish();
+            }
+
+            let step = if b'<' == self.xml[at] {
+                self.parse_markup(at)
+            } else {
+                self.parse_text(at)
+            };
+
+            match step {
+                Ok(Step::Token) => {
+                    self.token_starts_at = at;
+                    self.token_length = self.bytes_already_parsed - at;
+                    self.parser_state = ParserState::MatchedToken;
+                    return true;
+                }
+                Ok(Step::Skip) => continue,
+                Ok(Step::Incomplete) => {
+                    self.reset_token();
+                    self.bytes_already_parsed = at;
+                    if self.expecting_more_input {
+                        self.parser_state = ParserState::IncompleteInput;
+                        return false;
+                    }
+                    return self.fail(XmlProcessorError::UnexpectedEndOfInput);
+                }
+                Err(error) => return self.fail(error),
+            }
+        }
+    }
+
+    /// Returns the type of the matched token, if matched.
+    pub fn get_token_type(&self) -> Option<&XmlTokenType> {
+        self.token_type.as_ref()
+    }
+
+    /// Returns the name of the matched token, if matched.
+    ///
+    /// Tags report their tag name, processing instructions their target,
+    /// and other tokens a name such as `#text` or `#comment`.
+    pub fn get_token_name(&self) -> Option<Box<[u8]>> {
+        match self.token_type.as_ref()? {
+            XmlTokenType::Tag | XmlTokenType::ProcessingInstruction => Some(self.name().into()),
+            token_type => {
+                let name: &str = token_type.into();
+                Some(name.as_bytes().into())
+            }
+        }
+    }
+
+    /// Returns the name of the matched tag, as written in the document.
+    pub fn get_tag(&self) -> Option<Box<[u8]>> {
+        match self.token_type {
+            Some(XmlTokenType::Tag) => Some(self.name().into()),
+            _ => None,
+        }
+    }
+
+    /// Whether the matched token is a closing tag.
+    pub fn is_tag_closer(&self) -> bool {
+        self.token_type == Some(XmlTokenType::Tag) && self.is_closing_tag
+    }
+
+    /// Whether the matched tag is an empty-element tag, e.g. `<br/>`.
+    ///
+    /// Empty-element tags are not followed by a closing tag.
+    pub fn is_empty_element(&self) -> bool {
+        self.token_type == Some(XmlTokenType::Tag) && self.is_empty_element
+    }
+
+    /// Returns the decoded value of the attribute with the given case-sensitive name.
+    ///
+    /// Character references are decoded and whitespace is normalized
+    /// according to the attribute-value normalization rules.
+    ///
+    /// @see https://www.w3.org/TR/xml/#AVNormalize
+    pub fn get_attribute(&self, name: &[u8]) -> Option<Box<[u8]>> {
+        if self.token_type != Some(XmlTokenType::Tag) || self.is_closing_tag {
+            return None;
+        }
+
+        let attribute = self.attributes.iter().find(|attribute| {
+            name == &self.xml
+                [attribute.name_starts_at..attribute.name_starts_at + attribute.name_length]
+        })?;
+        let raw = &self.xml
+            [attribute.value_starts_at..attribute.value_starts_at + attribute.value_length];
+
+        Some(decode(raw, true).unwrap_or_else(|_| raw.into()))
+    }
+
+    /// Returns the names of attributes on the matched tag which start with the given prefix.
+    pub fn get_attribute_names_with_prefix(&self, prefix: &[u8]) -> Option<Vec<Box<[u8]>>> {
+        if self.token_type != Some(XmlTokenType::Tag) || self.is_closing_tag {
+            return None;
+        }
+
+        Some(
+            self.attributes
+                .iter()
+                .map(|attribute| {
+                    &self.xml
+                        [attribute.name_starts_at..attribute.name_starts_at + attribute.name_length]
+                })
+                .filter(|name| name.starts_with(prefix))
+                .map(Into::into)
+                .collect(),
+        )
+    }
+
+    /// Returns the text content of the matched token.
+    ///
+    /// - Text nodes are decoded.
+    /// - CDATA sections, comments, processing instructions and
+    ///   declarations are returned raw, with newlines normalized.
+    /// - Tags have no modifiable text.
+    pub fn get_modifiable_text(&self) -> Box<[u8]> {
+        let raw = &self.xml[self.text_starts_at..self.text_starts_at + self.text_length];
+        match self.token_type {
+            None | Some(XmlTokenType::Tag) => Box::new([]),
+            Some(XmlTokenType::Text) => decode(raw, false).unwrap_or_else(|_| raw.into()),
+            Some(_) => normalize_newlines(raw),
+        }
+    }
+
+    /// Returns the raw bytes of the matched token.
+    pub fn get_token_raw(&self) -> Option<&[u8]> {
+        self.token_type?;
+        Some(&self.xml[self.token_starts_at..self.token_starts_at + self.token_length])
+    }
+
+    /// Returns the names of the open elements, from the root element to the matched node.
+    ///
+    /// Closing tags and empty-element tags include the element they close.
+    pub fn get_breadcrumbs(&self) -> &[Box<[u8]>] {
+        &self.stack_of_open_elements
+    }
+
+    /// Returns the number of open elements at the matched node.
+    pub fn get_current_depth(&self) -> usize {
+        self.stack_of_open_elements.len()
+    }
+
+    fn name(&self) -> &[u8] {
+        &self.xml[self.name_starts_at..self.name_starts_at + self.name_length]
+    }
+
+    fn reset_token(&mut self) {
+        self.token_type = None;
+        self.token_starts_at = 0;
+        self.token_length = 0;
+        self.name_starts_at = 0;
+        self.name_length = 0;
+        self.text_starts_at = 0;
+        self.text_length = 0;
+        self.is_closing_tag = false;
+        self.is_empty_element = false;
+        self.attributes.clear();
+    }
+
+    fn fail(&mut self, error: XmlProcessorError) -> bool {
+        self.reset_token();
+        self.last_error = Some(error);
+        false
+    }
+
+    /// Handles reaching the end of the available input between tokens.
+    fn finish(&mut self) -> bool {
+        if self.expecting_more_input {
+            self.parser_state = ParserState::IncompleteInput;
+            return false;
+        }
+
+        match self.parser_context {
+            ParserContext::Prolog => self.fail(XmlProcessorError::MissingRootElement),
+            ParserContext::Element => self.fail(XmlProcessorError::UnexpectedEndOfInput),
+            ParserContext::Misc => {
+                self.parser_state = ParserState::Complete;
+                false
+            }
+        }
+    }
+
+    /// Checks whether `needle` appears at `at`.
+    ///
+    /// Returns `None` when the input ends before a decision can be made.
+    fn matches_at(&self, at: usize, needle: &[u8]) -> Option<bool> {
+        let available = &self.xml[at..self.xml.len().min(at + needle.len())];
+        if available.len() < needle.len() {
+            if needle.starts_with(available) {
+                None
+            } else {
+                Some(false)
+            }
+        } else {
+            Some(available == needle)
+        }
+    }
+
+    /// Returns the byte length of the XML name starting at `at`, or 0 if there is none.
+    ///
+    /// @see https://www.w3.org/TR/xml/#NT-Name
+    fn name_length(&self, at: usize) -> usize {
+        let mut length = 0;
+        for &c in &self.xml[at..] {
+            let is_name_char = match c {
+                b'A'..=b'Z' | b'a'..=b'z' | b'_' | b':' | 0x80.. => true,
+                b'0'..=b'9' | b'-' | b'.' => length > 0,
+                _ => false,
+            };
+            if !is_name_char {
+                break;
+            }
+            length += 1;
+        }
+        length
+    }
+
+    fn parse_text(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        let end = match memchr(b'<', &self.xml[at..]) {
+            Some(length) => at + length,
+            None if self.expecting_more_input => return Ok(Step::Incomplete),
+            None => self.xml.len(),
+        };
+        let text = &self.xml[at..end];
+
+        if self.parser_context != ParserContext::Element {
+            if text
+                .iter()
+                .all(|c| matches!(c, b' ' | b'\t' | b'\n' | b'\r'))
+            {
+                self.bytes_already_parsed = end;
+                return Ok(Step::Skip);
+            }
+            return Err(XmlProcessorError::ContentOutsideRootElement);
+        }
+
+        if memmem::find(text, b"]]>").is_some() {
+            return Err(XmlProcessorError::CdataCloserInText);
+        }
+        decode(text, false)?;
+
+        self.token_type = Some(XmlTokenType::Text);
+        self.text_starts_at = at;
+        self.text_length = end - at;
+        self.bytes_already_parsed = end;
+        Ok(Step::Token)
+    }
+
+    fn parse_markup(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        if at + 1 >= self.xml.len() {
+            return Ok(Step::Incomplete);
+        }
+
+        match self.xml[at + 1] {
+            b'/' => self.parse_closing_tag(at),
+            b'?' => self.parse_processing_instruction(at),
+            b'!' => {
+                let comment = self.matches_at(at, b"<!--");
+                let cdata = self.matches_at(at, b"<![CDATA[");
+                let doctype = self.matches_at(at, b"<!DOCTYPE");
+
+                if comment == Some(true) {
+                    self.parse_comment(at)
+                } else if cdata == Some(true) {
+                    self.parse_cdata(at)
+                } else if doctype == Some(true) {
+                    self.parse_doctype(at)
+                } else if comment.is_none() || cdata.is_none() || doctype.is_none() {
+                    Ok(Step::Incomplete)
+                } else {
+                    Err(XmlProcessorError::InvalidMarkup)
+                }
+            }
+            _ => self.parse_opening_tag(at),
+        }
+    }
+
+    /// Parses a comment.
+    ///
+    /// > For compatibility, the string "--" (double-hyphen) MUST NOT occur within comments.
+    ///
+    /// @see https://www.w3.org/TR/xml/#sec-comments
+    fn parse_comment(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        let text_starts_at = at + 4;
+        let Some(closer_at) = memmem::find(&self.xml[text_starts_at..], b"--") else {
+            return Ok(Step::Incomplete);
+        };
+        let closer_at = text_starts_at + closer_at;
+
+        if closer_at + 2 >= self.xml.len() {
+            return Ok(Step::Incomplete);
+        }
+        if b'>' != self.xml[closer_at + 2] {
+            return Err(XmlProcessorError::InvalidComment);
+        }
+
+        self.token_type = Some(XmlTokenType::Comment);
+        self.text_starts_at = text_starts_at;
+        self.text_length = closer_at - text_starts_at;
+        self.bytes_already_parsed = closer_at + 3;
+        Ok(Step::Token)
+    }
+
+    /// Parses a CDATA section, which may only appear inside the root element.
+    ///
+    /// @see https://www.w3.org/TR/xml/#sec-cdata-sect
+    fn parse_cdata(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        if self.parser_context != ParserContext::Element {
+            return Err(XmlProcessorError::ContentOutsideRootElement);
+        }
+
+        let text_starts_at = at + 9;
+        let Some(closer_at) = memmem::find(&self.xml[text_starts_at..], b"]]>") else {
+            return Ok(Step::Incomplete);
+        };
+
+        self.token_type = Some(XmlTokenType::CdataSection);
+        self.text_starts_at = text_starts_at;
+        self.text_length = closer_at;
+        self.bytes_already_parsed = text_starts_at + closer_at + 3;
+        Ok(Step::Token)
+    }
+
+    /// Parses a DOCTYPE declaration, which may only appear once, before the root element.
+    ///
+    /// The declaration is scanned for its end, skipping over quoted strings and
+    /// any internal subset, but its contents are not otherwise interpreted.
+    ///
+    /// @see https://www.w3.org/TR/xml/#sec-prolog-dtd
+    fn parse_doctype(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        if self.parser_context != ParserContext::Prolog || self.seen_doctype {
+            return Err(XmlProcessorError::InvalidDoctype);
+        }
+
+        let mut cursor = at + 9;
+        if cursor >= self.xml.len() {
+            return Ok(Step::Incomplete);
+        }
+        if !matches!(self.xml[cursor], b' ' | b'\t' | b'\n' | b'\r') {
+            return Err(XmlProcessorError::InvalidDoctype);
+        }
+        let text_starts_at = cursor + 1;
+
+        let mut quote: Option<u8> = None;
+        let mut in_internal_subset = false;
+        let closer_at = loop {
+            let Some(&c) = self.xml.get(cursor) else {
+                return Ok(Step::Incomplete);
+            };
+            match (quote, c) {
+                (Some(q), c) if q == c => quote = None,
+                (Some(_), _) => {}
+                (None, b'"' | b'\'') => quote = Some(c),
+                (None, b'[') => in_internal_subset = true,
+                (None, b']') => in_internal_subset = false,
+                (None, b'>') if !in_internal_subset => break cursor,
+                _ => {}
+            }
+            cursor += 1;
+        };
+
+        self.seen_doctype = true;
+        self.token_type = Some(XmlTokenType::Doctype);
+        self.text_starts_at = text_starts_at;
+        self.text_length = closer_at - text_starts_at;
+        self.bytes_already_parsed = closer_at + 1;
+        Ok(Step::Token)
+    }
+
+    /// Parses a processing instruction or the XML declaration.
+    ///
+    /// > The target names "XML", "xml", and so on are reserved for standardization.
+    ///
+    /// The XML declaration is only allowed at the very start of the document.
+    ///
+    /// @see https://www.w3.org/TR/xml/#sec-pi
+    fn parse_processing_instruction(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        let name_starts_at = at + 2;
+        let name_length = self.name_length(name_starts_at);
+        if name_starts_at + name_length >= self.xml.len() {
+            return Ok(Step::Incomplete);
+        }
+        if 0 == name_length {
+            return Err(XmlProcessorError::InvalidProcessingInstruction);
+        }
+
+        let after_name = name_starts_at + name_length;
+        let Some(closer_at) = memmem::find(&self.xml[after_name..], b"?>") else {
+            return Ok(Step::Incomplete);
+        };
+        let closer_at = after_name + closer_at;
+
+        if closer_at > after_name && !matches!(self.xml[after_name], b' ' | b'\t' | b'\n' | b'\r') {
+            return Err(XmlProcessorError::InvalidProcessingInstruction);
+        }
+        let text_starts_at = after_name
+            + strspn!(
+                self.xml,
+                b' ' | b'\t' | b'\n' | b'\r',
+                after_name,
+                closer_at - after_name
+            );
+
+        let target = &self.xml[name_starts_at..after_name];
+        self.token_type = if target.eq_ignore_ascii_case(b"xml") {
+            if b"xml" != target || 0 != at {
+                return Err(XmlProcessorError::InvalidProcessingInstruction);
+            }
+            Some(XmlTokenType::XmlDeclaration)
+        } else {
+            Some(XmlTokenType::ProcessingInstruction)
+        };
+
+        self.name_starts_at = name_starts_at;
+        self.name_length = name_length;
+        self.text_starts_at = text_starts_at;
+        self.text_length = closer_at - text_starts_at;
+        self.bytes_already_parsed = closer_at + 2;
+        Ok(Step::Token)
+    }
+
+    /// Parses a closing tag, which must close the current element.
+    ///
+    /// @see https://www.w3.org/TR/xml/#NT-ETag
+    fn parse_closing_tag(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        let name_starts_at = at + 2;
+        let name_length = self.name_length(name_starts_at);
+        if name_starts_at + name_length >= self.xml.len() {
+            return Ok(Step::Incomplete);
+        }
+        if 0 == name_length {
+            return Err(XmlProcessorError::InvalidTagName);
+        }
+
+        let mut cursor = name_starts_at + name_length;
+        cursor += strspn!(self.xml, b' ' | b'\t' | b'\n' | b'\r', cursor);
+        if cursor >= self.xml.len() {
+            return Ok(Step::Incomplete);
+        }
+        if b'>' != self.xml[cursor] {
+            return Err(XmlProcessorError::InvalidMarkup);
+        }
+
+        let name = &self.xml[name_starts_at..name_starts_at + name_length];
+        if self
+            .stack_of_open_elements
+            .last()
+            .is_none_or(|open_element| name != open_element.as_ref())
+        {
+            return Err(XmlProcessorError::MismatchedClosingTag);
+        }
+
+        self.token_type = Some(XmlTokenType::Tag);
+        self.is_closing_tag = true;
+        self.name_starts_at = name_starts_at;
+        self.name_length = name_length;
+        self.bytes_already_parsed = cursor + 1;
+        self.pop_before_next_token = true;
+        Ok(Step::Token)
+    }
+
+    /// Parses an opening or empty-element tag and its attributes.
+    ///
+    /// @see https://www.w3.org/TR/xml/#sec-starttags
+    fn parse_opening_tag(&mut self, at: usize) -> Result<Step, XmlProcessorError> {
+        let name_starts_at = at + 1;
+        let name_length = self.name_length(name_starts_at);
+        if 0 == name_length {
+            return Err(XmlProcessorError::InvalidMarkup);
+        }
+        if name_starts_at + name_length >= self.xml.len() {
+            return Ok(Step::Incomplete);
+        }
+        if self.parser_context == ParserContext::Misc {
+            return Err(XmlProcessorError::ContentOutsideRootElement);
+        }
+
+        let mut attributes: Vec<XmlAttributeToken> = Vec::new();
+        let mut cursor = name_starts_at + name_length;
+        let is_empty_element = loop {
+            let whitespace_length = strspn!(self.xml, b' ' | b'\t' | b'\n' | b'\r', cursor);
+            cursor += whitespace_length;
+            if cursor >= self.xml.len() {
+                return Ok(Step::Incomplete);
+            }
+
+            match self.xml[cursor] {
+                b'>' => {
+                    cursor += 1;
+                    break false;
+                }
+                b'/' => {
+                    if cursor + 1 >= self.xml.len() {
+                        return Ok(Step::Incomplete);
+                    }
+                    if b'>' != self.xml[cursor + 1] {
+                        return Err(XmlProcessorError::InvalidMarkup);
+                    }
+                    cursor += 2;
+                    break true;
+                }
+                _ if 0 == whitespace_length => return Err(XmlProcessorError::InvalidAttribute),
+                _ => {}
+            }
+
+            let attribute_name_starts_at = cursor;
+            let attribute_name_length = self.name_length(cursor);
+            if 0 == attribute_name_length {
+                return Err(XmlProcessorError::InvalidAttribute);
+            }
+            cursor += attribute_name_length;
+            cursor += strspn!(self.xml, b' ' | b'\t' | b'\n' | b'\r', cursor);
+            if cursor >= self.xml.len() {
+                return Ok(Step::Incomplete);
+            }
+            if b'=' != self.xml[cursor] {
+                return Err(XmlProcessorError::InvalidAttribute);
+            }
+            cursor += 1;
+            cursor += strspn!(self.xml, b' ' | b'\t' | b'\n' | b'\r', cursor);
+            if cursor >= self.xml.len() {
+                return Ok(Step::Incomplete);
+            }
+
+            let quote = self.xml[cursor];
+            if b'"' != quote && b'\'' != quote {
+                return Err(XmlProcessorError::InvalidAttribute);
+            }
+            let value_starts_at = cursor + 1;
+            let Some(value_length) = memchr(quote, &self.xml[value_starts_at..]) else {
+                return Ok(Step::Incomplete);
+            };
+            let value = &self.xml[value_starts_at..value_starts_at + value_length];
+            if memchr(b'<', value).is_some() {
+                return Err(XmlProcessorError::InvalidAttribute);
+            }
+            decode(value, true)?;
+
+            let attribute_name = &self.xml
+                [attribute_name_starts_at..attribute_name_starts_at + attribute_name_length];
+            if attributes.iter().any(|attribute| {
+                attribute_name
+                    == &self.xml
+                        [attribute.name_starts_at..attribute.name_starts_at + attribute.name_length]
+            }) {
+                return Err(XmlProcessorError::DuplicateAttribute);
+            }
+
+            attributes.push(XmlAttributeToken {
+                name_starts_at: attribute_name_starts_at,
+                name_length: attribute_name_length,
+                value_starts_at,
+                value_length,
+            });
+            cursor = value_starts_at + value_length + 1;
+        };
+
+        self.stack_of_open_elements
+            .push(self.xml[name_starts_at..name_starts_at + name_length].into());
+        self.parser_context = ParserContext::Element;
+
+        self.token_type = Some(XmlTokenType::Tag);
+        self.is_empty_element = is_empty_element;
+        self.pop_before_next_token = is_empty_element;
+        self.name_starts_at = name_starts_at;
+        self.name_length = name_length;
+        self.attributes = attributes;
+        self.bytes_already_parsed = cursor;
+        Ok(Step::Token)
+    }
+}
+
+/// Normalizes `\r\n` and lone `\r` to `\n`.
+///
+/// @see https://www.w3.org/TR/xml/#sec-line-ends
+fn normalize_newlines(raw: &[u8]) -> Box<[u8]> {
+    if memchr(b'\r', raw).is_none() {
+        return raw.into();
+    }
+
+    let mut normalized = Vec::with_capacity(raw.len());
+    let mut at = 0;
+    while at < raw.len() {
+        if b'\r' == raw[at] {
+            normalized.push(b'\n');
+            if raw.get(at + 1) == Some(&b'\n') {
+                at += 1;
+            }
+        } else {
+            normalized.push(raw[at]);
+        }
+        at += 1;
+    }
+    normalized.into()
+}
+
+/// Decodes character and predefined entity references in text or an attribute value.
+///
+/// Newlines are normalized. In attribute values, literal whitespace characters
+/// are additionally replaced with spaces, while whitespace produced by character
+/// references is preserved.
+///
+/// @see https://www.w3.org/TR/xml/#sec-references
+/// @see https://www.w3.org/TR/xml/#AVNormalize
+fn decode(raw: &[u8], is_attribute: bool) -> Result<Box<[u8]>, XmlProcessorError> {
+    let needs_decoding = raw
+        .iter()
+        .any(|&c| b'&' == c || b'\r' == c || (is_attribute && matches!(c, b'\t' | b'\n')));
+    if !needs_decoding {
+        return Ok(raw.into());
+    }
+
+    let mut decoded = Vec::with_capacity(raw.len());
+    let mut at = 0;
+    while at < raw.len() {
+        match raw[at] {
+            b'&' => {
+                let Some(name_length) = memchr(b';', &raw[at + 1..]) else {
+                    return Err(XmlProcessorError::InvalidCharacterReference);
+                };
+                let name = &raw[at + 1..at + 1 + name_length];
+                match name {
+                    b"lt" => decoded.push(b'<'),
+                    b"gt" => decoded.push(b'>'),
+                    b"amp" => decoded.push(b'&'),
+                    b"apos" => decoded.push(b'\''),
+                    b"quot" => decoded.push(b'"'),
+                    [b'#', b'x', digits @ ..] => push_code_point(&mut decoded, digits, 16)?,
+                    [b'#', digits @ ..] => push_code_point(&mut decoded, digits, 10)?,
+                    _ => return Err(XmlProcessorError::InvalidCharacterReference),
+                }
+                at += name_length + 2;
+                continue;
+            }
+            b'\r' => {
+                decoded.push(if is_attribute { b' ' } else { b'\n' });
+                if raw.get(at + 1) == Some(&b'\n') {
+                    at += 1;
+                }
+            }
+            b'\t' | b'\n' if is_attribute => decoded.push(b' '),
+            c => decoded.push(c),
+        }
+        at += 1;
+    }
+
+    Ok(decoded.into())
+}
+
+/// Appends the UTF-8 encoding of a numeric character reference.
+///
+/// > Characters referred to using character references MUST match the production for Char.
+///
+/// @see https://www.w3.org/TR/xml/#NT-Char
+fn push_code_point(
+    decoded: &mut Vec<u8>,
+    digits: &[u8],
+    radix: u32,
+) -> Result<(), XmlProcessorError> {
+    if digits.is_empty() {
+        return Err(XmlProcessorError::InvalidCharacterReference);
+    }
+
+    let mut code_point: u32 = 0;
+    for &digit in digits {
+        code_point = (digit as char)
+            .to_digit(radix)
+            .and_then(|digit| code_point.checked_mul(radix)?.checked_add(digit))
+            .ok_or(XmlProcessorError::InvalidCharacterReference)?;
+    }
+
+    if !matches!(
+        code_point,
+        0x9 | 0xA | 0xD | 0x20..=0xD7FF | 0xE000..=0xFFFD | 0x10000..=0x10FFFF
+    ) {
+        return Err(XmlProcessorError::InvalidCharacterReference);
+    }
+
+    let c = char::from_u32(code_point).ok_or(XmlProcessorError::InvalidCharacterReference)?;
+    decoded.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token_names(processor: &mut XmlProcessor) -> Vec<String> {
+        let mut names = Vec::new();
+        while processor.next_token() {
+            let name = String::from_utf8(processor.get_token_name().unwrap().into()).unwrap();
+            names.push(if processor.is_tag_closer() {
+                format!("/{}", name)
+            } else {
+                name
+            });
+        }
+        names
+    }
+
+    #[test]
+    fn test_tokenizes_document() {
+        let mut processor = XmlProcessor::create_from_string(
+            b"<?xml version=\"1.0\"?>\n<!DOCTYPE rss>\n<!-- c --><rss><channel><title>A &amp; B</title><br/><![CDATA[<raw>]]><?php echo 1 ?></channel></rss>\n",
+        );
+
+        assert_eq!(
+            token_names(&mut processor),
+            vec![
+                "#xml-declaration",
+                "#doctype",
+                "#comment",
+                "rss",
+                "channel",
+                "title",
+                "#text",
+                "/title",
+                "br",
+                "#cdata-section",
+                "php",
+                "/channel",
+                "/rss",
+            ]
+        );
+        assert!(processor.get_last_error().is_none());
+        assert!(!processor.paused_at_incomplete_input());
+    }
+
+    #[test]
+    fn test_modifiable_text() {
+        let mut processor = XmlProcessor::create_from_string(
+            b"<a>&lt;&#x1F600;&#65;\r\n</a><!--x\ry--><?pi  data ?>",
+        );
+
+        processor.next_token();
+        processor.next_token();
+        assert_eq!(
+            processor.get_modifiable_text().as_ref(),
+            "<😀A\n".as_bytes()
+        );
+        processor.next_token();
+        processor.next_token();
+        assert_eq!(processor.get_modifiable_text().as_ref(), b"x\ny");
+        processor.next_token();
+        assert_eq!(
+            processor.get_token_type(),
+            Some(&XmlTokenType::ProcessingInstruction)
+        );
+        assert_eq!(processor.get_modifiable_text().as_ref(), b"data ");
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut processor = XmlProcessor::create_from_string(
+            b"<url xmlns:image='ns' image:loc=\"a&amp;b\" ws=\"a\tb&#9;c\"/>",
+        );
+
+        assert!(processor.next_tag(Some(b"url")));
+        assert!(processor.is_empty_element());
+        assert_eq!(
+            processor.get_attribute(b"image:loc").as_deref(),
+            Some(b"a&b".as_slice())
+        );
+        assert_eq!(
+            processor.get_attribute(b"ws").as_deref(),
+            Some(b"a b\tc".as_slice())
+        );
+        assert_eq!(processor.get_attribute(b"IMAGE:LOC"), None);
+        assert_eq!(
+            processor.get_attribute_names_with_prefix(b"xmlns:"),
+            Some(vec![b"xmlns:image".as_slice().into()])
+        );
+    }
+
+    #[test]
+    fn test_breadcrumbs() {
+        let mut processor = XmlProcessor::create_from_string(b"<a><b><c/></b><d/></a>");
+
+        assert!(processor.next_tag(Some(b"c")));
+        assert_eq!(
+            processor.get_breadcrumbs(),
+            [
+                b"a".as_slice().into(),
+                b"b".as_slice().into(),
+                b"c".as_slice().into()
+            ]
+        );
+        assert!(processor.next_token());
+        assert!(processor.is_tag_closer());
+        assert_eq!(processor.get_current_depth(), 2);
+        assert!(processor.next_tag(None));
+        assert_eq!(processor.get_current_depth(), 2);
+        assert_eq!(processor.get_tag().as_deref(), Some(b"d".as_slice()));
+    }
+
+    #[test]
+    fn test_well_formedness_errors() {
+        let cases: &[(&[u8], XmlProcessorError)] = &[
+            (b"", XmlProcessorError::MissingRootElement),
+            (b"<!-- only -->", XmlProcessorError::MissingRootElement),
+            (b"<a>", XmlProcessorError::UnexpectedEndOfInput),
+            (b"<a><b></a>", XmlProcessorError::MismatchedClosingTag),
+            (b"<a></A>", XmlProcessorError::MismatchedClosingTag),
+            (b"<a/><b/>", XmlProcessorError::ContentOutsideRootElement),
+            (b"text<a/>", XmlProcessorError::ContentOutsideRootElement),
+            (b"<a/>text", XmlProcessorError::ContentOutsideRootElement),
+            (b"<a x=1/>", XmlProcessorError::InvalidAttribute),
+            (b"<a x/>", XmlProcessorError::InvalidAttribute),
+            (b"<a x='1'y='2'/>", XmlProcessorError::InvalidAttribute),
+            (b"<a x='<'/>", XmlProcessorError::InvalidAttribute),
+            (b"<a x='1' x='2'/>", XmlProcessorError::DuplicateAttribute),
+            (
+                b"<a>&nbsp;</a>",
+                XmlProcessorError::InvalidCharacterReference,
+            ),
+            (b"<a>&amp</a>", XmlProcessorError::InvalidCharacterReference),
+            (b"<a>&#0;</a>", XmlProcessorError::InvalidCharacterReference),
+            (
+                b"<a>&#xD800;</a>",
+                XmlProcessorError::InvalidCharacterReference,
+            ),
+            (b"<a>]]></a>", XmlProcessorError::CdataCloserInText),
+            (b"<a><!-- -- --></a>", XmlProcessorError::InvalidComment),
+            (b"<a><!-- x ---></a>", XmlProcessorError::InvalidComment),
+            (
+                b" <?xml version='1.0'?><a/>",
+                XmlProcessorError::InvalidProcessingInstruction,
+            ),
+            (
+                b"<a><?XML x?></a>",
+                XmlProcessorError::InvalidProcessingInstruction,
+            ),
+            (b"<a><!DOCTYPE a></a>", XmlProcessorError::InvalidDoctype),
+            (
+                b"<!DOCTYPE a><!DOCTYPE a><a/>",
+                XmlProcessorError::InvalidDoctype,
+            ),
+            (
+                b"<![CDATA[x]]><a/>",
+                XmlProcessorError::ContentOutsideRootElement,
+            ),
+            (b"<a><!ELEMENT></a>", XmlProcessorError::InvalidMarkup),
+            (b"<a>< b/></a>", XmlProcessorError::InvalidMarkup),
+            (b"<a><b", XmlProcessorError::UnexpectedEndOfInput),
+        ];
+
+        for (xml, expected) in cases {
+            let mut processor = XmlProcessor::create_from_string(xml);
+            while processor.next_token() {}
+            assert_eq!(
+                processor.get_last_error(),
+                Some(expected),
+                "Unexpected error for {:?}",
+                String::from_utf8_lossy(xml)
+            );
+            assert!(
+                !processor.next_token(),
+                "Should not continue after an error."
+            );
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        let mut processor = XmlProcessor::create_for_streaming(b"<feed><entry id=\"1");
+
+        assert!(processor.next_tag(Some(b"feed")));
+        assert!(!processor.next_token());
+        assert!(processor.paused_at_incomplete_input());
+        assert!(processor.get_last_error().is_none());
+
+        processor.append_bytes(b"\">Hello, ");
+        assert!(processor.next_tag(Some(b"entry")));
+        assert_eq!(
+            processor.get_attribute(b"id").as_deref(),
+            Some(b"1".as_slice())
+        );
+
+        // Text may continue in the next chunk, so it isn't matched yet.
+        assert!(!processor.next_token());
+        assert!(processor.paused_at_incomplete_input());
+
+        processor.append_bytes(b"world!</entry></feed>");
+        assert!(processor.next_token());
+        assert_eq!(processor.get_modifiable_text().as_ref(), b"Hello, world!");
+        assert!(processor.next_token());
+        assert!(processor.next_token());
+        assert!(!processor.next_token());
+        assert!(processor.paused_at_incomplete_input());
+
+        processor.input_finished();
+        assert!(!processor.next_token());
+        assert!(!processor.paused_at_incomplete_input());
+        assert!(processor.get_last_error().is_none());
+    }
+
+    #[test]
+    fn test_streaming_incomplete_markup_prefixes() {
+        for chunk in [
+            b"<".as_slice(),
+            b"<!",
+            b"<!-",
+            b"<![CDA",
+            b"<!DOC",
+            b"<?",
+            b"</",
+        ] {
+            let mut processor = XmlProcessor::create_for_streaming(b"<root>");
+            assert!(processor.next_token());
+            processor.append_bytes(chunk);
+            assert!(!processor.next_token());
+            assert!(
+                processor.paused_at_incomplete_input(),
+                "Should pause at {:?}",
+                String::from_utf8_lossy(chunk)
+            );
+        }
+    }
+
+    #[test]
+    fn test_incomplete_input_is_an_error_once_finished() {
+        let mut processor = XmlProcessor::create_for_streaming(b"<root><!-- unfinished");
+
+        assert!(processor.next_token());
+        assert!(!processor.next_token());
+        assert!(processor.paused_at_incomplete_input());
+
+        processor.input_finished();
+        assert!(!processor.next_token());
+        assert_eq!(
+            processor.get_last_error(),
+            Some(&XmlProcessorError::UnexpectedEndOfInput)
+        );
+    }
+}
@@ -0,0 +1,65 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum XmlProcessorError {
+    /// The document ended in the middle of a token or with elements left open.
+    UnexpectedEndOfInput,
+    /// The document contains no root element.
+    MissingRootElement,
+    /// Text, CDATA or a second root element was found outside of the root element.
+    ContentOutsideRootElement,
+    /// A closing tag does not match the currently open element.
+    MismatchedClosingTag,
+    /// A tag name is not a valid XML name.
+    InvalidTagName,
+    /// An attribute is missing its name, `=`, quotes, or leading whitespace.
+    InvalidAttribute,
+    /// The same attribute appears more than once on a tag.
+    DuplicateAttribute,
+    /// A character or entity reference is malformed or refers to an undeclared entity.
+    InvalidCharacterReference,
+    /// Text contains the `]]>` sequence, which is only allowed to close CDATA sections.
+    CdataCloserInText,
+    /// A comment contains `--` or ends with `--->`.
+    InvalidComment,
+    /// A processing instruction has no valid target, or an XML declaration is misplaced.
+    InvalidProcessingInstruction,
+    /// A DOCTYPE declaration appears after the root element or more than once.
+    InvalidDoctype,
+    /// `<` is followed by something which isn't valid markup.
+    InvalidMarkup,
+}
+impl core::error::Error for XmlProcessorError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+impl core::fmt::Display for XmlProcessorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.into())
+    }
+}
+impl From<XmlProcessorError> for &str {
+    fn from(val: XmlProcessorError) -> Self {
+        use XmlProcessorError as E;
+        match val {
+            E::UnexpectedEndOfInput => "unexpected-end-of-input",
+            E::MissingRootElement => "missing-root-element",
+            E::ContentOutsideRootElement => "content-outside-root-element",
+            E::MismatchedClosingTag => "mismatched-closing-tag",
+            E::InvalidTagName => "invalid-tag-name",
+            E::InvalidAttribute => "invalid-attribute",
+            E::DuplicateAttribute => "duplicate-attribute",
+            E::InvalidCharacterReference => "invalid-character-reference",
+            E::CdataCloserInText => "cdata-closer-in-text",
+            E::InvalidComment => "invalid-comment",
+            E::InvalidProcessingInstruction => "invalid-processing-instruction",
+            E::InvalidDoctype => "invalid-doctype",
+            E::InvalidMarkup => "invalid-markup",
+        }
+    }
+}
+impl From<&XmlProcessorError> for &str {
+    fn from(val: &XmlProcessorError) -> Self {
+        Into::<&str>::into(*val)
+    }
+}
@@ -0,0 +1,1114 @@
+//! Cases ported from WordPress core's PHPUnit tests for `WP_HTML_Tag_Processor`.
+//!
+//! Each test keeps the name of the PHP test it was ported from, so that differences
+//! in behavior can be traced back to the original. Values which are `null` in PHP
+//! are `None` here, except for missing attributes, which are `AttributeValue::BooleanFalse`
+//! on any matched tag, including tag closers.
+//!
+//! @see https://github.com/WordPress/wordpress-develop/tree/trunk/tests/phpunit/tests/html-api
+
+use wp_html_api::{
+    tag_name::TagName,
+    tag_processor::{
+        AttributeValue, CommentType, NextTagQuery, NodeName, TagClosers, TagProcessor, TokenType,
+    },
+};
+
+const HTML_SIMPLE: &[u8] = b"<div id=\"first\"><span id=\"second\">Text</span></div>";
+const HTML_WITH_CLASSES: &[u8] =
+    b"<div class=\"main with-border\" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>";
+const HTML_MALFORMED: &[u8] =
+    b"<div><span class=\"d-md-none\" Notifications</span><span class=\"d-none d-md-inline\">Back to notifications</span></div>";
+
+fn tag(name: TagName) -> Option<NextTagQuery> {
+    Some(NextTagQuery {
+        tag_name: Some(name),
+        ..Default::default()
+    })
+}
+
+fn tag_and_closers(name: TagName) -> Option<NextTagQuery> {
+    Some(NextTagQuery {
+        tag_name: Some(name),
+        tag_closers: TagClosers::Visit,
+        ..Default::default()
+    })
+}
+
+fn string(value: &str) -> Option<AttributeValue> {
+    Some(AttributeValue::String(value.as_bytes().into()))
+}
+
+fn updated_html(processor: &TagProcessor) -> String {
+    String::from_utf8(processor.get_updated_html().into_vec()).unwrap()
+}
+
+/*
+ * wpHtmlTagProcessor.php
+ */
+
+#[test]
+fn test_get_tag_returns_null_before_finding_tags() {
+    let processor = TagProcessor::new(b"<div>Test</div>");
+    assert_eq!(processor.get_tag(), None);
+}
+
+#[test]
+fn test_get_tag_returns_null_when_not_in_open_tag() {
+    let mut processor = TagProcessor::new(b"<div>Test</div>");
+    assert!(!processor.next_tag(tag(TagName::P)));
+    assert_eq!(processor.get_tag(), None);
+}
+
+#[test]
+fn test_get_tag_returns_open_tag_name() {
+    let mut processor = TagProcessor::new(b"<div>Test</div>");
+    assert!(processor.next_tag(tag(TagName::DIV)));
+    assert_eq!(processor.get_tag(), Some(TagName::DIV));
+}
+
+#[test]
+fn test_has_self_closing_flag_matches_input_html() {
+    for (html, flag_is_set) in [
+        ("<div>", false),
+        ("<div/>", true),
+        ("<div />", true),
+        ("<div id=a/>", true),
+        ("<div id=a />", true),
+        ("<div id=\"a\"/>", true),
+        ("<div id=a/ >", false),
+        ("<div / id=a>", false),
+        ("<img/>", true),
+        ("<svg/>", true),
+    ] {
+        let mut processor = TagProcessor::new(html.as_bytes());
+        assert!(processor.next_tag(None), "{html}");
+        assert_eq!(processor.has_self_closing_flag(), flag_is_set, "{html}");
+    }
+}
+
+#[test]
+fn test_get_attribute_returns_null_when_not_in_open_tag() {
+    let processor = TagProcessor::new(b"<div class=\"test\">Test</div>");
+    assert_eq!(processor.get_attribute(b"class"), None);
+}
+
+#[test]
+fn test_get_attribute_returns_null_when_in_closing_tag() {
+    let mut processor = TagProcessor::new(b"<div class=\"test\">Test</div>");
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert!(processor.is_tag_closer());
+    assert_eq!(
+        processor.get_attribute(b"class"),
+        Some(AttributeValue::BooleanFalse)
+    );
+}
+
+#[test]
+fn test_get_attribute_returns_null_when_attribute_missing() {
+    let mut processor = TagProcessor::new(b"<div class=\"test\">Test</div>");
+    assert!(processor.next_tag(tag(TagName::DIV)));
+    assert_eq!(
+        processor.get_attribute(b"test-id"),
+        Some(AttributeValue::BooleanFalse)
+    );
+}
+
+#[test]
+fn test_get_attribute_returns_attribute_value() {
+    let mut processor = TagProcessor::new(b"<div class=\"test\">Test</div>");
+    assert!(processor.next_tag(tag(TagName::DIV)));
+    assert_eq!(processor.get_attribute(b"class"), string("test"));
+}
+
+#[test]
+fn test_get_attribute_returns_true_for_boolean_attribute() {
+    let mut processor = TagProcessor::new(b"<div enabled class=\"test\">Test</div>");
+    assert!(processor.next_tag(None));
+    assert_eq!(
+        processor.get_attribute(b"enabled"),
+        Some(AttributeValue::BooleanTrue)
+    );
+}
+
+#[test]
+fn test_get_attribute_returns_string_for_truthy_attributes() {
+    let mut processor = TagProcessor::new(
+        b"<div enabled=enabled checked=1 hidden=\"true\" class=\"test\">Test</div>",
+    );
+    assert!(processor.next_tag(None));
+    assert_eq!(processor.get_attribute(b"enabled"), string("enabled"));
+    assert_eq!(processor.get_attribute(b"checked"), string("1"));
+    assert_eq!(processor.get_attribute(b"hidden"), string("true"));
+}
+
+#[cfg(feature = "named-character-references")]
+#[test]
+fn test_get_attribute_decodes_html_character_references() {
+    let mut processor = TagProcessor::new(
+        b"<div id=\"the &quot;grande&quot; is &lt; &#x033;&#50;oz&dagger;\"></div>",
+    );
+    assert!(processor.next_tag(None));
+    assert_eq!(
+        processor.get_attribute(b"id"),
+        string("the \"grande\" is < 32oz\u{2020}")
+    );
+}
+
+#[test]
+fn test_attributes_parser_treats_slash_as_attribute_separator() {
+    let mut processor = TagProcessor::new(b"<div a/b/c/d/e=\"f\"/>");
+    assert!(processor.next_tag(None));
+    for name in [b"a", b"b", b"c", b"d"] {
+        assert_eq!(
+            processor.get_attribute(name),
+            Some(AttributeValue::BooleanTrue)
+        );
+    }
+    assert_eq!(processor.get_attribute(b"e"), string("f"));
+}
+
+#[test]
+fn test_get_attribute_is_case_insensitive() {
+    let mut processor = TagProcessor::new(b"<div DATA-enabled=\"true\">Test</div>");
+    assert!(processor.next_tag(None));
+    assert_eq!(processor.get_attribute(b"data-enabled"), string("true"));
+    assert_eq!(processor.get_attribute(b"DATA-enabled"), string("true"));
+}
+
+#[test]
+fn test_remove_attribute_is_case_insensitive() {
+    let mut processor = TagProcessor::new(b"<div DATA-enabled=\"true\">Test</div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_attribute("data-enabled"));
+    assert_eq!(updated_html(&processor), "<div >Test</div>");
+}
+
+#[test]
+fn test_set_attribute_is_case_insensitive() {
+    let mut processor = TagProcessor::new(b"<div DATA-enabled=\"true\">Test</div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("data-enabled", "abc"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div data-enabled=\"abc\">Test</div>"
+    );
+}
+
+#[test]
+fn test_get_attribute_names_with_prefix_returns_null_before_finding_tags() {
+    let processor = TagProcessor::new(b"<div data-foo=\"bar\">Test</div>");
+    assert_eq!(processor.get_attribute_names_with_prefix(b"data-"), None);
+}
+
+#[test]
+fn test_get_attribute_names_with_prefix_returns_null_when_not_in_open_tag() {
+    let mut processor = TagProcessor::new(b"<div data-foo=\"bar\">Test</div>");
+    assert!(!processor.next_tag(tag(TagName::P)));
+    assert_eq!(processor.get_attribute_names_with_prefix(b"data-"), None);
+}
+
+#[test]
+fn test_get_attribute_names_with_prefix_returns_null_when_in_closing_tag() {
+    let mut processor = TagProcessor::new(b"<div data-foo=\"bar\">Test</div>");
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert_eq!(processor.get_attribute_names_with_prefix(b"data-"), None);
+}
+
+#[test]
+fn test_get_attribute_names_with_prefix_returns_empty_array_when_no_attributes_present() {
+    let mut processor = TagProcessor::new(b"<div>Test</div>");
+    assert!(processor.next_tag(None));
+    assert_eq!(
+        processor.get_attribute_names_with_prefix(b"data-"),
+        Some(Vec::new())
+    );
+}
+
+#[test]
+fn test_get_attribute_names_with_prefix_returns_matching_attribute_names_in_lowercase() {
+    let mut processor =
+        TagProcessor::new(b"<div DATA-enabled class=\"test\" data-test-id=\"14\">Test</div>");
+    assert!(processor.next_tag(None));
+    let names: Vec<_> = processor
+        .get_attribute_names_with_prefix(b"data-")
+        .unwrap()
+        .iter()
+        .map(|name| name.to_vec())
+        .collect();
+    assert_eq!(
+        names,
+        vec![b"data-enabled".to_vec(), b"data-test-id".to_vec()]
+    );
+}
+
+#[test]
+fn test_get_attribute_names_with_prefix_returns_attribute_added_by_set_attribute() {
+    let mut processor = TagProcessor::new(b"<div data-foo=\"bar\">Test</div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("data-test-id", "14"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div data-test-id=\"14\" data-foo=\"bar\">Test</div>"
+    );
+    let names: Vec<_> = processor
+        .get_attribute_names_with_prefix(b"data-")
+        .unwrap()
+        .iter()
+        .map(|name| name.to_vec())
+        .collect();
+    assert_eq!(names, vec![b"data-test-id".to_vec(), b"data-foo".to_vec()]);
+}
+
+#[test]
+fn test_to_string_returns_updated_html() {
+    let mut processor = TagProcessor::new(
+        b"<hr id=\"remove\" /><div enabled class=\"test\">Test</div><span id=\"span-id\"></span>",
+    );
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_attribute("id"));
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("id", "div-id-1"));
+    assert!(processor.add_class("new_class_1"));
+    assert_eq!(
+        updated_html(&processor),
+        "<hr  /><div id=\"div-id-1\" enabled class=\"test new_class_1\">Test</div><span id=\"span-id\"></span>"
+    );
+}
+
+#[test]
+fn test_set_attribute_on_a_non_existing_tag_does_not_change_the_markup() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(!processor.next_tag(tag(TagName::P)));
+    assert!(!processor.set_attribute("id", "primary"));
+    assert_eq!(processor.get_updated_html().as_ref(), HTML_SIMPLE);
+}
+
+#[test]
+fn test_set_attribute_with_a_non_existing_attribute_adds_a_new_attribute_to_the_markup() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("test-attribute", "test-value"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div test-attribute=\"test-value\" id=\"first\"><span id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_set_attribute_with_an_existing_attribute_name_updates_its_value_in_the_markup() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("id", "new-id"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div id=\"new-id\"><span id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_next_tag_and_set_attribute_in_a_loop_update_all_tags_in_the_markup() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    while processor.next_tag(None) {
+        assert!(processor.set_attribute("data-foo", "bar"));
+    }
+    assert_eq!(
+        updated_html(&processor),
+        "<div data-foo=\"bar\" id=\"first\"><span data-foo=\"bar\" id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_update_first_attribute_when_duplicated_attributes_exist() {
+    let mut processor = TagProcessor::new(
+        b"<div id=\"update-me\" id=\"ignored-id\"><span id=\"second\">Text</span></div>",
+    );
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("id", "updated-value"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div id=\"updated-value\" id=\"ignored-id\"><span id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_remove_attribute_with_an_existing_attribute_name_removes_it_from_the_markup() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_attribute("id"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div ><span id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_remove_first_when_duplicated_attribute() {
+    let mut processor = TagProcessor::new(
+        b"<div id=\"update-me\" id=\"ignored-id\"><span id=\"second\">Text</span></div>",
+    );
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_attribute("id"));
+    assert_eq!(
+        processor.get_attribute(b"id"),
+        Some(AttributeValue::BooleanFalse)
+    );
+    assert_eq!(
+        updated_html(&processor),
+        "<div  ><span id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_remove_attribute_with_a_non_existing_attribute_name_does_not_change_the_markup() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    assert!(!processor.remove_attribute("no-such-attribute"));
+    assert_eq!(processor.get_updated_html().as_ref(), HTML_SIMPLE);
+}
+
+#[test]
+fn test_add_class_creates_a_class_attribute_when_there_is_none() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("foo-class"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"foo-class\" id=\"first\"><span id=\"second\">Text</span></div>"
+    );
+    assert_eq!(processor.get_attribute(b"class"), string("foo-class"));
+}
+
+#[test]
+fn test_calling_add_class_twice_creates_a_class_attribute_with_both_class_names_when_there_is_no_class_attribute()
+ {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("foo-class"));
+    assert!(processor.add_class("bar-class"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"foo-class bar-class\" id=\"first\"><span id=\"second\">Text</span></div>"
+    );
+    assert_eq!(
+        processor.get_attribute(b"class"),
+        string("foo-class bar-class")
+    );
+}
+
+#[test]
+fn test_remove_class_does_not_change_the_markup_when_there_is_no_class_attribute() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    processor.remove_class("foo-class");
+    assert_eq!(processor.get_updated_html().as_ref(), HTML_SIMPLE);
+    assert_eq!(
+        processor.get_attribute(b"class"),
+        Some(AttributeValue::BooleanFalse)
+    );
+}
+
+#[test]
+fn test_add_class_appends_class_names_to_the_existing_class_attribute_when_one_already_exists() {
+    let mut processor = TagProcessor::new(HTML_WITH_CLASSES);
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("foo-class"));
+    assert!(processor.add_class("bar-class"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"main with-border foo-class bar-class\" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_remove_class_removes_a_single_class_from_the_class_attribute_when_one_exists() {
+    let mut processor = TagProcessor::new(HTML_WITH_CLASSES);
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_class("main"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"with-border\" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>"
+    );
+    assert_eq!(processor.get_attribute(b"class"), string("with-border"));
+}
+
+#[test]
+fn test_calling_remove_class_with_all_listed_class_names_removes_the_existing_class_attribute_from_the_markup()
+ {
+    let mut processor = TagProcessor::new(HTML_WITH_CLASSES);
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_class("main"));
+    assert!(processor.remove_class("with-border"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div  id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>"
+    );
+    assert_eq!(
+        processor.get_attribute(b"class"),
+        Some(AttributeValue::BooleanFalse)
+    );
+}
+
+#[test]
+fn test_add_class_does_not_add_duplicate_class_names() {
+    let mut processor = TagProcessor::new(HTML_WITH_CLASSES);
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("with-border"));
+    assert_eq!(processor.get_updated_html().as_ref(), HTML_WITH_CLASSES);
+    assert_eq!(
+        processor.get_attribute(b"class"),
+        string("main with-border")
+    );
+}
+
+#[test]
+fn test_add_class_preserves_class_name_order_when_a_duplicate_class_name_is_added() {
+    let mut processor = TagProcessor::new(HTML_WITH_CLASSES);
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("main"));
+    assert_eq!(processor.get_updated_html().as_ref(), HTML_WITH_CLASSES);
+    assert_eq!(
+        processor.get_attribute(b"class"),
+        string("main with-border")
+    );
+}
+
+#[test]
+fn test_add_class_when_there_is_a_class_attribute_with_excessive_whitespaces() {
+    let mut processor = TagProcessor::new(
+        b"<div class=\"   main   with-border   \" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>",
+    );
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("foo-class"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"main   with-border foo-class\" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_remove_class_preserves_whitespaces_when_there_is_a_class_attribute_with_excessive_whitespaces()
+ {
+    let mut processor = TagProcessor::new(
+        b"<div class=\"   main   with-border   \" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>",
+    );
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_class("with-border"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"main\" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_set_attribute_takes_priority_over_add_class() {
+    let mut processor = TagProcessor::new(HTML_WITH_CLASSES);
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("add_class"));
+    assert!(processor.set_attribute("class", "set_attribute"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"set_attribute\" id=\"first\"><span class=\"not-main bold with-border\" id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_add_class_overrides_boolean_class_attribute() {
+    let mut processor = TagProcessor::new(HTML_SIMPLE);
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("foo"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"foo\" id=\"first\"><span id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_has_class_matches_whole_class_names() {
+    let mut processor = TagProcessor::new(b"<div class=\"main with-border\">");
+    assert_eq!(processor.has_class("main"), None);
+    assert!(processor.next_tag(None));
+    assert_eq!(processor.has_class("main"), Some(true));
+    assert_eq!(processor.has_class("with-border"), Some(true));
+    assert_eq!(processor.has_class("with"), Some(false));
+    assert_eq!(processor.has_class("MAIN"), Some(false));
+}
+
+#[test]
+fn test_class_list_visits_each_class_name_once() {
+    let mut processor = TagProcessor::new(b"<div class=\"one two  one\tthree\">");
+    assert!(processor.next_tag(None));
+    let class_names: Vec<_> = processor
+        .class_list()
+        .iter()
+        .map(|class_name| class_name.to_vec())
+        .collect();
+    assert_eq!(
+        class_names,
+        vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+    );
+}
+
+#[test]
+fn test_next_tag_with_class_name_finds_matching_tags() {
+    let mut processor = TagProcessor::new(HTML_WITH_CLASSES);
+    assert!(processor.next_tag(Some(NextTagQuery {
+        class_name: Some("with-border".into()),
+        match_offset: 2,
+        ..Default::default()
+    })));
+    assert_eq!(processor.get_tag(), Some(TagName::SPAN));
+    assert_eq!(processor.get_attribute(b"id"), string("second"));
+}
+
+#[test]
+fn test_works_with_single_quote_marks() {
+    let mut processor = TagProcessor::new(b"<div id='first'><span id='second'>Text</span></div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_attribute("id"));
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("id", "second"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div ><span id=\"second\">Text</span></div>"
+    );
+}
+
+#[test]
+fn test_set_attribute_with_value_equal_to_double_quote_is_escaped() {
+    let mut processor = TagProcessor::new(b"<div></div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.set_attribute("id", "\"><script>alert(1)</script>"));
+    assert_eq!(
+        updated_html(&processor),
+        "<div id=\"&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;\"></div>"
+    );
+}
+
+#[test]
+fn test_set_attribute_prevents_invalid_attribute_names() {
+    let mut processor = TagProcessor::new(b"<div></div>");
+    assert!(processor.next_tag(None));
+    for name in ["", "a b", "a\"b", "a'b", "a>b", "a/b", "a=b", "a\0b"] {
+        assert!(!processor.set_attribute(name, "value"), "{name:?}");
+    }
+    assert_eq!(updated_html(&processor), "<div></div>");
+}
+
+#[test]
+fn test_next_tag_ignores_contents_of_a_script_tag() {
+    let mut processor = TagProcessor::new(
+        b"<script>var html = '<div id=\"inside\">';</script><div id=\"outside\">",
+    );
+    assert!(processor.next_tag(tag(TagName::DIV)));
+    assert_eq!(processor.get_attribute(b"id"), string("outside"));
+}
+
+#[test]
+fn test_next_tag_ignores_script_tag_contents_with_escaped_closers() {
+    for html in [
+        "<script><!--</script><div id=outside>",
+        "<script><!-- <script> </script> --></script><div id=outside>",
+        "<script></SCRIPT ><div id=outside>",
+    ] {
+        let mut processor = TagProcessor::new(html.as_bytes());
+        assert!(processor.next_tag(tag(TagName::DIV)), "{html}");
+        assert_eq!(processor.get_attribute(b"id"), string("outside"), "{html}");
+    }
+}
+
+#[test]
+fn test_next_tag_ignores_contents_of_rcdata_tags() {
+    for (html, element) in [
+        (
+            "<textarea><div id=inside></textarea><div id=outside>",
+            TagName::TEXTAREA,
+        ),
+        (
+            "<title><div id=inside></title><div id=outside>",
+            TagName::TITLE,
+        ),
+        ("<textarea></TEXTAREA ><div id=outside>", TagName::TEXTAREA),
+    ] {
+        let mut processor = TagProcessor::new(html.as_bytes());
+        assert!(processor.next_tag(None), "{html}");
+        assert_eq!(processor.get_tag(), Some(element), "{html}");
+        assert!(processor.next_tag(tag(TagName::DIV)), "{html}");
+        assert_eq!(processor.get_attribute(b"id"), string("outside"), "{html}");
+    }
+}
+
+#[test]
+fn test_next_tag_ignores_contents_of_rawtext_tags() {
+    for html in [
+        "<iframe><div id=inside></iframe><div id=outside>",
+        "<noembed><div id=inside></noembed><div id=outside>",
+        "<noframes><div id=inside></noframes><div id=outside>",
+        "<style><div id=inside></style><div id=outside>",
+        "<xmp><div id=inside></xmp><div id=outside>",
+    ] {
+        let mut processor = TagProcessor::new(html.as_bytes());
+        assert!(processor.next_tag(tag(TagName::DIV)), "{html}");
+        assert_eq!(processor.get_attribute(b"id"), string("outside"), "{html}");
+    }
+}
+
+#[test]
+fn test_next_tag_on_malformed_html_finds_each_tag() {
+    let mut processor = TagProcessor::new(HTML_MALFORMED);
+    let mut tags = Vec::new();
+    while processor.next_tag(None) {
+        tags.push(processor.get_tag().unwrap());
+    }
+    assert_eq!(tags, vec![TagName::DIV, TagName::SPAN, TagName::SPAN]);
+}
+
+#[test]
+fn test_updating_specific_attributes_in_malformed_html() {
+    let mut processor = TagProcessor::new(HTML_MALFORMED);
+    assert!(processor.next_tag(tag(TagName::SPAN)));
+    assert!(processor.set_attribute("class", "d-md-none"));
+    assert!(processor.next_tag(tag(TagName::SPAN)));
+    assert!(processor.set_attribute("class", "d-none d-md-inline"));
+    assert_eq!(processor.get_updated_html().as_ref(), HTML_MALFORMED);
+}
+
+#[test]
+fn test_unclosed_tag_pauses_at_incomplete_token() {
+    for html in ["<div", "<div id=\"a", "<div><!-- comment", "<div><script>x"] {
+        let mut processor = TagProcessor::new(html.as_bytes());
+        while processor.next_token() {}
+        assert!(processor.paused_at_incomplete_token(), "{html}");
+    }
+}
+
+#[test]
+fn test_closing_tag_with_attributes_is_still_a_closer() {
+    let mut processor = TagProcessor::new(b"<div></div id=\"x\" class=\"y\">");
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert!(processor.is_tag_closer());
+    assert_eq!(
+        processor.get_attribute(b"id"),
+        Some(AttributeValue::BooleanFalse)
+    );
+    assert!(!processor.set_attribute("id", "z"));
+}
+
+#[test]
+fn test_tag_names_are_uppercase_and_raw_names_are_preserved() {
+    let mut processor = TagProcessor::new(b"<Custom-Element><dIv>");
+    assert!(processor.next_tag(None));
+    assert_eq!(processor.get_tag_raw(), Some(b"Custom-Element".as_slice()));
+    assert_eq!(
+        processor.get_qualified_tag_name().as_deref(),
+        Some(b"CUSTOM-ELEMENT".as_slice())
+    );
+    assert!(processor.next_tag(None));
+    assert_eq!(processor.get_tag(), Some(TagName::DIV));
+}
+
+/*
+ * wpHtmlTagProcessor-token-scanning.php
+ */
+
+#[test]
+fn test_basic_assertion_text_node() {
+    let mut processor = TagProcessor::new(b"Hello, World!");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::Text));
+    assert_eq!(
+        processor.get_token_name(),
+        Some(NodeName::Token(TokenType::Text))
+    );
+    assert_eq!(processor.get_modifiable_text().as_ref(), b"Hello, World!");
+    assert!(!processor.next_token());
+}
+
+#[test]
+fn test_basic_assertion_element() {
+    let mut processor =
+        TagProcessor::new(b"<div id=\"test\" class=\"compound\">Hello, World!</div>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::Tag));
+    assert_eq!(processor.get_tag(), Some(TagName::DIV));
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::Text));
+    assert!(processor.next_token());
+    assert_eq!(processor.get_tag(), Some(TagName::DIV));
+    assert!(processor.is_tag_closer());
+    assert!(!processor.next_token());
+}
+
+#[test]
+fn test_basic_assertion_script_element() {
+    let mut processor =
+        TagProcessor::new(b"<script type=\"module\">console.log( \"Hello, World!\" );</script>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_tag(), Some(TagName::SCRIPT));
+    assert_eq!(processor.get_attribute(b"type"), string("module"));
+    assert_eq!(
+        processor.get_modifiable_text().as_ref(),
+        b"console.log( \"Hello, World!\" );"
+    );
+    assert!(!processor.next_token());
+}
+
+#[test]
+fn test_basic_assertion_textarea_strips_leading_newline() {
+    let mut processor =
+        TagProcessor::new(b"<textarea rows=30 cols=80>\nHello, <b>World</b>!</textarea>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_tag(), Some(TagName::TEXTAREA));
+    assert_eq!(
+        processor.get_modifiable_text().as_ref(),
+        b"Hello, <b>World</b>!"
+    );
+}
+
+#[cfg(feature = "named-character-references")]
+#[test]
+fn test_basic_assertion_title_decodes_character_references() {
+    let mut processor = TagProcessor::new(b"<title>Hello &amp; <b>World</b></title>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_tag(), Some(TagName::TITLE));
+    assert_eq!(
+        processor.get_modifiable_text().as_ref(),
+        b"Hello & <b>World</b>"
+    );
+}
+
+#[test]
+fn test_basic_assertion_rawtext_elements_do_not_decode_character_references() {
+    for (html, element) in [
+        ("<iframe>Hello &amp; World</iframe>", TagName::IFRAME),
+        ("<noembed>Hello &amp; World</noembed>", TagName::NOEMBED),
+        ("<noframes>Hello &amp; World</noframes>", TagName::NOFRAMES),
+        ("<style>Hello &amp; World</style>", TagName::STYLE),
+        ("<xmp>Hello &amp; World</xmp>", TagName::XMP),
+    ] {
+        let mut processor = TagProcessor::new(html.as_bytes());
+        assert!(processor.next_token(), "{html}");
+        assert_eq!(processor.get_tag(), Some(element), "{html}");
+        assert_eq!(
+            processor.get_modifiable_text().as_ref(),
+            b"Hello &amp; World",
+            "{html}"
+        );
+    }
+}
+
+#[test]
+fn test_basic_assertion_cdata_section_outside_foreign_content_is_a_comment() {
+    let mut processor = TagProcessor::new(b"<![CDATA[this is a comment]]>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::Comment));
+    assert!(processor.get_comment_type() == Some(&CommentType::CdataLookalike));
+    assert_eq!(
+        processor.get_modifiable_text().as_ref(),
+        b"this is a comment"
+    );
+}
+
+#[test]
+fn test_basic_assertion_abruptly_closed_comment() {
+    for (html, text) in [("<!-->", ""), ("<!--->", "")] {
+        let mut processor = TagProcessor::new(html.as_bytes());
+        assert!(processor.next_token(), "{html}");
+        assert_eq!(processor.get_token_type(), Some(&TokenType::Comment));
+        assert!(
+            processor.get_comment_type() == Some(&CommentType::AbruptlyClosedComment),
+            "{html}"
+        );
+        assert_eq!(processor.get_modifiable_text().as_ref(), text.as_bytes());
+    }
+}
+
+#[test]
+fn test_basic_assertion_html_comment() {
+    let mut processor = TagProcessor::new(b"<!-- wp:paragraph --><!---->");
+    assert!(processor.next_token());
+    assert!(processor.get_comment_type() == Some(&CommentType::HtmlComment));
+    assert_eq!(processor.get_modifiable_text().as_ref(), b" wp:paragraph ");
+    assert!(processor.next_token());
+    assert!(processor.get_comment_type() == Some(&CommentType::HtmlComment));
+    assert_eq!(processor.get_modifiable_text().as_ref(), b"");
+}
+
+#[test]
+fn test_basic_assertion_processing_instruction_lookalike() {
+    let mut processor = TagProcessor::new(b"<?wp-bit {\"just\": \"kidding\"}?>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::Comment));
+    assert!(processor.get_comment_type() == Some(&CommentType::PiNodeLookalike));
+    assert_eq!(
+        processor.get_modifiable_text().as_ref(),
+        b" {\"just\": \"kidding\"}"
+    );
+    assert_eq!(
+        processor.get_full_comment_text().as_deref(),
+        Some(b"?wp-bit {\"just\": \"kidding\"}?".as_slice())
+    );
+}
+
+#[test]
+fn test_basic_assertion_invalid_html_comment() {
+    let mut processor = TagProcessor::new(b"<!something else>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::Comment));
+    assert!(processor.get_comment_type() == Some(&CommentType::InvalidHtml));
+    assert_eq!(processor.get_modifiable_text().as_ref(), b"something else");
+}
+
+#[test]
+fn test_basic_assertion_doctype() {
+    let mut processor = TagProcessor::new(b"<!DOCTYPE html>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::Doctype));
+    assert_eq!(processor.get_modifiable_text().as_ref(), b" html");
+}
+
+#[test]
+fn test_basic_assertion_presumptuous_tag() {
+    let mut processor = TagProcessor::new(b"</>");
+    assert!(processor.next_token());
+    assert_eq!(
+        processor.get_token_type(),
+        Some(&TokenType::PresumptuousTag)
+    );
+    assert_eq!(processor.get_modifiable_text().as_ref(), b"");
+}
+
+#[test]
+fn test_basic_assertion_funky_comment() {
+    let mut processor = TagProcessor::new(b"</%url>");
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::FunkyComment));
+    assert_eq!(processor.get_modifiable_text().as_ref(), b"%url");
+}
+
+#[test]
+fn test_funky_comments_are_not_tag_closers() {
+    let mut processor = TagProcessor::new(b"<div></#></div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.next_token());
+    assert_eq!(processor.get_token_type(), Some(&TokenType::FunkyComment));
+    assert_eq!(processor.get_modifiable_text().as_ref(), b"#");
+    assert!(processor.next_token());
+    assert!(processor.is_tag_closer());
+}
+
+/*
+ * wpHtmlTagProcessor-bookmark.php
+ */
+
+#[test]
+fn test_set_bookmark() {
+    let mut processor = TagProcessor::new(b"<ul><li>One</li><li>Two</li><li>Three</li></ul>");
+    assert!(processor.next_tag(tag(TagName::LI)));
+    assert!(processor.set_bookmark("first li").is_ok());
+    assert!(processor.next_tag(tag(TagName::LI)));
+    assert!(processor.set_bookmark("second li").is_ok());
+    assert!(processor.has_bookmark("first li"));
+    assert!(processor.has_bookmark("second li"));
+}
+
+#[test]
+fn test_release_bookmark() {
+    let mut processor = TagProcessor::new(b"<ul><li>One</li><li>Two</li><li>Three</li></ul>");
+    assert!(processor.next_tag(tag(TagName::LI)));
+    assert!(processor.set_bookmark("first li").is_ok());
+    assert!(processor.release_bookmark("first li"));
+    assert!(!processor.has_bookmark("first li"));
+    assert!(!processor.release_bookmark("first li"));
+}
+
+#[test]
+fn test_seek() {
+    let mut processor = TagProcessor::new(b"<ul><li>One</li><li>Two</li><li>Three</li></ul>");
+    assert!(processor.next_tag(tag(TagName::LI)));
+    assert!(processor.set_bookmark("first li").is_ok());
+    assert!(processor.next_tag(tag(TagName::LI)));
+    assert!(processor.set_attribute("foo-2", "bar-2"));
+
+    assert!(processor.seek("first li"));
+    assert!(processor.set_attribute("foo-1", "bar-1"));
+
+    assert_eq!(
+        updated_html(&processor),
+        "<ul><li foo-1=\"bar-1\">One</li><li foo-2=\"bar-2\">Two</li><li>Three</li></ul>"
+    );
+}
+
+#[test]
+fn test_seeks_to_tag_closer_bookmark() {
+    let mut processor = TagProcessor::new(b"<div>First</div><span>Second</span>");
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert!(processor.next_tag(tag_and_closers(TagName::DIV)));
+    assert!(processor.is_tag_closer());
+    assert!(processor.set_bookmark("closer").is_ok());
+    assert!(processor.next_tag(tag(TagName::SPAN)));
+
+    assert!(processor.seek("closer"));
+    assert_eq!(processor.get_tag(), Some(TagName::DIV));
+    assert!(processor.is_tag_closer());
+}
+
+#[test]
+fn test_seek_fails_for_unknown_bookmarks() {
+    let mut processor = TagProcessor::new(b"<div>");
+    assert!(processor.next_tag(None));
+    assert!(!processor.seek("unknown"));
+    assert_eq!(processor.get_tag(), Some(TagName::DIV));
+}
+
+#[test]
+fn test_removing_long_attributes_doesnt_break_seek() {
+    let mut processor =
+        TagProcessor::new(b"<button twenty_one_characters 7_chars></button><button></button>");
+    assert!(processor.next_tag(tag(TagName::BUTTON)));
+    assert!(processor.set_bookmark("first").is_ok());
+    assert!(processor.next_tag(tag(TagName::BUTTON)));
+    assert!(processor.set_bookmark("second").is_ok());
+
+    assert!(processor.seek("first"));
+    assert!(processor.remove_attribute("twenty_one_characters"));
+    assert!(processor.remove_attribute("7_chars"));
+
+    assert!(processor.seek("second"));
+    assert!(processor.set_attribute("id", "second"));
+    assert_eq!(
+        updated_html(&processor),
+        "<button  ></button><button id=\"second\"></button>"
+    );
+}
+
+#[test]
+fn test_bookmarks_complex_use_case() {
+    let mut processor = TagProcessor::new(
+        b"<div selected class=\"merge-message\" checked>\
+          <div class=\"select-menu d-inline-block\">\
+          <div checked class=\"BtnGroup MixedCaseHTML position-relative\" />\
+          <div checked class=\"BtnGroup MixedCaseHTML position-relative\">\
+          <button type=\"button\" class=\"merge-box-button btn-group-merge rounded-left-2 btn  BtnGroup-item js-details-target hx_create-pr-button\" aria-expanded=\"false\" data-details-container=\".js-merge-pr\" disabled=\"\">\
+          Merge pull request\
+          </button>\
+          <button type=\"button\" class=\"merge-box-button btn-group-squash rounded-left-2 btn  BtnGroup-item js-details-target hx_create-pr-button\" aria-expanded=\"false\" data-details-container=\".js-merge-pr\" disabled=\"\">\
+          Squash and merge\
+          </button>\
+          </div>\
+          </div>\
+          </div>",
+    );
+    assert!(processor.next_tag(tag(TagName::BUTTON)));
+    assert!(processor.set_bookmark("first").is_ok());
+    assert!(processor.next_tag(tag(TagName::BUTTON)));
+    assert!(processor.set_bookmark("second").is_ok());
+
+    assert!(processor.seek("first"));
+    assert!(processor.remove_attribute("type"));
+    assert!(processor.remove_attribute("class"));
+    assert!(processor.remove_attribute("aria-expanded"));
+    assert!(processor.remove_attribute("data-details-container"));
+    assert!(processor.remove_attribute("disabled"));
+    assert!(processor.set_attribute("id", "first"));
+
+    assert!(processor.seek("second"));
+    assert!(processor.add_class("second"));
+    assert_eq!(processor.get_attribute(b"type"), string("button"));
+
+    let html = updated_html(&processor);
+    assert!(
+        html.contains("<button id=\"first\"     >Merge pull request</button>"),
+        "{html}"
+    );
+    assert!(html.contains("class=\"merge-box-button btn-group-squash rounded-left-2 btn  BtnGroup-item js-details-target hx_create-pr-button second\""), "{html}");
+}
+
+#[test]
+fn test_updates_bookmark_for_additions_after_both_sides() {
+    let mut processor = TagProcessor::new(b"<div>First</div><div>Second</div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.set_bookmark("first").is_ok());
+    assert!(processor.next_tag(None));
+    assert!(processor.add_class("second"));
+
+    assert!(processor.seek("first"));
+    assert!(processor.add_class("first"));
+
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"first\">First</div><div class=\"second\">Second</div>"
+    );
+}
+
+#[test]
+fn test_updates_bookmark_for_additions_before_both_sides() {
+    let mut processor = TagProcessor::new(b"<div>First</div><div>Second</div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.set_bookmark("first").is_ok());
+    assert!(processor.next_tag(None));
+    assert!(processor.set_bookmark("second").is_ok());
+
+    assert!(processor.seek("first"));
+    assert!(processor.add_class("first"));
+
+    assert!(processor.seek("second"));
+    assert!(processor.add_class("second"));
+
+    assert_eq!(
+        updated_html(&processor),
+        "<div class=\"first\">First</div><div class=\"second\">Second</div>"
+    );
+}
+
+#[test]
+fn test_updates_bookmark_for_deletions_after_both_sides() {
+    let mut processor = TagProcessor::new(b"<div>First</div><div disabled>Second</div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.set_bookmark("first").is_ok());
+    assert!(processor.next_tag(None));
+    assert!(processor.remove_attribute("disabled"));
+
+    assert!(processor.seek("first"));
+    assert!(processor.set_attribute("untouched", "yes"));
+
+    assert_eq!(
+        updated_html(&processor),
+        "<div untouched=\"yes\">First</div><div >Second</div>"
+    );
+}
+
+#[test]
+fn test_updates_bookmark_for_deletions_before_both_sides() {
+    let mut processor = TagProcessor::new(b"<div disabled>First</div><div>Second</div>");
+    assert!(processor.next_tag(None));
+    assert!(processor.set_bookmark("first").is_ok());
+    assert!(processor.next_tag(None));
+    assert!(processor.set_bookmark("second").is_ok());
+
+    assert!(processor.seek("first"));
+    assert!(processor.remove_attribute("disabled"));
+
+    assert!(processor.seek("second"));
+    assert!(processor.set_attribute("safe", "yes"));
+
+    assert_eq!(
+        updated_html(&processor),
+        "<div >First</div><div safe=\"yes\">Second</div>"
+    );
+}
+
+#[test]
+fn test_limits_the_number_of_seek_calls() {
+    let mut processor = TagProcessor::new(b"<ul><li>One</li><li>Two</li><li>Three</li></ul>");
+    assert!(processor.next_tag(tag(TagName::LI)));
+    assert!(processor.set_bookmark("bookmark").is_ok());
+
+    for _ in 0..1000 {
+        assert!(processor.seek("bookmark"));
+    }
+    assert!(!processor.seek("bookmark"));
+}